@@ -0,0 +1,14 @@
+pub mod avb;
+pub mod bootimg;
+pub mod dtbo;
+pub mod erofs;
+pub mod ext4;
+pub mod fsimage;
+pub mod gpt;
+pub mod lp;
+pub mod payload;
+pub mod ramdisk;
+pub mod sparse;
+
+// Parsers for generic Android image formats. Nothing in here talks to
+// a device; callers feed it files from packages or read-back dumps.
@@ -0,0 +1,759 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fs, path::Path};
+
+// Android boot images (boot, recovery, init_boot, vendor_boot).
+//
+//   boot v0-v2   "ANDROID!"  page_size from header; kernel, ramdisk,
+//                second, recovery_dtbo (v1+), dtb (v2+)
+//   boot v3-v4   "ANDROID!"  fixed 4 KiB pages; kernel, ramdisk,
+//                boot signature (v4)
+//   vendor v3-v4 "VNDRBOOT"  vendor ramdisk(s), dtb, ramdisk table and
+//                bootconfig (v4)
+//
+// Every section starts on a page boundary. Unpacking keeps the raw
+// header page so repacking an unmodified image is byte-identical; only
+// the fields that changed are written back.
+
+pub const BOOT_MAGIC: &[u8] = b"ANDROID!";
+pub const VENDOR_BOOT_MAGIC: &[u8] = b"VNDRBOOT";
+
+const BOOT_V3_PAGE_SIZE: u32 = 4096;
+const RAMDISK_TABLE_ENTRY_SIZE: usize = 108;
+
+const MANIFEST: &str = "bootimg.json";
+const HEADER_FILE: &str = "header.bin";
+const TAIL_FILE: &str = "tail.bin";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BootImageKind {
+    Boot,
+    VendorBoot,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BootSection {
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// One entry of the vendor_boot v4 ramdisk table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VendorRamdisk {
+    pub name: String,
+    /// 1 = platform, 2 = recovery, 3 = dlkm
+    pub ramdisk_type: u32,
+    pub board_id: Vec<u32>,
+    /// Relative to the start of the vendor ramdisk section
+    pub offset: u64,
+    pub size: u64,
+    pub file: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BootImage {
+    pub kind: BootImageKind,
+    pub header_version: u32,
+    pub page_size: u32,
+    /// Size of the header region, page aligned
+    pub header_len: u64,
+
+    pub name: String,
+    pub cmdline: String,
+    /// v0-v2 only; appended to `cmdline` by the bootloader
+    pub extra_cmdline: String,
+    pub os_version: u32,
+    /// Decoded `os_version`, e.g. "13.0.0"
+    pub android_version: Option<String>,
+    /// Decoded `os_version`, e.g. "2023-05"
+    pub patch_level: Option<String>,
+
+    pub kernel_addr: Option<u32>,
+    pub ramdisk_addr: Option<u32>,
+    pub tags_addr: Option<u32>,
+
+    pub sections: Vec<BootSection>,
+    pub vendor_ramdisks: Vec<VendorRamdisk>,
+
+    /// Non-zero data after the last section (usually the AVB footer)
+    pub tail_offset: Option<u64>,
+    pub total_size: u64,
+}
+
+impl BootImage {
+    pub fn section(&self, name: &str) -> Option<&BootSection> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// Bytes of a section within the image it was parsed from.
+    pub fn section_data<'a>(&self, data: &'a [u8], name: &str) -> Option<&'a [u8]> {
+        let section = self.section(name)?;
+        data.get(section.offset as usize..(section.offset + section.size) as usize)
+    }
+
+    /// Each (still compressed) ramdisk with its name; one for boot images,
+    /// one per table entry for vendor_boot.
+    pub fn ramdisks<'a>(&self, data: &'a [u8]) -> Vec<(String, &'a [u8])> {
+        if self.kind == BootImageKind::Boot {
+            return self
+                .section_data(data, "ramdisk")
+                .filter(|r| !r.is_empty())
+                .map(|r| vec![(String::new(), r)])
+                .unwrap_or_default();
+        }
+
+        let section = self.section_data(data, "vendor_ramdisk").unwrap_or_default();
+
+        self.vendor_ramdisks
+            .iter()
+            .filter_map(|r| {
+                let bytes = section.get(r.offset as usize..(r.offset + r.size) as usize)?;
+                Some((r.name.clone(), bytes))
+            })
+            .collect()
+    }
+}
+
+/* ================= LAYOUT ================= */
+
+/// Field offsets shared by parse and repack.
+struct Layout {
+    /// Section name and the header offset of its u32 size field
+    sections: &'static [(&'static str, usize)],
+    name: Option<(usize, usize)>,
+    cmdline: (usize, usize),
+    extra_cmdline: Option<(usize, usize)>,
+    os_version: Option<usize>,
+}
+
+const BOOT_V0: &[(&str, usize)] = &[("kernel", 8), ("ramdisk", 16), ("second", 24)];
+const BOOT_V1: &[(&str, usize)] =
+    &[("kernel", 8), ("ramdisk", 16), ("second", 24), ("recovery_dtbo", 1632)];
+const BOOT_V2: &[(&str, usize)] = &[
+    ("kernel", 8),
+    ("ramdisk", 16),
+    ("second", 24),
+    ("recovery_dtbo", 1632),
+    ("dtb", 1648),
+];
+const BOOT_V3: &[(&str, usize)] = &[("kernel", 8), ("ramdisk", 12)];
+const BOOT_V4: &[(&str, usize)] = &[("kernel", 8), ("ramdisk", 12), ("signature", 1580)];
+const VENDOR_V3: &[(&str, usize)] = &[("vendor_ramdisk", 24), ("dtb", 2100)];
+const VENDOR_V4: &[(&str, usize)] = &[
+    ("vendor_ramdisk", 24),
+    ("dtb", 2100),
+    ("vendor_ramdisk_table", 2112),
+    ("bootconfig", 2124),
+];
+
+fn layout(kind: BootImageKind, version: u32) -> Result<Layout, String> {
+    let layout = match (kind, version) {
+        (BootImageKind::Boot, 0..=2) => Layout {
+            sections: [BOOT_V0, BOOT_V1, BOOT_V2][version as usize],
+            name: Some((48, 16)),
+            cmdline: (64, 512),
+            extra_cmdline: Some((608, 1024)),
+            os_version: Some(44),
+        },
+        (BootImageKind::Boot, 3 | 4) => Layout {
+            sections: if version == 3 { BOOT_V3 } else { BOOT_V4 },
+            name: None,
+            cmdline: (44, 1536),
+            extra_cmdline: None,
+            os_version: Some(16),
+        },
+        (BootImageKind::VendorBoot, 3 | 4) => Layout {
+            sections: if version == 3 { VENDOR_V3 } else { VENDOR_V4 },
+            name: Some((2080, 16)),
+            cmdline: (28, 2048),
+            extra_cmdline: None,
+            os_version: None,
+        },
+        (kind, version) => {
+            return Err(format!("Unsupported {:?} header version {}", kind, version))
+        }
+    };
+
+    Ok(layout)
+}
+
+/* ================= PARSER ================= */
+
+pub fn detect_kind(data: &[u8]) -> Option<BootImageKind> {
+    if data.starts_with(BOOT_MAGIC) {
+        Some(BootImageKind::Boot)
+    } else if data.starts_with(VENDOR_BOOT_MAGIC) {
+        Some(BootImageKind::VendorBoot)
+    } else {
+        None
+    }
+}
+
+pub fn parse_boot_image(data: &[u8]) -> Result<BootImage, String> {
+    let kind = detect_kind(data).ok_or("Not an Android boot image (bad magic)")?;
+
+    if data.len() < 4096 {
+        return Err("Boot image truncated".into());
+    }
+
+    let (version, page_size, header_size) = match kind {
+        BootImageKind::Boot => {
+            let version = le32(data, 40);
+            let page_size = if version >= 3 { BOOT_V3_PAGE_SIZE } else { le32(data, 36) };
+            (version, page_size, 0)
+        }
+        BootImageKind::VendorBoot => (le32(data, 8), le32(data, 12), le32(data, 2096)),
+    };
+
+    if !page_size.is_power_of_two() || !(2048..=65536).contains(&page_size) {
+        return Err(format!("Invalid page size {}", page_size));
+    }
+
+    let layout = layout(kind, version)?;
+    let page = u64::from(page_size);
+    let header_len = align(u64::from(header_size).max(1), page);
+
+    let mut sections = Vec::new();
+    let mut offset = header_len;
+
+    for &(name, field) in layout.sections {
+        let size = u64::from(le32(data, field));
+
+        if offset + size > data.len() as u64 {
+            return Err(format!("Section {} extends past end of image", name));
+        }
+
+        let bytes = &data[offset as usize..(offset + size) as usize];
+        sections.push(BootSection {
+            name: name.to_string(),
+            offset,
+            size,
+            sha256: sha256_hex(bytes),
+        });
+
+        offset += align(size, page);
+    }
+
+    let end = offset.min(data.len() as u64);
+    let tail_offset = data[end as usize..]
+        .iter()
+        .position(|&b| b != 0)
+        .map(|p| end + p as u64);
+
+    let os_version = layout.os_version.map(|o| le32(data, o)).unwrap_or(0);
+    let (android_version, patch_level) = decode_os_version(os_version);
+
+    let mut image = BootImage {
+        kind,
+        header_version: version,
+        page_size,
+        header_len,
+        name: layout.name.map(|(o, l)| c_string(&data[o..o + l])).unwrap_or_default(),
+        cmdline: c_string(&data[layout.cmdline.0..layout.cmdline.0 + layout.cmdline.1]),
+        extra_cmdline: layout
+            .extra_cmdline
+            .map(|(o, l)| c_string(&data[o..o + l]))
+            .unwrap_or_default(),
+        os_version,
+        android_version,
+        patch_level,
+        kernel_addr: None,
+        ramdisk_addr: None,
+        tags_addr: None,
+        sections,
+        vendor_ramdisks: Vec::new(),
+        tail_offset,
+        total_size: data.len() as u64,
+    };
+
+    match (kind, version) {
+        (BootImageKind::Boot, 0..=2) => {
+            image.kernel_addr = Some(le32(data, 12));
+            image.ramdisk_addr = Some(le32(data, 20));
+            image.tags_addr = Some(le32(data, 32));
+        }
+        (BootImageKind::VendorBoot, _) => {
+            image.kernel_addr = Some(le32(data, 16));
+            image.ramdisk_addr = Some(le32(data, 20));
+            image.tags_addr = Some(le32(data, 2076));
+        }
+        _ => {}
+    }
+
+    if kind == BootImageKind::VendorBoot {
+        image.vendor_ramdisks = vendor_ramdisks(data, &image)?;
+    }
+
+    Ok(image)
+}
+
+/// v3 has a single vendor ramdisk; v4 describes several in a table.
+fn vendor_ramdisks(data: &[u8], image: &BootImage) -> Result<Vec<VendorRamdisk>, String> {
+    let section = image.section("vendor_ramdisk").ok_or("Missing vendor ramdisk")?;
+
+    let Some(table) = image.section_data(data, "vendor_ramdisk_table") else {
+        return Ok(vec![VendorRamdisk {
+            name: String::new(),
+            ramdisk_type: 1,
+            board_id: Vec::new(),
+            offset: 0,
+            size: section.size,
+            file: "vendor_ramdisk".into(),
+        }]);
+    };
+
+    let count = le32(data, 2116) as usize;
+    let entry_size = le32(data, 2120) as usize;
+
+    if entry_size < RAMDISK_TABLE_ENTRY_SIZE || count * entry_size > table.len() {
+        return Err("Invalid vendor ramdisk table".into());
+    }
+
+    (0..count)
+        .map(|i| {
+            let entry = &table[i * entry_size..];
+            let (size, offset) = (u64::from(le32(entry, 0)), u64::from(le32(entry, 4)));
+
+            if offset + size > section.size {
+                return Err(format!("Vendor ramdisk {} exceeds the ramdisk section", i));
+            }
+
+            let name = c_string(&entry[12..44]);
+            let file = if name.is_empty() {
+                format!("vendor_ramdisk_{:02}", i)
+            } else {
+                format!("vendor_ramdisk_{:02}_{}", i, sanitize(&name))
+            };
+
+            Ok(VendorRamdisk {
+                name,
+                ramdisk_type: le32(entry, 8),
+                board_id: (0..16).map(|j| le32(entry, 44 + j * 4)).collect(),
+                offset,
+                size,
+                file,
+            })
+        })
+        .collect()
+}
+
+/* ================= UNPACK / REPACK ================= */
+
+/// Extract every section into `out_dir`, with `bootimg.json` describing
+/// the image. Edit the section files or the manifest's cmdline / name /
+/// os_version, then `repack_boot_image`.
+pub fn unpack_boot_image(image_path: &Path, out_dir: &Path) -> Result<BootImage, String> {
+    let data = fs::read(image_path).map_err(|e| format!("{}: {}", image_path.display(), e))?;
+    let image = parse_boot_image(&data)?;
+
+    fs::create_dir_all(out_dir).map_err(|e| e.to_string())?;
+    let write = |name: &str, bytes: &[u8]| {
+        fs::write(out_dir.join(name), bytes).map_err(|e| format!("{}: {}", name, e))
+    };
+
+    write(HEADER_FILE, &data[..image.header_len as usize])?;
+
+    for section in &image.sections {
+        // Rebuilt from the manifest on repack
+        if section.name == "vendor_ramdisk_table" || section.size == 0 {
+            continue;
+        }
+
+        let bytes = image.section_data(&data, &section.name).unwrap_or_default();
+
+        if section.name == "vendor_ramdisk" {
+            for ramdisk in &image.vendor_ramdisks {
+                let start = ramdisk.offset as usize;
+                write(&ramdisk.file, &bytes[start..start + ramdisk.size as usize])?;
+            }
+        } else {
+            write(&section.name, bytes)?;
+        }
+    }
+
+    if let Some(tail) = image.tail_offset {
+        write(TAIL_FILE, &data[tail as usize..])?;
+    }
+
+    let json = serde_json::to_string_pretty(&image).map_err(|e| e.to_string())?;
+    write(MANIFEST, json.as_bytes())?;
+
+    Ok(image)
+}
+
+/// Rebuild a boot image from a directory written by `unpack_boot_image`.
+/// Returns warnings (e.g. a stale AVB footer after edits).
+pub fn repack_boot_image(dir: &Path, out: &Path) -> Result<Vec<String>, String> {
+    let read = |name: &str| fs::read(dir.join(name)).map_err(|e| format!("{}: {}", name, e));
+
+    let manifest: BootImage = serde_json::from_slice(&read(MANIFEST)?)
+        .map_err(|e| format!("{}: {}", MANIFEST, e))?;
+    let mut header = read(HEADER_FILE)?;
+
+    if header.len() as u64 != manifest.header_len || detect_kind(&header) != Some(manifest.kind) {
+        return Err("header.bin does not match bootimg.json".into());
+    }
+
+    let layout = layout(manifest.kind, manifest.header_version)?;
+    let page = u64::from(manifest.page_size);
+    let mut warnings = Vec::new();
+
+    patch_header_fields(&mut header, &layout, &manifest);
+    let mut changed = header != read(HEADER_FILE)?;
+
+    let mut body = Vec::new();
+
+    for &(name, field) in layout.sections {
+        let bytes = match name {
+            "vendor_ramdisk" => {
+                let mut all = Vec::new();
+                for ramdisk in &manifest.vendor_ramdisks {
+                    all.extend(read(&ramdisk.file)?);
+                }
+                all
+            }
+            "vendor_ramdisk_table" => build_ramdisk_table(dir, &manifest, &mut header)?,
+            _ => match read(name) {
+                Ok(bytes) => bytes,
+                Err(_) if manifest.section(name).is_none_or(|s| s.size == 0) => Vec::new(),
+                Err(e) => return Err(e),
+            },
+        };
+
+        let size = u32::try_from(bytes.len()).map_err(|_| format!("{} too large", name))?;
+        header[field..field + 4].copy_from_slice(&size.to_le_bytes());
+
+        if manifest.section(name).is_none_or(|s| s.sha256 != sha256_hex(&bytes)) {
+            changed = true;
+        }
+
+        // v1/v2 also record where recovery_dtbo starts
+        if name == "recovery_dtbo" && size > 0 {
+            let offset = manifest.header_len + body.len() as u64;
+            header[1636..1644].copy_from_slice(&offset.to_le_bytes());
+        }
+
+        body.extend(&bytes);
+        body.resize(align(body.len() as u64, page) as usize, 0);
+    }
+
+    let mut image = header;
+    image.extend(body);
+
+    if let Some(tail_offset) = manifest.tail_offset {
+        let tail = read(TAIL_FILE)?;
+
+        if image.len() as u64 > tail_offset {
+            return Err("Repacked image overlaps the preserved footer; it no longer fits".into());
+        }
+
+        image.resize(tail_offset as usize, 0);
+        image.extend(tail);
+
+        if changed {
+            warnings.push(
+                "Contents changed: the AVB footer is stale and must be re-signed".into(),
+            );
+        }
+    } else if (image.len() as u64) < manifest.total_size && !changed {
+        // Unmodified images padded to partition size stay that size
+        image.resize(manifest.total_size as usize, 0);
+    }
+
+    fs::write(out, &image).map_err(|e| format!("{}: {}", out.display(), e))?;
+    Ok(warnings)
+}
+
+/// Write back editable fields that differ from what header.bin holds.
+fn patch_header_fields(header: &mut [u8], layout: &Layout, manifest: &BootImage) {
+    let mut patch_str = |field: Option<(usize, usize)>, value: &str| {
+        let Some((offset, len)) = field else {
+            return;
+        };
+
+        if c_string(&header[offset..offset + len]) != value {
+            let slot = &mut header[offset..offset + len];
+            slot.fill(0);
+            let n = value.len().min(len - 1);
+            slot[..n].copy_from_slice(&value.as_bytes()[..n]);
+        }
+    };
+
+    patch_str(layout.name, &manifest.name);
+    patch_str(Some(layout.cmdline), &manifest.cmdline);
+    patch_str(layout.extra_cmdline, &manifest.extra_cmdline);
+
+    if let Some(offset) = layout.os_version {
+        header[offset..offset + 4].copy_from_slice(&manifest.os_version.to_le_bytes());
+    }
+}
+
+/// Rebuild the v4 ramdisk table from the manifest and actual file sizes.
+fn build_ramdisk_table(
+    dir: &Path,
+    manifest: &BootImage,
+    header: &mut [u8],
+) -> Result<Vec<u8>, String> {
+    let mut table = Vec::new();
+    let mut offset = 0u32;
+
+    for ramdisk in &manifest.vendor_ramdisks {
+        let size = fs::metadata(dir.join(&ramdisk.file))
+            .map_err(|e| format!("{}: {}", ramdisk.file, e))?
+            .len() as u32;
+
+        let mut entry = [0u8; RAMDISK_TABLE_ENTRY_SIZE];
+        entry[0..4].copy_from_slice(&size.to_le_bytes());
+        entry[4..8].copy_from_slice(&offset.to_le_bytes());
+        entry[8..12].copy_from_slice(&ramdisk.ramdisk_type.to_le_bytes());
+
+        let name = ramdisk.name.as_bytes();
+        let n = name.len().min(31);
+        entry[12..12 + n].copy_from_slice(&name[..n]);
+
+        for (j, id) in ramdisk.board_id.iter().take(16).enumerate() {
+            entry[44 + j * 4..48 + j * 4].copy_from_slice(&id.to_le_bytes());
+        }
+
+        table.extend(entry);
+        offset += size;
+    }
+
+    header[2116..2120].copy_from_slice(&(manifest.vendor_ramdisks.len() as u32).to_le_bytes());
+    header[2120..2124].copy_from_slice(&(RAMDISK_TABLE_ENTRY_SIZE as u32).to_le_bytes());
+
+    Ok(table)
+}
+
+/* ================= HELPERS ================= */
+
+/// `os_version` packs A.B.C in the top 21 bits and YYYY-MM below.
+fn decode_os_version(value: u32) -> (Option<String>, Option<String>) {
+    if value == 0 {
+        return (None, None);
+    }
+
+    let version = value >> 11;
+    let level = value & 0x7FF;
+
+    let android = Some(format!("{}.{}.{}", version >> 14, (version >> 7) & 0x7F, version & 0x7F))
+        .filter(|_| version != 0);
+    let patch = Some(format!("{:04}-{:02}", 2000 + (level >> 4), level & 0xF))
+        .filter(|_| level != 0);
+
+    (android, patch)
+}
+
+fn align(value: u64, page: u64) -> u64 {
+    value.next_multiple_of(page)
+}
+
+fn c_string(raw: &[u8]) -> String {
+    let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..end]).into_owned()
+}
+
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::path::PathBuf;
+
+    /// Header page with `fields` set, then each (size field, data) section
+    /// on its own page boundary, in layout order.
+    fn build(page: usize, fields: &[(usize, u32)], sections: &[(usize, Vec<u8>)]) -> Vec<u8> {
+        let mut image = vec![0u8; page];
+        for &(offset, value) in fields {
+            image[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        for (field, data) in sections {
+            image[*field..*field + 4].copy_from_slice(&(data.len() as u32).to_le_bytes());
+
+            if *field == 1632 && !data.is_empty() {
+                let offset = image.len() as u64;
+                image[1636..1644].copy_from_slice(&offset.to_le_bytes());
+            }
+
+            let start = image.len();
+            image.extend(data);
+            image.resize(align((image.len() - start) as u64, page as u64) as usize + start, 0);
+        }
+
+        image
+    }
+
+    fn boot_v0_v2(version: u32) -> Vec<u8> {
+        let mut sections = vec![(8, vec![1u8; 3000]), (16, vec![2u8; 100]), (24, vec![3u8; 10])];
+        if version >= 1 {
+            sections.push((1632, vec![4u8; 20]));
+        }
+        if version >= 2 {
+            sections.push((1648, vec![5u8; 50]));
+        }
+
+        let os_version = (13 << 25) | (23 << 4) | 5;
+        let fields = [(36, 2048), (40, version), (44, os_version), (1644, 1660)];
+        let mut image = build(2048, &fields, &sections);
+        image[..8].copy_from_slice(BOOT_MAGIC);
+        image[48..52].copy_from_slice(b"test");
+        image[64..80].copy_from_slice(b"console=ttyS0 x ");
+        image
+    }
+
+    fn boot_v3_v4(version: u32) -> Vec<u8> {
+        let mut sections = vec![(8, vec![1u8; 5000]), (12, vec![2u8; 300])];
+        if version == 4 {
+            sections.push((1580, vec![6u8; 64]));
+        }
+
+        let mut image = build(4096, &[(20, 1580), (40, version)], &sections);
+        image[..8].copy_from_slice(BOOT_MAGIC);
+        image[44..57].copy_from_slice(b"androidboot.x");
+        image
+    }
+
+    fn vendor_boot(version: u32) -> Vec<u8> {
+        let fields = [(8, version), (12, 4096), (2096, if version == 4 { 2128 } else { 2112 })];
+
+        let ramdisks = [vec![4u8; 200], vec![5u8; 100]].concat();
+        let mut sections = vec![(24, ramdisks), (2100, vec![7u8; 9])];
+
+        if version == 4 {
+            let mut table = vec![0u8; 2 * RAMDISK_TABLE_ENTRY_SIZE];
+            let entries = [(200u32, 0u32, 1u32, "plat"), (100, 200, 3, "dlkm")];
+            for (i, (size, offset, kind, name)) in entries.into_iter().enumerate() {
+                let entry = &mut table[i * RAMDISK_TABLE_ENTRY_SIZE..];
+                entry[0..4].copy_from_slice(&size.to_le_bytes());
+                entry[4..8].copy_from_slice(&offset.to_le_bytes());
+                entry[8..12].copy_from_slice(&kind.to_le_bytes());
+                entry[12..12 + name.len()].copy_from_slice(name.as_bytes());
+            }
+            sections.push((2112, table));
+            sections.push((2124, b"androidboot.x=1\n".to_vec()));
+        }
+
+        let mut image = build(4096, &fields, &sections);
+        image[..8].copy_from_slice(VENDOR_BOOT_MAGIC);
+        if version == 4 {
+            image[2116..2120].copy_from_slice(&2u32.to_le_bytes());
+            image[2120..2124].copy_from_slice(&(RAMDISK_TABLE_ENTRY_SIZE as u32).to_le_bytes());
+        }
+        image
+    }
+
+    /// Unpack and repack `image` unchanged; returns the repacked bytes.
+    fn round_trip(name: &str, image: &[u8]) -> (BootImage, Vec<u8>, Vec<String>) {
+        let dir = temp_dir("bootimg", name);
+        fs::write(dir.join("in.img"), image).unwrap();
+
+        let parsed = unpack_boot_image(&dir.join("in.img"), &dir.join("unpacked"));
+        let warnings = repack_boot_image(&dir.join("unpacked"), &dir.join("out.img"));
+        let out = fs::read(dir.join("out.img"));
+        fs::remove_dir_all(&dir).ok();
+
+        (parsed.unwrap(), out.unwrap(), warnings.unwrap())
+    }
+
+    #[test]
+    fn boot_v0_to_v2_repack_identically() {
+        for version in 0..=2 {
+            let image = boot_v0_v2(version);
+            let (parsed, out, warnings) = round_trip(&format!("boot-v{}", version), &image);
+
+            assert_eq!(parsed.header_version, version);
+            assert_eq!(parsed.sections.len(), 3 + version as usize);
+            assert_eq!(parsed.name, "test");
+            assert_eq!(parsed.android_version.as_deref(), Some("13.0.0"));
+            assert_eq!(parsed.patch_level.as_deref(), Some("2023-05"));
+            assert!(warnings.is_empty());
+            assert!(out == image, "boot v{} repack differs", version);
+        }
+    }
+
+    #[test]
+    fn boot_v3_v4_repack_identically() {
+        for version in 3..=4 {
+            let image = boot_v3_v4(version);
+            let (parsed, out, _) = round_trip(&format!("boot-v{}", version), &image);
+
+            assert_eq!(parsed.page_size, 4096);
+            assert_eq!(parsed.cmdline, "androidboot.x");
+            assert_eq!(parsed.section("kernel").unwrap().offset, 4096);
+            assert!(out == image, "boot v{} repack differs", version);
+        }
+    }
+
+    #[test]
+    fn vendor_boot_v3_v4_repack_identically() {
+        let (parsed, out, _) = round_trip("vendor-v3", &vendor_boot(3));
+        assert_eq!(parsed.vendor_ramdisks.len(), 1);
+        assert_eq!(parsed.vendor_ramdisks[0].size, 300);
+        assert!(out == vendor_boot(3), "vendor_boot v3 repack differs");
+
+        let (parsed, out, _) = round_trip("vendor-v4", &vendor_boot(4));
+        assert_eq!(parsed.vendor_ramdisks.len(), 2);
+        assert_eq!(parsed.vendor_ramdisks[1].file, "vendor_ramdisk_01_dlkm");
+        assert_eq!(parsed.vendor_ramdisks[1].ramdisk_type, 3);
+        assert!(out == vendor_boot(4), "vendor_boot v4 repack differs");
+    }
+
+    #[test]
+    fn footer_is_kept_and_edits_warn() {
+        let mut image = boot_v0_v2(2);
+        let tail = image.len() + 4096;
+        image.resize(tail, 0);
+        image.extend(b"AVBf");
+        image.resize(tail + 64, 0);
+
+        let (parsed, out, warnings) = round_trip("footer", &image);
+        assert_eq!(parsed.tail_offset, Some(tail as u64));
+        assert!(warnings.is_empty());
+        assert!(out == image);
+
+        let dir = temp_dir("bootimg", "edit");
+        fs::write(dir.join("in.img"), &image).unwrap();
+        let mut manifest = unpack_boot_image(&dir.join("in.img"), &dir.join("unpacked")).unwrap();
+        manifest.cmdline = "quiet".into();
+        let json = serde_json::to_string(&manifest).unwrap();
+        fs::write(dir.join("unpacked").join(MANIFEST), json).unwrap();
+
+        let warnings = repack_boot_image(&dir.join("unpacked"), &dir.join("out.img")).unwrap();
+        let edited = parse_boot_image(&fs::read(dir.join("out.img")).unwrap()).unwrap();
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(edited.cmdline, "quiet");
+        assert_eq!(edited.tail_offset, Some(tail as u64));
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        assert!(parse_boot_image(&[0u8; 4096]).is_err());
+        assert!(parse_boot_image(BOOT_MAGIC).is_err());
+
+        let mut image = boot_v0_v2(0);
+        image[40..44].copy_from_slice(&9u32.to_le_bytes());
+        assert!(parse_boot_image(&image).is_err());
+
+        let mut image = boot_v0_v2(0);
+        image[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse_boot_image(&image).is_err());
+    }
+}
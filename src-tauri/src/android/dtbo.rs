@@ -0,0 +1,547 @@
+use flate2::read::{GzDecoder, ZlibDecoder};
+use serde::Serialize;
+use std::{fs, io::Read, path::Path};
+
+// DTBO partition image (big-endian): a table header, `dt_entry_count`
+// entries of (size, offset, id, rev, custom[4]) and the overlay blobs
+// they point at. Version 1 repurposes custom[0] as flags, whose low
+// nibble is the blob compression.
+//
+// Each blob is a flattened device tree: header, memory reservations,
+// a token stream for the node structure and a string table holding
+// property names.
+
+const DTBO_MAGIC: u32 = 0xD7B7_AB1E;
+const DTBO_HEADER_SIZE: usize = 32;
+const DTBO_ENTRY_SIZE: usize = 32;
+
+const FDT_MAGIC: u32 = 0xD00D_FEED;
+const FDT_HEADER_SIZE: usize = 40;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DtboTable {
+    pub version: u32,
+    pub total_size: u32,
+    pub page_size: u32,
+    pub entries: Vec<DtboEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DtboEntry {
+    pub index: usize,
+    pub size: u32,
+    pub offset: u32,
+    pub id: u32,
+    pub rev: u32,
+    pub custom: [u32; 4],
+    /// "none", "zlib" or "gzip" (always "none" before version 1)
+    pub compression: &'static str,
+    /// None if the blob could not be decoded; see `error`
+    pub fdt: Option<FdtSummary>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FdtSummary {
+    pub version: u32,
+    pub size: u32,
+    pub model: Option<String>,
+    /// Root node compatible list
+    pub compatible: Vec<String>,
+    pub nodes: Vec<FdtNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FdtNode {
+    pub path: String,
+    pub compatible: Vec<String>,
+    /// Overlay fragment target: `target-path`, or the `target` phandle
+    pub target: Option<String>,
+}
+
+impl DtboTable {
+    /// Root compatibles of every overlay, deduplicated.
+    pub fn compatibles(&self) -> Vec<&str> {
+        let mut all: Vec<&str> = self
+            .entries
+            .iter()
+            .filter_map(|e| e.fdt.as_ref())
+            .flat_map(|f| f.compatible.iter().map(|c| c.as_str()))
+            .collect();
+
+        all.sort();
+        all.dedup();
+        all
+    }
+
+    /// Whether any overlay names `platform` in its root compatible. None
+    /// when the overlays carry no compatible to compare.
+    pub fn matches_platform(&self, platform: &str) -> Option<bool> {
+        let compatibles = self.compatibles();
+        if compatibles.is_empty() {
+            return None;
+        }
+
+        let platform = platform.to_ascii_lowercase();
+        Some(compatibles.iter().any(|c| c.to_ascii_lowercase().contains(&platform)))
+    }
+}
+
+/* ================= DTBO ================= */
+
+pub fn is_dtbo(data: &[u8]) -> bool {
+    data.len() >= 4 && be32(data, 0) == DTBO_MAGIC
+}
+
+pub fn parse_dtbo(data: &[u8]) -> Result<DtboTable, String> {
+    if data.len() < DTBO_HEADER_SIZE || !is_dtbo(data) {
+        return Err("Not a DTBO image (bad table magic)".into());
+    }
+
+    let total_size = be32(data, 4);
+    let header_size = be32(data, 8) as usize;
+    let entry_size = be32(data, 12) as usize;
+    let count = be32(data, 16) as usize;
+    let entries_offset = be32(data, 20) as usize;
+    let page_size = be32(data, 24);
+    let version = be32(data, 28);
+
+    if header_size < DTBO_HEADER_SIZE || entry_size < DTBO_ENTRY_SIZE {
+        return Err(format!("Unexpected DTBO header/entry size {}/{}", header_size, entry_size));
+    }
+
+    let table_end = count
+        .checked_mul(entry_size)
+        .and_then(|len| len.checked_add(entries_offset))
+        .filter(|&end| end <= data.len())
+        .ok_or("DTBO entry table runs past end of image")?;
+
+    let mut entries = Vec::new();
+
+    for (index, raw) in data[entries_offset..table_end].chunks_exact(entry_size).enumerate() {
+        let size = be32(raw, 0);
+        let offset = be32(raw, 4);
+        let custom = [be32(raw, 16), be32(raw, 20), be32(raw, 24), be32(raw, 28)];
+
+        let compression = match (version, custom[0] & 0xF) {
+            (0, _) | (_, 0) => "none",
+            (_, 1) => "zlib",
+            (_, 2) => "gzip",
+            _ => "unknown",
+        };
+
+        let mut entry = DtboEntry {
+            index,
+            size,
+            offset,
+            id: be32(raw, 8),
+            rev: be32(raw, 12),
+            custom,
+            compression,
+            fdt: None,
+            error: None,
+        };
+
+        match entry_blob(data, &entry).and_then(|blob| parse_fdt(&blob)) {
+            Ok(fdt) => entry.fdt = Some(fdt),
+            Err(e) => entry.error = Some(e),
+        }
+
+        entries.push(entry);
+    }
+
+    Ok(DtboTable { version, total_size, page_size, entries })
+}
+
+pub fn read_dtbo(path: &Path) -> Result<DtboTable, String> {
+    let data = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_dtbo(&data)
+}
+
+/// Write entry `index` as a standalone, decompressed `.dtb`.
+pub fn extract_entry(path: &Path, index: usize, out: &Path) -> Result<u64, String> {
+    let data = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let table = parse_dtbo(&data)?;
+
+    let entry = table
+        .entries
+        .get(index)
+        .ok_or_else(|| format!("No entry {} ({} entries)", index, table.entries.len()))?;
+
+    let blob = entry_blob(&data, entry)?;
+    fs::write(out, &blob).map_err(|e| format!("{}: {}", out.display(), e))?;
+
+    Ok(blob.len() as u64)
+}
+
+/// The entry's FDT, decompressed.
+fn entry_blob(data: &[u8], entry: &DtboEntry) -> Result<Vec<u8>, String> {
+    let start = entry.offset as usize;
+    let raw = start
+        .checked_add(entry.size as usize)
+        .and_then(|end| data.get(start..end))
+        .ok_or_else(|| format!("Entry {} runs past end of image", entry.index))?;
+
+    let mut blob = Vec::new();
+
+    match entry.compression {
+        "none" => blob.extend_from_slice(raw),
+        "zlib" => {
+            ZlibDecoder::new(raw).read_to_end(&mut blob).map_err(|e| e.to_string())?;
+        }
+        "gzip" => {
+            GzDecoder::new(raw).read_to_end(&mut blob).map_err(|e| e.to_string())?;
+        }
+        other => return Err(format!("Entry {}: unsupported compression {}", entry.index, other)),
+    }
+
+    Ok(blob)
+}
+
+/* ================= FDT ================= */
+
+pub fn parse_fdt(data: &[u8]) -> Result<FdtSummary, String> {
+    if data.len() < FDT_HEADER_SIZE || be32(data, 0) != FDT_MAGIC {
+        return Err("Not a flattened device tree (bad magic)".into());
+    }
+
+    let size = be32(data, 4);
+    let struct_offset = be32(data, 8) as usize;
+    let strings_offset = be32(data, 12) as usize;
+    let version = be32(data, 20);
+    let strings_size = be32(data, 32) as usize;
+    let struct_size = be32(data, 36) as usize;
+
+    let structure = struct_offset
+        .checked_add(struct_size)
+        .and_then(|end| data.get(struct_offset..end))
+        .ok_or("FDT structure block out of range")?;
+    let strings = strings_offset
+        .checked_add(strings_size)
+        .and_then(|end| data.get(strings_offset..end))
+        .ok_or("FDT strings block out of range")?;
+
+    let mut summary = FdtSummary {
+        version,
+        size,
+        model: None,
+        compatible: Vec::new(),
+        nodes: Vec::new(),
+    };
+
+    // Indexes into `summary.nodes` of the currently open nodes
+    let mut open: Vec<usize> = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        let token = structure
+            .get(pos..pos + 4)
+            .map(|t| be32(t, 0))
+            .ok_or("FDT structure ends without FDT_END")?;
+        pos += 4;
+
+        match token {
+            FDT_BEGIN_NODE => {
+                let name = c_string_at(structure, pos)?;
+                pos = align4(pos + name.len() + 1);
+
+                let path = match open.last() {
+                    None => "/".to_string(),
+                    Some(&parent) if summary.nodes[parent].path == "/" => format!("/{}", name),
+                    Some(&parent) => format!("{}/{}", summary.nodes[parent].path, name),
+                };
+
+                summary.nodes.push(FdtNode { path, compatible: Vec::new(), target: None });
+                open.push(summary.nodes.len() - 1);
+            }
+            FDT_END_NODE => {
+                open.pop().ok_or("Unbalanced FDT_END_NODE")?;
+            }
+            FDT_PROP => {
+                let header = structure.get(pos..pos + 8).ok_or("Truncated FDT property")?;
+                let len = be32(header, 0) as usize;
+                let name = c_string_at(strings, be32(header, 4) as usize)?;
+                let value = structure.get(pos + 8..pos + 8 + len).ok_or("Truncated FDT property")?;
+                pos = align4(pos + 8 + len);
+
+                let &node = open.last().ok_or("FDT property outside any node")?;
+                let is_root = open.len() == 1;
+
+                match name.as_str() {
+                    "compatible" => {
+                        let list = string_list(value);
+                        if is_root {
+                            summary.compatible = list.clone();
+                        }
+                        summary.nodes[node].compatible = list;
+                    }
+                    "model" if is_root => summary.model = string_list(value).into_iter().next(),
+                    "target-path" => {
+                        summary.nodes[node].target = string_list(value).into_iter().next()
+                    }
+                    "target" if len == 4 => {
+                        summary.nodes[node].target = Some(format!("phandle {:#x}", be32(value, 0)))
+                    }
+                    _ => {}
+                }
+            }
+            FDT_NOP => {}
+            FDT_END => break,
+            other => return Err(format!("Unknown FDT token {:#x} at {}", other, pos - 4)),
+        }
+    }
+
+    Ok(summary)
+}
+
+/* ================= HELPERS ================= */
+
+fn string_list(value: &[u8]) -> Vec<String> {
+    value
+        .split(|&b| b == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
+
+fn c_string_at(data: &[u8], offset: usize) -> Result<String, String> {
+    let rest = data.get(offset..).ok_or("FDT string offset out of range")?;
+    let end = rest.iter().position(|&b| b == 0).ok_or("Unterminated FDT string")?;
+    Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+fn be32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{
+        write::{GzEncoder, ZlibEncoder},
+        Compression,
+    };
+    use std::io::Write;
+
+    enum Token<'a> {
+        Begin(&'a str),
+        End,
+        Prop(&'a str, &'a [u8]),
+        Nop,
+    }
+
+    fn pad4(out: &mut Vec<u8>) {
+        out.resize(align4(out.len()), 0);
+    }
+
+    fn fdt(tokens: &[Token]) -> Vec<u8> {
+        let mut structure = Vec::new();
+        let mut strings: Vec<u8> = Vec::new();
+
+        for token in tokens {
+            match token {
+                Token::Begin(name) => {
+                    structure.extend(FDT_BEGIN_NODE.to_be_bytes());
+                    structure.extend(name.as_bytes());
+                    structure.push(0);
+                    pad4(&mut structure);
+                }
+                Token::End => structure.extend(FDT_END_NODE.to_be_bytes()),
+                Token::Prop(name, value) => {
+                    let name_offset = strings.len() as u32;
+                    strings.extend(name.as_bytes());
+                    strings.push(0);
+
+                    structure.extend(FDT_PROP.to_be_bytes());
+                    structure.extend((value.len() as u32).to_be_bytes());
+                    structure.extend(name_offset.to_be_bytes());
+                    structure.extend(*value);
+                    pad4(&mut structure);
+                }
+                Token::Nop => structure.extend(FDT_NOP.to_be_bytes()),
+            }
+        }
+        structure.extend(FDT_END.to_be_bytes());
+
+        // Empty memory reservation map between the header and the structure
+        let struct_offset = FDT_HEADER_SIZE + 16;
+        let strings_offset = struct_offset + structure.len();
+        let total = strings_offset + strings.len();
+
+        let mut blob = Vec::new();
+        for value in [
+            FDT_MAGIC,
+            total as u32,
+            struct_offset as u32,
+            strings_offset as u32,
+            FDT_HEADER_SIZE as u32,
+            17,
+            16,
+            0,
+            strings.len() as u32,
+            structure.len() as u32,
+        ] {
+            blob.extend(value.to_be_bytes());
+        }
+        blob.extend([0u8; 16]);
+        blob.extend(structure);
+        blob.extend(strings);
+        blob
+    }
+
+    fn overlay(compatible: &[u8], target: &[u8]) -> Vec<u8> {
+        fdt(&[
+            Token::Begin(""),
+            Token::Prop("compatible", compatible),
+            Token::Prop("model", b"Kansas\0"),
+            Token::Begin("fragment@0"),
+            Token::Prop("target-path", target),
+            Token::Begin("__overlay__"),
+            Token::Nop,
+            Token::Prop("compatible", b"goodix,gt9896\0"),
+            Token::End,
+            Token::End,
+            Token::Begin("fragment@1"),
+            Token::Prop("target", &0x42u32.to_be_bytes()),
+            Token::End,
+            Token::End,
+        ])
+    }
+
+    /// Version 1 table; each blob is stored with the given custom[0] flags
+    fn dtbo(blobs: &[(Vec<u8>, u32)]) -> Vec<u8> {
+        let entries_offset = DTBO_HEADER_SIZE;
+        let mut offset = entries_offset + blobs.len() * DTBO_ENTRY_SIZE;
+        let mut table = Vec::new();
+        let mut data: Vec<u8> = Vec::new();
+
+        for (index, (blob, flags)) in blobs.iter().enumerate() {
+            for value in [blob.len() as u32, offset as u32, index as u32, 0, *flags, 0, 0, 0] {
+                table.extend(value.to_be_bytes());
+            }
+            offset += blob.len();
+            data.extend(blob);
+        }
+
+        let mut image = Vec::new();
+        for value in [
+            DTBO_MAGIC,
+            offset as u32,
+            DTBO_HEADER_SIZE as u32,
+            DTBO_ENTRY_SIZE as u32,
+            blobs.len() as u32,
+            entries_offset as u32,
+            2048,
+            1,
+        ] {
+            image.extend(value.to_be_bytes());
+        }
+        image.extend(table);
+        image.extend(data);
+        image
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn parses_overlay_tree() {
+        let summary = parse_fdt(&overlay(b"moto,kansas\0mediatek,mt6835\0", b"/soc/i2c@0\0"))
+            .unwrap();
+
+        assert_eq!(summary.version, 17);
+        assert_eq!(summary.model.as_deref(), Some("Kansas"));
+        assert_eq!(summary.compatible, ["moto,kansas", "mediatek,mt6835"]);
+
+        let paths: Vec<_> = summary.nodes.iter().map(|n| n.path.as_str()).collect();
+        assert_eq!(paths, ["/", "/fragment@0", "/fragment@0/__overlay__", "/fragment@1"]);
+        assert_eq!(summary.nodes[1].target.as_deref(), Some("/soc/i2c@0"));
+        assert_eq!(summary.nodes[2].compatible, ["goodix,gt9896"]);
+        assert_eq!(summary.nodes[3].target.as_deref(), Some("phandle 0x42"));
+    }
+
+    #[test]
+    fn parses_table_with_compressed_entries() {
+        let plain = overlay(b"mediatek,mt6835\0", b"/a\0");
+        let other = overlay(b"mediatek,mt6789\0", b"/b\0");
+        let image = dtbo(&[
+            (plain.clone(), 0),
+            (zlib(&other), 1),
+            (gzip(&plain), 2),
+            (b"garbage".to_vec(), 0),
+        ]);
+
+        assert!(is_dtbo(&image));
+        let table = parse_dtbo(&image).unwrap();
+
+        assert_eq!((table.version, table.page_size), (1, 2048));
+        assert_eq!(table.total_size as usize, image.len());
+
+        let compression: Vec<_> = table.entries.iter().map(|e| e.compression).collect();
+        assert_eq!(compression, ["none", "zlib", "gzip", "none"]);
+        assert!(table.entries[..3].iter().all(|e| e.fdt.is_some()));
+
+        // A bad blob is reported on its entry, not fatal for the table
+        assert!(table.entries[3].fdt.is_none());
+        assert!(table.entries[3].error.as_ref().unwrap().contains("bad magic"));
+
+        assert_eq!(table.compatibles(), ["mediatek,mt6789", "mediatek,mt6835"]);
+        assert_eq!(table.matches_platform("MT6835"), Some(true));
+        assert_eq!(table.matches_platform("mt6765"), Some(false));
+    }
+
+    #[test]
+    fn extracts_decompressed_entry() {
+        let blob = overlay(b"mediatek,mt6835\0", b"/a\0");
+        let image = dtbo(&[(blob.clone(), 0), (zlib(&blob), 1)]);
+
+        let dir = std::env::temp_dir().join(format!("dtbo-{}-extract", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("dtbo.img"), &image).unwrap();
+
+        let size = extract_entry(&dir.join("dtbo.img"), 1, &dir.join("1.dtb"));
+        let written = fs::read(dir.join("1.dtb"));
+        let missing = extract_entry(&dir.join("dtbo.img"), 2, &dir.join("2.dtb"));
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(size.unwrap(), blob.len() as u64);
+        assert_eq!(written.unwrap(), blob);
+        assert!(missing.unwrap_err().contains("No entry 2"));
+    }
+
+    #[test]
+    fn rejects_malformed_images() {
+        assert!(parse_dtbo(&[0u8; 64]).unwrap_err().contains("bad table magic"));
+
+        let mut image = dtbo(&[(overlay(b"x\0", b"/a\0"), 0)]);
+        image[16..20].copy_from_slice(&1000u32.to_be_bytes());
+        assert!(parse_dtbo(&image).unwrap_err().contains("runs past end"));
+
+        let unbalanced = fdt(&[Token::Begin(""), Token::End, Token::End]);
+        assert!(parse_fdt(&unbalanced).unwrap_err().contains("Unbalanced"));
+
+        let mut unterminated = fdt(&[Token::Begin(""), Token::End]);
+        let struct_size = be32(&unterminated, 36) as usize;
+        let end = FDT_HEADER_SIZE + 16 + struct_size - 4;
+        unterminated[end..end + 4].copy_from_slice(&FDT_NOP.to_be_bytes());
+        assert!(parse_fdt(&unterminated).unwrap_err().contains("without FDT_END"));
+    }
+}
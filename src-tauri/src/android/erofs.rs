@@ -0,0 +1,954 @@
+use std::io::Write;
+
+use crate::android::fsimage::{self, Filesystem, NodeInfo};
+use crate::android::sparse::ReadSeek;
+
+// EROFS, little-endian. The superblock sits at byte 1024. Inodes are
+// addressed by nid: byte `meta_blkaddr * block_size + nid * 32`. An
+// inode is 32 (compact) or 64 (extended) bytes, followed by its inline
+// xattrs and, depending on the data layout, by:
+//
+//   FLAT_PLAIN     nothing; data is contiguous from `raw_blkaddr`
+//   FLAT_INLINE    the tail of the data (whole blocks from `raw_blkaddr`)
+//   CHUNK_BASED    a table of chunk block addresses
+//   COMPRESSED_*   a map header and logical cluster indexes, full (8
+//                  bytes each) or compacted (packs of 2 or 16 indexes
+//                  sharing one block address)
+//
+// Compressed files are split into logical clusters (lclusters). A HEAD
+// lcluster starts an extent at `clusterofs` within it, backed by a
+// physical cluster (pcluster) of one or more blocks; NONHEAD lclusters
+// continue the previous extent. Directory data is blocks of 12-byte
+// dirents followed by the names they point at.
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const EROFS_MAGIC: u32 = 0xE0F5_E1E2;
+const INODE_SLOT: u64 = 32;
+const DIRENT_SIZE: usize = 12;
+
+const INCOMPAT_ZERO_PADDING: u32 = 0x1;
+
+const LAYOUT_FLAT_PLAIN: u16 = 0;
+const LAYOUT_COMPRESSED_FULL: u16 = 1;
+const LAYOUT_FLAT_INLINE: u16 = 2;
+const LAYOUT_COMPRESSED_COMPACT: u16 = 3;
+const LAYOUT_CHUNK_BASED: u16 = 4;
+
+const CHUNK_FORMAT_BLKBITS_MASK: u16 = 0x1F;
+const CHUNK_FORMAT_INDEXES: u16 = 0x20;
+const NULL_ADDR: u32 = 0xFFFF_FFFF;
+
+const ADVISE_COMPACTED_2B: u16 = 0x0001;
+const ADVISE_BIG_PCLUSTER_1: u16 = 0x0002;
+const ADVISE_BIG_PCLUSTER_2: u16 = 0x0004;
+const ADVISE_INLINE_PCLUSTER: u16 = 0x0008;
+const ADVISE_INTERLACED_PCLUSTER: u16 = 0x0010;
+const ADVISE_FRAGMENT_PCLUSTER: u16 = 0x0020;
+const CLUSTERBITS_FRAGMENT_INODE: u8 = 0x80;
+
+const LCLUSTER_PLAIN: u8 = 0;
+const LCLUSTER_HEAD1: u8 = 1;
+const LCLUSTER_NONHEAD: u8 = 2;
+const LCLUSTER_HEAD2: u8 = 3;
+/// NONHEAD delta[0] flag: the low bits are the pcluster block count
+const D0_CBLKCNT: u32 = 0x800;
+
+const ALG_LZ4: u8 = 0;
+
+pub struct Erofs {
+    reader: Box<dyn ReadSeek>,
+    blkszbits: u32,
+    root_nid: u64,
+    meta_offset: u64,
+    dir_block_size: usize,
+    zero_padding: bool,
+    pub volume_name: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct ErofsInode {
+    nid: u64,
+    mode: u16,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    layout: u16,
+    /// `raw_blkaddr` or the chunk format, depending on the layout
+    i_u: u32,
+    /// Where the xattrs end and layout-specific data begins
+    tail_offset: u64,
+}
+
+/// One decoded logical cluster index.
+struct Lcluster {
+    kind: u8,
+    clusterofs: u64,
+    pblk: u64,
+    compressed_blocks: Option<u64>,
+}
+
+struct CompressedMap {
+    advise: u16,
+    algorithms: u8,
+    lclusterbits: u32,
+    lcluster_blocks: u64,
+    idata_size: u64,
+    compact: bool,
+    index_base: u64,
+    index: Vec<u8>,
+    count: u64,
+}
+
+impl Erofs {
+    pub fn new(mut reader: Box<dyn ReadSeek>) -> Result<Self, String> {
+        let sb = fsimage::read_at(&mut reader, SUPERBLOCK_OFFSET, 128)?;
+
+        if le32(&sb, 0) != EROFS_MAGIC {
+            return Err("Not an EROFS filesystem (bad superblock magic)".into());
+        }
+
+        let blkszbits = u32::from(sb[12]);
+        if !(9..=16).contains(&blkszbits) {
+            return Err(format!("Unsupported EROFS block size 2^{}", blkszbits));
+        }
+
+        let volume_name = c_string(&sb[64..80]);
+
+        Ok(Erofs {
+            reader,
+            blkszbits,
+            root_nid: u64::from(le16(&sb, 14)),
+            meta_offset: u64::from(le32(&sb, 40)) << blkszbits,
+            dir_block_size: 1 << (blkszbits + u32::from(sb[90])),
+            zero_padding: le32(&sb, 80) & INCOMPAT_ZERO_PADDING != 0,
+            volume_name: (!volume_name.is_empty()).then_some(volume_name),
+        })
+    }
+
+    fn block_size(&self) -> u64 {
+        1 << self.blkszbits
+    }
+
+    fn inode(&mut self, nid: u64) -> Result<ErofsInode, String> {
+        let offset = self.meta_offset + nid * INODE_SLOT;
+        let raw = fsimage::read_at(&mut self.reader, offset, INODE_SLOT as usize)?;
+
+        let format = le16(&raw, 0);
+        let extended = format & 1 != 0;
+        let xattr_count = u64::from(le16(&raw, 2));
+        let xattr_size = if xattr_count == 0 { 0 } else { 12 + (xattr_count - 1) * 4 };
+
+        let (inode_size, size, uid, gid) = if extended {
+            let raw = fsimage::read_at(&mut self.reader, offset, 64)?;
+            (64, le64(&raw, 8), le32(&raw, 24), le32(&raw, 28))
+        } else {
+            (32, u64::from(le32(&raw, 8)), u32::from(le16(&raw, 24)), u32::from(le16(&raw, 26)))
+        };
+
+        Ok(ErofsInode {
+            nid,
+            mode: le16(&raw, 4),
+            uid,
+            gid,
+            size,
+            layout: (format >> 1) & 7,
+            i_u: le32(&raw, 16),
+            tail_offset: offset + inode_size + xattr_size,
+        })
+    }
+
+    /* ================= UNCOMPRESSED ================= */
+
+    fn read_flat(&mut self, inode: &ErofsInode, out: &mut dyn Write) -> Result<(), String> {
+        let start = u64::from(inode.i_u) << self.blkszbits;
+
+        if inode.layout == LAYOUT_FLAT_PLAIN {
+            return fsimage::copy_range(&mut self.reader, start, inode.size, out);
+        }
+
+        // FLAT_INLINE: the last (possibly partial) block follows the inode
+        let blocks = inode.size.div_ceil(self.block_size());
+        let head = blocks.saturating_sub(1) * self.block_size();
+
+        fsimage::copy_range(&mut self.reader, start, head, out)?;
+        fsimage::copy_range(&mut self.reader, inode.tail_offset, inode.size - head, out)
+    }
+
+    fn read_chunked(&mut self, inode: &ErofsInode, out: &mut dyn Write) -> Result<(), String> {
+        let format = inode.i_u as u16;
+        let chunk_size = 1u64 << (self.blkszbits + u32::from(format & CHUNK_FORMAT_BLKBITS_MASK));
+        let unit = if format & CHUNK_FORMAT_INDEXES != 0 { 8 } else { 4 };
+
+        let chunks = inode.size.div_ceil(chunk_size);
+        let table_offset = align(inode.tail_offset, unit);
+        let table = fsimage::read_at(&mut self.reader, table_offset, (chunks * unit) as usize)?;
+
+        for (i, entry) in table.chunks_exact(unit as usize).enumerate() {
+            // Chunk indexes are (advise, device_id, blkaddr); the plain
+            // block map is just the address
+            let blkaddr = if unit == 8 { le32(entry, 4) } else { le32(entry, 0) };
+            let len = chunk_size.min(inode.size - i as u64 * chunk_size);
+
+            if blkaddr == NULL_ADDR {
+                fsimage::write_zeros(out, len)?;
+            } else {
+                let start = u64::from(blkaddr) << self.blkszbits;
+                fsimage::copy_range(&mut self.reader, start, len, out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /* ================= COMPRESSED ================= */
+
+    fn compressed_map(&mut self, inode: &ErofsInode) -> Result<CompressedMap, String> {
+        let header_offset = align(inode.tail_offset, 8);
+        let header = fsimage::read_at(&mut self.reader, header_offset, 8)?;
+
+        let advise = le16(&header, 4);
+        let clusterbits = header[7];
+
+        if clusterbits & CLUSTERBITS_FRAGMENT_INODE != 0 || advise & ADVISE_FRAGMENT_PCLUSTER != 0
+        {
+            return Err(format!("nid {}: EROFS fragments are not supported", inode.nid));
+        }
+        if advise & ADVISE_INTERLACED_PCLUSTER != 0 {
+            return Err(format!("nid {}: interlaced EROFS pclusters are not supported", inode.nid));
+        }
+
+        let lclusterbits = self.blkszbits + u32::from(clusterbits & 7);
+        let count = inode.size.div_ceil(1 << lclusterbits);
+        let compact = inode.layout == LAYOUT_COMPRESSED_COMPACT;
+
+        let (index_base, index_len) = if compact {
+            if lclusterbits > 14 {
+                return Err(format!("nid {}: lcluster size 2^{} too big", inode.nid, lclusterbits));
+            }
+
+            let base = header_offset + 8;
+            let (_, two_byte) = compact_split(base, advise, count);
+            let four_byte = count - two_byte;
+            // The last 4-byte pack is stored whole
+            (base, align(four_byte * 4 + two_byte * 2, 8))
+        } else {
+            (header_offset + 16, count * 8)
+        };
+
+        let index = fsimage::read_at(&mut self.reader, index_base, index_len as usize)?;
+
+        Ok(CompressedMap {
+            advise,
+            algorithms: header[6],
+            lclusterbits,
+            lcluster_blocks: 1 << (clusterbits & 7),
+            idata_size: u64::from(le16(&header, 2)),
+            compact,
+            index_base,
+            index,
+            count,
+        })
+    }
+
+    fn read_compressed(&mut self, inode: &ErofsInode, out: &mut dyn Write) -> Result<(), String> {
+        if inode.size == 0 {
+            return Ok(());
+        }
+
+        let map = self.compressed_map(inode)?;
+        let lcluster_size = 1u64 << map.lclusterbits;
+
+        // Extent heads in file order: (lcn, logical start, index)
+        let mut heads = Vec::new();
+        for lcn in 0..map.count {
+            let lcluster = map.lcluster(lcn)?;
+            if lcluster.kind != LCLUSTER_NONHEAD {
+                heads.push((lcn, lcn * lcluster_size + lcluster.clusterofs, lcluster));
+            }
+        }
+
+        if heads.first().map(|h| h.1) != Some(0) {
+            return Err(format!("nid {}: compressed data does not start at 0", inode.nid));
+        }
+
+        for (i, (lcn, start, head)) in heads.iter().enumerate() {
+            let end = heads.get(i + 1).map_or(inode.size, |h| h.1).min(inode.size);
+            if end <= *start {
+                continue;
+            }
+            let len = (end - start) as usize;
+
+            let is_tail = i + 1 == heads.len() && map.advise & ADVISE_INLINE_PCLUSTER != 0;
+
+            let (offset, plen) = if is_tail {
+                (map.tail_data_offset()?, map.idata_size)
+            } else {
+                (head.pblk << self.blkszbits, map.pcluster_blocks(*lcn, head)? << self.blkszbits)
+            };
+            let pcluster = fsimage::read_at(&mut self.reader, offset, plen as usize)?;
+
+            let data = match head.kind {
+                LCLUSTER_PLAIN => pcluster.get(..len).map(|d| d.to_vec()).ok_or_else(|| {
+                    format!("nid {}: plain extent larger than its pcluster", inode.nid)
+                })?,
+                kind => {
+                    let algorithm = if kind == LCLUSTER_HEAD1 {
+                        map.algorithms & 0xF
+                    } else {
+                        map.algorithms >> 4
+                    };
+
+                    if algorithm != ALG_LZ4 {
+                        return Err(format!(
+                            "nid {}: {} compression is not supported",
+                            inode.nid,
+                            algorithm_name(algorithm)
+                        ));
+                    }
+
+                    let input = if self.zero_padding {
+                        let skip = pcluster.iter().position(|&b| b != 0).unwrap_or(pcluster.len());
+                        &pcluster[skip..]
+                    } else {
+                        &pcluster[..]
+                    };
+
+                    lz4_decompress_partial(input, len)
+                        .map_err(|e| format!("nid {}: extent at {:#x}: {}", inode.nid, start, e))?
+                }
+            };
+
+            out.write_all(&data).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /* ================= DIRECTORIES ================= */
+
+    fn dir_entries(&mut self, dir: &ErofsInode) -> Result<Vec<(String, u64)>, String> {
+        let mut data = Vec::new();
+        self.read_data(dir, &mut data)?;
+
+        let mut names = Vec::new();
+
+        for block in data.chunks(self.dir_block_size) {
+            if block.len() < DIRENT_SIZE {
+                break;
+            }
+
+            let count = usize::from(le16(block, 8)) / DIRENT_SIZE;
+            if count == 0 || count * DIRENT_SIZE > block.len() {
+                return Err(format!("nid {}: corrupt EROFS directory block", dir.nid));
+            }
+
+            for i in 0..count {
+                let dirent = &block[i * DIRENT_SIZE..];
+                let name_start = usize::from(le16(dirent, 8));
+                let name_end = if i + 1 < count {
+                    usize::from(le16(dirent, DIRENT_SIZE + 8))
+                } else {
+                    block.len()
+                };
+
+                let name = block
+                    .get(name_start..name_end)
+                    .ok_or_else(|| format!("nid {}: corrupt EROFS dirent", dir.nid))?;
+                let name = c_string(name);
+
+                if name != "." && name != ".." {
+                    names.push((name, le64(dirent, 0)));
+                }
+            }
+        }
+
+        Ok(names)
+    }
+}
+
+impl CompressedMap {
+    fn lcluster(&self, lcn: u64) -> Result<Lcluster, String> {
+        if self.compact {
+            self.compact_lcluster(lcn)
+        } else {
+            self.full_lcluster(lcn)
+        }
+    }
+
+    fn big_pcluster(&self) -> bool {
+        self.advise & (ADVISE_BIG_PCLUSTER_1 | ADVISE_BIG_PCLUSTER_2) != 0
+    }
+
+    fn full_lcluster(&self, lcn: u64) -> Result<Lcluster, String> {
+        let raw = self
+            .index
+            .get(lcn as usize * 8..lcn as usize * 8 + 8)
+            .ok_or("EROFS lcluster index out of range")?;
+        let kind = (le16(raw, 0) & 3) as u8;
+
+        if kind == LCLUSTER_NONHEAD {
+            let delta0 = u32::from(le16(raw, 4));
+            let compressed_blocks = (delta0 & D0_CBLKCNT != 0 && self.big_pcluster())
+                .then(|| u64::from(delta0 & !D0_CBLKCNT));
+
+            return Ok(Lcluster { kind, clusterofs: 0, pblk: 0, compressed_blocks });
+        }
+
+        Ok(Lcluster {
+            kind,
+            clusterofs: u64::from(le16(raw, 2)),
+            pblk: u64::from(le32(raw, 4)),
+            compressed_blocks: None,
+        })
+    }
+
+    /// Port of the kernel's `unpack_compacted_index`.
+    fn compact_lcluster(&self, lcn: u64) -> Result<Lcluster, String> {
+        let (pos, shift) = self.compact_position(lcn);
+        let vcnt: u64 = if shift == 2 { 2 } else { 16 };
+        let pack_size = vcnt << shift;
+
+        let pack_start = pos / pack_size * pack_size;
+        let rel = (pack_start - self.index_base) as usize;
+        let pack = self
+            .index
+            .get(rel..rel + pack_size as usize)
+            .ok_or("EROFS compacted index out of range")?;
+
+        let lobits = self.lclusterbits.max(12);
+        let encodebits = ((pack_size - 4) * 8 / vcnt) as u32;
+        let decode = |i: u64| -> (u32, u8) {
+            let bit = (encodebits as u64 * i) as usize;
+            let v = le32(pack, bit / 8) >> (bit & 7);
+            (v & ((1 << lobits) - 1), ((v >> lobits) & 3) as u8)
+        };
+
+        let mut i = ((pos - pack_start) >> shift) as i64;
+        let (lo, kind) = decode(i as u64);
+
+        if kind == LCLUSTER_NONHEAD {
+            let compressed_blocks = (lo & D0_CBLKCNT != 0 && self.big_pcluster())
+                .then(|| u64::from(lo & !D0_CBLKCNT));
+
+            return Ok(Lcluster { kind, clusterofs: 0, pblk: 0, compressed_blocks });
+        }
+
+        // The pack stores one block address; count the pclusters before
+        // this one to find ours
+        let mut blocks: u64 = 0;
+
+        if self.advise & ADVISE_BIG_PCLUSTER_1 == 0 {
+            blocks = 1;
+            while i > 0 {
+                i -= 1;
+                let (lo, kind) = decode(i as u64);
+                if kind == LCLUSTER_NONHEAD {
+                    i -= i64::from(lo);
+                }
+                if i >= 0 {
+                    blocks += 1;
+                }
+            }
+        } else {
+            while i > 0 {
+                i -= 1;
+                let (lo, kind) = decode(i as u64);
+                if kind == LCLUSTER_NONHEAD {
+                    if lo & D0_CBLKCNT != 0 {
+                        i -= 1;
+                        blocks += u64::from(lo & !D0_CBLKCNT);
+                        continue;
+                    }
+                    if lo <= 1 {
+                        return Err("Corrupt EROFS compacted index".into());
+                    }
+                    i -= i64::from(lo) - 2;
+                    continue;
+                }
+                blocks += 1;
+            }
+        }
+
+        let base = le32(pack, pack_size as usize - 4);
+
+        Ok(Lcluster {
+            kind,
+            clusterofs: u64::from(lo),
+            pblk: u64::from(base) + blocks,
+            compressed_blocks: None,
+        })
+    }
+
+    /// Absolute position of the index for `lcn` and its amortized size
+    /// shift (2 for 4-byte, 1 for 2-byte).
+    fn compact_position(&self, lcn: u64) -> (u64, u32) {
+        let (initial, two_byte) = compact_split(self.index_base, self.advise, self.count);
+        let mut pos = self.index_base;
+
+        if lcn < initial {
+            return (pos + lcn * 4, 2);
+        }
+        pos += initial * 4;
+
+        let lcn = lcn - initial;
+        if lcn < two_byte {
+            return (pos + lcn * 2, 1);
+        }
+
+        (pos + two_byte * 2 + (lcn - two_byte) * 4, 2)
+    }
+
+    /// Byte right after the index (pack) of the last lcluster, where
+    /// the inline tail pcluster is stored.
+    fn tail_data_offset(&self) -> Result<u64, String> {
+        let last = self.count.checked_sub(1).ok_or("Empty EROFS index")?;
+
+        if !self.compact {
+            return Ok(self.index_base + (last + 1) * 8);
+        }
+
+        let (pos, shift) = self.compact_position(last);
+        let pack_size = if shift == 2 { 8 } else { 32 };
+        Ok(pos / pack_size * pack_size + pack_size)
+    }
+
+    /// Blocks in the pcluster of the head at `lcn`: the count stored in
+    /// the next lcluster for big pclusters, one lcluster otherwise.
+    fn pcluster_blocks(&self, lcn: u64, head: &Lcluster) -> Result<u64, String> {
+        let big = match head.kind {
+            LCLUSTER_HEAD1 => self.advise & ADVISE_BIG_PCLUSTER_1 != 0,
+            LCLUSTER_PLAIN | LCLUSTER_HEAD2 => self.advise & ADVISE_BIG_PCLUSTER_2 != 0,
+            _ => false,
+        };
+        if !big || lcn + 1 >= self.count {
+            return Ok(self.lcluster_blocks);
+        }
+
+        let next = self.lcluster(lcn + 1)?;
+        match (next.kind, next.compressed_blocks) {
+            (LCLUSTER_NONHEAD, Some(blocks)) => Ok(blocks),
+            (LCLUSTER_NONHEAD, None) => Err("EROFS big pcluster without a block count".into()),
+            _ => Ok(1),
+        }
+    }
+}
+
+impl Filesystem for Erofs {
+    type Inode = ErofsInode;
+
+    fn root(&mut self) -> Result<ErofsInode, String> {
+        self.inode(self.root_nid)
+    }
+
+    fn info(&self, inode: &ErofsInode) -> NodeInfo {
+        NodeInfo { mode: inode.mode, uid: inode.uid, gid: inode.gid, size: inode.size }
+    }
+
+    fn read_dir(&mut self, dir: &ErofsInode) -> Result<Vec<(String, ErofsInode)>, String> {
+        self.dir_entries(dir)?
+            .into_iter()
+            .map(|(name, nid)| Ok((name, self.inode(nid)?)))
+            .collect()
+    }
+
+    fn read_data(&mut self, inode: &ErofsInode, out: &mut dyn Write) -> Result<u64, String> {
+        match inode.layout {
+            LAYOUT_FLAT_PLAIN | LAYOUT_FLAT_INLINE => self.read_flat(inode, out)?,
+            LAYOUT_CHUNK_BASED => self.read_chunked(inode, out)?,
+            LAYOUT_COMPRESSED_FULL | LAYOUT_COMPRESSED_COMPACT => {
+                self.read_compressed(inode, out)?
+            }
+            other => return Err(format!("nid {}: unknown EROFS data layout {}", inode.nid, other)),
+        }
+
+        Ok(inode.size)
+    }
+}
+
+/* ================= HELPERS ================= */
+
+/// Number of leading 4-byte indexes (up to 32-byte alignment) and of
+/// 2-byte indexes in a compacted index area starting at `base`.
+fn compact_split(base: u64, advise: u16, count: u64) -> (u64, u64) {
+    let initial = match (32 - base % 32) / 4 {
+        8 => 0,
+        n => n,
+    }
+    .min(count);
+
+    let two_byte = if advise & ADVISE_COMPACTED_2B != 0 && initial < count {
+        (count - initial) / 16 * 16
+    } else {
+        0
+    };
+
+    (initial, two_byte)
+}
+
+/// LZ4 block decoder that stops once `limit` bytes are produced; EROFS
+/// pclusters may decode to more than the extent that uses them.
+fn lz4_decompress_partial(input: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+    let mut out: Vec<u8> = Vec::with_capacity(limit);
+    let mut pos = 0;
+
+    let length = |pos: &mut usize, base: usize| -> Result<usize, String> {
+        let mut len = base;
+        if base == 15 {
+            loop {
+                let byte = *input.get(*pos).ok_or("LZ4 length runs past input")?;
+                *pos += 1;
+                len += usize::from(byte);
+                if byte != 255 {
+                    break;
+                }
+            }
+        }
+        Ok(len)
+    };
+
+    while out.len() < limit {
+        let token = *input.get(pos).ok_or("LZ4 data ends early")?;
+        pos += 1;
+
+        let literals = length(&mut pos, usize::from(token >> 4))?;
+        let literal_data = input.get(pos..pos + literals).ok_or("LZ4 literals run past input")?;
+        out.extend_from_slice(literal_data);
+        pos += literals;
+
+        if out.len() >= limit || pos == input.len() {
+            break;
+        }
+
+        let offset = usize::from(le16(input.get(pos..pos + 2).ok_or("LZ4 data ends early")?, 0));
+        pos += 2;
+
+        if offset == 0 || offset > out.len() {
+            return Err(format!("Bad LZ4 match offset {}", offset));
+        }
+
+        let match_len = length(&mut pos, usize::from(token & 0xF))? + 4;
+        let from = out.len() - offset;
+
+        // Matches may overlap their own output
+        for i in 0..match_len.min(limit - out.len()) {
+            let byte = out[from + i];
+            out.push(byte);
+        }
+    }
+
+    if out.len() < limit {
+        return Err(format!("LZ4 data decodes to {} bytes, expected {}", out.len(), limit));
+    }
+
+    out.truncate(limit);
+    Ok(out)
+}
+
+fn algorithm_name(algorithm: u8) -> &'static str {
+    match algorithm {
+        0 => "LZ4",
+        1 => "LZMA",
+        2 => "DEFLATE",
+        3 => "Zstandard",
+        _ => "unknown",
+    }
+}
+
+fn align(offset: u64, to: u64) -> u64 {
+    offset.div_ceil(to) * to
+}
+
+fn c_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+fn le16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn le64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const BLOCK: usize = 4096;
+    /// meta_blkaddr 1
+    const META: usize = BLOCK;
+
+    const FLAT_SIZE: usize = 5000;
+    const COMP_SIZE: usize = 14000;
+    const COMPACT_SIZE: usize = 9000;
+    const PACK2B_SIZE: usize = 30 * BLOCK - 100;
+    const CHUNK_SIZE: usize = 6000;
+
+    fn put16(data: &mut [u8], offset: usize, value: u16) {
+        data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put64(data: &mut [u8], offset: usize, value: u64) {
+        data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Compact inode owned by 1000:2000; returns the offset right after it
+    fn inode(disk: &mut [u8], nid: usize, mode: u16, layout: u16, size: usize, i_u: u32) -> usize {
+        let at = META + nid * INODE_SLOT as usize;
+        put16(disk, at, layout << 1);
+        put16(disk, at + 4, mode);
+        put32(disk, at + 8, size as u32);
+        put32(disk, at + 16, i_u);
+        put16(disk, at + 24, 1000);
+        put16(disk, at + 26, 2000);
+        at + 32
+    }
+
+    fn dirents(entries: &[(&str, u64)]) -> Vec<u8> {
+        let mut block = vec![0u8; entries.len() * DIRENT_SIZE];
+        let mut names: Vec<u8> = Vec::new();
+        for (i, (name, nid)) in entries.iter().enumerate() {
+            put64(&mut block, i * DIRENT_SIZE, *nid);
+            let name_offset = block.len() + names.len();
+            put16(&mut block, i * DIRENT_SIZE + 8, name_offset as u16);
+            names.extend(name.as_bytes());
+        }
+        block.extend(names);
+        block
+    }
+
+    fn text(len: usize, seed: usize) -> Vec<u8> {
+        (0..len).map(|i| b"abcdefghij\n"[(i * seed / 7 + i / 13) % 11]).collect()
+    }
+
+    /// Right-aligned in its pcluster, as with zero padding
+    fn put_pcluster(disk: &mut [u8], end_block: usize, data: &[u8]) {
+        disk[end_block * BLOCK - data.len()..end_block * BLOCK].copy_from_slice(data);
+    }
+
+    /// 4 KiB blocks, zero padding, every data layout:
+    ///
+    ///   /          nid 0   FLAT_INLINE directory
+    ///   /flat      nid 8   extended inode, FLAT_PLAIN at block 3
+    ///   /link      nid 10  symlink to sub/again
+    ///   /comp      nid 12  full index, big pclusters (blocks 5-6, 7)
+    ///   /compact   nid 15  4-byte compacted index, plain middle extent,
+    ///                      inline tail pcluster
+    ///   /chunk     nid 40  chunk block map with a hole
+    ///   /sub       nid 44  FLAT_PLAIN directory at block 11
+    ///   /pack2b    nid 48  2-byte compacted index, plain lclusters
+    ///
+    /// Returns the image and the expected contents of each file.
+    pub fn image() -> (Vec<u8>, Vec<(&'static str, Vec<u8>)>) {
+        let mut disk = vec![0u8; 42 * BLOCK];
+
+        let sb = SUPERBLOCK_OFFSET as usize;
+        put32(&mut disk, sb, EROFS_MAGIC);
+        disk[sb + 12] = 12;
+        put32(&mut disk, sb + 40, (META / BLOCK) as u32);
+        disk[sb + 64..sb + 70].copy_from_slice(b"vendor");
+        put32(&mut disk, sb + 80, INCOMPAT_ZERO_PADDING);
+
+        let root = dirents(&[
+            (".", 0),
+            ("..", 0),
+            ("chunk", 40),
+            ("comp", 12),
+            ("compact", 15),
+            ("flat", 8),
+            ("link", 10),
+            ("pack2b", 48),
+            ("sub", 44),
+        ]);
+        let tail = inode(&mut disk, 0, 0o040755, LAYOUT_FLAT_INLINE, root.len(), 0);
+        disk[tail..tail + root.len()].copy_from_slice(&root);
+
+        let flat = text(FLAT_SIZE, 3);
+        let at = META + 8 * INODE_SLOT as usize;
+        put16(&mut disk, at, 1);
+        put16(&mut disk, at + 4, 0o100644);
+        put64(&mut disk, at + 8, FLAT_SIZE as u64);
+        put32(&mut disk, at + 16, 3);
+        disk[3 * BLOCK..3 * BLOCK + FLAT_SIZE].copy_from_slice(&flat);
+
+        let tail = inode(&mut disk, 10, 0o120777, LAYOUT_FLAT_INLINE, 9, 0);
+        disk[tail..tail + 9].copy_from_slice(b"sub/again");
+
+        // Two extents: 8692 bytes from a 2-block pcluster, then the rest
+        // starting 500 bytes into lcluster 2
+        let comp = text(COMP_SIZE, 5);
+        let tail = inode(&mut disk, 12, 0o100644, LAYOUT_COMPRESSED_FULL, COMP_SIZE, 0);
+        let header = align(tail as u64, 8) as usize;
+        put16(&mut disk, header + 4, ADVISE_BIG_PCLUSTER_1);
+        let index = header + 16;
+        put16(&mut disk, index, LCLUSTER_HEAD1.into());
+        put32(&mut disk, index + 4, 5);
+        put16(&mut disk, index + 8, LCLUSTER_NONHEAD.into());
+        put16(&mut disk, index + 12, D0_CBLKCNT as u16 | 2);
+        put16(&mut disk, index + 16, LCLUSTER_HEAD1.into());
+        put16(&mut disk, index + 18, 500);
+        put32(&mut disk, index + 20, 7);
+        put16(&mut disk, index + 24, LCLUSTER_NONHEAD.into());
+        put16(&mut disk, index + 28, D0_CBLKCNT as u16 | 1);
+        put_pcluster(&mut disk, 7, &lz4_flex::block::compress(&comp[..8692]));
+        put_pcluster(&mut disk, 8, &lz4_flex::block::compress(&comp[8692..]));
+
+        // LZ4 extent at block 8, plain extent at block 9 (both found from
+        // the pack's base address), LZ4 tail stored after the index
+        let compact = text(COMPACT_SIZE, 11);
+        let tail = inode(&mut disk, 15, 0o100600, LAYOUT_COMPRESSED_COMPACT, COMPACT_SIZE, 0);
+        let header = align(tail as u64, 8) as usize;
+        let packed_tail = lz4_flex::block::compress(&compact[8392..]);
+        put16(&mut disk, header + 2, packed_tail.len() as u16);
+        put16(&mut disk, header + 4, ADVISE_INLINE_PCLUSTER);
+        let index = header + 8;
+        put16(&mut disk, index, u16::from(LCLUSTER_HEAD1) << 12);
+        put16(&mut disk, index + 2, 404);
+        put32(&mut disk, index + 4, 7);
+        put16(&mut disk, index + 8, u16::from(LCLUSTER_HEAD1) << 12 | 200);
+        disk[index + 16..index + 16 + packed_tail.len()].copy_from_slice(&packed_tail);
+        put_pcluster(&mut disk, 9, &lz4_flex::block::compress(&compact[..4500]));
+        disk[9 * BLOCK..9 * BLOCK + 3892].copy_from_slice(&compact[4500..8392]);
+
+        let tail = inode(&mut disk, 40, 0o100644, LAYOUT_CHUNK_BASED, CHUNK_SIZE, 0);
+        let table = align(tail as u64, 4) as usize;
+        put32(&mut disk, table, 10);
+        put32(&mut disk, table + 4, NULL_ADDR);
+        disk[10 * BLOCK..11 * BLOCK].fill(0x5A);
+        let chunk = [vec![0x5A; BLOCK], vec![0; CHUNK_SIZE - BLOCK]].concat();
+
+        let sub = dirents(&[(".", 44), ("..", 0), ("again", 8)]);
+        inode(&mut disk, 44, 0o040700, LAYOUT_FLAT_PLAIN, sub.len(), 11);
+        disk[11 * BLOCK..11 * BLOCK + sub.len()].copy_from_slice(&sub);
+
+        // 30 plain lclusters at blocks 12..: three 4-byte packs, then a
+        // 2-byte pack of 16 and four more 4-byte packs
+        let pack2b: Vec<u8> = (0..PACK2B_SIZE).map(|i| (i / BLOCK * 31 + i % 7) as u8).collect();
+        let tail = inode(&mut disk, 48, 0o100644, LAYOUT_COMPRESSED_COMPACT, PACK2B_SIZE, 0);
+        let header = align(tail as u64, 8) as usize;
+        put16(&mut disk, header + 4, ADVISE_COMPACTED_2B);
+        let index = header + 8;
+        for k in 0..3 {
+            put32(&mut disk, index + k * 8 + 4, (12 + 2 * k - 1) as u32);
+        }
+        let two_byte = index + 24;
+        put32(&mut disk, two_byte + 28, 12 + 6 - 1);
+        for k in 0..4 {
+            put32(&mut disk, two_byte + 32 + k * 8 + 4, (12 + 22 + 2 * k - 1) as u32);
+        }
+        disk[12 * BLOCK..12 * BLOCK + PACK2B_SIZE].copy_from_slice(&pack2b);
+
+        let files = vec![
+            ("/flat", flat.clone()),
+            ("/link", flat),
+            ("/comp", comp),
+            ("/compact", compact),
+            ("/chunk", chunk),
+            ("/pack2b", pack2b),
+        ];
+        (disk, files)
+    }
+
+    fn open(disk: Vec<u8>) -> Result<Erofs, String> {
+        Erofs::new(Box::new(Cursor::new(disk)))
+    }
+
+    fn child(fs: &mut Erofs, dir: &ErofsInode, name: &str) -> ErofsInode {
+        let entries = fs.read_dir(dir).unwrap();
+        entries.into_iter().find(|(n, _)| n == name).unwrap().1
+    }
+
+    fn contents(fs: &mut Erofs, inode: &ErofsInode) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        let written = fs.read_data(inode, &mut data)?;
+        assert_eq!(written as usize, data.len());
+        Ok(data)
+    }
+
+    #[test]
+    fn lists_directories() {
+        let mut fs = open(image().0).unwrap();
+        assert_eq!(fs.volume_name.as_deref(), Some("vendor"));
+
+        let root = fs.root().unwrap();
+        let names: Vec<_> = fs.read_dir(&root).unwrap().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(names, ["chunk", "comp", "compact", "flat", "link", "pack2b", "sub"]);
+
+        let sub = child(&mut fs, &root, "sub");
+        let info = fs.info(&sub);
+        assert_eq!((info.mode, info.uid, info.gid), (0o040700, 1000, 2000));
+
+        let again = child(&mut fs, &sub, "again");
+        assert_eq!(again.nid, 8);
+        assert_eq!(fs.info(&again).uid, 0, "extended inode ids");
+    }
+
+    #[test]
+    fn reads_every_layout() {
+        let (disk, files) = image();
+        let mut fs = open(disk).unwrap();
+        let root = fs.root().unwrap();
+
+        for (path, expected) in files {
+            let inode = child(&mut fs, &root, &path[1..]);
+            let data = contents(&mut fs, &inode).unwrap();
+            assert_eq!(fs.info(&inode).size as usize, data.len(), "{}", path);
+            assert!(data == expected || path == "/link", "{}", path);
+        }
+
+        let link = child(&mut fs, &root, "link");
+        assert_eq!(contents(&mut fs, &link).unwrap(), b"sub/again");
+    }
+
+    #[test]
+    fn rejects_unsupported_data() {
+        let mut disk = image().0;
+        disk[SUPERBLOCK_OFFSET as usize] ^= 1;
+        assert!(open(disk).err().unwrap().contains("bad superblock magic"));
+
+        let mut disk = image().0;
+        disk[SUPERBLOCK_OFFSET as usize + 12] = 20;
+        assert!(open(disk).err().unwrap().contains("block size"));
+
+        // comp: switch HEAD1 extents to LZMA
+        let mut disk = image().0;
+        let header = align((META + 13 * INODE_SLOT as usize) as u64, 8) as usize;
+        disk[header + 6] = 1;
+        let mut fs = open(disk).unwrap();
+        let root = fs.root().unwrap();
+        let comp = child(&mut fs, &root, "comp");
+        assert!(contents(&mut fs, &comp).unwrap_err().contains("LZMA compression"));
+
+        // flat: unknown layout
+        let mut disk = image().0;
+        put16(&mut disk, META + 8 * INODE_SLOT as usize, 7 << 1 | 1);
+        let mut fs = open(disk).unwrap();
+        let root = fs.root().unwrap();
+        let flat = child(&mut fs, &root, "flat");
+        assert!(contents(&mut fs, &flat).unwrap_err().contains("unknown EROFS data layout 7"));
+    }
+
+    #[test]
+    fn lz4_decodes_only_what_is_needed() {
+        let data = text(10000, 7);
+        let packed = lz4_flex::block::compress(&data);
+
+        assert_eq!(lz4_decompress_partial(&packed, data.len()).unwrap(), data);
+        assert_eq!(lz4_decompress_partial(&packed, 1234).unwrap(), &data[..1234]);
+        let short = lz4_decompress_partial(&packed, data.len() + 1).unwrap_err();
+        assert!(short.contains("decodes to"), "{}", short);
+
+        // A match reaching before the start of the output
+        assert!(lz4_decompress_partial(&[0x10, b'a', 5, 0], 8).unwrap_err().contains("offset 5"));
+    }
+}
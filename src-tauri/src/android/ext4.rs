@@ -0,0 +1,739 @@
+use std::io::Write;
+
+use crate::android::fsimage::{self, Filesystem, NodeInfo};
+use crate::android::sparse::ReadSeek;
+
+// ext4, little-endian. The superblock sits at byte 1024; block groups
+// follow, each described by a group descriptor in the table right after
+// the superblock's block. An inode number maps to (group, index) through
+// `inodes_per_group`, and the inode itself lives in that group's inode
+// table.
+//
+// File data is found through an extent tree (or the ext2/3 block map on
+// old inodes) rooted in the 60-byte `i_block` field. Small files and
+// directories may instead be stored inline: the first 60 bytes in
+// `i_block`, the rest in the in-inode xattr `system.data`.
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const ROOT_INODE: u32 = 2;
+const GOOD_OLD_INODE_SIZE: usize = 128;
+
+const INCOMPAT_META_BG: u32 = 0x10;
+const INCOMPAT_64BIT: u32 = 0x80;
+
+const INODE_FLAG_EXTENTS: u32 = 0x0008_0000;
+const INODE_FLAG_INLINE_DATA: u32 = 0x1000_0000;
+
+const EXTENT_MAGIC: u16 = 0xF30A;
+const EXTENT_MAX_DEPTH: u16 = 5;
+/// Extent lengths above this mark an unwritten (zero-reading) extent
+const EXTENT_INIT_MAX_LEN: u16 = 32768;
+
+const XATTR_MAGIC: u32 = 0xEA02_0000;
+/// `system.` prefix index; inline data lives in `system.data`
+const XATTR_INDEX_SYSTEM: u8 = 7;
+
+const I_BLOCK: usize = 40;
+const I_BLOCK_LEN: usize = 60;
+
+pub struct Ext4 {
+    reader: Box<dyn ReadSeek>,
+    block_size: u64,
+    inodes_per_group: u32,
+    inode_size: usize,
+    desc_size: u64,
+    descriptors_offset: u64,
+    group_count: u32,
+    pub volume_name: Option<String>,
+}
+
+/// Inode number with its raw on-disk record.
+#[derive(Clone)]
+pub struct Ext4Inode {
+    number: u32,
+    raw: Vec<u8>,
+}
+
+/// Contiguous run of file blocks.
+struct Run {
+    logical: u64,
+    physical: u64,
+    len: u64,
+    unwritten: bool,
+}
+
+impl Ext4Inode {
+    fn mode(&self) -> u16 {
+        le16(&self.raw, 0)
+    }
+
+    fn flags(&self) -> u32 {
+        le32(&self.raw, 32)
+    }
+
+    fn size(&self) -> u64 {
+        u64::from(le32(&self.raw, 4)) | u64::from(le32(&self.raw, 108)) << 32
+    }
+
+    fn i_block(&self) -> &[u8] {
+        &self.raw[I_BLOCK..I_BLOCK + I_BLOCK_LEN]
+    }
+}
+
+impl Ext4 {
+    pub fn new(mut reader: Box<dyn ReadSeek>) -> Result<Self, String> {
+        let sb = fsimage::read_at(&mut reader, SUPERBLOCK_OFFSET, 1024)?;
+
+        if le16(&sb, 56) != 0xEF53 {
+            return Err("Not an ext4 filesystem (bad superblock magic)".into());
+        }
+
+        let log_block_size = le32(&sb, 24);
+        if log_block_size > 6 {
+            return Err(format!("Unsupported ext4 block size 2^{}", 10 + log_block_size));
+        }
+        let block_size = 1024u64 << log_block_size;
+
+        let incompat = le32(&sb, 96);
+        if incompat & INCOMPAT_META_BG != 0 {
+            return Err("ext4 meta_bg layout is not supported".into());
+        }
+
+        let inode_size = match le32(&sb, 76) {
+            0 => GOOD_OLD_INODE_SIZE,
+            _ => usize::from(le16(&sb, 88)),
+        };
+        if inode_size < GOOD_OLD_INODE_SIZE || !inode_size.is_power_of_two() {
+            return Err(format!("Bad ext4 inode size {}", inode_size));
+        }
+
+        let desc_size = if incompat & INCOMPAT_64BIT != 0 {
+            u64::from(le16(&sb, 254)).max(32)
+        } else {
+            32
+        };
+
+        let inodes_per_group = le32(&sb, 40);
+        if inodes_per_group == 0 {
+            return Err("Bad ext4 superblock (no inodes per group)".into());
+        }
+
+        let volume_name = c_string(&sb[120..136]);
+
+        Ok(Ext4 {
+            reader,
+            block_size,
+            inodes_per_group,
+            inode_size,
+            desc_size,
+            descriptors_offset: (u64::from(le32(&sb, 20)) + 1) * block_size,
+            group_count: le32(&sb, 0).div_ceil(inodes_per_group),
+            volume_name: (!volume_name.is_empty()).then_some(volume_name),
+        })
+    }
+
+    fn inode(&mut self, number: u32) -> Result<Ext4Inode, String> {
+        let group = number.wrapping_sub(1) / self.inodes_per_group;
+        let index = number.wrapping_sub(1) % self.inodes_per_group;
+
+        if number == 0 || group >= self.group_count {
+            return Err(format!("Inode {} out of range", number));
+        }
+
+        let desc_offset = self.descriptors_offset + u64::from(group) * self.desc_size;
+        let desc = fsimage::read_at(&mut self.reader, desc_offset, self.desc_size as usize)?;
+
+        let mut table = u64::from(le32(&desc, 8));
+        if self.desc_size >= 64 {
+            table |= u64::from(le32(&desc, 40)) << 32;
+        }
+
+        let offset = table * self.block_size + u64::from(index) * self.inode_size as u64;
+        let raw = fsimage::read_at(&mut self.reader, offset, self.inode_size)?;
+
+        Ok(Ext4Inode { number, raw })
+    }
+
+    /* ================= BLOCK MAPPING ================= */
+
+    fn runs(&mut self, inode: &Ext4Inode) -> Result<Vec<Run>, String> {
+        let mut runs = Vec::new();
+
+        if inode.flags() & INODE_FLAG_EXTENTS != 0 {
+            self.extent_runs(inode.i_block(), EXTENT_MAX_DEPTH, &mut runs)?;
+        } else {
+            // Twelve direct pointers, then single, double and triple
+            // indirect ones
+            let blocks = inode.size().div_ceil(self.block_size);
+            let mut logical = 0;
+
+            for (i, ptr) in inode.i_block().chunks_exact(4).enumerate() {
+                if logical >= blocks {
+                    break;
+                }
+                let level = i.saturating_sub(11) as u32;
+                self.map_runs(u64::from(le32(ptr, 0)), level, &mut logical, blocks, &mut runs)?;
+            }
+        }
+
+        runs.sort_by_key(|r| r.logical);
+        Ok(runs)
+    }
+
+    fn extent_runs(
+        &mut self,
+        node: &[u8],
+        depth_left: u16,
+        runs: &mut Vec<Run>,
+    ) -> Result<(), String> {
+        if node.len() < 12 || le16(node, 0) != EXTENT_MAGIC {
+            return Err("Bad ext4 extent header".into());
+        }
+
+        let entries = usize::from(le16(node, 2));
+        let depth = le16(node, 6);
+
+        if depth >= depth_left || 12 + entries * 12 > node.len() {
+            return Err("Corrupt ext4 extent tree".into());
+        }
+
+        for entry in node[12..12 + entries * 12].chunks_exact(12) {
+            if depth == 0 {
+                let len = le16(entry, 4);
+                let unwritten = len > EXTENT_INIT_MAX_LEN;
+
+                runs.push(Run {
+                    logical: u64::from(le32(entry, 0)),
+                    physical: u64::from(le16(entry, 6)) << 32 | u64::from(le32(entry, 8)),
+                    len: u64::from(if unwritten { len - EXTENT_INIT_MAX_LEN } else { len }),
+                    unwritten,
+                });
+            } else {
+                let leaf = u64::from(le16(entry, 8)) << 32 | u64::from(le32(entry, 4));
+                let block_size = self.block_size;
+                let child =
+                    fsimage::read_at(&mut self.reader, leaf * block_size, block_size as usize)?;
+                self.extent_runs(&child, depth, runs)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Legacy block map: `level` 0 is a data block, 1-3 are single,
+    /// double and triple indirect blocks.
+    fn map_runs(
+        &mut self,
+        block: u64,
+        level: u32,
+        logical: &mut u64,
+        blocks: u64,
+        runs: &mut Vec<Run>,
+    ) -> Result<(), String> {
+        let per_block = self.block_size / 4;
+
+        if block == 0 {
+            *logical += per_block.pow(level);
+            return Ok(());
+        }
+
+        if level == 0 {
+            match runs.last_mut() {
+                Some(run)
+                    if run.logical + run.len == *logical && run.physical + run.len == block =>
+                {
+                    run.len += 1
+                }
+                _ => {
+                    runs.push(Run { logical: *logical, physical: block, len: 1, unwritten: false })
+                }
+            }
+            *logical += 1;
+            return Ok(());
+        }
+
+        let block_size = self.block_size;
+        let table = fsimage::read_at(&mut self.reader, block * block_size, block_size as usize)?;
+
+        for ptr in table.chunks_exact(4) {
+            if *logical >= blocks {
+                break;
+            }
+            self.map_runs(u64::from(le32(ptr, 0)), level - 1, logical, blocks, runs)?;
+        }
+
+        Ok(())
+    }
+
+    /* ================= INLINE DATA ================= */
+
+    /// The `i_block` part and the `system.data` xattr part.
+    fn inline_parts(&self, inode: &Ext4Inode) -> Result<(Vec<u8>, Vec<u8>), String> {
+        let head = inode.i_block().to_vec();
+        let raw = &inode.raw;
+
+        let extra = usize::from(if raw.len() > GOOD_OLD_INODE_SIZE { le16(raw, 128) } else { 0 });
+        let start = GOOD_OLD_INODE_SIZE + extra;
+
+        if start + 4 > raw.len() || le32(raw, start) != XATTR_MAGIC {
+            return Ok((head, Vec::new()));
+        }
+
+        let first = start + 4;
+        let mut pos = first;
+
+        while pos + 16 <= raw.len() && le32(raw, pos) != 0 {
+            let name_len = usize::from(raw[pos]);
+            let name = raw.get(pos + 16..pos + 16 + name_len).ok_or("Truncated ext4 xattr")?;
+
+            if raw[pos + 1] == XATTR_INDEX_SYSTEM && name == b"data" {
+                let value_offset = first + usize::from(le16(raw, pos + 2));
+                let value_size = le32(raw, pos + 8) as usize;
+
+                let value = raw
+                    .get(value_offset..value_offset + value_size)
+                    .ok_or("ext4 inline data runs past the inode")?;
+                return Ok((head, value.to_vec()));
+            }
+
+            pos += (16 + name_len + 3) & !3;
+        }
+
+        Ok((head, Vec::new()))
+    }
+
+    /* ================= DIRECTORIES ================= */
+
+    fn dir_entries(&mut self, dir: &Ext4Inode) -> Result<Vec<(String, u32)>, String> {
+        let mut names = Vec::new();
+
+        if dir.flags() & INODE_FLAG_INLINE_DATA != 0 {
+            // The first four bytes are the parent inode, not a dirent
+            let (head, rest) = self.inline_parts(dir)?;
+            parse_dirents(&head[4..], &mut names)?;
+            parse_dirents(&rest, &mut names)?;
+        } else {
+            let mut data = Vec::new();
+            self.read_data(dir, &mut data)?;
+
+            for block in data.chunks(self.block_size as usize) {
+                parse_dirents(block, &mut names)?;
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn is_fast_symlink(&self, inode: &Ext4Inode) -> bool {
+        let blocks = u64::from(le32(&inode.raw, 28)) | u64::from(le16(&inode.raw, 116)) << 32;
+        let acl = u64::from(le32(&inode.raw, 104)) | u64::from(le16(&inode.raw, 118)) << 32;
+        let acl_sectors = if acl != 0 { self.block_size / 512 } else { 0 };
+
+        inode.mode() & 0o170000 == 0o120000
+            && inode.flags() & INODE_FLAG_INLINE_DATA == 0
+            && blocks.saturating_sub(acl_sectors) == 0
+    }
+}
+
+impl Filesystem for Ext4 {
+    type Inode = Ext4Inode;
+
+    fn root(&mut self) -> Result<Ext4Inode, String> {
+        self.inode(ROOT_INODE)
+    }
+
+    fn info(&self, inode: &Ext4Inode) -> NodeInfo {
+        let raw = &inode.raw;
+
+        NodeInfo {
+            mode: inode.mode(),
+            uid: u32::from(le16(raw, 2)) | u32::from(le16(raw, 120)) << 16,
+            gid: u32::from(le16(raw, 24)) | u32::from(le16(raw, 122)) << 16,
+            size: inode.size(),
+        }
+    }
+
+    fn read_dir(&mut self, dir: &Ext4Inode) -> Result<Vec<(String, Ext4Inode)>, String> {
+        self.dir_entries(dir)?
+            .into_iter()
+            .map(|(name, number)| Ok((name, self.inode(number)?)))
+            .collect()
+    }
+
+    fn read_data(&mut self, inode: &Ext4Inode, out: &mut dyn Write) -> Result<u64, String> {
+        let size = inode.size();
+
+        if inode.flags() & INODE_FLAG_INLINE_DATA != 0 || self.is_fast_symlink(inode) {
+            let (mut data, rest) = self.inline_parts(inode)?;
+            data.extend_from_slice(&rest);
+
+            if data.len() < size as usize {
+                return Err(format!("Inode {}: inline data is truncated", inode.number));
+            }
+
+            out.write_all(&data[..size as usize]).map_err(|e| e.to_string())?;
+            return Ok(size);
+        }
+
+        let mut written = 0;
+
+        for run in self.runs(inode)? {
+            let start = (run.logical * self.block_size).min(size);
+            let len = (run.len * self.block_size).min(size - start);
+
+            if start < written {
+                return Err(format!("Inode {}: overlapping extents", inode.number));
+            }
+
+            fsimage::write_zeros(out, start - written)?;
+
+            if run.unwritten {
+                fsimage::write_zeros(out, len)?;
+            } else {
+                fsimage::copy_range(&mut self.reader, run.physical * self.block_size, len, out)?;
+            }
+
+            written = start + len;
+        }
+
+        fsimage::write_zeros(out, size - written)?;
+        Ok(size)
+    }
+}
+
+/* ================= HELPERS ================= */
+
+/// Linear scan of directory records; htree index blocks look like one
+/// empty record and are skipped the same way.
+fn parse_dirents(data: &[u8], names: &mut Vec<(String, u32)>) -> Result<(), String> {
+    let mut pos = 0;
+
+    while pos + 8 <= data.len() {
+        let inode = le32(data, pos);
+        let rec_len = usize::from(le16(data, pos + 4));
+        let name_len = usize::from(data[pos + 6]);
+
+        if rec_len < 8 || pos + rec_len > data.len() {
+            return Err(format!("Corrupt ext4 directory record at {}", pos));
+        }
+
+        if inode != 0 && 8 + name_len <= rec_len {
+            let name = String::from_utf8_lossy(&data[pos + 8..pos + 8 + name_len]).into_owned();
+            if name != "." && name != ".." {
+                names.push((name, inode));
+            }
+        }
+
+        pos += rec_len;
+    }
+
+    Ok(())
+}
+
+fn c_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+fn le16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const BLOCK: usize = 1024;
+    const INODE_SIZE: usize = 256;
+    const INODES_PER_GROUP: u32 = 32;
+    const INODE_TABLE: usize = 4;
+    const BLOCKS: usize = 34;
+
+    const ROOT_DIR: usize = 12;
+    const SMALL_DATA: usize = 13;
+    const BIG_LEAF: usize = 14;
+    const BIG_DATA: usize = 15;
+    const OLD_DATA: usize = 19;
+    const OLD_INDIRECT: usize = 33;
+
+    pub const BIG_SIZE: usize = 4 * BLOCK + 500;
+    pub const OLD_SIZE: usize = 14 * BLOCK - 10;
+    pub const INLINE_SIZE: usize = 70;
+
+    fn put16(data: &mut [u8], offset: usize, value: u16) {
+        data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn put32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn dirents(entries: &[(&str, u32)], len: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (i, (name, inode)) in entries.iter().enumerate() {
+            let rec_len = if i + 1 == entries.len() { len - data.len() } else { 8 + name.len() };
+            let start = data.len();
+            data.resize(start + rec_len, 0);
+            put32(&mut data, start, *inode);
+            put16(&mut data, start + 4, rec_len as u16);
+            data[start + 6] = name.len() as u8;
+            data[start + 8..start + 8 + name.len()].copy_from_slice(name.as_bytes());
+        }
+        data
+    }
+
+    fn extent_header(entries: u16, depth: u16) -> Vec<u8> {
+        let mut header = vec![0u8; 12];
+        put16(&mut header, 0, EXTENT_MAGIC);
+        put16(&mut header, 2, entries);
+        put16(&mut header, 4, 4);
+        put16(&mut header, 6, depth);
+        header
+    }
+
+    fn extent(logical: u32, len: u16, physical: u32) -> Vec<u8> {
+        let mut entry = vec![0u8; 12];
+        put32(&mut entry, 0, logical);
+        put16(&mut entry, 4, len);
+        put32(&mut entry, 8, physical);
+        entry
+    }
+
+    /// Bytes of block `n` of a data pattern
+    fn pattern(n: usize) -> Vec<u8> {
+        (0..BLOCK).map(|i| (n * 31 + i % 251) as u8).collect()
+    }
+
+    pub fn big_data() -> Vec<u8> {
+        let mut data = [pattern(0), pattern(1), vec![0; 2 * BLOCK], pattern(4)].concat();
+        data.truncate(BIG_SIZE);
+        data
+    }
+
+    pub fn old_data() -> Vec<u8> {
+        let mut data: Vec<u8> = (0..14)
+            .flat_map(|n| if n == 5 { vec![0; BLOCK] } else { pattern(100 + n) })
+            .collect();
+        data.truncate(OLD_SIZE);
+        data
+    }
+
+    pub fn inline_data() -> Vec<u8> {
+        (0..INLINE_SIZE as u8).collect()
+    }
+
+    /// Returns the inode's offset in the image
+    fn inode(
+        disk: &mut [u8],
+        number: usize,
+        mode: u16,
+        flags: u32,
+        size: usize,
+        i_block: &[u8],
+    ) -> usize {
+        let at = INODE_TABLE * BLOCK + (number - 1) * INODE_SIZE;
+        let raw = &mut disk[at..at + INODE_SIZE];
+        put16(raw, 0, mode);
+        put32(raw, 4, size as u32);
+        put32(raw, 32, flags);
+        raw[I_BLOCK..I_BLOCK + i_block.len()].copy_from_slice(i_block);
+        // i_extra_isize
+        put16(raw, 128, 32);
+        at
+    }
+
+    fn put_block(disk: &mut [u8], block: usize, data: &[u8]) {
+        disk[block * BLOCK..block * BLOCK + data.len()].copy_from_slice(data);
+    }
+
+    /// 1 KiB blocks, one group, 256-byte inodes:
+    ///
+    ///   /            extents    etc/ big.bin link loop inline old
+    ///   /etc         inline     small.txt
+    ///   /etc/small.txt          "hello", uid 1000
+    ///   /big.bin     depth-1 extent tree with a hole and an unwritten extent
+    ///   /link        fast symlink to etc/small.txt
+    ///   /loop        fast symlink to itself
+    ///   /inline      inline file continued in `system.data`
+    ///   /old         ext2 block map with a hole and an indirect block
+    pub fn image() -> Vec<u8> {
+        let mut disk = vec![0u8; BLOCKS * BLOCK];
+
+        let sb = SUPERBLOCK_OFFSET as usize;
+        put32(&mut disk, sb, INODES_PER_GROUP);
+        put32(&mut disk, sb + 4, BLOCKS as u32);
+        put32(&mut disk, sb + 20, 1);
+        put32(&mut disk, sb + 40, INODES_PER_GROUP);
+        put16(&mut disk, sb + 56, 0xEF53);
+        put32(&mut disk, sb + 76, 1);
+        put16(&mut disk, sb + 88, INODE_SIZE as u16);
+        disk[sb + 120..sb + 126].copy_from_slice(b"sysvol");
+
+        // Group descriptor in the block after the superblock
+        put32(&mut disk, 2 * BLOCK + 8, INODE_TABLE as u32);
+
+        let root = [extent_header(1, 0), extent(0, 1, ROOT_DIR as u32)].concat();
+        inode(&mut disk, 2, 0o40755, INODE_FLAG_EXTENTS, BLOCK, &root);
+
+        let etc = [vec![2, 0, 0, 0], dirents(&[("small.txt", 16)], I_BLOCK_LEN - 4)].concat();
+        inode(&mut disk, 11, 0o40755, INODE_FLAG_INLINE_DATA, I_BLOCK_LEN, &etc);
+
+        let small = [extent_header(1, 0), extent(0, 1, SMALL_DATA as u32)].concat();
+        let at = inode(&mut disk, 16, 0o100644, INODE_FLAG_EXTENTS, 5, &small);
+        put16(&mut disk, at + 2, 1000);
+
+        let mut index = extent_header(1, 1);
+        index.extend(0u32.to_le_bytes());
+        index.extend((BIG_LEAF as u32).to_le_bytes());
+        index.extend([0u8; 4]);
+        inode(&mut disk, 12, 0o100644, INODE_FLAG_EXTENTS, BIG_SIZE, &index);
+
+        let leaf = [
+            extent_header(3, 0),
+            extent(0, 2, BIG_DATA as u32),
+            extent(3, EXTENT_INIT_MAX_LEN + 1, BIG_DATA as u32 + 2),
+            extent(4, 1, BIG_DATA as u32 + 3),
+        ]
+        .concat();
+
+        inode(&mut disk, 13, 0o120777, 0, 13, b"etc/small.txt");
+        inode(&mut disk, 17, 0o120777, 0, 5, b"/loop");
+
+        let inline = inline_data();
+        let head = &inline[..I_BLOCK_LEN];
+        let at = inode(&mut disk, 14, 0o100644, INODE_FLAG_INLINE_DATA, INLINE_SIZE, head);
+        // In-inode xattrs after i_extra_isize: magic, then entries
+        let first = at + GOOD_OLD_INODE_SIZE + 32 + 4;
+        put32(&mut disk, first - 4, XATTR_MAGIC);
+        disk[first] = 4;
+        disk[first + 1] = XATTR_INDEX_SYSTEM;
+        put16(&mut disk, first + 2, 40);
+        put32(&mut disk, first + 8, (INLINE_SIZE - I_BLOCK_LEN) as u32);
+        disk[first + 16..first + 20].copy_from_slice(b"data");
+        disk[first + 40..first + 40 + INLINE_SIZE - I_BLOCK_LEN]
+            .copy_from_slice(&inline[I_BLOCK_LEN..]);
+
+        let mut map = Vec::new();
+        for i in 0..12 {
+            let block = if i == 5 { 0 } else { OLD_DATA + i };
+            map.extend((block as u32).to_le_bytes());
+        }
+        map.extend((OLD_INDIRECT as u32).to_le_bytes());
+        inode(&mut disk, 15, 0o100644, 0, OLD_SIZE, &map);
+
+        put_block(
+            &mut disk,
+            ROOT_DIR,
+            &dirents(
+                &[
+                    (".", 2),
+                    ("..", 2),
+                    ("etc", 11),
+                    ("big.bin", 12),
+                    ("link", 13),
+                    ("loop", 17),
+                    ("inline", 14),
+                    ("old", 15),
+                ],
+                BLOCK,
+            ),
+        );
+        put_block(&mut disk, SMALL_DATA, b"hello");
+        put_block(&mut disk, BIG_LEAF, &leaf);
+        put_block(&mut disk, BIG_DATA, &pattern(0));
+        put_block(&mut disk, BIG_DATA + 1, &pattern(1));
+        put_block(&mut disk, BIG_DATA + 2, &pattern(99));
+        put_block(&mut disk, BIG_DATA + 3, &pattern(4));
+        for i in (0..12).filter(|&i| i != 5) {
+            put_block(&mut disk, OLD_DATA + i, &pattern(100 + i));
+        }
+        let indirect = [OLD_DATA + 12, OLD_DATA + 13].map(|block| (block as u32).to_le_bytes());
+        put_block(&mut disk, OLD_INDIRECT, &indirect.concat());
+        put_block(&mut disk, OLD_DATA + 12, &pattern(112));
+        put_block(&mut disk, OLD_DATA + 13, &pattern(113));
+
+        disk
+    }
+
+    fn open(disk: Vec<u8>) -> Result<Ext4, String> {
+        Ext4::new(Box::new(Cursor::new(disk)))
+    }
+
+    fn child(fs: &mut Ext4, dir: &Ext4Inode, name: &str) -> Ext4Inode {
+        let entries = fs.read_dir(dir).unwrap();
+        entries.into_iter().find(|(n, _)| n == name).unwrap().1
+    }
+
+    fn contents(fs: &mut Ext4, inode: &Ext4Inode) -> Vec<u8> {
+        let mut data = Vec::new();
+        let written = fs.read_data(inode, &mut data).unwrap();
+        assert_eq!(written as usize, data.len());
+        data
+    }
+
+    #[test]
+    fn lists_directories() {
+        let mut fs = open(image()).unwrap();
+        assert_eq!(fs.volume_name.as_deref(), Some("sysvol"));
+
+        let root = fs.root().unwrap();
+        let names: Vec<_> = fs.read_dir(&root).unwrap().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(names, ["etc", "big.bin", "link", "loop", "inline", "old"]);
+
+        // Inline directory
+        let etc = child(&mut fs, &root, "etc");
+        let small = child(&mut fs, &etc, "small.txt");
+        let info = fs.info(&small);
+        assert_eq!((info.mode, info.uid, info.size), (0o100644, 1000, 5));
+        assert_eq!(contents(&mut fs, &small), b"hello");
+    }
+
+    #[test]
+    fn reads_extents_block_maps_and_inline_data() {
+        let mut fs = open(image()).unwrap();
+        let root = fs.root().unwrap();
+
+        let big = child(&mut fs, &root, "big.bin");
+        assert_eq!(contents(&mut fs, &big), big_data());
+
+        let old = child(&mut fs, &root, "old");
+        assert_eq!(contents(&mut fs, &old), old_data());
+
+        let inline = child(&mut fs, &root, "inline");
+        assert_eq!(contents(&mut fs, &inline), inline_data());
+
+        let link = child(&mut fs, &root, "link");
+        assert_eq!(contents(&mut fs, &link), b"etc/small.txt");
+    }
+
+    #[test]
+    fn rejects_bad_superblocks_and_extents() {
+        let mut disk = image();
+        disk[SUPERBLOCK_OFFSET as usize + 56] = 0;
+        assert!(open(disk).err().unwrap().contains("bad superblock magic"));
+
+        let mut disk = image();
+        put32(&mut disk, SUPERBLOCK_OFFSET as usize + 96, INCOMPAT_META_BG);
+        assert!(open(disk).err().unwrap().contains("meta_bg"));
+
+        let mut disk = image();
+        put16(&mut disk, SUPERBLOCK_OFFSET as usize + 88, 100);
+        assert!(open(disk).err().unwrap().contains("inode size"));
+
+        // Leaf claiming to be an index node one level down
+        let mut disk = image();
+        put16(&mut disk, BIG_LEAF * BLOCK + 6, 1);
+        let mut fs = open(disk).unwrap();
+        let root = fs.root().unwrap();
+        let big = child(&mut fs, &root, "big.bin");
+        let err = fs.read_data(&big, &mut Vec::new()).unwrap_err();
+        assert!(err.contains("Corrupt ext4 extent tree"), "{}", err);
+
+        assert!(fs.inode(0).is_err() && fs.inode(INODES_PER_GROUP + 1).is_err());
+    }
+}
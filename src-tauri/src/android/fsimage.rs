@@ -0,0 +1,362 @@
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{BufWriter, Read, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::android::sparse::{self, ReadSeek};
+use crate::android::{erofs::Erofs, ext4::Ext4};
+
+// Read-only browsing of filesystem images (system, vendor, product...)
+// without mounting them. Raw and sparse images are accepted; both
+// filesystems sit behind `Filesystem` so path lookup, listing and
+// extraction are shared.
+
+const SUPERBLOCK_OFFSET: u64 = 1024;
+const EXT4_MAGIC: u16 = 0xEF53;
+const EROFS_MAGIC: u32 = 0xE0F5_E1E2;
+/// Symlinks followed while resolving one path
+const MAX_SYMLINKS: usize = 16;
+
+const S_IFMT: u16 = 0o170000;
+const S_IFDIR: u16 = 0o040000;
+const S_IFREG: u16 = 0o100000;
+const S_IFLNK: u16 = 0o120000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FsEntry {
+    pub name: String,
+    /// "file", "dir", "symlink" or "other"
+    pub kind: &'static str,
+    /// Permission bits
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub link_target: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FsListing {
+    pub filesystem: &'static str,
+    pub volume_name: Option<String>,
+    pub path: String,
+    pub entries: Vec<FsEntry>,
+}
+
+/// What the shared code needs to know about an inode.
+pub struct NodeInfo {
+    pub mode: u16,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+}
+
+impl NodeInfo {
+    fn kind(&self) -> &'static str {
+        match self.mode & S_IFMT {
+            S_IFDIR => "dir",
+            S_IFREG => "file",
+            S_IFLNK => "symlink",
+            _ => "other",
+        }
+    }
+}
+
+pub trait Filesystem {
+    type Inode: Clone;
+
+    fn root(&mut self) -> Result<Self::Inode, String>;
+    fn info(&self, inode: &Self::Inode) -> NodeInfo;
+    /// Children of a directory, without "." and "..".
+    fn read_dir(&mut self, dir: &Self::Inode) -> Result<Vec<(String, Self::Inode)>, String>;
+    /// Stream the file (or symlink target) contents. Returns bytes written.
+    fn read_data(&mut self, inode: &Self::Inode, out: &mut dyn Write) -> Result<u64, String>;
+}
+
+/* ================= PUBLIC API ================= */
+
+/// "ext4" or "erofs", from the superblock.
+pub fn detect(reader: &mut dyn ReadSeek) -> Option<&'static str> {
+    let superblock = read_at(reader, SUPERBLOCK_OFFSET, 128).ok()?;
+
+    if u32::from_le_bytes(superblock[..4].try_into().unwrap()) == EROFS_MAGIC {
+        Some("erofs")
+    } else if u16::from_le_bytes([superblock[56], superblock[57]]) == EXT4_MAGIC {
+        Some("ext4")
+    } else {
+        None
+    }
+}
+
+/// List directory `path` ("/" for the root) of an image.
+pub fn list_dir(image: &Path, path: &str) -> Result<FsListing, String> {
+    let mut reader = sparse::open_raw(image)?;
+
+    match detect(&mut reader) {
+        Some("ext4") => {
+            let mut fs = Ext4::new(reader)?;
+            let volume_name = fs.volume_name.clone();
+            listing(&mut fs, "ext4", volume_name, path)
+        }
+        Some("erofs") => {
+            let mut fs = Erofs::new(reader)?;
+            let volume_name = fs.volume_name.clone();
+            listing(&mut fs, "erofs", volume_name, path)
+        }
+        _ => Err(format!("{} is not an ext4 or EROFS image", image.display())),
+    }
+}
+
+/// Copy file `path` out of an image. Returns its size.
+pub fn extract_file(image: &Path, path: &str, out: &Path) -> Result<u64, String> {
+    let mut reader = sparse::open_raw(image)?;
+
+    match detect(&mut reader) {
+        Some("ext4") => extract(&mut Ext4::new(reader)?, path, out),
+        Some("erofs") => extract(&mut Erofs::new(reader)?, path, out),
+        _ => Err(format!("{} is not an ext4 or EROFS image", image.display())),
+    }
+}
+
+fn listing<F: Filesystem>(
+    fs: &mut F,
+    filesystem: &'static str,
+    volume_name: Option<String>,
+    path: &str,
+) -> Result<FsListing, String> {
+    let dir = lookup(fs, path)?;
+
+    if fs.info(&dir).kind() != "dir" {
+        return Err(format!("{} is not a directory", path));
+    }
+
+    let mut entries = Vec::new();
+
+    for (name, inode) in fs.read_dir(&dir)? {
+        let info = fs.info(&inode);
+
+        let link_target = if info.kind() == "symlink" {
+            Some(read_link(fs, &inode)?)
+        } else {
+            None
+        };
+
+        entries.push(FsEntry {
+            name,
+            kind: info.kind(),
+            mode: u32::from(info.mode & 0o7777),
+            uid: info.uid,
+            gid: info.gid,
+            size: info.size,
+            link_target,
+        });
+    }
+
+    entries.sort_by(|a, b| (a.kind != "dir", &a.name).cmp(&(b.kind != "dir", &b.name)));
+
+    Ok(FsListing { filesystem, volume_name, path: path.to_string(), entries })
+}
+
+fn extract<F: Filesystem>(fs: &mut F, path: &str, out: &Path) -> Result<u64, String> {
+    let inode = lookup(fs, path)?;
+
+    if fs.info(&inode).kind() != "file" {
+        return Err(format!("{} is not a regular file", path));
+    }
+
+    let file = File::create(out).map_err(|e| format!("{}: {}", out.display(), e))?;
+    let mut writer = BufWriter::new(file);
+
+    let written = fs.read_data(&inode, &mut writer)?;
+    writer.flush().map_err(|e| e.to_string())?;
+
+    Ok(written)
+}
+
+/// Resolve an absolute path, following symlinks that stay inside the
+/// image.
+fn lookup<F: Filesystem>(fs: &mut F, path: &str) -> Result<F::Inode, String> {
+    let mut pending: Vec<String> = components(path).rev().collect();
+    let mut parents = vec![fs.root()?];
+    let mut followed = 0;
+
+    while let Some(name) = pending.pop() {
+        let current = parents.last().unwrap().clone();
+
+        match name.as_str() {
+            "." => continue,
+            ".." => {
+                if parents.len() > 1 {
+                    parents.pop();
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let child = fs
+            .read_dir(&current)?
+            .into_iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, inode)| inode)
+            .ok_or_else(|| format!("{}: no such file or directory", path))?;
+
+        if fs.info(&child).kind() == "symlink" {
+            followed += 1;
+            if followed > MAX_SYMLINKS {
+                return Err(format!("{}: too many levels of symbolic links", path));
+            }
+
+            let target = read_link(fs, &child)?;
+            if target.starts_with('/') {
+                parents.truncate(1);
+            }
+            pending.extend(components(&target).rev());
+            continue;
+        }
+
+        parents.push(child);
+    }
+
+    Ok(parents.pop().unwrap())
+}
+
+fn components(path: &str) -> impl DoubleEndedIterator<Item = String> + '_ {
+    path.split('/').filter(|c| !c.is_empty()).map(String::from)
+}
+
+fn read_link<F: Filesystem>(fs: &mut F, inode: &F::Inode) -> Result<String, String> {
+    let mut target = Vec::new();
+    fs.read_data(inode, &mut target)?;
+    Ok(String::from_utf8_lossy(&target).into_owned())
+}
+
+/* ================= HELPERS ================= */
+
+pub fn read_at(reader: &mut dyn ReadSeek, offset: u64, len: usize) -> Result<Vec<u8>, String> {
+    let mut buf = vec![0u8; len];
+    reader.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+    reader
+        .read_exact(&mut buf)
+        .map_err(|_| format!("Read of {} bytes at {:#x} runs past end of image", len, offset))?;
+    Ok(buf)
+}
+
+/// Copy `len` bytes at `offset` to `out`.
+pub fn copy_range(
+    reader: &mut dyn ReadSeek,
+    offset: u64,
+    len: u64,
+    out: &mut dyn Write,
+) -> Result<(), String> {
+    reader.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+
+    let copied = std::io::copy(&mut reader.take(len), out).map_err(|e| e.to_string())?;
+    if copied != len {
+        return Err(format!("Data at {:#x} runs past end of image", offset));
+    }
+
+    Ok(())
+}
+
+/// Write `len` zero bytes (holes, unwritten extents).
+pub fn write_zeros(out: &mut dyn Write, len: u64) -> Result<(), String> {
+    std::io::copy(&mut std::io::repeat(0).take(len), out).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use crate::android::{erofs, ext4};
+    use std::{fs, path::PathBuf};
+
+    #[test]
+    fn lists_and_extracts_ext4() {
+        let dir = temp_dir("fsimage", "ext4");
+        let image = dir.join("system.img");
+        fs::write(&image, ext4::tests::image()).unwrap();
+
+        let root = list_dir(&image, "/").unwrap();
+        assert_eq!(root.filesystem, "ext4");
+        assert_eq!(root.volume_name.as_deref(), Some("sysvol"));
+
+        // Directories first, then by name
+        let names: Vec<_> = root.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["etc", "big.bin", "inline", "link", "loop", "old"]);
+        assert_eq!(root.entries[0].kind, "dir");
+        assert_eq!(root.entries[3].link_target.as_deref(), Some("etc/small.txt"));
+
+        let etc = list_dir(&image, "etc").unwrap();
+        assert_eq!((etc.entries[0].mode, etc.entries[0].uid), (0o644, 1000));
+
+        let out = dir.join("out");
+        let big = extract_file(&image, "/big.bin", &out).map(|_| fs::read(&out).unwrap());
+        let old = extract_file(&image, "/etc/../old", &out).map(|_| fs::read(&out).unwrap());
+        let link = extract_file(&image, "/link", &out).map(|_| fs::read(&out).unwrap());
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(big.unwrap(), ext4::tests::big_data());
+        assert_eq!(old.unwrap(), ext4::tests::old_data());
+        assert_eq!(link.unwrap(), b"hello");
+    }
+
+    #[test]
+    fn reports_bad_paths() {
+        let dir = temp_dir("fsimage", "paths");
+        let image = dir.join("system.img");
+        fs::write(&image, ext4::tests::image()).unwrap();
+        let out = dir.join("out");
+
+        let missing = list_dir(&image, "/nope");
+        let not_dir = list_dir(&image, "/big.bin");
+        let not_file = extract_file(&image, "/etc", &out);
+        let looped = extract_file(&image, "/loop", &out);
+        let not_fs = list_dir(&dir.join("out-missing"), "/");
+        fs::write(&out, vec![0u8; 4096]).unwrap();
+        let garbage = list_dir(&out, "/");
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(missing.unwrap_err().contains("no such file"));
+        assert!(not_dir.unwrap_err().contains("not a directory"));
+        assert!(not_file.unwrap_err().contains("not a regular file"));
+        assert!(looped.unwrap_err().contains("too many levels"));
+        assert!(not_fs.is_err());
+        assert!(garbage.unwrap_err().contains("not an ext4 or EROFS image"));
+    }
+
+    #[test]
+    fn lists_and_extracts_sparse_erofs() {
+        let dir = temp_dir("fsimage", "erofs");
+        let (data, files) = erofs::tests::image();
+        fs::write(dir.join("raw.img"), data).unwrap();
+        sparse::sparse_from_raw(&dir.join("raw.img"), &dir.join("vendor.img"), 4096).unwrap();
+        let image = dir.join("vendor.img");
+
+        let root = list_dir(&image, "/");
+        let up = list_dir(&image, "/link/..");
+        let out = dir.join("out");
+        let extracted: Vec<_> = files
+            .iter()
+            .map(|(path, _)| extract_file(&image, path, &out).map(|_| fs::read(&out).unwrap()))
+            .collect();
+        fs::remove_dir_all(&dir).ok();
+
+        let root = root.unwrap();
+        assert_eq!(root.filesystem, "erofs");
+        assert_eq!(root.volume_name.as_deref(), Some("vendor"));
+        assert_eq!(root.entries[0].name, "sub");
+        assert_eq!(root.entries[0].mode, 0o700);
+
+        // /link resolves to /sub/again, so ".." is /sub
+        assert_eq!(up.unwrap().entries.len(), 1);
+
+        for ((path, expected), data) in files.iter().zip(extracted) {
+            let expected = if *path == "/link" { &files[0].1 } else { expected };
+            assert!(data.unwrap() == *expected, "{}", path);
+        }
+    }
+}
@@ -0,0 +1,449 @@
+use serde::Serialize;
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::android::sparse;
+
+// GUID partition table, little-endian:
+//
+//   LBA 0       protective MBR
+//   LBA 1       primary header ("EFI PART", CRC32 of itself and of the
+//               entry array)
+//   LBA 2..     entry array (usually 128 × 128 bytes)
+//   ...
+//   last LBAs   backup entry array, then the backup header in the very
+//               last LBA
+//
+// eMMC uses 512-byte sectors, UFS 4096; both are tried. Dumps may hold
+// a whole disk or only its first sectors (`pgpt`), in which case the
+// backup copy is reported as not read.
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const SECTOR_SIZES: [u64; 2] = [512, 4096];
+const MIN_HEADER_SIZE: usize = 92;
+const MIN_ENTRY_SIZE: usize = 128;
+/// Sanity cap on the entry array (the usual array is 16 KiB)
+const MAX_ENTRIES_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GptTable {
+    pub sector_size: u64,
+    pub disk_guid: String,
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub primary: GptHeaderCheck,
+    pub backup: GptHeaderCheck,
+    /// Which copy `partitions` came from
+    pub source: &'static str,
+    pub partitions: Vec<GptPartition>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GptHeaderCheck {
+    pub lba: Option<u64>,
+    pub valid: bool,
+    /// Why it is not valid, or why it was not read
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GptPartition {
+    /// 1-based, as in `/dev/block/sdc12`
+    pub index: u32,
+    pub name: String,
+    pub type_guid: String,
+    pub unique_guid: String,
+    pub first_lba: u64,
+    /// Inclusive
+    pub last_lba: u64,
+    pub start: u64,
+    pub size: u64,
+    pub attributes: u64,
+}
+
+impl GptTable {
+    pub fn partition(&self, name: &str) -> Option<&GptPartition> {
+        self.partitions.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+}
+
+struct Header {
+    backup_lba: u64,
+    first_usable_lba: u64,
+    last_usable_lba: u64,
+    disk_guid: String,
+    entries_lba: u64,
+    entry_count: u32,
+    entry_size: u32,
+    entries_crc: u32,
+}
+
+/* ================= PARSE ================= */
+
+/// Parse the GPT of a disk of `size` bytes, reading through `read_at`
+/// (offset, length). The primary copy is preferred; the backup is used
+/// when the primary is damaged or absent.
+pub fn parse_gpt(
+    size: u64,
+    mut read_at: impl FnMut(u64, usize) -> Result<Vec<u8>, String>,
+) -> Result<GptTable, String> {
+    let mut last_error = String::from("No GPT header found");
+
+    for sector_size in SECTOR_SIZES {
+        let last_lba = (size / sector_size).saturating_sub(1);
+
+        let primary = read_copy(&mut read_at, sector_size, 1);
+
+        // The backup LBA comes from the primary header when it is usable;
+        // otherwise assume a whole-disk dump ending in it
+        let backup_lba = match &primary {
+            Ok((header, _)) => header.backup_lba,
+            Err(_) => last_lba,
+        };
+        let backup = if backup_lba > 1 && backup_lba <= last_lba {
+            Some(read_copy(&mut read_at, sector_size, backup_lba))
+        } else {
+            None
+        };
+
+        let primary_check = header_check(Some(1), &primary);
+        let mut backup_check = match &backup {
+            Some(result) => header_check(Some(backup_lba), result),
+            None => GptHeaderCheck {
+                lba: None,
+                valid: false,
+                detail: "Backup header is outside the data read".into(),
+            },
+        };
+
+        if let (Ok((p, _)), Some(Ok((b, _)))) = (&primary, &backup) {
+            if p.entries_crc != b.entries_crc {
+                backup_check.detail = "CRC32 OK, but the entries differ from the primary".into();
+            }
+        }
+
+        let (source, (header, partitions)) = match (primary, backup) {
+            (Ok(copy), _) => ("primary", copy),
+            (Err(_), Some(Ok(copy))) => ("backup", copy),
+            (Err(e), _) => {
+                last_error = e;
+                continue;
+            }
+        };
+
+        return Ok(GptTable {
+            sector_size,
+            disk_guid: header.disk_guid,
+            first_usable_lba: header.first_usable_lba,
+            last_usable_lba: header.last_usable_lba,
+            primary: primary_check,
+            backup: backup_check,
+            source,
+            partitions,
+        });
+    }
+
+    Err(last_error)
+}
+
+/// Parse a GPT from a disk dump or a `pgpt` region dump.
+pub fn read_gpt(path: &Path) -> Result<GptTable, String> {
+    let mut reader = sparse::open_raw(path)?;
+    let size = reader.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+
+    parse_gpt(size, |offset, len| {
+        let mut buf = vec![0u8; len];
+        reader.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+        reader
+            .read_exact(&mut buf)
+            .map_err(|_| format!("{}: truncated at offset {:#x}", path.display(), offset))?;
+        Ok(buf)
+    })
+}
+
+fn header_check<T>(lba: Option<u64>, result: &Result<T, String>) -> GptHeaderCheck {
+    GptHeaderCheck {
+        lba,
+        valid: result.is_ok(),
+        detail: match result {
+            Ok(_) => "CRC32 OK".into(),
+            Err(e) => e.clone(),
+        },
+    }
+}
+
+/// One header and its entry array, both CRC-checked.
+fn read_copy(
+    read_at: &mut impl FnMut(u64, usize) -> Result<Vec<u8>, String>,
+    sector_size: u64,
+    lba: u64,
+) -> Result<(Header, Vec<GptPartition>), String> {
+    let raw = read_at(lba * sector_size, sector_size as usize)?;
+    let header = parse_header(&raw, lba)?;
+
+    let entries_len = u64::from(header.entry_count) * u64::from(header.entry_size);
+    if entries_len > MAX_ENTRIES_BYTES {
+        return Err(format!("Entry array of {} bytes is implausible", entries_len));
+    }
+
+    let entries_offset = header
+        .entries_lba
+        .checked_mul(sector_size)
+        .ok_or_else(|| format!("Entry array LBA {} is out of range", header.entries_lba))?;
+    let entries = read_at(entries_offset, entries_len as usize)?;
+    if crc32fast::hash(&entries) != header.entries_crc {
+        return Err(format!("Entry array CRC32 mismatch (LBA {})", header.entries_lba));
+    }
+
+    let partitions = entries
+        .chunks_exact(header.entry_size as usize)
+        .enumerate()
+        .filter(|(_, e)| e[..16].iter().any(|&b| b != 0))
+        .map(|(i, e)| parse_entry(i as u32 + 1, e, sector_size))
+        .collect();
+
+    Ok((header, partitions))
+}
+
+fn parse_header(raw: &[u8], lba: u64) -> Result<Header, String> {
+    if raw.len() < MIN_HEADER_SIZE || &raw[..8] != GPT_SIGNATURE {
+        return Err(format!("No GPT signature at LBA {}", lba));
+    }
+
+    let header_size = le32(raw, 12) as usize;
+    if header_size < MIN_HEADER_SIZE || header_size > raw.len() {
+        return Err(format!("Bad header size {} at LBA {}", header_size, lba));
+    }
+
+    let mut unsummed = raw[..header_size].to_vec();
+    unsummed[16..20].fill(0);
+    if crc32fast::hash(&unsummed) != le32(raw, 16) {
+        return Err(format!("Header CRC32 mismatch at LBA {}", lba));
+    }
+
+    if le64(raw, 24) != lba {
+        return Err(format!("Header at LBA {} claims to be at LBA {}", lba, le64(raw, 24)));
+    }
+
+    let entry_size = le32(raw, 84);
+    if (entry_size as usize) < MIN_ENTRY_SIZE {
+        return Err(format!("Bad entry size {}", entry_size));
+    }
+
+    Ok(Header {
+        backup_lba: le64(raw, 32),
+        first_usable_lba: le64(raw, 40),
+        last_usable_lba: le64(raw, 48),
+        disk_guid: guid(&raw[56..72]),
+        entries_lba: le64(raw, 72),
+        entry_count: le32(raw, 80),
+        entry_size,
+        entries_crc: le32(raw, 88),
+    })
+}
+
+fn parse_entry(index: u32, raw: &[u8], sector_size: u64) -> GptPartition {
+    let first_lba = le64(raw, 32);
+    let last_lba = le64(raw, 40);
+
+    let name: Vec<u16> = raw[56..128]
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&c| c != 0)
+        .collect();
+
+    GptPartition {
+        index,
+        name: String::from_utf16_lossy(&name),
+        type_guid: guid(&raw[..16]),
+        unique_guid: guid(&raw[16..32]),
+        first_lba,
+        last_lba,
+        start: first_lba.saturating_mul(sector_size),
+        size: last_lba.saturating_add(1).saturating_sub(first_lba).saturating_mul(sector_size),
+        attributes: le64(raw, 48),
+    }
+}
+
+/* ================= HELPERS ================= */
+
+/// Mixed-endian GUID text form: the first three fields are little-endian.
+fn guid(raw: &[u8]) -> String {
+    format!(
+        "{:08X}-{:04X}-{:04X}-{}-{}",
+        le32(raw, 0),
+        u16::from_le_bytes([raw[4], raw[5]]),
+        u16::from_le_bytes([raw[6], raw[7]]),
+        hex(&raw[8..10]),
+        hex(&raw[10..16])
+    )
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn le64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const ENTRY_COUNT: usize = 128;
+    const ENTRY_SIZE: usize = 128;
+    /// Microsoft basic data
+    const TYPE_GUID: [u8; 16] = [
+        0xA2, 0xA0, 0xD0, 0xEB, 0xE5, 0xB9, 0x33, 0x44, 0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26,
+        0x99, 0xC7,
+    ];
+
+    fn header(current: u64, other: u64, entries_lba: u64, last: u64, entries_crc: u32) -> Vec<u8> {
+        let entry_sectors = (ENTRY_COUNT * ENTRY_SIZE) as u64 / 512;
+        let mut header = vec![0u8; MIN_HEADER_SIZE];
+        header[..8].copy_from_slice(GPT_SIGNATURE);
+        header[8..12].copy_from_slice(&0x10000u32.to_le_bytes());
+        header[12..16].copy_from_slice(&(MIN_HEADER_SIZE as u32).to_le_bytes());
+        header[24..32].copy_from_slice(&current.to_le_bytes());
+        header[32..40].copy_from_slice(&other.to_le_bytes());
+        header[40..48].copy_from_slice(&(2 + entry_sectors).to_le_bytes());
+        header[48..56].copy_from_slice(&(last - 1 - entry_sectors).to_le_bytes());
+        header[56..72].copy_from_slice(&[0x11; 16]);
+        header[72..80].copy_from_slice(&entries_lba.to_le_bytes());
+        header[80..84].copy_from_slice(&(ENTRY_COUNT as u32).to_le_bytes());
+        header[84..88].copy_from_slice(&(ENTRY_SIZE as u32).to_le_bytes());
+        header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+
+        let crc = crc32fast::hash(&header);
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+        header
+    }
+
+    /// Whole disk of `sectors` with primary and backup tables holding
+    /// `partitions` (name, first LBA, last LBA).
+    pub fn disk(sector_size: usize, sectors: usize, partitions: &[(&str, u64, u64)]) -> Vec<u8> {
+        let mut entries = vec![0u8; ENTRY_COUNT * ENTRY_SIZE];
+        for (i, (name, first, last)) in partitions.iter().enumerate() {
+            let entry = &mut entries[i * ENTRY_SIZE..(i + 1) * ENTRY_SIZE];
+            entry[..16].copy_from_slice(&TYPE_GUID);
+            entry[16] = i as u8 + 1;
+            entry[32..40].copy_from_slice(&first.to_le_bytes());
+            entry[40..48].copy_from_slice(&last.to_le_bytes());
+            entry[48..56].copy_from_slice(&(1u64 << 60).to_le_bytes());
+            for (j, c) in name.encode_utf16().enumerate() {
+                entry[56 + j * 2..58 + j * 2].copy_from_slice(&c.to_le_bytes());
+            }
+        }
+
+        let crc = crc32fast::hash(&entries);
+        let last = sectors as u64 - 1;
+        let backup_entries = last - entries.len().div_ceil(sector_size) as u64;
+
+        let mut disk = vec![0u8; sector_size * sectors];
+        let mut put = |lba: u64, data: &[u8]| {
+            let at = lba as usize * sector_size;
+            disk[at..at + data.len()].copy_from_slice(data);
+        };
+        put(1, &header(1, last, 2, last, crc));
+        put(2, &entries);
+        put(backup_entries, &entries);
+        put(last, &header(last, 1, backup_entries, last, crc));
+        disk
+    }
+
+    fn parse(data: &[u8]) -> Result<GptTable, String> {
+        parse_gpt(data.len() as u64, |offset, len| {
+            offset
+                .checked_add(len as u64)
+                .and_then(|end| data.get(offset as usize..end as usize))
+                .map(|slice| slice.to_vec())
+                .ok_or_else(|| format!("truncated at offset {:#x}", offset))
+        })
+    }
+
+    #[test]
+    fn parses_both_sector_sizes() {
+        for sector_size in SECTOR_SIZES {
+            let mib = 1024 * 1024 / sector_size;
+            let sectors = 8 * mib;
+            let image = disk(
+                sector_size as usize,
+                sectors as usize,
+                &[("boot_a", mib, 2 * mib - 1), ("userdata", 2 * mib, sectors - 40)],
+            );
+
+            let table = parse(&image).unwrap();
+            assert_eq!(table.sector_size, sector_size);
+            assert_eq!(table.source, "primary");
+            assert!(table.primary.valid && table.backup.valid, "{:?}", table.backup);
+            assert_eq!(table.backup.lba, Some(sectors - 1));
+            assert_eq!(table.disk_guid, "11111111-1111-1111-1111-111111111111");
+
+            assert_eq!(table.partitions.len(), 2);
+            let boot = table.partition("BOOT_A").unwrap();
+            assert_eq!((boot.index, boot.start, boot.size), (1, 1024 * 1024, 1024 * 1024));
+            assert_eq!(boot.type_guid, "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7");
+            assert_eq!(boot.attributes, 1 << 60);
+        }
+    }
+
+    #[test]
+    fn primary_only_dump_reports_backup_unread() {
+        let image = disk(512, 16384, &[("boot_a", 2048, 4095)]);
+
+        let table = parse(&image[..34 * 512]).unwrap();
+        assert!(table.primary.valid);
+        assert!(!table.backup.valid && table.backup.lba.is_none());
+        assert_eq!(table.partitions.len(), 1);
+    }
+
+    #[test]
+    fn falls_back_to_backup_copy() {
+        let mut image = disk(512, 16384, &[("boot_a", 2048, 4095), ("super", 4096, 8191)]);
+        image[2 * 512 + 60] ^= 1;
+
+        let table = parse(&image).unwrap();
+        assert_eq!(table.source, "backup");
+        assert!(!table.primary.valid && table.backup.valid);
+        assert!(table.primary.detail.contains("Entry array CRC32"), "{}", table.primary.detail);
+        assert_eq!(table.partitions[1].name, "super");
+    }
+
+    #[test]
+    fn reads_dump_file() {
+        let image = disk(4096, 2048, &[("boot_a", 256, 511)]);
+        let path = std::env::temp_dir().join(format!("gpt-{}-disk.img", std::process::id()));
+        std::fs::write(&path, &image).unwrap();
+
+        let table = read_gpt(&path);
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(table.unwrap().partitions[0].start, 256 * 4096);
+    }
+
+    #[test]
+    fn rejects_damaged_headers() {
+        // 4096-byte sectors are tried last, so their error is the one returned
+        let mut image = disk(4096, 2048, &[("boot_a", 256, 511)]);
+        image[4096 + 40] ^= 1;
+        image[2047 * 4096 + 40] ^= 1;
+        assert!(parse(&image).unwrap_err().contains("Header CRC32 mismatch"));
+
+        assert!(parse(&[0u8; 64 * 1024]).unwrap_err().contains("No GPT signature"));
+
+        // A header pointing its entries at an LBA that overflows the offset
+        let mut image = disk(512, 16384, &[("boot_a", 2048, 4095)]);
+        let bad = header(1, 16383, u64::MAX, 16383, 0);
+        image[512..512 + bad.len()].copy_from_slice(&bad);
+        let table = parse(&image).unwrap();
+        assert_eq!(table.source, "backup");
+        assert!(table.primary.detail.contains("out of range"), "{}", table.primary.detail);
+    }
+}
@@ -0,0 +1,687 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use crate::android::sparse;
+use crate::snapshot::DeviceSnapshot;
+
+// Dynamic partition metadata (liblp), little-endian, at the start of
+// `super`:
+//
+//   0x0000  reserved (4 KiB)
+//   0x1000  geometry, then its backup at 0x2000 (4 KiB each)
+//   0x3000  metadata slot 0..n, then the backup copies of every slot
+//
+// Each metadata slot is a header followed by four tables: partitions,
+// extents, groups and block devices. Extents address 512-byte sectors
+// of a block device; only single-device super images are extracted.
+
+const RESERVED_BYTES: u64 = 4096;
+const GEOMETRY_SIZE: u64 = 4096;
+const GEOMETRY_MAGIC: u32 = 0x616C_4467;
+const HEADER_MAGIC: u32 = 0x414C_5030;
+const HEADER_MAJOR: u16 = 10;
+const SECTOR_SIZE: u64 = 512;
+/// Version 10.0 header; 10.2 adds flags and padding up to 256 bytes
+const HEADER_V0_SIZE: usize = 128;
+/// Far above any real layout (64 KiB is usual); bounds the metadata read
+const MAX_METADATA_SIZE: u32 = 1024 * 1024;
+/// Devices use 1 to 3 (Virtual A/B); anything larger is corruption
+const MAX_METADATA_SLOTS: u32 = 16;
+
+const ATTR_READONLY: u32 = 1 << 0;
+const ATTR_SLOT_SUFFIXED: u32 = 1 << 1;
+const ATTR_UPDATED: u32 = 1 << 2;
+const ATTR_DISABLED: u32 = 1 << 3;
+
+const TARGET_LINEAR: u32 = 0;
+const TARGET_ZERO: u32 = 1;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LpGeometry {
+    pub metadata_max_size: u32,
+    pub metadata_slot_count: u32,
+    pub logical_block_size: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LpExtent {
+    pub num_sectors: u64,
+    /// "linear" or "zero"
+    pub target: &'static str,
+    /// First sector on the block device (linear only)
+    pub target_data: u64,
+    pub block_device: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LpPartition {
+    pub name: String,
+    pub group: String,
+    pub attributes: Vec<&'static str>,
+    pub size: u64,
+    pub extents: Vec<LpExtent>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LpGroup {
+    pub name: String,
+    pub flags: u32,
+    /// 0 means unlimited
+    pub maximum_size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LpBlockDevice {
+    pub name: String,
+    pub first_logical_sector: u64,
+    pub alignment: u32,
+    pub alignment_offset: u32,
+    pub size: u64,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LpMetadata {
+    pub version: String,
+    pub slot: u32,
+    /// Read from the backup copy because the primary failed its checksum
+    pub from_backup: bool,
+    pub geometry: LpGeometry,
+    pub header_flags: u32,
+    pub partitions: Vec<LpPartition>,
+    pub groups: Vec<LpGroup>,
+    pub block_devices: Vec<LpBlockDevice>,
+}
+
+impl LpMetadata {
+    pub fn partition(&self, name: &str) -> Option<&LpPartition> {
+        self.partitions.iter().find(|p| p.name == name)
+    }
+}
+
+/* ================= PARSE ================= */
+
+/// Metadata of `slot` (0 for the `_a` / unslotted copy).
+pub fn parse_super<R: Read + Seek>(reader: &mut R, slot: u32) -> Result<LpMetadata, String> {
+    let geometry = [RESERVED_BYTES, RESERVED_BYTES + GEOMETRY_SIZE]
+        .iter()
+        .find_map(|&offset| read_geometry(reader, offset).ok())
+        .ok_or("No valid liblp geometry (not a super image?)")?;
+
+    if slot >= geometry.metadata_slot_count {
+        return Err(format!(
+            "Metadata slot {} out of range ({} slots)",
+            slot, geometry.metadata_slot_count
+        ));
+    }
+
+    let max = u64::from(geometry.metadata_max_size);
+    let base = RESERVED_BYTES + 2 * GEOMETRY_SIZE;
+    let primary = base + u64::from(slot) * max;
+    let backup = base + u64::from(geometry.metadata_slot_count) * max + u64::from(slot) * max;
+
+    let mut metadata = match read_metadata(reader, primary, &geometry) {
+        Ok(metadata) => metadata,
+        Err(primary_err) => {
+            let mut metadata = read_metadata(reader, backup, &geometry)
+                .map_err(|_| format!("Metadata slot {}: {}", slot, primary_err))?;
+            metadata.from_backup = true;
+            metadata
+        }
+    };
+
+    metadata.slot = slot;
+    Ok(metadata)
+}
+
+/// True if `data` reaches the primary geometry and it carries the magic.
+pub fn is_super(data: &[u8]) -> bool {
+    let offset = RESERVED_BYTES as usize;
+    data.len() >= offset + 4 && le32(data, offset) == GEOMETRY_MAGIC
+}
+
+/// Parse a super image file, raw or sparse.
+pub fn read_super(path: &Path, slot: u32) -> Result<LpMetadata, String> {
+    let mut reader = sparse::open_raw(path)?;
+    parse_super(&mut reader, slot)
+}
+
+fn read_geometry<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<LpGeometry, String> {
+    let mut raw = [0u8; 52];
+    reader.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+    reader.read_exact(&mut raw).map_err(|e| e.to_string())?;
+
+    if le32(&raw, 0) != GEOMETRY_MAGIC {
+        return Err("Bad geometry magic".into());
+    }
+    if le32(&raw, 4) as usize != raw.len() {
+        return Err(format!("Unexpected geometry size {}", le32(&raw, 4)));
+    }
+
+    let mut unsummed = raw;
+    unsummed[8..40].fill(0);
+    if Sha256::digest(unsummed)[..] != raw[8..40] {
+        return Err("Geometry checksum mismatch".into());
+    }
+
+    let geometry = LpGeometry {
+        metadata_max_size: le32(&raw, 40),
+        metadata_slot_count: le32(&raw, 44),
+        logical_block_size: le32(&raw, 48),
+    };
+
+    // Both sizes drive allocations and offsets, so a checksummed but
+    // hostile geometry must not reach read_metadata
+    let max_size = geometry.metadata_max_size;
+    if max_size < HEADER_V0_SIZE as u32
+        || max_size > MAX_METADATA_SIZE
+        || !max_size.is_multiple_of(SECTOR_SIZE as u32)
+    {
+        return Err(format!("Metadata max size {} out of range", max_size));
+    }
+    if geometry.metadata_slot_count == 0 || geometry.metadata_slot_count > MAX_METADATA_SLOTS {
+        return Err(format!("Metadata slot count {} out of range", geometry.metadata_slot_count));
+    }
+
+    Ok(geometry)
+}
+
+fn read_metadata<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+    geometry: &LpGeometry,
+) -> Result<LpMetadata, String> {
+    let mut data = vec![0u8; geometry.metadata_max_size as usize];
+    reader.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+    reader.read_exact(&mut data).map_err(|e| e.to_string())?;
+
+    if data.len() < HEADER_V0_SIZE || le32(&data, 0) != HEADER_MAGIC {
+        return Err("Bad metadata header magic".into());
+    }
+
+    let major = le16(&data, 4);
+    let minor = le16(&data, 6);
+    let header_size = le32(&data, 8) as usize;
+    let tables_size = le32(&data, 44) as usize;
+
+    if major != HEADER_MAJOR {
+        return Err(format!("Unsupported metadata version {}.{}", major, minor));
+    }
+    if header_size < HEADER_V0_SIZE || header_size + tables_size > data.len() {
+        return Err("Metadata header sizes out of range".into());
+    }
+
+    let mut header = data[..header_size].to_vec();
+    header[12..44].fill(0);
+    if Sha256::digest(&header)[..] != data[12..44] {
+        return Err("Metadata header checksum mismatch".into());
+    }
+
+    let tables = &data[header_size..header_size + tables_size];
+    if Sha256::digest(tables)[..] != data[48..80] {
+        return Err("Metadata tables checksum mismatch".into());
+    }
+
+    let extents: Vec<LpExtent> = table(&data, 92, 24, tables)?
+        .map(|e| LpExtent {
+            num_sectors: le64(e, 0),
+            target: match le32(e, 8) {
+                TARGET_LINEAR => "linear",
+                TARGET_ZERO => "zero",
+                _ => "unknown",
+            },
+            target_data: le64(e, 12),
+            block_device: le32(e, 20),
+        })
+        .collect();
+
+    let groups: Vec<LpGroup> = table(&data, 104, 48, tables)?
+        .map(|g| LpGroup {
+            name: c_string(&g[..36]),
+            flags: le32(g, 36),
+            maximum_size: le64(g, 40),
+        })
+        .collect();
+
+    let block_devices: Vec<LpBlockDevice> = table(&data, 116, 64, tables)?
+        .map(|b| LpBlockDevice {
+            first_logical_sector: le64(b, 0),
+            alignment: le32(b, 8),
+            alignment_offset: le32(b, 12),
+            size: le64(b, 16),
+            name: c_string(&b[24..60]),
+            flags: le32(b, 60),
+        })
+        .collect();
+
+    let mut partitions = Vec::new();
+
+    for p in table(&data, 80, 52, tables)? {
+        let name = c_string(&p[..36]);
+        let first = le32(p, 40) as usize;
+        let count = le32(p, 44) as usize;
+
+        let own = extents
+            .get(first..first + count)
+            .ok_or_else(|| format!("Partition {} has extents out of range", name))?
+            .to_vec();
+
+        partitions.push(LpPartition {
+            group: groups
+                .get(le32(p, 48) as usize)
+                .map(|g| g.name.clone())
+                .unwrap_or_default(),
+            attributes: attribute_names(le32(p, 36)),
+            size: own.iter().map(|e| e.num_sectors * SECTOR_SIZE).sum(),
+            extents: own,
+            name,
+        });
+    }
+
+    Ok(LpMetadata {
+        version: format!("{}.{}", major, minor),
+        slot: 0,
+        from_backup: false,
+        geometry: geometry.clone(),
+        header_flags: if header_size >= 132 { le32(&data, 128) } else { 0 },
+        partitions,
+        groups,
+        block_devices,
+    })
+}
+
+/// Entries of the table whose descriptor (offset, count, entry size)
+/// sits at `descriptor` in the header. Newer versions may grow entries
+/// past `min_entry_size`; the extra bytes are ignored.
+fn table<'a>(
+    header: &[u8],
+    descriptor: usize,
+    min_entry_size: usize,
+    tables: &'a [u8],
+) -> Result<impl Iterator<Item = &'a [u8]>, String> {
+    let offset = le32(header, descriptor) as usize;
+    let count = le32(header, descriptor + 4) as usize;
+    let entry_size = le32(header, descriptor + 8) as usize;
+
+    let end = count
+        .checked_mul(entry_size)
+        .and_then(|len| len.checked_add(offset))
+        .filter(|&end| end <= tables.len() && entry_size >= min_entry_size)
+        .ok_or("Metadata table out of range")?;
+
+    Ok(tables[offset..end].chunks_exact(entry_size))
+}
+
+fn attribute_names(attributes: u32) -> Vec<&'static str> {
+    [
+        (ATTR_READONLY, "readonly"),
+        (ATTR_SLOT_SUFFIXED, "slot-suffixed"),
+        (ATTR_UPDATED, "updated"),
+        (ATTR_DISABLED, "disabled"),
+    ]
+    .iter()
+    .filter(|(bit, _)| attributes & bit != 0)
+    .map(|(_, name)| *name)
+    .collect()
+}
+
+/* ================= EXTRACT ================= */
+
+/// Write logical partition `name` from a super image (raw or sparse) to
+/// `out` as a raw image. Returns the bytes written.
+pub fn extract_partition(
+    super_image: &Path,
+    name: &str,
+    slot: u32,
+    out: &Path,
+) -> Result<u64, String> {
+    let mut reader = sparse::open_raw(super_image)?;
+    let metadata = parse_super(&mut reader, slot)?;
+
+    let partition = metadata
+        .partition(name)
+        .ok_or_else(|| format!("No logical partition {} in metadata slot {}", name, slot))?;
+
+    let file = File::create(out).map_err(|e| format!("{}: {}", out.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    let mut written = 0u64;
+
+    for extent in &partition.extents {
+        let len = extent.num_sectors * SECTOR_SIZE;
+
+        match extent.target {
+            "linear" if extent.block_device == 0 => {
+                reader
+                    .seek(SeekFrom::Start(extent.target_data * SECTOR_SIZE))
+                    .map_err(|e| e.to_string())?;
+
+                let copied = io::copy(&mut (&mut reader).take(len), &mut writer)
+                    .map_err(|e| e.to_string())?;
+                if copied != len {
+                    return Err(format!("{}: extent runs past end of super image", name));
+                }
+            }
+            "linear" => {
+                return Err(format!(
+                    "{} has extents on block device {}; only the first is in this image",
+                    name, extent.block_device
+                ));
+            }
+            "zero" => {
+                io::copy(&mut io::repeat(0).take(len), &mut writer).map_err(|e| e.to_string())?;
+            }
+            other => return Err(format!("{}: unsupported extent target {}", name, other)),
+        }
+
+        written += len;
+    }
+
+    writer.flush().map_err(|e| e.to_string())?;
+    Ok(written)
+}
+
+/* ================= DEVICE CROSS-CHECK ================= */
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogicalCheck {
+    pub partition: String,
+    pub in_image: bool,
+    /// The device's `is-logical:` answer, if it gave one
+    pub device_logical: Option<bool>,
+    pub consistent: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SuperReport {
+    pub path: String,
+    pub metadata: LpMetadata,
+    /// Empty when no device reported `is-logical:` getvars
+    pub device_check: Vec<LogicalCheck>,
+}
+
+/// Compare the image's logical partitions with the device's
+/// `is-logical:<partition>` getvars.
+pub fn cross_check(metadata: &LpMetadata, snapshot: &DeviceSnapshot) -> Vec<LogicalCheck> {
+    let device_logical = |name: &str| {
+        snapshot.getvar(&format!("is-logical:{}", name)).map(|v| v == "yes")
+    };
+
+    let mut checks: Vec<LogicalCheck> = metadata
+        .partitions
+        .iter()
+        .map(|p| {
+            let device = device_logical(&p.name);
+            LogicalCheck {
+                partition: p.name.clone(),
+                in_image: true,
+                device_logical: device,
+                consistent: device,
+            }
+        })
+        .collect();
+
+    // Logical on the device but absent from the image
+    for key in snapshot.getvars.keys() {
+        let Some(name) = key.strip_prefix("is-logical:") else {
+            continue;
+        };
+
+        if device_logical(name) == Some(true) && metadata.partition(name).is_none() {
+            checks.push(LogicalCheck {
+                partition: name.to_string(),
+                in_image: false,
+                device_logical: Some(true),
+                consistent: Some(false),
+            });
+        }
+    }
+
+    checks
+}
+
+/* ================= HELPERS ================= */
+
+fn c_string(raw: &[u8]) -> String {
+    let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..end]).into_owned()
+}
+
+fn le16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn le64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection_service::DeviceState;
+    use std::{fs, io::Cursor};
+
+    const MAX_METADATA: u32 = 65536;
+    const SLOTS: u32 = 2;
+    const SYSTEM_SECTOR: usize = 2048;
+    const VENDOR_SECTOR: usize = 4096;
+
+    fn named(name: &str, len: usize) -> Vec<u8> {
+        let mut field = vec![0u8; len];
+        field[..name.len()].copy_from_slice(name.as_bytes());
+        field
+    }
+
+    fn partition(name: &str, attributes: u32, first_extent: u32, extents: u32) -> Vec<u8> {
+        let mut entry = named(name, 36);
+        for value in [attributes, first_extent, extents, 1] {
+            entry.extend(value.to_le_bytes());
+        }
+        entry
+    }
+
+    fn geometry() -> Vec<u8> {
+        geometry_with(MAX_METADATA, SLOTS)
+    }
+
+    fn geometry_with(max_size: u32, slots: u32) -> Vec<u8> {
+        let mut geometry = vec![0u8; 52];
+        geometry[0..4].copy_from_slice(&GEOMETRY_MAGIC.to_le_bytes());
+        geometry[4..8].copy_from_slice(&52u32.to_le_bytes());
+        geometry[40..44].copy_from_slice(&max_size.to_le_bytes());
+        geometry[44..48].copy_from_slice(&slots.to_le_bytes());
+        geometry[48..52].copy_from_slice(&4096u32.to_le_bytes());
+
+        let sum = Sha256::digest(&geometry);
+        geometry[8..40].copy_from_slice(&sum);
+        geometry
+    }
+
+    /// v10.2 metadata: system_a (linear + zero extent), vendor_a (linear)
+    fn metadata() -> Vec<u8> {
+        let partitions =
+            [partition("system_a", ATTR_READONLY, 0, 2), partition("vendor_a", 0, 2, 1)];
+
+        let mut extents = Vec::new();
+        for (sectors, target, data) in
+            [(8u64, TARGET_LINEAR, SYSTEM_SECTOR as u64), (4, TARGET_ZERO, 0), (16, 0, 4096)]
+        {
+            extents.extend(sectors.to_le_bytes());
+            extents.extend(target.to_le_bytes());
+            extents.extend(data.to_le_bytes());
+            extents.extend(0u32.to_le_bytes());
+        }
+
+        let mut groups = Vec::new();
+        for group in ["default", "main_a"] {
+            groups.extend(named(group, 36));
+            groups.extend([0u8; 12]);
+        }
+
+        let mut device = 2048u64.to_le_bytes().to_vec();
+        device.extend([0u8; 8]);
+        device.extend((4u64 << 20).to_le_bytes());
+        device.extend(named("super", 36));
+        device.extend(0u32.to_le_bytes());
+
+        let mut header = vec![0u8; 256];
+        header[0..4].copy_from_slice(&HEADER_MAGIC.to_le_bytes());
+        header[4..6].copy_from_slice(&HEADER_MAJOR.to_le_bytes());
+        header[6..8].copy_from_slice(&2u16.to_le_bytes());
+        header[8..12].copy_from_slice(&256u32.to_le_bytes());
+
+        let mut tables = Vec::new();
+        let all = [(partitions.concat(), 52u32), (extents, 24), (groups, 48), (device, 64)];
+        for (i, (table, entry_size)) in all.iter().enumerate() {
+            let descriptor = 80 + i * 12;
+            let values = [tables.len() as u32, table.len() as u32 / entry_size, *entry_size];
+            for (j, value) in values.into_iter().enumerate() {
+                header[descriptor + j * 4..descriptor + j * 4 + 4]
+                    .copy_from_slice(&value.to_le_bytes());
+            }
+            tables.extend(table);
+        }
+
+        header[44..48].copy_from_slice(&(tables.len() as u32).to_le_bytes());
+        let tables_sum = Sha256::digest(&tables);
+        header[48..80].copy_from_slice(&tables_sum);
+        let header_sum = Sha256::digest(&header);
+        header[12..44].copy_from_slice(&header_sum);
+
+        [header, tables].concat()
+    }
+
+    fn super_image() -> Vec<u8> {
+        let mut image = vec![0u8; 4 << 20];
+        let geometry = geometry();
+        image[4096..4096 + geometry.len()].copy_from_slice(&geometry);
+        image[8192..8192 + geometry.len()].copy_from_slice(&geometry);
+
+        let metadata = metadata();
+        for copy in 0..SLOTS * 2 {
+            let offset = 12288 + (copy * MAX_METADATA) as usize;
+            image[offset..offset + metadata.len()].copy_from_slice(&metadata);
+        }
+
+        for i in 0..8 * 512 {
+            image[SYSTEM_SECTOR * 512 + i] = (i % 251) as u8;
+        }
+        for i in 0..16 * 512 {
+            image[VENDOR_SECTOR * 512 + i] = (i % 13) as u8 + 1;
+        }
+        image
+    }
+
+    #[test]
+    fn parses_partitions_groups_and_devices() {
+        let image = super_image();
+        assert!(is_super(&image));
+
+        let metadata = parse_super(&mut Cursor::new(&image), 0).unwrap();
+        assert!(!metadata.from_backup);
+        assert_eq!(metadata.version, "10.2");
+        assert_eq!(metadata.geometry.metadata_slot_count, SLOTS);
+
+        let system = metadata.partition("system_a").unwrap();
+        assert_eq!(system.size, 12 * 512);
+        assert_eq!(system.group, "main_a");
+        assert_eq!(system.attributes, ["readonly"]);
+        assert_eq!(system.extents[1].target, "zero");
+        assert_eq!(metadata.block_devices[0].name, "super");
+
+        assert!(parse_super(&mut Cursor::new(&image), SLOTS).is_err());
+    }
+
+    #[test]
+    fn falls_back_to_backup_metadata() {
+        let mut image = super_image();
+        image[12288 + 300] ^= 0xFF;
+        image[4096 + 50] ^= 0xFF;
+
+        let metadata = parse_super(&mut Cursor::new(&image), 0).unwrap();
+        assert!(metadata.from_backup);
+        assert!(!parse_super(&mut Cursor::new(&image), 1).unwrap().from_backup);
+
+        image[8192 + 50] ^= 0xFF;
+        assert!(parse_super(&mut Cursor::new(&image), 0).is_err());
+    }
+
+    #[test]
+    fn rejects_out_of_range_geometry() {
+        let cases = [
+            (u32::MAX, SLOTS),
+            (MAX_METADATA + 1, SLOTS),
+            (0, SLOTS),
+            (MAX_METADATA, 0),
+            (MAX_METADATA, u32::MAX),
+        ];
+
+        for (max_size, slots) in cases {
+            let geometry = geometry_with(max_size, slots);
+            let mut raw = vec![0u8; 8192];
+            raw[4096..4096 + geometry.len()].copy_from_slice(&geometry);
+
+            assert!(read_geometry(&mut Cursor::new(&raw), 4096).is_err(), "{} {}", max_size, slots);
+            assert!(parse_super(&mut Cursor::new(&raw), 0).is_err());
+        }
+    }
+
+    #[test]
+    fn extracts_from_raw_and_sparse_images() {
+        let dir = std::env::temp_dir().join(format!("lp-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let image = super_image();
+        fs::write(dir.join("super.img"), &image).unwrap();
+        sparse::sparse_from_raw(&dir.join("super.img"), &dir.join("super.simg"), 4096).unwrap();
+
+        let mut results = Vec::new();
+        for source in ["super.img", "super.simg"] {
+            let system = extract_partition(&dir.join(source), "system_a", 0, &dir.join("sys.img"));
+            let vendor = extract_partition(&dir.join(source), "vendor_a", 0, &dir.join("ven.img"));
+            let missing = extract_partition(&dir.join(source), "odm_a", 0, &dir.join("odm.img"));
+            results.push((
+                system.map(|n| (n, fs::read(dir.join("sys.img")).unwrap())),
+                vendor.map(|n| (n, fs::read(dir.join("ven.img")).unwrap())),
+                missing,
+            ));
+        }
+        fs::remove_dir_all(&dir).ok();
+
+        for (system, vendor, missing) in results {
+            let (written, system) = system.unwrap();
+            assert_eq!(written, 12 * 512);
+            assert_eq!(system[..4096], image[SYSTEM_SECTOR * 512..SYSTEM_SECTOR * 512 + 4096]);
+            assert!(system[4096..].iter().all(|&b| b == 0));
+
+            let (_, vendor) = vendor.unwrap();
+            assert_eq!(vendor, image[VENDOR_SECTOR * 512..VENDOR_SECTOR * 512 + 8192]);
+            assert!(missing.is_err());
+        }
+    }
+
+    #[test]
+    fn cross_checks_device_logical_partitions() {
+        let metadata = parse_super(&mut Cursor::new(super_image()), 0).unwrap();
+
+        let mut snapshot = DeviceSnapshot::empty(DeviceState::Fastboot);
+        for (name, value) in [("system_a", "yes"), ("vendor_a", "no"), ("product_a", "yes")] {
+            snapshot.getvars.insert(format!("is-logical:{}", name), value.into());
+        }
+
+        let checks = cross_check(&metadata, &snapshot);
+        assert_eq!(checks.len(), 3);
+        assert_eq!(checks[0].consistent, Some(true));
+        assert_eq!(checks[1].consistent, Some(false));
+        assert_eq!(checks[2].partition, "product_a");
+        assert!(!checks[2].in_image);
+    }
+}
@@ -0,0 +1,799 @@
+use bzip2::read::BzDecoder;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+use xz2::read::XzDecoder;
+use zip::{CompressionMethod, ZipArchive};
+
+// A/B OTA payload (update_engine), big-endian header:
+//
+//   "CrAU" | u64 version | u64 manifest size | u32 metadata signature size
+//   | manifest (protobuf DeltaArchiveManifest) | metadata signature
+//   | data blobs referenced by operation offset/length
+//
+// Full OTAs only write new data (REPLACE*, ZERO, DISCARD) and can be
+// applied to an empty file. Delta OTAs read the source partitions and
+// are reported, not applied.
+
+const PAYLOAD_MAGIC: &[u8; 4] = b"CrAU";
+/// The metadata signature size was added in version 2
+const HEADER_V1_SIZE: u64 = 20;
+const HEADER_V2_SIZE: u64 = 24;
+const MAX_MANIFEST_SIZE: u64 = 64 * 1024 * 1024;
+const DEFAULT_BLOCK_SIZE: u32 = 4096;
+
+const OP_REPLACE: u32 = 0;
+const OP_REPLACE_BZ: u32 = 1;
+const OP_ZERO: u32 = 6;
+const OP_DISCARD: u32 = 7;
+const OP_REPLACE_XZ: u32 = 8;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Payload {
+    pub path: String,
+    pub version: u64,
+    pub block_size: u32,
+    /// 0 for full payloads
+    pub minor_version: u32,
+    pub max_timestamp: i64,
+    pub partial_update: bool,
+    pub security_patch_level: Option<String>,
+    pub partitions: Vec<PayloadPartition>,
+    /// Absolute offset of the data blobs in the file
+    #[serde(skip)]
+    data_offset: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PayloadPartition {
+    pub name: String,
+    pub size: u64,
+    pub sha256: Option<String>,
+    /// Count of operations by type
+    pub operations: BTreeMap<&'static str, usize>,
+    /// Delta operation types we cannot apply without the source partition
+    pub unsupported: Vec<&'static str>,
+    #[serde(skip)]
+    ops: Vec<Operation>,
+}
+
+impl PayloadPartition {
+    /// Extractable without the source build
+    pub fn is_full(&self) -> bool {
+        self.unsupported.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Operation {
+    kind: u32,
+    data_offset: u64,
+    data_length: u64,
+    /// (start block, block count)
+    dst_extents: Vec<(u64, u64)>,
+    data_sha256: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractedPartition {
+    pub name: String,
+    pub path: String,
+    pub size: u64,
+    pub operations: usize,
+    pub sha256: String,
+    /// Whole-image hash matched the manifest (false if it carried none)
+    pub verified: bool,
+}
+
+fn operation_name(kind: u32) -> &'static str {
+    match kind {
+        OP_REPLACE => "REPLACE",
+        OP_REPLACE_BZ => "REPLACE_BZ",
+        2 => "MOVE",
+        3 => "BSDIFF",
+        4 => "SOURCE_COPY",
+        5 => "SOURCE_BSDIFF",
+        OP_ZERO => "ZERO",
+        OP_DISCARD => "DISCARD",
+        OP_REPLACE_XZ => "REPLACE_XZ",
+        9 => "PUFFDIFF",
+        10 => "BROTLI_BSDIFF",
+        11 => "ZUCCHINI",
+        12 => "LZ4DIFF_BSDIFF",
+        13 => "LZ4DIFF_PUFFDIFF",
+        _ => "UNKNOWN",
+    }
+}
+
+fn is_supported(kind: u32) -> bool {
+    matches!(kind, OP_REPLACE | OP_REPLACE_BZ | OP_REPLACE_XZ | OP_ZERO | OP_DISCARD)
+}
+
+/* ================= PARSE ================= */
+
+/// Parse `payload.bin`, or the one stored in an OTA zip.
+pub fn read_payload(path: &Path) -> Result<Payload, String> {
+    let (mut file, base) = open_payload(path)?;
+
+    let mut header = [0u8; HEADER_V2_SIZE as usize];
+    file.seek(SeekFrom::Start(base)).map_err(|e| e.to_string())?;
+    file.read_exact(&mut header).map_err(|_| "File too small for a payload header")?;
+
+    if &header[..4] != PAYLOAD_MAGIC {
+        return Err("Not an OTA payload (missing CrAU magic)".into());
+    }
+
+    let version = be64(&header, 4);
+    let manifest_size = be64(&header, 12);
+
+    let (header_size, signature_size) = match version {
+        1 => (HEADER_V1_SIZE, 0),
+        2 => (HEADER_V2_SIZE, u64::from(be32(&header, 20))),
+        other => return Err(format!("Unsupported payload version {}", other)),
+    };
+
+    if manifest_size > MAX_MANIFEST_SIZE {
+        return Err(format!("Manifest size {} is implausible", manifest_size));
+    }
+
+    let mut manifest = vec![0u8; manifest_size as usize];
+    file.seek(SeekFrom::Start(base + header_size)).map_err(|e| e.to_string())?;
+    file.read_exact(&mut manifest).map_err(|_| "Payload manifest is truncated")?;
+
+    let mut payload = parse_manifest(&manifest)?;
+    payload.path = path.display().to_string();
+    payload.version = version;
+    payload.data_offset = base + header_size + manifest_size + signature_size;
+
+    Ok(payload)
+}
+
+/// The payload file and the offset its header starts at. OTA zips keep
+/// payload.bin stored, so it is read in place.
+fn open_payload(path: &Path) -> Result<(File, u64), String> {
+    let mut file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic).map_err(|_| "File too small for a payload")?;
+
+    if &magic != b"PK\x03\x04" {
+        return Ok((file, 0));
+    }
+
+    let reader = BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+    let mut archive = ZipArchive::new(reader).map_err(|e| e.to_string())?;
+    let entry = archive.by_name("payload.bin").map_err(|_| "No payload.bin in this zip")?;
+
+    if entry.compression() != CompressionMethod::Stored {
+        return Err("payload.bin is compressed inside the zip; extract it first".into());
+    }
+
+    Ok((file, entry.data_start()))
+}
+
+fn parse_manifest(data: &[u8]) -> Result<Payload, String> {
+    let mut payload = Payload {
+        path: String::new(),
+        version: 0,
+        block_size: DEFAULT_BLOCK_SIZE,
+        minor_version: 0,
+        max_timestamp: 0,
+        partial_update: false,
+        security_patch_level: None,
+        partitions: Vec::new(),
+        data_offset: 0,
+    };
+
+    let mut fields = Proto::new(data);
+
+    while let Some((number, value)) = fields.next_field()? {
+        match (number, value) {
+            (3, Field::Varint(v)) => payload.block_size = v as u32,
+            (12, Field::Varint(v)) => payload.minor_version = v as u32,
+            (13, Field::Bytes(b)) => payload.partitions.push(parse_partition(b)?),
+            (14, Field::Varint(v)) => payload.max_timestamp = v as i64,
+            (16, Field::Varint(v)) => payload.partial_update = v != 0,
+            (18, Field::Bytes(b)) => {
+                payload.security_patch_level = Some(String::from_utf8_lossy(b).into_owned())
+            }
+            _ => {}
+        }
+    }
+
+    if payload.partitions.is_empty() {
+        return Err("Manifest lists no partitions (pre-A/B payload?)".into());
+    }
+
+    Ok(payload)
+}
+
+fn parse_partition(data: &[u8]) -> Result<PayloadPartition, String> {
+    let mut partition = PayloadPartition {
+        name: String::new(),
+        size: 0,
+        sha256: None,
+        operations: BTreeMap::new(),
+        unsupported: Vec::new(),
+        ops: Vec::new(),
+    };
+
+    let mut fields = Proto::new(data);
+
+    while let Some((number, value)) = fields.next_field()? {
+        match (number, value) {
+            (1, Field::Bytes(b)) => partition.name = String::from_utf8_lossy(b).into_owned(),
+            // new_partition_info
+            (7, Field::Bytes(b)) => {
+                let mut info = Proto::new(b);
+                while let Some((number, value)) = info.next_field()? {
+                    match (number, value) {
+                        (1, Field::Varint(v)) => partition.size = v,
+                        (2, Field::Bytes(h)) => partition.sha256 = Some(hex(h)),
+                        _ => {}
+                    }
+                }
+            }
+            (8, Field::Bytes(b)) => partition.ops.push(parse_operation(b)?),
+            _ => {}
+        }
+    }
+
+    for op in &partition.ops {
+        let name = operation_name(op.kind);
+        *partition.operations.entry(name).or_default() += 1;
+
+        if !is_supported(op.kind) && !partition.unsupported.contains(&name) {
+            partition.unsupported.push(name);
+        }
+    }
+
+    Ok(partition)
+}
+
+fn parse_operation(data: &[u8]) -> Result<Operation, String> {
+    let mut op = Operation {
+        kind: 0,
+        data_offset: 0,
+        data_length: 0,
+        dst_extents: Vec::new(),
+        data_sha256: None,
+    };
+
+    let mut fields = Proto::new(data);
+
+    while let Some((number, value)) = fields.next_field()? {
+        match (number, value) {
+            (1, Field::Varint(v)) => op.kind = v as u32,
+            (2, Field::Varint(v)) => op.data_offset = v,
+            (3, Field::Varint(v)) => op.data_length = v,
+            (6, Field::Bytes(b)) => {
+                let (mut start, mut blocks) = (0, 0);
+                let mut extent = Proto::new(b);
+                while let Some((number, value)) = extent.next_field()? {
+                    match (number, value) {
+                        (1, Field::Varint(v)) => start = v,
+                        (2, Field::Varint(v)) => blocks = v,
+                        _ => {}
+                    }
+                }
+                op.dst_extents.push((start, blocks));
+            }
+            (8, Field::Bytes(b)) => op.data_sha256 = Some(b.to_vec()),
+            _ => {}
+        }
+    }
+
+    Ok(op)
+}
+
+/* ================= EXTRACT ================= */
+
+/// Apply the operations of each named partition to `<out_dir>/<name>.img`.
+/// Operation data is streamed and checked against its SHA-256 as it is
+/// written, and the finished image against the manifest hash; a partial
+/// image is removed on any error.
+pub fn extract_partitions(
+    payload: &Payload,
+    names: &[String],
+    out_dir: &Path,
+    mut progress: impl FnMut(&str),
+) -> Result<Vec<ExtractedPartition>, String> {
+    let mut selected = Vec::new();
+
+    for name in names {
+        // The name comes from the manifest and becomes a file name
+        if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+            return Err(format!("Refusing to extract unsafe partition name {:?}", name));
+        }
+
+        let partition = payload
+            .partitions
+            .iter()
+            .find(|p| &p.name == name)
+            .ok_or_else(|| format!("No partition {} in payload", name))?;
+
+        if !partition.is_full() {
+            return Err(format!(
+                "{} is a delta update ({}); it needs the source build and cannot be extracted",
+                name,
+                partition.unsupported.join(", ")
+            ));
+        }
+
+        selected.push(partition);
+    }
+
+    let (mut source, _) = open_payload(Path::new(&payload.path))?;
+    let source_len = source.metadata().map_err(|e| e.to_string())?.len();
+    fs::create_dir_all(out_dir).map_err(|e| e.to_string())?;
+
+    let mut extracted = Vec::new();
+
+    for partition in selected {
+        progress(&partition.name);
+
+        let out = out_dir.join(format!("{}.img", partition.name));
+        let result = extract_one(payload, partition, &mut source, source_len, &out);
+
+        if result.is_err() {
+            fs::remove_file(&out).ok();
+        }
+
+        extracted.push(result?);
+    }
+
+    Ok(extracted)
+}
+
+fn extract_one(
+    payload: &Payload,
+    partition: &PayloadPartition,
+    source: &mut File,
+    source_len: u64,
+    out: &Path,
+) -> Result<ExtractedPartition, String> {
+    let block_size = u64::from(payload.block_size);
+    let mut file = File::create(out).map_err(|e| format!("{}: {}", out.display(), e))?;
+
+    // Unwritten space reads back as zeros, which covers ZERO and DISCARD
+    file.set_len(partition.size).map_err(|e| e.to_string())?;
+
+    for (index, op) in partition.ops.iter().enumerate() {
+        if matches!(op.kind, OP_ZERO | OP_DISCARD) {
+            continue;
+        }
+
+        let context = || format!("{} operation {}", partition.name, index);
+
+        // Lengths come from the manifest; never read or allocate past the file
+        let start = payload
+            .data_offset
+            .checked_add(op.data_offset)
+            .filter(|start| start.checked_add(op.data_length).is_some_and(|end| end <= source_len))
+            .ok_or_else(|| format!("{}: data runs past end of payload", context()))?;
+        source.seek(SeekFrom::Start(start)).map_err(|e| e.to_string())?;
+
+        let mut blob = HashingReader {
+            inner: (&mut *source).take(op.data_length),
+            hasher: Sha256::new(),
+        };
+
+        {
+            let mut decoded: Box<dyn Read + '_> = match op.kind {
+                OP_REPLACE => Box::new(&mut blob),
+                OP_REPLACE_BZ => Box::new(BzDecoder::new(&mut blob)),
+                OP_REPLACE_XZ => Box::new(XzDecoder::new(&mut blob)),
+                other => unreachable!("{} filtered by is_full()", operation_name(other)),
+            };
+
+            for &(start, blocks) in &op.dst_extents {
+                let (offset, len) = start
+                    .checked_mul(block_size)
+                    .zip(blocks.checked_mul(block_size))
+                    .filter(|&(offset, len)| {
+                        offset.checked_add(len).is_some_and(|end| end <= partition.size)
+                    })
+                    .ok_or_else(|| format!("{}: extent outside the partition", context()))?;
+                file.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+
+                let written = io::copy(&mut (&mut decoded).take(len), &mut file)
+                    .map_err(|e| format!("{}: {}", context(), e))?;
+
+                if written != len {
+                    return Err(format!("{}: decoded data shorter than its extents", context()));
+                }
+            }
+        }
+
+        // Decoders may stop before the end of the blob; hash all of it
+        io::copy(&mut blob, &mut io::sink()).map_err(|e| e.to_string())?;
+
+        if blob.inner.limit() != 0 {
+            return Err(format!("{}: data runs past end of payload", context()));
+        }
+
+        if let Some(expected) = &op.data_sha256 {
+            if blob.hasher.finalize()[..] != expected[..] {
+                return Err(format!("{}: data SHA-256 mismatch", context()));
+            }
+        }
+    }
+
+    file.flush().map_err(|e| e.to_string())?;
+    drop(file);
+
+    let sha256 = sha256_file(out)?;
+    let verified = match &partition.sha256 {
+        Some(expected) if *expected != sha256 => {
+            return Err(format!("{}: image SHA-256 does not match the manifest", partition.name));
+        }
+        Some(_) => true,
+        None => false,
+    };
+
+    Ok(ExtractedPartition {
+        name: partition.name.clone(),
+        path: out.display().to_string(),
+        size: partition.size,
+        operations: partition.ops.len(),
+        sha256,
+        verified,
+    })
+}
+
+fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).map_err(|e| e.to_string())?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Hashes everything read through it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/* ================= PROTOBUF ================= */
+
+// Just enough of the wire format to walk the manifest: varints and
+// length-delimited fields are returned, fixed-width ones skipped.
+
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+struct Proto<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Proto<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn next_field(&mut self) -> Result<Option<(u32, Field<'a>)>, String> {
+        if self.pos >= self.data.len() {
+            return Ok(None);
+        }
+
+        let key = self.varint()?;
+        let number = (key >> 3) as u32;
+
+        let field = match key & 7 {
+            0 => Field::Varint(self.varint()?),
+            1 => self.skip(8)?,
+            2 => {
+                let len = self.varint()? as usize;
+                let end = self
+                    .pos
+                    .checked_add(len)
+                    .filter(|&end| end <= self.data.len())
+                    .ok_or("Manifest field runs past its end")?;
+                let bytes = &self.data[self.pos..end];
+                self.pos = end;
+                Field::Bytes(bytes)
+            }
+            5 => self.skip(4)?,
+            other => return Err(format!("Unsupported protobuf wire type {}", other)),
+        };
+
+        Ok(Some((number, field)))
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.pos).ok_or("Truncated varint in manifest")?;
+            self.pos += 1;
+            value |= u64::from(byte & 0x7F) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err("Varint too long in manifest".into())
+    }
+
+    fn skip(&mut self, len: usize) -> Result<Field<'a>, String> {
+        if self.pos + len > self.data.len() {
+            return Err("Truncated manifest".into());
+        }
+        self.pos += len;
+        Ok(Field::Fixed)
+    }
+}
+
+/* ================= HELPERS ================= */
+
+fn be32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn be64(data: &[u8], offset: usize) -> u64 {
+    u64::from_be_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::path::PathBuf;
+
+    const BLOCK: usize = 4096;
+
+    fn varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn field(out: &mut Vec<u8>, number: u64, value: u64) {
+        varint(out, number << 3);
+        varint(out, value);
+    }
+
+    fn bytes(out: &mut Vec<u8>, number: u64, value: &[u8]) {
+        varint(out, number << 3 | 2);
+        varint(out, value.len() as u64);
+        out.extend(value);
+    }
+
+    /// Appends `blob` to `blobs` and returns the encoded operation
+    fn operation(kind: u32, blob: &[u8], extents: &[(u64, u64)], blobs: &mut Vec<u8>) -> Vec<u8> {
+        let mut op = Vec::new();
+        field(&mut op, 1, kind.into());
+
+        if !blob.is_empty() {
+            field(&mut op, 2, blobs.len() as u64);
+            field(&mut op, 3, blob.len() as u64);
+            bytes(&mut op, 8, &Sha256::digest(blob));
+            blobs.extend(blob);
+        }
+
+        for &(start, blocks) in extents {
+            let mut extent = Vec::new();
+            field(&mut extent, 1, start);
+            field(&mut extent, 2, blocks);
+            bytes(&mut op, 6, &extent);
+        }
+
+        op
+    }
+
+    fn partition(name: &str, image: &[u8], ops: &[Vec<u8>]) -> Vec<u8> {
+        let mut info = Vec::new();
+        field(&mut info, 1, image.len() as u64);
+        bytes(&mut info, 2, &Sha256::digest(image));
+
+        let mut partition = Vec::new();
+        bytes(&mut partition, 1, name.as_bytes());
+        bytes(&mut partition, 7, &info);
+        for op in ops {
+            bytes(&mut partition, 8, op);
+        }
+        partition
+    }
+
+    fn payload(partitions: &[Vec<u8>], blobs: &[u8]) -> Vec<u8> {
+        let mut manifest = Vec::new();
+        field(&mut manifest, 3, BLOCK as u64);
+        for partition in partitions {
+            bytes(&mut manifest, 13, partition);
+        }
+        bytes(&mut manifest, 18, b"2026-09-05");
+
+        let signature = [0u8; 10];
+        let mut payload = PAYLOAD_MAGIC.to_vec();
+        payload.extend(2u64.to_be_bytes());
+        payload.extend((manifest.len() as u64).to_be_bytes());
+        payload.extend((signature.len() as u32).to_be_bytes());
+        payload.extend(manifest);
+        payload.extend(signature);
+        payload.extend(blobs);
+        payload
+    }
+
+    /// 8 blocks with a zero hole in block 2, written with every full
+    /// operation type, plus a delta `system` partition
+    pub fn full_payload() -> (Vec<u8>, Vec<u8>) {
+        let image: Vec<u8> = (0..BLOCK * 8)
+            .map(|i| if (2 * BLOCK..3 * BLOCK).contains(&i) { 0 } else { (i * 13 % 255) as u8 })
+            .collect();
+
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::best());
+        bz.write_all(&image[3 * BLOCK..5 * BLOCK]).unwrap();
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(&image[5 * BLOCK..]).unwrap();
+
+        let mut blobs = Vec::new();
+        let ops = [
+            operation(OP_REPLACE, &image[..2 * BLOCK], &[(0, 2)], &mut blobs),
+            operation(OP_ZERO, &[], &[(2, 1)], &mut blobs),
+            operation(OP_REPLACE_BZ, &bz.finish().unwrap(), &[(3, 1), (4, 1)], &mut blobs),
+            operation(OP_REPLACE_XZ, &xz.finish().unwrap(), &[(5, 3)], &mut blobs),
+        ];
+
+        // SOURCE_COPY
+        let mut delta = Vec::new();
+        field(&mut delta, 1, 4);
+
+        let partitions = [partition("boot", &image, &ops), partition("system", &[], &[delta])];
+        (payload(&partitions, &blobs), image)
+    }
+
+    fn stored_zip(path: &Path, payload: &[u8]) {
+        use zip::{write::FileOptions, ZipWriter};
+
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        zip.start_file("payload_properties.txt", FileOptions::default()).unwrap();
+        zip.write_all(b"FILE_HASH=x\n").unwrap();
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file("payload.bin", stored).unwrap();
+        zip.write_all(payload).unwrap();
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn parses_manifest() {
+        let dir = temp_dir("payload", "parse");
+        let (bin, _) = full_payload();
+        fs::write(dir.join("payload.bin"), &bin).unwrap();
+
+        let parsed = read_payload(&dir.join("payload.bin"));
+        fs::remove_dir_all(&dir).ok();
+        let parsed = parsed.unwrap();
+
+        assert_eq!(parsed.version, 2);
+        assert_eq!(parsed.block_size, BLOCK as u32);
+        assert_eq!(parsed.security_patch_level.as_deref(), Some("2026-09-05"));
+        assert_eq!(parsed.partitions.len(), 2);
+
+        let boot = &parsed.partitions[0];
+        assert_eq!(boot.size, 8 * BLOCK as u64);
+        assert!(boot.is_full());
+        assert_eq!(boot.operations.values().sum::<usize>(), 4);
+        assert_eq!(parsed.partitions[1].unsupported, vec!["SOURCE_COPY"]);
+    }
+
+    #[test]
+    fn extracts_from_bin_and_stored_zip() {
+        let dir = temp_dir("payload", "extract");
+        let (bin, image) = full_payload();
+        fs::write(dir.join("payload.bin"), &bin).unwrap();
+        stored_zip(&dir.join("ota.zip"), &bin);
+
+        for source in ["payload.bin", "ota.zip"] {
+            let parsed = read_payload(&dir.join(source)).unwrap();
+            let out = dir.join(format!("out-{}", source));
+
+            let extracted = extract_partitions(&parsed, &["boot".into()], &out, |_| {}).unwrap();
+            assert!(extracted[0].verified);
+            assert_eq!(fs::read(out.join("boot.img")).unwrap(), image, "{}", source);
+
+            let err = extract_partitions(&parsed, &["system".into()], &out, |_| {}).unwrap_err();
+            assert!(err.contains("SOURCE_COPY"), "{}", err);
+        }
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn corrupt_blob_removes_partial_image() {
+        let dir = temp_dir("payload", "corrupt");
+        let (mut bin, image) = full_payload();
+        // Inside the uncompressed REPLACE blob, so only its hash catches it
+        let at = bin.windows(64).position(|w| w == &image[BLOCK..BLOCK + 64]).unwrap();
+        bin[at] ^= 1;
+        fs::write(dir.join("payload.bin"), &bin).unwrap();
+
+        let parsed = read_payload(&dir.join("payload.bin")).unwrap();
+        let err = extract_partitions(&parsed, &["boot".into()], &dir.join("out"), |_| {});
+        let leftover = dir.join("out/boot.img").exists();
+        fs::remove_dir_all(&dir).ok();
+
+        let err = err.unwrap_err();
+        assert!(err.contains("operation 0") && err.contains("SHA-256"), "{}", err);
+        assert!(!leftover);
+    }
+
+    #[test]
+    fn rejects_unsafe_partition_names() {
+        let dir = temp_dir("payload", "names");
+        let image = vec![0x5Au8; BLOCK];
+        let mut blobs = Vec::new();
+        let partitions: Vec<_> = ["../boot", "a/b", "a\\b"]
+            .iter()
+            .map(|name| {
+                let op = operation(OP_REPLACE, &image, &[(0, 1)], &mut blobs);
+                partition(name, &image, &[op])
+            })
+            .collect();
+        fs::write(dir.join("payload.bin"), payload(&partitions, &blobs)).unwrap();
+
+        let parsed = read_payload(&dir.join("payload.bin")).unwrap();
+        let out = dir.join("out");
+        let results: Vec<_> = parsed
+            .partitions
+            .iter()
+            .map(|p| extract_partitions(&parsed, std::slice::from_ref(&p.name), &out, |_| {}))
+            .collect();
+        let escaped = dir.join("boot.img").exists();
+        fs::remove_dir_all(&dir).ok();
+
+        for result in results {
+            assert!(result.unwrap_err().contains("unsafe partition name"));
+        }
+        assert!(!escaped);
+    }
+
+    #[test]
+    fn rejects_data_and_extents_past_bounds() {
+        let dir = temp_dir("payload", "bounds");
+        let image = vec![0x5Au8; BLOCK];
+
+        // data_length claims far more than the file holds
+        let mut blobs = Vec::new();
+        let mut huge = operation(OP_REPLACE, &image, &[(0, 1)], &mut blobs);
+        field(&mut huge, 3, u64::MAX / 2);
+        // an extent past the end of the partition
+        let outside = operation(OP_REPLACE, &image, &[(1, 1)], &mut blobs);
+
+        let partitions =
+            [partition("huge", &image, &[huge]), partition("outside", &image, &[outside])];
+        fs::write(dir.join("payload.bin"), payload(&partitions, &blobs)).unwrap();
+
+        let parsed = read_payload(&dir.join("payload.bin")).unwrap();
+        let out = dir.join("out");
+        let huge = extract_partitions(&parsed, &["huge".into()], &out, |_| {});
+        let outside = extract_partitions(&parsed, &["outside".into()], &out, |_| {});
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(huge.unwrap_err().contains("runs past end of payload"));
+        assert!(outside.unwrap_err().contains("extent outside the partition"));
+    }
+}
@@ -0,0 +1,681 @@
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::Serialize;
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+// Boot / init_boot / vendor ramdisks are newc ("070701") cpio archives,
+// usually gzip or legacy-lz4 compressed. Vendor ramdisks may hold several
+// archives back to back; they are read as one entry list.
+//
+// Writing keeps every header field as read, and the hex digit case of
+// each archive (AOSP and Magisk write lower case, some tools upper), so
+// an unmodified archive serialises to the same bytes. Recompression is
+// not byte-identical; callers that changed nothing should keep the
+// original file.
+
+const NEWC_MAGIC: &[u8] = b"070701";
+const TRAILER: &str = "TRAILER!!!";
+const NEWC_HEADER_LEN: usize = 110;
+
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const LZ4_LEGACY_MAGIC: u32 = 0x184C_2102;
+const LZ4_FRAME_MAGIC: u32 = 0x184D_2204;
+/// Block size used by `lz4 -l`
+const LZ4_LEGACY_BLOCK: usize = 8 * 1024 * 1024;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum RamdiskCompression {
+    None,
+    Gzip,
+    Lz4Legacy,
+    Lz4Frame,
+}
+
+pub fn detect_compression(data: &[u8]) -> Option<RamdiskCompression> {
+    let magic = data.get(..4).map(|m| u32::from_le_bytes(m.try_into().unwrap()));
+
+    if data.starts_with(NEWC_MAGIC) {
+        Some(RamdiskCompression::None)
+    } else if data.starts_with(GZIP_MAGIC) {
+        Some(RamdiskCompression::Gzip)
+    } else if magic == Some(LZ4_LEGACY_MAGIC) {
+        Some(RamdiskCompression::Lz4Legacy)
+    } else if magic == Some(LZ4_FRAME_MAGIC) {
+        Some(RamdiskCompression::Lz4Frame)
+    } else {
+        None
+    }
+}
+
+pub fn decompress(data: &[u8]) -> Result<(RamdiskCompression, Vec<u8>), String> {
+    let compression = detect_compression(data).ok_or("Unknown ramdisk compression")?;
+    let mut out = Vec::new();
+
+    match compression {
+        RamdiskCompression::None => out.extend_from_slice(data),
+        RamdiskCompression::Gzip => {
+            // Single-member decoder: zero padding after the stream is ignored
+            GzDecoder::new(data)
+                .read_to_end(&mut out)
+                .map_err(|e| format!("gzip: {}", e))?;
+        }
+        RamdiskCompression::Lz4Legacy => out = lz4_legacy_decompress(data)?,
+        RamdiskCompression::Lz4Frame => {
+            lz4_flex::frame::FrameDecoder::new(data)
+                .read_to_end(&mut out)
+                .map_err(|e| format!("lz4: {}", e))?;
+        }
+    }
+
+    Ok((compression, out))
+}
+
+pub fn compress(data: &[u8], compression: RamdiskCompression) -> Result<Vec<u8>, String> {
+    match compression {
+        RamdiskCompression::None => Ok(data.to_vec()),
+        RamdiskCompression::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+            encoder.write_all(data).map_err(|e| e.to_string())?;
+            encoder.finish().map_err(|e| e.to_string())
+        }
+        RamdiskCompression::Lz4Legacy => {
+            let mut out = LZ4_LEGACY_MAGIC.to_le_bytes().to_vec();
+            for block in data.chunks(LZ4_LEGACY_BLOCK) {
+                let compressed = lz4_flex::block::compress(block);
+                out.extend((compressed.len() as u32).to_le_bytes());
+                out.extend(compressed);
+            }
+            Ok(out)
+        }
+        RamdiskCompression::Lz4Frame => {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+            encoder.write_all(data).map_err(|e| e.to_string())?;
+            encoder.finish().map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// `lz4 -l`: magic, then (u32 size, block) pairs until EOF or the next magic.
+/// Magisk appends the uncompressed size as a final u32 with no block.
+fn lz4_legacy_decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let mut pos = 4;
+
+    while pos + 4 <= data.len() {
+        let size = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap());
+        pos += 4;
+
+        // Concatenated streams restart with the magic; zero padding or a
+        // trailing size word ends it
+        if size == LZ4_LEGACY_MAGIC {
+            continue;
+        }
+        if size == 0 || pos == data.len() {
+            break;
+        }
+
+        let end = pos + size as usize;
+        let block = data.get(pos..end).ok_or("lz4: truncated block")?;
+
+        let decoded = lz4_flex::block::decompress(block, LZ4_LEGACY_BLOCK)
+            .map_err(|e| format!("lz4: {}", e))?;
+        out.extend(decoded);
+        pos = end;
+    }
+
+    Ok(out)
+}
+
+/* ================= CPIO ================= */
+
+#[derive(Debug, Clone)]
+pub struct CpioEntry {
+    pub name: String,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub nlink: u32,
+    pub mtime: u32,
+    pub dev_major: u32,
+    pub dev_minor: u32,
+    pub rdev_major: u32,
+    pub rdev_minor: u32,
+    pub check: u32,
+    pub data: Vec<u8>,
+}
+
+impl CpioEntry {
+    pub fn file(name: &str, mode: u32, data: Vec<u8>) -> Self {
+        Self {
+            name: name.to_string(),
+            ino: 0,
+            mode: S_IFREG | (mode & 0o7777),
+            uid: 0,
+            gid: 0,
+            nlink: 1,
+            mtime: 0,
+            dev_major: 0,
+            dev_minor: 0,
+            rdev_major: 0,
+            rdev_minor: 0,
+            check: 0,
+            data,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+}
+
+/// One or more concatenated archives. `segments` records where each
+/// archive's trailer falls so they are written back the same way.
+#[derive(Debug, Clone, Default)]
+pub struct Cpio {
+    pub entries: Vec<CpioEntry>,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone)]
+struct Segment {
+    /// Number of entries in this archive (trailer excluded)
+    entries: usize,
+    trailer: CpioEntry,
+    /// Zero bytes after the trailer
+    padding: usize,
+    /// Header fields were written with upper-case hex digits
+    uppercase: bool,
+}
+
+impl Cpio {
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let mut cpio = Cpio::default();
+        let mut pos = 0;
+        let mut segment_start = 0;
+        let mut uppercase = false;
+
+        while pos < data.len() {
+            if !data[pos..].starts_with(NEWC_MAGIC) {
+                return Err(format!("Bad cpio magic at {:#x}", pos));
+            }
+
+            let (entry, next) = read_entry(data, pos)?;
+            uppercase |= data[pos..pos + NEWC_HEADER_LEN].iter().any(|b| (b'A'..=b'F').contains(b));
+            pos = next;
+
+            if entry.name != TRAILER {
+                cpio.entries.push(entry);
+                continue;
+            }
+
+            let padding = data[pos..].iter().take_while(|&&b| b == 0).count();
+            pos += padding;
+
+            cpio.segments.push(Segment {
+                entries: cpio.entries.len() - segment_start,
+                trailer: entry,
+                padding,
+                uppercase,
+            });
+            segment_start = cpio.entries.len();
+            uppercase = false;
+        }
+
+        if cpio.segments.is_empty() {
+            return Err("cpio archive has no trailer".into());
+        }
+
+        Ok(cpio)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut entries = self.entries.iter();
+
+        let default_segment = [Segment {
+            entries: self.entries.len(),
+            trailer: trailer_entry(),
+            padding: 0,
+            uppercase: false,
+        }];
+        let segments = if self.segments.is_empty() { &default_segment[..] } else { &self.segments };
+
+        for (i, segment) in segments.iter().enumerate() {
+            // The last archive takes whatever entries remain (inserts included)
+            let count = if i + 1 == segments.len() { usize::MAX } else { segment.entries };
+
+            for entry in entries.by_ref().take(count) {
+                write_entry(&mut out, entry, segment.uppercase);
+            }
+
+            write_entry(&mut out, &segment.trailer, segment.uppercase);
+            out.resize(out.len() + segment.padding, 0);
+        }
+
+        out
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CpioEntry> {
+        let name = name.trim_start_matches('/');
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// Replace an existing entry's contents, or append a new regular file.
+    pub fn put_file(&mut self, name: &str, mode: u32, data: Vec<u8>) {
+        let name = name.trim_start_matches('/');
+
+        match self.entries.iter_mut().find(|e| e.name == name) {
+            Some(entry) => entry.data = data,
+            None => self.entries.push(CpioEntry::file(name, mode, data)),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let name = name.trim_start_matches('/');
+        let Some(index) = self.entries.iter().position(|e| e.name == name) else {
+            return false;
+        };
+
+        self.entries.remove(index);
+
+        // Keep segment boundaries pointing at the same archives
+        let mut start = 0;
+        for segment in &mut self.segments {
+            if index < start + segment.entries {
+                segment.entries -= 1;
+                break;
+            }
+            start += segment.entries;
+        }
+
+        true
+    }
+}
+
+fn read_entry(data: &[u8], pos: usize) -> Result<(CpioEntry, usize), String> {
+    let header = data
+        .get(pos..pos + NEWC_HEADER_LEN)
+        .ok_or("Truncated cpio header")?;
+
+    let field = |i: usize| -> Result<u32, String> {
+        let hex = std::str::from_utf8(&header[6 + i * 8..14 + i * 8])
+            .map_err(|_| format!("Bad cpio header at {:#x}", pos))?;
+        u32::from_str_radix(hex, 16).map_err(|_| format!("Bad cpio header field at {:#x}", pos))
+    };
+
+    let file_size = field(6)? as usize;
+    let name_size = field(11)? as usize;
+
+    let name_start = pos + NEWC_HEADER_LEN;
+    let name_bytes = data
+        .get(name_start..name_start + name_size)
+        .ok_or("Truncated cpio name")?;
+    let name = name_bytes.strip_suffix(&[0]).unwrap_or(name_bytes);
+    let name = String::from_utf8_lossy(name).into_owned();
+
+    let data_start = align4(name_start + name_size);
+    let content = data
+        .get(data_start..data_start + file_size)
+        .ok_or_else(|| format!("Truncated cpio data for {}", name))?;
+
+    let entry = CpioEntry {
+        name,
+        ino: field(0)?,
+        mode: field(1)?,
+        uid: field(2)?,
+        gid: field(3)?,
+        nlink: field(4)?,
+        mtime: field(5)?,
+        dev_major: field(7)?,
+        dev_minor: field(8)?,
+        rdev_major: field(9)?,
+        rdev_minor: field(10)?,
+        check: field(12)?,
+        data: content.to_vec(),
+    };
+
+    Ok((entry, align4(data_start + file_size)))
+}
+
+fn write_entry(out: &mut Vec<u8>, entry: &CpioEntry, uppercase: bool) {
+    let fields = [
+        entry.ino,
+        entry.mode,
+        entry.uid,
+        entry.gid,
+        entry.nlink,
+        entry.mtime,
+        entry.data.len() as u32,
+        entry.dev_major,
+        entry.dev_minor,
+        entry.rdev_major,
+        entry.rdev_minor,
+        entry.name.len() as u32 + 1,
+        entry.check,
+    ];
+
+    out.extend(NEWC_MAGIC);
+    for value in fields {
+        let hex = if uppercase { format!("{:08X}", value) } else { format!("{:08x}", value) };
+        out.extend(hex.as_bytes());
+    }
+
+    out.extend(entry.name.as_bytes());
+    out.push(0);
+    out.resize(align4(out.len()), 0);
+
+    out.extend(&entry.data);
+    out.resize(align4(out.len()), 0);
+}
+
+fn trailer_entry() -> CpioEntry {
+    let mut trailer = CpioEntry::file(TRAILER, 0, Vec::new());
+    trailer.mode = 0;
+    trailer.nlink = 1;
+    trailer
+}
+
+fn align4(value: usize) -> usize {
+    value.next_multiple_of(4)
+}
+
+/* ================= LISTING ================= */
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RamdiskEntry {
+    pub name: String,
+    pub kind: &'static str,
+    /// Permission bits in octal, e.g. "0750"
+    pub mode: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u64,
+    pub link_target: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RamdiskListing {
+    /// Vendor ramdisk name, empty for boot / init_boot
+    pub name: String,
+    pub compression: RamdiskCompression,
+    pub entries: Vec<RamdiskEntry>,
+    /// Evidence that the ramdisk was patched by Magisk
+    pub magisk: Vec<String>,
+}
+
+pub fn list_ramdisk(name: &str, data: &[u8]) -> Result<RamdiskListing, String> {
+    let (compression, raw) = decompress(data)?;
+    let cpio = Cpio::parse(&raw)?;
+
+    let entries = cpio
+        .entries
+        .iter()
+        .map(|e| RamdiskEntry {
+            name: e.name.clone(),
+            kind: if e.is_dir() {
+                "dir"
+            } else if e.is_symlink() {
+                "symlink"
+            } else if e.is_file() {
+                "file"
+            } else {
+                "special"
+            },
+            mode: format!("{:04o}", e.mode & 0o7777),
+            uid: e.uid,
+            gid: e.gid,
+            size: e.data.len() as u64,
+            link_target: e
+                .is_symlink()
+                .then(|| String::from_utf8_lossy(&e.data).into_owned()),
+        })
+        .collect();
+
+    Ok(RamdiskListing {
+        name: name.to_string(),
+        compression,
+        entries,
+        magisk: detect_magisk(&cpio),
+    })
+}
+
+/// Magisk leaves a backup of the stock ramdisk, its config and overlay
+/// directory, and replaces /init with magiskinit.
+pub fn detect_magisk(cpio: &Cpio) -> Vec<String> {
+    let mut evidence = Vec::new();
+
+    for marker in [".backup/.magisk", ".backup/init", "overlay.d", "overlay.d/sbin"] {
+        if cpio.get(marker).is_some() {
+            evidence.push(format!("{} present", marker));
+        }
+    }
+
+    if let Some(init) = cpio.get("init") {
+        let needle = b"magiskinit";
+        if init.data.windows(needle.len()).any(|w| w == needle) {
+            evidence.push("init is magiskinit".into());
+        }
+    }
+
+    evidence
+}
+
+/* ================= EDITING ================= */
+
+/// Add or replace `entry` in an unpacked ramdisk file, keeping its
+/// compression. New files get mode 0644 unless `mode` is given.
+pub fn put_file(
+    ramdisk: &Path,
+    entry: &str,
+    contents: Vec<u8>,
+    mode: Option<u32>,
+) -> Result<(), String> {
+    let (compression, mut cpio) = load(ramdisk)?;
+    cpio.put_file(entry, mode.unwrap_or(0o644), contents);
+    store(ramdisk, &cpio, compression)
+}
+
+/// Remove `entry` from an unpacked ramdisk file, keeping its compression.
+pub fn remove_file(ramdisk: &Path, entry: &str) -> Result<(), String> {
+    let (compression, mut cpio) = load(ramdisk)?;
+
+    if !cpio.remove(entry) {
+        return Err(format!("{} is not in the ramdisk", entry));
+    }
+
+    store(ramdisk, &cpio, compression)
+}
+
+/// Copy a regular file out of a ramdisk into `output`; returns its size.
+pub fn extract_file(ramdisk: &Path, entry: &str, output: &Path) -> Result<u64, String> {
+    let (_, cpio) = load(ramdisk)?;
+
+    let file = cpio
+        .get(entry)
+        .ok_or_else(|| format!("{} is not in the ramdisk", entry))?;
+    if !file.is_file() {
+        return Err(format!("{} is not a regular file", entry));
+    }
+
+    fs::write(output, &file.data).map_err(|e| format!("{}: {}", output.display(), e))?;
+    Ok(file.data.len() as u64)
+}
+
+fn load(ramdisk: &Path) -> Result<(RamdiskCompression, Cpio), String> {
+    let data = fs::read(ramdisk).map_err(|e| format!("{}: {}", ramdisk.display(), e))?;
+    let (compression, raw) = decompress(&data)?;
+    Ok((compression, Cpio::parse(&raw)?))
+}
+
+fn store(ramdisk: &Path, cpio: &Cpio, compression: RamdiskCompression) -> Result<(), String> {
+    let out = compress(&cpio.to_bytes(), compression)?;
+    fs::write(ramdisk, out).map_err(|e| format!("{}: {}", ramdisk.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    /// newc archive as `cpio -H newc` would write it, in either hex case.
+    fn newc(files: &[(&str, u32, &[u8])], uppercase: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        let trailer: &[(&str, u32, &[u8])] = &[(TRAILER, 0, b"")];
+
+        for (ino, (name, mode, data)) in files.iter().chain(trailer).enumerate() {
+            let fields = [ino as u32 + 1, *mode, 0, 0, 1, 0x5F5E_1000, data.len() as u32]
+                .into_iter()
+                .chain([0, 0, 0, 0, name.len() as u32 + 1, 0]);
+
+            out.extend(NEWC_MAGIC);
+            for value in fields {
+                let hex = format!("{:08x}", value);
+                out.extend(if uppercase { hex.to_uppercase() } else { hex }.as_bytes());
+            }
+            out.extend(name.as_bytes());
+            out.push(0);
+            out.resize(align4(out.len()), 0);
+            out.extend(*data);
+            out.resize(align4(out.len()), 0);
+        }
+
+        out.resize(out.len().next_multiple_of(512), 0);
+        out
+    }
+
+    const FILES: &[(&str, u32, &[u8])] = &[
+        ("init", S_IFREG | 0o750, b"\x7fELF magiskinit"),
+        ("overlay.d", S_IFDIR | 0o750, b""),
+        ("sbin", S_IFLNK | 0o777, b"/system/bin"),
+    ];
+
+    #[test]
+    fn round_trips_both_hex_cases() {
+        for uppercase in [false, true] {
+            let archive = newc(FILES, uppercase);
+            let cpio = Cpio::parse(&archive).unwrap();
+
+            assert_eq!(cpio.entries.len(), 3);
+            assert!(cpio.get("/sbin").unwrap().is_symlink());
+            assert_eq!(cpio.to_bytes(), archive, "uppercase = {}", uppercase);
+        }
+    }
+
+    #[test]
+    fn concatenated_archives_keep_their_own_case() {
+        let archive = [newc(&FILES[..1], false), newc(&FILES[1..], true)].concat();
+        let cpio = Cpio::parse(&archive).unwrap();
+
+        assert_eq!(cpio.entries.len(), 3);
+        assert_eq!(cpio.to_bytes(), archive);
+    }
+
+    #[test]
+    fn new_archives_use_lower_case() {
+        let mut cpio = Cpio::default();
+        cpio.put_file("/.backup/.magisk", 0o644, b"KEEPVERITY=true".to_vec());
+
+        let bytes = cpio.to_bytes();
+        assert!(!bytes[..NEWC_HEADER_LEN].iter().any(|b| (b'A'..=b'F').contains(b)));
+        assert_eq!(Cpio::parse(&bytes).unwrap().get(".backup/.magisk").unwrap().mode, 0o100644);
+    }
+
+    #[test]
+    fn edits_survive_compression() {
+        for compression in [
+            RamdiskCompression::None,
+            RamdiskCompression::Gzip,
+            RamdiskCompression::Lz4Legacy,
+            RamdiskCompression::Lz4Frame,
+        ] {
+            let packed = compress(&newc(FILES, false), compression).unwrap();
+            let (detected, raw) = decompress(&packed).unwrap();
+            assert_eq!(detected, compression);
+
+            let mut cpio = Cpio::parse(&raw).unwrap();
+            assert!(cpio.remove("overlay.d"));
+            cpio.put_file("init", 0, b"stock".to_vec());
+
+            let back = Cpio::parse(&cpio.to_bytes()).unwrap();
+            assert_eq!(back.entries.len(), 2);
+            assert_eq!(back.get("init").unwrap().data, b"stock");
+        }
+    }
+
+    #[test]
+    fn lz4_legacy_size_trailer_ends_the_stream() {
+        let archive = newc(FILES, false);
+        let mut packed = compress(&archive, RamdiskCompression::Lz4Legacy).unwrap();
+        packed.extend((archive.len() as u32).to_le_bytes());
+
+        let (_, raw) = decompress(&packed).unwrap();
+        assert_eq!(raw, archive);
+
+        // A size word with data after it is still a (truncated) block
+        packed.push(0x11);
+        assert_eq!(decompress(&packed).unwrap_err(), "lz4: truncated block");
+    }
+
+    #[test]
+    fn extracts_and_removes_files_in_place() {
+        let dir = temp_dir("ramdisk", "edit");
+        let ramdisk = dir.join("ramdisk");
+        let output = dir.join("init");
+        fs::write(&ramdisk, compress(&newc(FILES, false), RamdiskCompression::Gzip).unwrap())
+            .unwrap();
+
+        let size = extract_file(&ramdisk, "/init", &output);
+        let extracted = fs::read(&output);
+        let not_file = extract_file(&ramdisk, "overlay.d", &output);
+        let removed = remove_file(&ramdisk, "overlay.d");
+        let missing = remove_file(&ramdisk, "overlay.d");
+        let (compression, raw) = decompress(&fs::read(&ramdisk).unwrap()).unwrap();
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(size.unwrap(), 15);
+        assert_eq!(extracted.unwrap(), b"\x7fELF magiskinit");
+        assert_eq!(not_file.unwrap_err(), "overlay.d is not a regular file");
+        removed.unwrap();
+        assert_eq!(missing.unwrap_err(), "overlay.d is not in the ramdisk");
+
+        assert_eq!(compression, RamdiskCompression::Gzip);
+        let cpio = Cpio::parse(&raw).unwrap();
+        assert!(cpio.get("overlay.d").is_none());
+        assert_eq!(cpio.entries.len(), 2);
+    }
+
+    #[test]
+    fn lists_entries_and_magisk_evidence() {
+        let listing = list_ramdisk("", &newc(FILES, false)).unwrap();
+
+        assert_eq!(listing.compression, RamdiskCompression::None);
+        assert_eq!(listing.entries[0].mode, "0750");
+        assert_eq!(listing.entries[2].kind, "symlink");
+        assert_eq!(listing.entries[2].link_target.as_deref(), Some("/system/bin"));
+        assert_eq!(listing.magisk, vec!["overlay.d present", "init is magiskinit"]);
+    }
+
+    #[test]
+    fn rejects_malformed_archives() {
+        assert!(Cpio::parse(b"070701zzzz").is_err());
+        assert!(Cpio::parse(&newc(FILES, false)[..200]).is_err());
+        assert!(decompress(b"not a ramdisk").is_err());
+    }
+}
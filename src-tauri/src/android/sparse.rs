@@ -0,0 +1,858 @@
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+// Android sparse images (libsparse / img2simg). Little-endian:
+//
+//   file header (28 bytes): magic, version 1.0, header sizes, block size,
+//                           total blocks, chunk count, image checksum
+//   chunk header (12 bytes): type, reserved, blocks, total bytes
+//
+// RAW chunks carry blocks verbatim, FILL a repeated u32, DONT_CARE
+// nothing (the target keeps whatever it had) and CRC32 a checksum of
+// everything before it. Everything here streams: only chunk headers are
+// kept in memory, never image data.
+
+pub const SPARSE_MAGIC: u32 = 0xED26_FF3A;
+const FILE_HEADER_LEN: u64 = 28;
+const CHUNK_HEADER_LEN: u64 = 12;
+pub const DEFAULT_BLOCK_SIZE: u32 = 4096;
+/// Keeps RAW chunk sizes well inside the u32 size field
+const MAX_RAW_CHUNK: u64 = 64 * 1024 * 1024;
+
+const CHUNK_RAW: u16 = 0xCAC1;
+const CHUNK_FILL: u16 = 0xCAC2;
+const CHUNK_DONT_CARE: u16 = 0xCAC3;
+const CHUNK_CRC32: u16 = 0xCAC4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ChunkKind {
+    Raw,
+    Fill,
+    DontCare,
+    Crc32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SparseChunk {
+    pub kind: ChunkKind,
+    /// First output block covered
+    pub start_block: u64,
+    pub blocks: u32,
+    /// Payload offset in the sparse file (RAW only)
+    #[serde(skip)]
+    pub data_offset: u64,
+    /// Fill pattern or CRC value
+    pub value: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SparseImage {
+    pub block_size: u32,
+    pub total_blocks: u32,
+    pub image_checksum: u32,
+    /// Size once unsparsed
+    pub raw_size: u64,
+    pub chunks: Vec<SparseChunk>,
+}
+
+impl SparseImage {
+    /// Bytes a chunk occupies in a sparse file, header included.
+    fn chunk_len(&self, kind: ChunkKind, blocks: u32) -> u64 {
+        CHUNK_HEADER_LEN
+            + match kind {
+                ChunkKind::Raw => u64::from(blocks) * u64::from(self.block_size),
+                ChunkKind::Fill | ChunkKind::Crc32 => 4,
+                ChunkKind::DontCare => 0,
+            }
+    }
+}
+
+pub fn is_sparse(data: &[u8]) -> bool {
+    data.len() >= 4 && le32(data, 0) == SPARSE_MAGIC
+}
+
+/// True if the file at `path` starts with the sparse magic.
+pub fn is_sparse_file(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    File::open(path).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && is_sparse(&magic)
+}
+
+/* ================= READ ================= */
+
+/// Read the file and chunk headers, skipping over chunk payloads.
+pub fn parse_sparse<R: Read + Seek>(reader: &mut R) -> Result<SparseImage, String> {
+    let mut header = [0u8; FILE_HEADER_LEN as usize];
+    reader.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+    reader.read_exact(&mut header).map_err(|_| "File too short for a sparse header")?;
+
+    if le32(&header, 0) != SPARSE_MAGIC {
+        return Err("Not a sparse image (bad magic)".into());
+    }
+    if le16(&header, 4) != 1 {
+        return Err(format!("Unsupported sparse version {}", le16(&header, 4)));
+    }
+
+    let file_header_len = u64::from(le16(&header, 8));
+    let chunk_header_len = u64::from(le16(&header, 10));
+    let block_size = le32(&header, 12);
+    let total_blocks = le32(&header, 16);
+    let total_chunks = le32(&header, 20);
+
+    if file_header_len < FILE_HEADER_LEN || chunk_header_len < CHUNK_HEADER_LEN {
+        return Err("Sparse header sizes are too small".into());
+    }
+    if block_size == 0 || !block_size.is_multiple_of(4) {
+        return Err(format!("Invalid sparse block size {}", block_size));
+    }
+
+    let mut pos = file_header_len;
+    let mut block = 0u64;
+    let mut chunks = Vec::with_capacity(total_chunks.min(1 << 16) as usize);
+
+    for i in 0..total_chunks {
+        let mut raw = [0u8; CHUNK_HEADER_LEN as usize];
+        reader.seek(SeekFrom::Start(pos)).map_err(|e| e.to_string())?;
+        reader
+            .read_exact(&mut raw)
+            .map_err(|_| format!("Chunk {} header runs past end of file", i))?;
+
+        let blocks = le32(&raw, 4);
+        let total = u64::from(le32(&raw, 8));
+        let data_offset = pos + chunk_header_len;
+        let payload = total.checked_sub(chunk_header_len).ok_or("Chunk smaller than header")?;
+
+        let kind = match le16(&raw, 0) {
+            CHUNK_RAW => ChunkKind::Raw,
+            CHUNK_FILL => ChunkKind::Fill,
+            CHUNK_DONT_CARE => ChunkKind::DontCare,
+            CHUNK_CRC32 => ChunkKind::Crc32,
+            other => return Err(format!("Chunk {} has unknown type {:#06x}", i, other)),
+        };
+
+        let expected = match kind {
+            ChunkKind::Raw => u64::from(blocks) * u64::from(block_size),
+            ChunkKind::Fill | ChunkKind::Crc32 => 4,
+            ChunkKind::DontCare => 0,
+        };
+        if payload != expected {
+            return Err(format!(
+                "Chunk {} ({:?}) has {} payload bytes, expected {}",
+                i, kind, payload, expected
+            ));
+        }
+
+        let mut value = 0;
+        if payload == 4 {
+            let mut v = [0u8; 4];
+            reader.seek(SeekFrom::Start(data_offset)).map_err(|e| e.to_string())?;
+            reader.read_exact(&mut v).map_err(|e| e.to_string())?;
+            value = u32::from_le_bytes(v);
+        }
+
+        chunks.push(SparseChunk { kind, start_block: block, blocks, data_offset, value });
+
+        if kind != ChunkKind::Crc32 {
+            block += u64::from(blocks);
+        }
+        pos = data_offset + payload;
+    }
+
+    if block != u64::from(total_blocks) {
+        return Err(format!("Chunks cover {} blocks, header says {}", block, total_blocks));
+    }
+
+    Ok(SparseImage {
+        block_size,
+        total_blocks,
+        image_checksum: le32(&header, 24),
+        raw_size: u64::from(total_blocks) * u64::from(block_size),
+        chunks,
+    })
+}
+
+pub fn read_sparse(path: &Path) -> Result<SparseImage, String> {
+    let mut file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_sparse(&mut file)
+}
+
+/// Streams the unsparsed contents of a sparse image. DONT_CARE blocks
+/// read as zeros; CRC32 chunks are checked against the data so far,
+/// unless the reader has been seeked.
+pub struct SparseReader<R> {
+    inner: R,
+    image: SparseImage,
+    chunk: usize,
+    /// Bytes of the current chunk already produced
+    done: u64,
+    /// `inner` is not positioned at the current RAW chunk offset
+    needs_seek: bool,
+    crc: Option<crc32fast::Hasher>,
+}
+
+impl<R: Read + Seek> SparseReader<R> {
+    pub fn new(mut inner: R) -> Result<Self, String> {
+        let image = parse_sparse(&mut inner)?;
+
+        Ok(Self {
+            inner,
+            image,
+            chunk: 0,
+            done: 0,
+            needs_seek: true,
+            crc: Some(crc32fast::Hasher::new()),
+        })
+    }
+
+    pub fn image(&self) -> &SparseImage {
+        &self.image
+    }
+}
+
+impl<R: Read + Seek> Read for SparseReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let Some(chunk) = self.image.chunks.get(self.chunk) else {
+                return Ok(0);
+            };
+
+            if chunk.kind == ChunkKind::Crc32 {
+                let actual = self.crc.clone().map(|c| c.finalize()).unwrap_or(chunk.value);
+                if actual != chunk.value {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "CRC32 mismatch: chunk says {:08x}, data is {:08x}",
+                            chunk.value, actual
+                        ),
+                    ));
+                }
+                self.chunk += 1;
+                continue;
+            }
+
+            let len = u64::from(chunk.blocks) * u64::from(self.image.block_size);
+            let remaining = len - self.done;
+
+            if remaining == 0 {
+                self.chunk += 1;
+                self.done = 0;
+                self.needs_seek = true;
+                continue;
+            }
+
+            let n = remaining.min(buf.len() as u64) as usize;
+            let out = &mut buf[..n];
+
+            match chunk.kind {
+                ChunkKind::Raw => {
+                    // Reads within a chunk are sequential; only seek when entering it
+                    if self.needs_seek {
+                        self.inner.seek(SeekFrom::Start(chunk.data_offset + self.done))?;
+                        self.needs_seek = false;
+                    }
+                    self.inner.read_exact(out)?;
+                }
+                ChunkKind::Fill => {
+                    // Fill patterns restart on every block, which is a multiple of 4
+                    let pattern = chunk.value.to_le_bytes();
+                    for (i, b) in out.iter_mut().enumerate() {
+                        *b = pattern[(self.done as usize + i) % 4];
+                    }
+                }
+                ChunkKind::DontCare => out.fill(0),
+                ChunkKind::Crc32 => unreachable!(),
+            }
+
+            if let Some(crc) = self.crc.as_mut() {
+                crc.update(out);
+            }
+            self.done += n as u64;
+            return Ok(n);
+        }
+    }
+}
+
+/// Random access into the unsparsed image, for formats that need it
+/// (super metadata, filesystems). Seeking turns CRC checking off.
+impl<R: Read + Seek> Seek for SparseReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let block_size = u64::from(self.image.block_size);
+
+        let current = match self.image.chunks.get(self.chunk) {
+            Some(chunk) => chunk.start_block * block_size + self.done,
+            None => self.image.raw_size,
+        };
+
+        let target = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::Current(d) => current.checked_add_signed(d),
+            SeekFrom::End(d) => self.image.raw_size.checked_add_signed(d),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before start"))?;
+
+        // Last data chunk starting at or before the target
+        let index = self
+            .image
+            .chunks
+            .partition_point(|c| c.start_block * block_size <= target)
+            .saturating_sub(1);
+
+        let chunk = self.image.chunks.iter().enumerate().skip(index).find(|(_, c)| {
+            c.kind != ChunkKind::Crc32
+                && target < (c.start_block + u64::from(c.blocks)) * block_size
+        });
+
+        match chunk {
+            Some((i, c)) => {
+                self.chunk = i;
+                self.done = target - c.start_block * block_size;
+            }
+            None => {
+                self.chunk = self.image.chunks.len();
+                self.done = 0;
+            }
+        }
+
+        self.needs_seek = true;
+        self.crc = None;
+        Ok(target)
+    }
+}
+
+/// Open a raw or sparse image for reading as raw bytes.
+pub fn open_raw(path: &Path) -> Result<Box<dyn ReadSeek>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    if is_sparse_file(path) {
+        Ok(Box::new(SparseReader::new(BufReader::new(file))?))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// sparse → raw. Returns the raw size.
+pub fn unsparse(src: &Path, dst: &Path) -> Result<u64, String> {
+    let file = File::open(src).map_err(|e| format!("{}: {}", src.display(), e))?;
+    let mut reader = SparseReader::new(BufReader::new(file))?;
+
+    let out = File::create(dst).map_err(|e| format!("{}: {}", dst.display(), e))?;
+    let mut writer = BufWriter::new(out);
+
+    let n = io::copy(&mut reader, &mut writer).map_err(|e| e.to_string())?;
+    writer.flush().map_err(|e| e.to_string())?;
+
+    Ok(n)
+}
+
+/* ================= WRITE ================= */
+
+/// raw → sparse. Blocks that are one repeated u32 (zeros included)
+/// become FILL chunks, everything else RAW; a short last block is
+/// zero-padded. DONT_CARE is never emitted since the target would keep
+/// stale data there.
+pub fn sparse_from_raw(src: &Path, dst: &Path, block_size: u32) -> Result<SparseImage, String> {
+    if block_size == 0 || !block_size.is_multiple_of(4) {
+        return Err(format!("Invalid block size {}", block_size));
+    }
+
+    let file = File::open(src).map_err(|e| format!("{}: {}", src.display(), e))?;
+    let mut reader = BufReader::new(file);
+    let mut out = SparseWriter::create(dst, block_size)?;
+
+    let mut block = vec![0u8; block_size as usize];
+
+    loop {
+        let n = read_full(&mut reader, &mut block).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        block[n..].fill(0);
+
+        match fill_value(&block) {
+            Some(value) => out.fill(value, 1)?,
+            None => out.raw(&block)?,
+        }
+
+        if n < block.len() {
+            break;
+        }
+    }
+
+    out.finish()
+}
+
+/// Split `src` (sparse) into sparse files of at most `max_size` bytes,
+/// as fastboot does for images over `max-download-size`. Every piece
+/// covers the whole image; blocks it does not carry are DONT_CARE.
+/// CRC32 chunks are dropped since they describe the whole image.
+pub fn split_sparse(src: &Path, out_dir: &Path, max_size: u64) -> Result<Vec<PathBuf>, String> {
+    let mut input = File::open(src).map_err(|e| format!("{}: {}", src.display(), e))?;
+    let image = parse_sparse(&mut input)?;
+
+    // Room for the file header plus leading and trailing DONT_CARE
+    let overhead = FILE_HEADER_LEN + 2 * CHUNK_HEADER_LEN;
+    let min_size = overhead + image.chunk_len(ChunkKind::Raw, 1);
+    if max_size < min_size {
+        return Err(format!("max size {} is below the minimum of {} bytes", max_size, min_size));
+    }
+
+    let stem = src.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+    let mut pieces = Vec::new();
+
+    // Queue of (chunk index, first block within it) still to be written
+    let chunks: Vec<&SparseChunk> =
+        image.chunks.iter().filter(|c| c.kind != ChunkKind::Crc32).collect();
+    let mut next = (0usize, 0u32);
+
+    loop {
+        // A piece never starts with DONT_CARE; the skip covers it
+        while next.0 < chunks.len() && chunks[next.0].kind == ChunkKind::DontCare {
+            next = (next.0 + 1, 0);
+        }
+        if next.0 == chunks.len() {
+            break;
+        }
+
+        let path = out_dir.join(format!("{}.{}.simg", stem, pieces.len()));
+        let mut out = SparseWriter::create(&path, image.block_size)?;
+
+        let (first, skip) = next;
+        let start_block = chunks[first].start_block + u64::from(skip);
+        if start_block > 0 {
+            out.dont_care(start_block as u32)?;
+        }
+
+        let mut budget = max_size - overhead;
+        let mut end_block = start_block;
+
+        while next.0 < chunks.len() {
+            let chunk = chunks[next.0];
+            let left = chunk.blocks - next.1;
+            let whole = image.chunk_len(chunk.kind, left);
+
+            let take = if whole <= budget {
+                left
+            } else if chunk.kind == ChunkKind::Raw && budget > CHUNK_HEADER_LEN {
+                ((budget - CHUNK_HEADER_LEN) / u64::from(image.block_size)) as u32
+            } else {
+                0
+            };
+
+            if take == 0 {
+                break;
+            }
+
+            match chunk.kind {
+                ChunkKind::Raw => {
+                    let block_size = u64::from(image.block_size);
+                    let offset = chunk.data_offset + u64::from(next.1) * block_size;
+                    let len = u64::from(take) * block_size;
+                    input.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+                    out.raw_from(&mut (&mut input).take(len), take)?;
+                }
+                ChunkKind::Fill => out.fill(chunk.value, take)?,
+                ChunkKind::DontCare => out.dont_care(take)?,
+                ChunkKind::Crc32 => unreachable!(),
+            }
+
+            budget -= image.chunk_len(chunk.kind, take);
+            end_block += u64::from(take);
+
+            if take == left {
+                next = (next.0 + 1, 0);
+            } else {
+                next.1 += take;
+                break;
+            }
+        }
+
+        let trailing = u64::from(image.total_blocks) - end_block;
+        if trailing > 0 {
+            out.dont_care(trailing as u32)?;
+        }
+
+        out.finish()?;
+        pieces.push(path);
+    }
+
+    // Nothing but DONT_CARE: still one piece, so the flash has something to send
+    if pieces.is_empty() {
+        let path = out_dir.join(format!("{}.0.simg", stem));
+        let mut out = SparseWriter::create(&path, image.block_size)?;
+        out.dont_care(image.total_blocks)?;
+        out.finish()?;
+        pieces.push(path);
+    }
+
+    Ok(pieces)
+}
+
+/// Chunk being accumulated; written out once something else follows.
+enum Pending {
+    None,
+    /// Header already written at `header_pos`, sizes patched on close
+    Raw { header_pos: u64, blocks: u32 },
+    Fill { value: u32, blocks: u32 },
+    DontCare { blocks: u32 },
+}
+
+/// Writes chunks sequentially, merging neighbours of the same kind, and
+/// fills in the file header at the end.
+struct SparseWriter {
+    out: BufWriter<File>,
+    block_size: u32,
+    total_blocks: u32,
+    chunks: Vec<SparseChunk>,
+    pending: Pending,
+    pos: u64,
+}
+
+impl SparseWriter {
+    fn create(path: &Path, block_size: u32) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let mut writer = Self {
+            out: BufWriter::new(file),
+            block_size,
+            total_blocks: 0,
+            chunks: Vec::new(),
+            pending: Pending::None,
+            pos: 0,
+        };
+
+        // Placeholder, rewritten by finish()
+        writer.write(&[0u8; FILE_HEADER_LEN as usize])?;
+        Ok(writer)
+    }
+
+    /// Append whole blocks, extending the open RAW chunk if there is one.
+    fn raw(&mut self, data: &[u8]) -> Result<(), String> {
+        let blocks = (data.len() / self.block_size as usize) as u32;
+        let max_blocks = (MAX_RAW_CHUNK / u64::from(self.block_size)) as u32;
+
+        let open = match self.pending {
+            Pending::Raw { blocks: n, .. } => n + blocks <= max_blocks,
+            _ => false,
+        };
+
+        if !open {
+            self.close()?;
+            self.pending = Pending::Raw { header_pos: self.pos, blocks: 0 };
+            self.chunk_header(CHUNK_RAW, 0, 0)?;
+        }
+
+        self.write(data)?;
+
+        if let Pending::Raw { blocks: n, .. } = &mut self.pending {
+            *n += blocks;
+        }
+        Ok(())
+    }
+
+    /// A RAW chunk of `blocks` copied straight from `reader`.
+    fn raw_from(&mut self, reader: &mut impl Read, blocks: u32) -> Result<(), String> {
+        self.close()?;
+
+        let len = u64::from(blocks) * u64::from(self.block_size);
+        let data_offset = self.pos + CHUNK_HEADER_LEN;
+        self.chunk_header(CHUNK_RAW, blocks, len)?;
+
+        let copied = io::copy(reader, &mut self.out).map_err(|e| e.to_string())?;
+        if copied != len {
+            return Err("Sparse source ended inside a RAW chunk".into());
+        }
+        self.pos += len;
+
+        self.record(ChunkKind::Raw, blocks, data_offset, 0);
+        Ok(())
+    }
+
+    fn fill(&mut self, value: u32, blocks: u32) -> Result<(), String> {
+        match &mut self.pending {
+            Pending::Fill { value: v, blocks: n } if *v == value => *n += blocks,
+            _ => {
+                self.close()?;
+                self.pending = Pending::Fill { value, blocks };
+            }
+        }
+        Ok(())
+    }
+
+    fn dont_care(&mut self, blocks: u32) -> Result<(), String> {
+        match &mut self.pending {
+            Pending::DontCare { blocks: n } => *n += blocks,
+            _ => {
+                self.close()?;
+                self.pending = Pending::DontCare { blocks };
+            }
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), String> {
+        match std::mem::replace(&mut self.pending, Pending::None) {
+            Pending::None => {}
+            Pending::Raw { header_pos, blocks } => {
+                let len = u64::from(blocks) * u64::from(self.block_size);
+                let total = (CHUNK_HEADER_LEN + len) as u32;
+
+                let mut sizes = blocks.to_le_bytes().to_vec();
+                sizes.extend(total.to_le_bytes());
+
+                self.out.seek(SeekFrom::Start(header_pos + 4)).map_err(|e| e.to_string())?;
+                self.out.write_all(&sizes).map_err(|e| e.to_string())?;
+                self.out.seek(SeekFrom::Start(self.pos)).map_err(|e| e.to_string())?;
+
+                self.record(ChunkKind::Raw, blocks, header_pos + CHUNK_HEADER_LEN, 0);
+            }
+            Pending::Fill { value, blocks } => {
+                self.chunk_header(CHUNK_FILL, blocks, 4)?;
+                self.write(&value.to_le_bytes())?;
+                self.record(ChunkKind::Fill, blocks, 0, value);
+            }
+            Pending::DontCare { blocks } => {
+                self.chunk_header(CHUNK_DONT_CARE, blocks, 0)?;
+                self.record(ChunkKind::DontCare, blocks, 0, 0);
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<SparseImage, String> {
+        self.close()?;
+
+        let mut header = Vec::with_capacity(FILE_HEADER_LEN as usize);
+        header.extend(SPARSE_MAGIC.to_le_bytes());
+        header.extend(1u16.to_le_bytes());
+        header.extend(0u16.to_le_bytes());
+        header.extend((FILE_HEADER_LEN as u16).to_le_bytes());
+        header.extend((CHUNK_HEADER_LEN as u16).to_le_bytes());
+        header.extend(self.block_size.to_le_bytes());
+        header.extend(self.total_blocks.to_le_bytes());
+        header.extend((self.chunks.len() as u32).to_le_bytes());
+        header.extend(0u32.to_le_bytes());
+
+        self.out.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+        self.out.write_all(&header).map_err(|e| e.to_string())?;
+        self.out.flush().map_err(|e| e.to_string())?;
+
+        Ok(SparseImage {
+            block_size: self.block_size,
+            total_blocks: self.total_blocks,
+            image_checksum: 0,
+            raw_size: u64::from(self.total_blocks) * u64::from(self.block_size),
+            chunks: self.chunks,
+        })
+    }
+
+    fn record(&mut self, kind: ChunkKind, blocks: u32, data_offset: u64, value: u32) {
+        self.chunks.push(SparseChunk {
+            kind,
+            start_block: u64::from(self.total_blocks),
+            blocks,
+            data_offset,
+            value,
+        });
+        self.total_blocks += blocks;
+    }
+
+    fn chunk_header(&mut self, kind: u16, blocks: u32, payload: u64) -> Result<(), String> {
+        let mut header = Vec::with_capacity(CHUNK_HEADER_LEN as usize);
+        header.extend(kind.to_le_bytes());
+        header.extend(0u16.to_le_bytes());
+        header.extend(blocks.to_le_bytes());
+        header.extend(((CHUNK_HEADER_LEN + payload) as u32).to_le_bytes());
+        self.write(&header)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        self.out.write_all(data).map_err(|e| e.to_string())?;
+        self.pos += data.len() as u64;
+        Ok(())
+    }
+}
+
+/* ================= HELPERS ================= */
+
+/// The u32 a block repeats, if it is one value throughout.
+fn fill_value(block: &[u8]) -> Option<u32> {
+    let first = &block[..4];
+    block.chunks_exact(4).all(|w| w == first).then(|| le32(first, 0))
+}
+
+/// Like `read_exact`, but a short read at EOF returns the count.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+fn le16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::{fs, io::Cursor};
+
+    const BLOCK: usize = 4096;
+
+    fn chunk(kind: u16, blocks: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = kind.to_le_bytes().to_vec();
+        out.extend([0, 0]);
+        out.extend(blocks.to_le_bytes());
+        out.extend((CHUNK_HEADER_LEN as u32 + payload.len() as u32).to_le_bytes());
+        out.extend(payload);
+        out
+    }
+
+    /// RAW, FILL, DONT_CARE and a CRC32 over the unsparsed data.
+    fn sample() -> (Vec<u8>, Vec<u8>) {
+        let raw_block: Vec<u8> = (0..BLOCK).map(|i| (i % 251) as u8).collect();
+        let mut unsparsed = raw_block.clone();
+        unsparsed.extend([0xEF, 0xBE, 0xAD, 0xDE].repeat(2 * BLOCK / 4));
+        unsparsed.extend(vec![0u8; BLOCK]);
+
+        let chunks = [
+            chunk(CHUNK_RAW, 1, &raw_block),
+            chunk(CHUNK_FILL, 2, &0xDEAD_BEEFu32.to_le_bytes()),
+            chunk(CHUNK_DONT_CARE, 1, &[]),
+            chunk(CHUNK_CRC32, 0, &crc32fast::hash(&unsparsed).to_le_bytes()),
+        ];
+
+        let mut sparse = SPARSE_MAGIC.to_le_bytes().to_vec();
+        for value in [1u16, 0, FILE_HEADER_LEN as u16, CHUNK_HEADER_LEN as u16] {
+            sparse.extend(value.to_le_bytes());
+        }
+        for value in [BLOCK as u32, 4, chunks.len() as u32, 0] {
+            sparse.extend(value.to_le_bytes());
+        }
+        sparse.extend(chunks.concat());
+
+        (sparse, unsparsed)
+    }
+
+    #[test]
+    fn reads_every_chunk_kind() {
+        let (sparse, unsparsed) = sample();
+        assert!(is_sparse(&sparse));
+
+        let mut reader = SparseReader::new(Cursor::new(&sparse)).unwrap();
+        let kinds: Vec<ChunkKind> = reader.image().chunks.iter().map(|c| c.kind).collect();
+        assert_eq!(kinds, [ChunkKind::Raw, ChunkKind::Fill, ChunkKind::DontCare, ChunkKind::Crc32]);
+        assert_eq!(reader.image().raw_size, 4 * BLOCK as u64);
+
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, unsparsed);
+    }
+
+    #[test]
+    fn seeks_into_chunks() {
+        let (sparse, unsparsed) = sample();
+        let mut reader = SparseReader::new(Cursor::new(&sparse)).unwrap();
+
+        let mut buf = [0u8; 8];
+        reader.seek(SeekFrom::Start(BLOCK as u64 + 2)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..], unsparsed[BLOCK + 2..BLOCK + 10]);
+
+        reader.seek(SeekFrom::Start(100)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf[..], unsparsed[100..108]);
+    }
+
+    #[test]
+    fn rejects_bad_chunks_and_checksums() {
+        let (mut sparse, _) = sample();
+        let crc = sparse.len() - 4;
+        sparse[crc] ^= 1;
+        let mut out = Vec::new();
+        let err = SparseReader::new(Cursor::new(&sparse)).unwrap().read_to_end(&mut out);
+        assert!(err.unwrap_err().to_string().contains("CRC32 mismatch"));
+
+        let (mut sparse, _) = sample();
+        sparse[16..20].copy_from_slice(&5u32.to_le_bytes());
+        assert!(parse_sparse(&mut Cursor::new(&sparse)).unwrap_err().contains("header says 5"));
+
+        let (mut sparse, _) = sample();
+        let fill = FILE_HEADER_LEN as usize + CHUNK_HEADER_LEN as usize + BLOCK;
+        sparse[fill + 8..fill + 12].copy_from_slice(&20u32.to_le_bytes());
+        assert!(parse_sparse(&mut Cursor::new(&sparse)).is_err());
+    }
+
+    #[test]
+    fn raw_to_sparse_and_back() {
+        let dir = temp_dir("sparse", "roundtrip");
+        let mut raw = vec![0u8; BLOCK * 10];
+        raw.extend((0..BLOCK * 40).map(|i| (i * 7 + i / 13) as u8));
+        raw.extend([1u8, 2, 3, 4].repeat(BLOCK * 5 / 4));
+        raw.extend(vec![9u8; 100]);
+        fs::write(dir.join("raw.img"), &raw).unwrap();
+
+        let written = sparse_from_raw(&dir.join("raw.img"), &dir.join("sparse.img"), BLOCK as u32);
+        let parsed = read_sparse(&dir.join("sparse.img"));
+        let size = unsparse(&dir.join("sparse.img"), &dir.join("back.img"));
+        let back = fs::read(dir.join("back.img"));
+        fs::remove_dir_all(&dir).ok();
+
+        let (written, parsed, back) = (written.unwrap(), parsed.unwrap(), back.unwrap());
+        let kinds: Vec<ChunkKind> = written.chunks.iter().map(|c| c.kind).collect();
+        assert_eq!(kinds, [ChunkKind::Fill, ChunkKind::Raw, ChunkKind::Fill, ChunkKind::Raw]);
+        assert_eq!(parsed.total_blocks, 56);
+        assert_eq!(size.unwrap(), 56 * BLOCK as u64);
+        assert_eq!(back[..raw.len()], raw[..]);
+        assert!(back[raw.len()..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn split_pieces_reassemble_the_image() {
+        let dir = temp_dir("sparse", "split");
+        let raw: Vec<u8> = (0..BLOCK * 60).map(|i| (i * 31 % 251) as u8).collect();
+        fs::write(dir.join("raw.img"), &raw).unwrap();
+        sparse_from_raw(&dir.join("raw.img"), &dir.join("sparse.img"), BLOCK as u32).unwrap();
+
+        let pieces = split_sparse(&dir.join("sparse.img"), &dir, 60_000).unwrap();
+        let mut merged = vec![0xAAu8; raw.len()];
+
+        for piece in &pieces {
+            assert!(fs::metadata(piece).unwrap().len() <= 60_000);
+
+            let mut reader = SparseReader::new(File::open(piece).unwrap()).unwrap();
+            let chunks = reader.image().chunks.clone();
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).unwrap();
+
+            for c in chunks.iter().filter(|c| c.kind != ChunkKind::DontCare) {
+                let start = c.start_block as usize * BLOCK;
+                let end = start + c.blocks as usize * BLOCK;
+                merged[start..end].copy_from_slice(&data[start..end]);
+            }
+        }
+
+        let too_small = split_sparse(&dir.join("sparse.img"), &dir, 100);
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(pieces.len() > 2);
+        assert_eq!(merged, raw);
+        assert!(too_small.is_err());
+    }
+}
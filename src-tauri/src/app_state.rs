@@ -1,11 +1,14 @@
 use std::sync::Mutex;
 use crate::detection_service::DeviceState;
+use crate::rollback::RollbackIndexes;
 use crate::root::RootStatus;
 
 pub struct AppState {
     pub device_state: Mutex<DeviceState>,
     pub root_state: Mutex<Option<RootStatus>>,
     pub tools_installed: Mutex<bool>,
+    /// Last root read-back of the device's rollback indexes
+    pub rollback_state: Mutex<Option<RollbackIndexes>>,
 }
 
 impl AppState {
@@ -14,6 +17,7 @@ impl AppState {
             device_state: Mutex::new(DeviceState::Disconnected),
             root_state: Mutex::new(None),
             tools_installed: Mutex::new(false),
+            rollback_state: Mutex::new(None),
         }
     }
 }
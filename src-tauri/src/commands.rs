@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     fs::File,
     io::Write,
     path::PathBuf,
@@ -8,9 +9,41 @@ use std::{
 use tauri::{AppHandle, State};
 
 use crate::{
+    android::avb::{self, VbmetaReport},
+    android::bootimg::{self, BootImage},
+    android::dtbo::{self, DtboTable},
+    android::fsimage::{self, FsListing},
+    android::gpt,
+    android::lp::{self, SuperReport},
+    android::payload::{self, ExtractedPartition, Payload},
+    android::ramdisk::{self, RamdiskListing},
+    android::sparse::{self, SparseImage},
     app_state::AppState,
     detection_service::DeviceState,
+    executor::{self, RunError},
+    firmware::{
+        self,
+        factory::FactoryOptions,
+        package::{self, PackageReport},
+        scatter::ScatterOptions,
+        vbmeta::{PatchedVbmeta, VbmetaPatchOptions},
+        FirmwareImport, SlotMode,
+    },
+    journal::{self, RunJournal},
+    layout::{self, GptReport},
     logger::emit_log,
+    mtk::brom::{self, BromIdentity, MtkPort},
+    mtk::capabilities::{self, MtkCapabilities},
+    mtk::logo::{self, LogoManifest},
+    mtk::partition::{self, MtkImage},
+    mtk::preloader::{self, PreloaderInfo},
+    mtk::scatter::{self, ScatterReport},
+    pipeline::{self, FlashPipeline},
+    planner::{self, ExecutionPlan},
+    profile,
+    rollback::{self, RollbackIndexes},
+    root,
+    snapshot::{capture_snapshot, describe_device, DeviceDetails, DeviceSnapshot},
     tools,
 };
 
@@ -32,33 +65,6 @@ pub fn install_platform_tools_cmd(
 }
 
 
-/* ================= FLASH RISK ================= */
-
-fn classify_flash_risk(partition: &str) -> &'static str {
-    match partition.to_lowercase().as_str() {
-        "preloader" | "bootloader" | "lk" | "lk2" =>
-            "CRITICAL: boot chain",
-
-        "vbmeta" | "vbmeta_a" | "vbmeta_b" =>
-            "CRITICAL: verified boot",
-
-        "boot" | "boot_a" | "boot_b"
-        | "vendor_boot" | "vendor_boot_a" | "vendor_boot_b"
-        | "init_boot" | "init_boot_a" | "init_boot_b" =>
-            "HIGH: kernel / ramdisk",
-
-        "dtbo" | "dtbo_a" | "dtbo_b" =>
-            "HIGH: device tree",
-
-        "system" | "system_a" | "system_b"
-        | "vendor" | "vendor_a" | "vendor_b" =>
-            "MEDIUM: system image",
-
-        _ =>
-            "UNKNOWN / USER-SPECIFIED",
-    }
-}
-
 /* ================= ADB ================= */
 
 #[tauri::command]
@@ -204,6 +210,807 @@ pub fn fastboot_flash(
 
 
 
+/* ================= PIPELINES ================= */
+
+fn plan_for_current_device(
+    state: &State<AppState>,
+    pipeline_id: &str,
+    variables: &BTreeMap<String, String>,
+) -> Result<(FlashPipeline, ExecutionPlan), String> {
+    let pipeline = pipeline::find_pipeline(pipeline_id)
+        .ok_or_else(|| format!("Unknown pipeline: {}", pipeline_id))?;
+
+    let snapshot = snapshot_with_rollback(state);
+
+    let plan = planner::plan_pipeline(&pipeline, &snapshot, variables);
+    Ok((pipeline, plan))
+}
+
+/// Fresh snapshot, plus rollback indexes read back earlier through root
+/// if they belong to the same device.
+fn snapshot_with_rollback(state: &State<AppState>) -> DeviceSnapshot {
+    let device_state = state.device_state.lock().unwrap().clone();
+    let mut snapshot = capture_snapshot(&device_state);
+
+    if let Some(cached) = state.rollback_state.lock().unwrap().as_ref() {
+        if cached.serial.is_some() && cached.serial == snapshot.serial {
+            snapshot.installed_rollback_indexes = cached.indexes.clone();
+        }
+    }
+
+    snapshot
+}
+
+/// Read the installed vbmeta rollback indexes through root so later
+/// plans can catch downgrades even from fastboot.
+#[tauri::command]
+pub fn read_rollback_indexes(
+    app: AppHandle,
+    state: State<AppState>,
+) -> Result<RollbackIndexes, String> {
+    emit_log(&app, "info", "Rollback index read-back requested");
+
+    let device_state = state.device_state.lock().unwrap().clone();
+    if device_state != DeviceState::AdbDevice {
+        return Err("ADB device not connected".into());
+    }
+
+    let root = root::detect_root_state();
+    let has_su = root.has_su;
+    *state.root_state.lock().unwrap() = Some(root);
+
+    if !has_su {
+        return Err("Root (su) is required to read vbmeta".into());
+    }
+
+    let indexes = rollback::read_via_root(&capture_snapshot(&device_state))
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    for note in &indexes.notes {
+        emit_log(&app, "warn", note.clone());
+    }
+
+    emit_log(&app, "info", format!("Rollback indexes: {:?}", indexes.indexes));
+    *state.rollback_state.lock().unwrap() = Some(indexes.clone());
+
+    Ok(indexes)
+}
+
+/// Dry-run: resolve and classify a pipeline without touching the device.
+#[tauri::command]
+pub fn plan_pipeline(
+    app: AppHandle,
+    state: State<AppState>,
+    pipeline_id: String,
+    variables: BTreeMap<String, String>,
+) -> Result<ExecutionPlan, String> {
+    emit_log(&app, "info", format!("Pipeline plan requested: {}", pipeline_id));
+
+    let (_, plan) = plan_for_current_device(&state, &pipeline_id, &variables)?;
+
+    emit_log(
+        &app,
+        "info",
+        format!(
+            "Plan {} → {} steps, max risk {:?}, {} bytes",
+            &plan.plan_hash[..12],
+            plan.runnable_steps().count(),
+            plan.max_risk,
+            plan.total_bytes
+        ),
+    );
+
+    Ok(plan)
+}
+
+/// Returns the run id. The journal is kept even if the run fails; the
+/// error then carries its `run_id` so the run can be inspected or resumed.
+#[tauri::command]
+pub fn execute_pipeline(
+    app: AppHandle,
+    state: State<AppState>,
+    pipeline_id: String,
+    variables: BTreeMap<String, String>,
+    approved_hash: String,
+) -> Result<String, RunError> {
+    emit_log(&app, "warn", format!("Pipeline execution requested: {}", pipeline_id));
+
+    // Re-plan against a fresh snapshot so a swapped device or changed
+    // slot invalidates the approval.
+    let (pipeline, plan) = plan_for_current_device(&state, &pipeline_id, &variables)?;
+
+    let journal = executor::execute_plan(&app, &pipeline, &plan, &approved_hash)
+        .inspect_err(|e| emit_log(&app, "error", format!("Pipeline FAILED → {}", e)))?;
+
+    emit_log(&app, "warn", format!("Pipeline SUCCESS → {}", pipeline_id));
+
+    Ok(journal.run_id)
+}
+
+/* ================= RUN HISTORY ================= */
+
+#[tauri::command]
+pub fn list_runs() -> Vec<RunJournal> {
+    journal::list_runs()
+}
+
+#[tauri::command]
+pub fn get_run(run_id: String) -> Result<RunJournal, String> {
+    journal::load_run(&run_id)
+}
+
+/// Continue a failed or interrupted run from its first incomplete step,
+/// after checking the same device is attached in the expected mode.
+#[tauri::command]
+pub fn resume_run(
+    app: AppHandle,
+    state: State<AppState>,
+    run_id: String,
+) -> Result<RunJournal, String> {
+    emit_log(&app, "warn", format!("Run resume requested: {}", run_id));
+
+    let mut run = journal::load_run(&run_id)?;
+
+    let pipeline = pipeline::find_pipeline(&run.pipeline_id)
+        .ok_or_else(|| format!("Unknown pipeline: {}", run.pipeline_id))?;
+
+    let snapshot = snapshot_with_rollback(&state);
+
+    let next = run.verify_resume(&pipeline, &snapshot).map_err(|e| {
+        emit_log(&app, "error", format!("Resume refused → {}", e));
+        e
+    })?;
+
+    emit_log(&app, "warn", format!("Resuming run {} at step {}", run_id, next));
+
+    run.resumed += 1;
+    executor::run_journal(&app, &mut run)?;
+
+    Ok(run)
+}
+
+/* ================= FIRMWARE IMPORT ================= */
+
+fn save_import(app: &AppHandle, import: FirmwareImport) -> Result<FirmwareImport, String> {
+    pipeline::save_pipeline(&import.pipeline)?;
+
+    emit_log(
+        app,
+        "info",
+        format!(
+            "Imported pipeline {} ({} steps, {} files verified, max risk {:?})",
+            import.pipeline.id,
+            import.pipeline.steps.len(),
+            import.files_verified,
+            import.max_risk
+        ),
+    );
+
+    for warning in &import.warnings {
+        emit_log(app, "warn", warning.clone());
+    }
+
+    Ok(import)
+}
+
+/// Identify a firmware zip and propose the import that handles it.
+#[tauri::command]
+pub fn inspect_package(app: AppHandle, path: String) -> Result<PackageReport, String> {
+    emit_log(&app, "info", format!("Inspecting package {}", path));
+
+    package::inspect_package(&PathBuf::from(&path))
+        .inspect(|r| emit_log(&app, "info", r.description.clone()))
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))
+}
+
+/// Motorola `flashfile.xml` package (zip or extracted folder) → pipeline.
+#[tauri::command]
+pub fn import_flashfile(
+    app: AppHandle,
+    path: String,
+    slot_mode: Option<SlotMode>,
+) -> Result<FirmwareImport, String> {
+    emit_log(&app, "info", format!("Flashfile import requested: {}", path));
+
+    let import = firmware::flashfile::import_flashfile(
+        &PathBuf::from(&path),
+        slot_mode.unwrap_or_default(),
+    )
+    .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    save_import(&app, import)
+}
+
+/// AOSP factory image (`flash-all.sh` + `image-*.zip`) → pipeline.
+#[tauri::command]
+pub fn import_factory_image(
+    app: AppHandle,
+    path: String,
+    options: Option<FactoryOptions>,
+) -> Result<FirmwareImport, String> {
+    emit_log(&app, "info", format!("Factory image import requested: {}", path));
+
+    let import = firmware::factory::import_factory_image(
+        &PathBuf::from(&path),
+        &options.unwrap_or_default(),
+    )
+    .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    save_import(&app, import)
+}
+
+/// MTK scatter package → fastboot pipeline.
+#[tauri::command]
+pub fn import_scatter(
+    app: AppHandle,
+    path: String,
+    options: Option<ScatterOptions>,
+) -> Result<FirmwareImport, String> {
+    emit_log(&app, "info", format!("Scatter import requested: {}", path));
+
+    let import = firmware::scatter::import_scatter(
+        &PathBuf::from(&path),
+        &options.unwrap_or_default(),
+    )
+    .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    save_import(&app, import)
+}
+
+/// Stock vbmeta → patched copy with verity / verification disabled, plus
+/// a pipeline that flashes it. The returned diff is for review; the
+/// flash still goes through plan approval.
+#[tauri::command]
+pub fn patch_vbmeta(
+    app: AppHandle,
+    path: String,
+    options: Option<VbmetaPatchOptions>,
+) -> Result<PatchedVbmeta, String> {
+    emit_log(&app, "info", format!("vbmeta patch requested: {}", path));
+
+    let mut patched = firmware::vbmeta::import_patched_vbmeta(
+        &PathBuf::from(&path),
+        &options.unwrap_or_default(),
+    )
+    .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    for change in &patched.patch.changes {
+        emit_log(
+            &app,
+            "info",
+            format!("vbmeta {}: {} → {}", change.field, change.before, change.after),
+        );
+    }
+
+    patched.import = save_import(&app, patched.import)?;
+    Ok(patched)
+}
+
+/* ================= ANDROID IMAGES ================= */
+
+/// Unpack a boot / vendor_boot image next to itself (`<name>_unpacked/`).
+#[tauri::command]
+pub fn unpack_boot_image(app: AppHandle, path: String) -> Result<BootImage, String> {
+    let image_path = PathBuf::from(&path);
+    let out_dir = unpacked_dir(&image_path);
+
+    let image = bootimg::unpack_boot_image(&image_path, &out_dir)
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    emit_log(
+        &app,
+        "info",
+        format!(
+            "Unpacked {:?} v{} to {}",
+            image.kind,
+            image.header_version,
+            out_dir.display()
+        ),
+    );
+
+    Ok(image)
+}
+
+#[tauri::command]
+pub fn repack_boot_image(
+    app: AppHandle,
+    dir: String,
+    output: String,
+) -> Result<Vec<String>, String> {
+    let warnings = bootimg::repack_boot_image(&PathBuf::from(&dir), &PathBuf::from(&output))
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    for warning in &warnings {
+        emit_log(&app, "warn", warning.clone());
+    }
+
+    emit_log(&app, "info", format!("Repacked boot image written to {}", output));
+    Ok(warnings)
+}
+
+/// Parse and verify a vbmeta image (or an image with an AVB footer).
+/// Hash and chain descriptors are checked against `<partition>.img`
+/// files next to it; `key` is an optional expected public key.
+#[tauri::command]
+pub fn inspect_vbmeta(path: String, key: Option<String>) -> Result<VbmetaReport, String> {
+    avb::verify_vbmeta(&PathBuf::from(path), key.as_deref().map(std::path::Path::new))
+}
+
+/// List every ramdisk in a boot / vendor_boot image.
+#[tauri::command]
+pub fn list_ramdisk(path: String) -> Result<Vec<RamdiskListing>, String> {
+    let data = std::fs::read(&path).map_err(|e| format!("{}: {}", path, e))?;
+    let image = bootimg::parse_boot_image(&data)?;
+
+    let ramdisks = image.ramdisks(&data);
+    if ramdisks.is_empty() {
+        return Err(format!("{} has no ramdisk", path));
+    }
+
+    ramdisks
+        .into_iter()
+        .map(|(name, bytes)| ramdisk::list_ramdisk(&name, bytes))
+        .collect()
+}
+
+/// Add or replace a file inside an unpacked ramdisk (`ramdisk` in a
+/// directory written by `unpack_boot_image`). Repack afterwards.
+#[tauri::command]
+pub fn replace_ramdisk_file(
+    app: AppHandle,
+    ramdisk: String,
+    entry: String,
+    source: String,
+) -> Result<(), String> {
+    let contents = std::fs::read(&source).map_err(|e| format!("{}: {}", source, e))?;
+
+    ramdisk::put_file(&PathBuf::from(&ramdisk), &entry, contents, None)
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    emit_log(&app, "info", format!("Replaced {} in {}", entry, ramdisk));
+    Ok(())
+}
+
+/// Copy one file out of an unpacked ramdisk.
+#[tauri::command]
+pub fn read_ramdisk_file(
+    app: AppHandle,
+    ramdisk: String,
+    entry: String,
+    output: String,
+) -> Result<u64, String> {
+    let bytes = ramdisk::extract_file(&PathBuf::from(&ramdisk), &entry, &PathBuf::from(&output))
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    emit_log(&app, "info", format!("Extracted {} → {} ({} bytes)", entry, output, bytes));
+    Ok(bytes)
+}
+
+/// Delete a file from an unpacked ramdisk. Repack afterwards.
+#[tauri::command]
+pub fn remove_ramdisk_file(app: AppHandle, ramdisk: String, entry: String) -> Result<(), String> {
+    ramdisk::remove_file(&PathBuf::from(&ramdisk), &entry)
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    emit_log(&app, "info", format!("Removed {} from {}", entry, ramdisk));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn inspect_sparse(path: String) -> Result<SparseImage, String> {
+    sparse::read_sparse(&PathBuf::from(path))
+}
+
+/// Sparse → raw, for inspection and hashing.
+#[tauri::command]
+pub fn unsparse_image(app: AppHandle, path: String, output: String) -> Result<u64, String> {
+    let bytes = sparse::unsparse(&PathBuf::from(&path), &PathBuf::from(&output))
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    emit_log(&app, "info", format!("Unsparsed {} → {} ({} bytes)", path, output, bytes));
+    Ok(bytes)
+}
+
+/// Raw → sparse, for transfer.
+#[tauri::command]
+pub fn sparse_image(
+    app: AppHandle,
+    path: String,
+    output: String,
+    block_size: Option<u32>,
+) -> Result<SparseImage, String> {
+    let image = sparse::sparse_from_raw(
+        &PathBuf::from(&path),
+        &PathBuf::from(&output),
+        block_size.unwrap_or(sparse::DEFAULT_BLOCK_SIZE),
+    )
+    .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    emit_log(
+        &app,
+        "info",
+        format!("Sparsed {} → {} ({} chunks)", path, output, image.chunks.len()),
+    );
+    Ok(image)
+}
+
+/// Split a sparse image into pieces under `max_size`, defaulting to the
+/// attached device's `max-download-size`. Raw images are sparsed first.
+#[tauri::command]
+pub fn split_sparse_image(
+    app: AppHandle,
+    state: State<AppState>,
+    path: String,
+    max_size: Option<u64>,
+) -> Result<Vec<String>, String> {
+    let max_size = match max_size {
+        Some(size) => size,
+        None => {
+            let device_state = state.device_state.lock().unwrap().clone();
+            capture_snapshot(&device_state)
+                .max_download_size()
+                .ok_or("max-download-size unknown; connect a fastboot device or pass a size")?
+        }
+    };
+
+    let source = PathBuf::from(&path);
+    let stem = source.file_stem().and_then(|s| s.to_str()).unwrap_or("image");
+    let out_dir = source.with_file_name(format!("{}_split", stem));
+    std::fs::create_dir_all(&out_dir).map_err(|e| e.to_string())?;
+
+    let sparse_source = if sparse::is_sparse_file(&source) {
+        source.clone()
+    } else {
+        let converted = out_dir.join("source.simg");
+        sparse::sparse_from_raw(&source, &converted, sparse::DEFAULT_BLOCK_SIZE)?;
+        converted
+    };
+
+    let pieces = sparse::split_sparse(&sparse_source, &out_dir, max_size)
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    if sparse_source != source {
+        std::fs::remove_file(&sparse_source).ok();
+    }
+
+    emit_log(
+        &app,
+        "info",
+        format!("Split {} into {} pieces of at most {} bytes", path, pieces.len(), max_size),
+    );
+
+    Ok(pieces.iter().map(|p| p.display().to_string()).collect())
+}
+
+/// List the logical partitions in a super image and compare them with
+/// the attached device's `is-logical:` answers.
+#[tauri::command]
+pub fn inspect_super(
+    state: State<AppState>,
+    path: String,
+    slot: Option<u32>,
+) -> Result<SuperReport, String> {
+    let metadata = lp::read_super(&PathBuf::from(&path), slot.unwrap_or(0))?;
+
+    let device_state = state.device_state.lock().unwrap().clone();
+    let device_check = lp::cross_check(&metadata, &capture_snapshot(&device_state))
+        .into_iter()
+        .filter(|c| c.device_logical.is_some())
+        .collect();
+
+    Ok(SuperReport { path, metadata, device_check })
+}
+
+/// Extract one logical partition from a super image to a raw image.
+#[tauri::command]
+pub fn extract_logical_partition(
+    app: AppHandle,
+    path: String,
+    partition: String,
+    output: String,
+    slot: Option<u32>,
+) -> Result<u64, String> {
+    let bytes = lp::extract_partition(
+        &PathBuf::from(&path),
+        &partition,
+        slot.unwrap_or(0),
+        &PathBuf::from(&output),
+    )
+    .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    emit_log(&app, "info", format!("Extracted {} from {} ({} bytes)", partition, path, bytes));
+    Ok(bytes)
+}
+
+#[tauri::command]
+pub fn inspect_dtbo(path: String) -> Result<DtboTable, String> {
+    dtbo::read_dtbo(&PathBuf::from(path))
+}
+
+/// Write one DTBO entry out as a decompressed `.dtb`.
+#[tauri::command]
+pub fn extract_dtbo_entry(
+    app: AppHandle,
+    path: String,
+    index: usize,
+    output: String,
+) -> Result<u64, String> {
+    let bytes = dtbo::extract_entry(&PathBuf::from(&path), index, &PathBuf::from(&output))
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    emit_log(
+        &app,
+        "info",
+        format!("Extracted DTBO entry {} → {} ({} bytes)", index, output, bytes),
+    );
+    Ok(bytes)
+}
+
+#[tauri::command]
+pub fn inspect_payload(path: String) -> Result<Payload, String> {
+    payload::read_payload(&PathBuf::from(path))
+}
+
+/// Extract partitions from a full OTA's payload.bin (or the OTA zip).
+/// Defaults to `<name>_unpacked/` next to the payload.
+#[tauri::command]
+pub fn extract_payload(
+    app: AppHandle,
+    path: String,
+    partitions: Vec<String>,
+    output_dir: Option<String>,
+) -> Result<Vec<ExtractedPartition>, String> {
+    let source = PathBuf::from(&path);
+    let out_dir = output_dir.map(PathBuf::from).unwrap_or_else(|| unpacked_dir(&source));
+
+    let payload = payload::read_payload(&source)
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    let extracted = payload::extract_partitions(&payload, &partitions, &out_dir, |name| {
+        emit_log(&app, "info", format!("Extracting {} from payload", name))
+    })
+    .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    for partition in &extracted {
+        let status = if partition.verified { "SHA-256 verified" } else { "no manifest hash" };
+        emit_log(&app, "info", format!("{} → {} ({})", partition.name, partition.path, status));
+    }
+
+    Ok(extracted)
+}
+
+/// List a directory inside an ext4 or EROFS partition image.
+#[tauri::command]
+pub fn browse_image(path: String, dir: Option<String>) -> Result<FsListing, String> {
+    fsimage::list_dir(&PathBuf::from(path), dir.as_deref().unwrap_or("/"))
+}
+
+/// Copy one file out of an ext4 or EROFS partition image.
+#[tauri::command]
+pub fn extract_image_file(
+    app: AppHandle,
+    path: String,
+    file: String,
+    output: String,
+) -> Result<u64, String> {
+    let bytes = fsimage::extract_file(&PathBuf::from(&path), &file, &PathBuf::from(&output))
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    emit_log(&app, "info", format!("Extracted {} → {} ({} bytes)", file, output, bytes));
+    Ok(bytes)
+}
+
+fn unpacked_dir(path: &std::path::Path) -> PathBuf {
+    path.with_file_name(format!(
+        "{}_unpacked",
+        path.file_stem().and_then(|s| s.to_str()).unwrap_or("image")
+    ))
+}
+
+/* ================= MTK ================= */
+
+#[tauri::command]
+pub fn list_mtk_ports() -> Vec<MtkPort> {
+    brom::find_ports()
+}
+
+/// Read-only BROM / preloader identification.
+#[tauri::command]
+pub fn mtk_identify(app: AppHandle, port: Option<String>) -> Result<BromIdentity, String> {
+    emit_log(&app, "info", "MTK identification requested");
+
+    brom::identify_port(port.as_deref())
+        .inspect(|id| emit_log(&app, "info", format!("MTK hw_code {:#06x}", id.hw_code)))
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))
+}
+
+#[tauri::command]
+pub fn inspect_preloader(path: String) -> Result<PreloaderInfo, String> {
+    preloader::parse_preloader_file(&PathBuf::from(&path))
+}
+
+/// Sub-images of an MTK partition image (lk, logo, md1img, tee...).
+#[tauri::command]
+pub fn inspect_mtk_image(path: String) -> Result<Vec<MtkImage>, String> {
+    partition::read_images(&PathBuf::from(&path))
+}
+
+/// Unpack logo.bin next to itself (`<name>_unpacked/`). Frame dimensions
+/// come from `profile`, or from the profile matched to the device.
+#[tauri::command]
+pub fn unpack_logo(
+    app: AppHandle,
+    state: State<AppState>,
+    path: String,
+    profile: Option<String>,
+) -> Result<LogoManifest, String> {
+    let logo_path = PathBuf::from(&path);
+    let out_dir = unpacked_dir(&logo_path);
+
+    let profile_name = profile.unwrap_or_else(|| {
+        let device_state = state.device_state.lock().unwrap().clone();
+        describe_device(&capture_snapshot(&device_state)).profile
+    });
+
+    let profiles = profile::load_profiles();
+    let display = profile::find_profile(&profiles, &profile_name).and_then(|p| p.display.as_ref());
+
+    if display.is_none() {
+        emit_log(&app, "warn", format!("No display info in profile {}", profile_name));
+    }
+
+    let manifest = logo::unpack_logo(&logo_path, &out_dir, display)
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    emit_log(
+        &app,
+        "info",
+        format!("Unpacked {} frames to {}", manifest.frames.len(), out_dir.display()),
+    );
+
+    Ok(manifest)
+}
+
+#[tauri::command]
+pub fn repack_logo(app: AppHandle, dir: String, output: String) -> Result<LogoManifest, String> {
+    let manifest = logo::repack_logo(&PathBuf::from(&dir), &PathBuf::from(&output))
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    emit_log(&app, "info", format!("Repacked logo written to {}", output));
+    Ok(manifest)
+}
+
+/// Capability matrix for the connected device. `probe` performs the
+/// read-only BROM handshake to learn SBC / SLA / DAA.
+#[tauri::command]
+pub fn device_capabilities(
+    state: State<AppState>,
+    probe: Option<bool>,
+) -> Result<MtkCapabilities, String> {
+    let device_state = state.device_state.lock().unwrap().clone();
+    let rooted = state.root_state.lock().unwrap().as_ref().map(|r| r.has_su);
+
+    let snapshot = capture_snapshot(&device_state);
+    let policy = profile::profile_policy(&describe_device(&snapshot).profile_id);
+
+    let (boot_mode, target_config) = match device_state {
+        DeviceState::MtkPreloader => capabilities::probe_brom(probe.unwrap_or(false))?,
+        _ => (None, None),
+    };
+
+    Ok(capabilities::evaluate(
+        &device_state,
+        boot_mode,
+        target_config.as_ref(),
+        snapshot.is_unlocked(),
+        rooted,
+        &policy,
+    ))
+}
+
+#[tauri::command]
+pub fn device_info(state: State<AppState>) -> DeviceDetails {
+    let device_state = state.device_state.lock().unwrap().clone();
+    describe_device(&capture_snapshot(&device_state))
+}
+
+#[tauri::command]
+pub fn inspect_scatter(app: AppHandle, path: String) -> Result<ScatterReport, String> {
+    emit_log(&app, "info", format!("Scatter inspection requested: {}", path));
+
+    let report = scatter::inspect_scatter(&PathBuf::from(&path))?;
+
+    emit_log(
+        &app,
+        if report.issues.is_empty() { "info" } else { "warn" },
+        format!(
+            "Scatter {} → {} partitions, {} issues",
+            report.scatter.platform.as_deref().unwrap_or("unknown platform"),
+            report.scatter.partitions.len(),
+            report.issues.len()
+        ),
+    );
+
+    Ok(report)
+}
+
+/// Partition table from a disk or `pgpt` dump, optionally compared with
+/// a scatter file and a device profile.
+#[tauri::command]
+pub fn inspect_gpt(
+    path: String,
+    scatter: Option<String>,
+    profile: Option<String>,
+) -> Result<GptReport, String> {
+    let table = gpt::read_gpt(&PathBuf::from(&path))?;
+    gpt_report(path, table, scatter, profile)
+}
+
+/// Read the device's partition table through root. `disk` defaults to
+/// the one holding userdata; `profile` to the profile matched to the device.
+#[tauri::command]
+pub fn read_device_gpt(
+    app: AppHandle,
+    state: State<AppState>,
+    disk: Option<String>,
+    scatter: Option<String>,
+    profile: Option<String>,
+) -> Result<GptReport, String> {
+    emit_log(&app, "info", "Partition table read-back requested");
+
+    let device_state = state.device_state.lock().unwrap().clone();
+    if device_state != DeviceState::AdbDevice {
+        return Err("ADB device not connected".into());
+    }
+
+    let root = root::detect_root_state();
+    let has_su = root.has_su;
+    *state.root_state.lock().unwrap() = Some(root);
+
+    if !has_su {
+        return Err("Root (su) is required to read the partition table".into());
+    }
+
+    let (node, table) = layout::read_via_root(disk.as_deref())
+        .inspect_err(|e| emit_log(&app, "error", e.clone()))?;
+
+    let profile = profile
+        .or_else(|| Some(describe_device(&capture_snapshot(&device_state)).profile));
+
+    let report = gpt_report(node, table, scatter, profile)?;
+
+    emit_log(
+        &app,
+        if report.issues.is_empty() { "info" } else { "warn" },
+        format!(
+            "{}: {} partitions ({} copy), {} layout issues",
+            report.source,
+            report.table.partitions.len(),
+            report.table.source,
+            report.issues.len()
+        ),
+    );
+
+    Ok(report)
+}
+
+fn gpt_report(
+    source: String,
+    table: gpt::GptTable,
+    scatter: Option<String>,
+    profile: Option<String>,
+) -> Result<GptReport, String> {
+    let scatter = scatter
+        .map(|path| scatter::parse_scatter_file(&PathBuf::from(path)))
+        .transpose()?;
+
+    let profiles = profile::load_profiles();
+    let profile = profile.and_then(|name| profile::find_profile(&profiles, &name));
+
+    let issues = layout::compare(&table, scatter.as_ref(), profile);
+    Ok(GptReport { source, table, issues })
+}
+
 /* ================= DIAGNOSTICS ================= */
 
 #[tauri::command]
@@ -216,7 +1023,7 @@ pub fn export_diagnostics(
 
     use zip::{ZipWriter, write::FileOptions};
 
-    let mut path = PathBuf::from(std::env::temp_dir());
+    let mut path = std::env::temp_dir();
     path.push("mtk-atlas-diagnostics.zip");
 
     let file = File::create(&path).map_err(|e| {
@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::{
     sync::Arc,
     thread,
@@ -9,8 +9,9 @@ use tauri::{AppHandle, Emitter};
 
 use crate::process::run;
 use crate::app_state::AppState;
+use crate::mtk::brom;
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum DeviceState {
     Disconnected,
     AdbUnauthorized,
@@ -60,5 +61,10 @@ fn detect_state() -> DeviceState {
         }
     }
 
+    // 3) MTK BROM / preloader (port enumeration only, never opened here)
+    if !brom::find_brom_ports().is_empty() {
+        return DeviceState::MtkPreloader;
+    }
+
     DeviceState::Disconnected
 }
@@ -1,46 +1,221 @@
-use std::process::Command;
+use serde::Serialize;
+use std::{fmt, thread, time::Duration};
+use tauri::AppHandle;
+
+use crate::journal::{RunJournal, RunStatus, StepStatus};
+use crate::logger::emit_log;
 use crate::pipeline::{FlashPipeline, PipelineStep};
+use crate::planner::ExecutionPlan;
+use crate::process::run_with_timeout;
+use crate::snapshot::now_secs;
 
-pub fn execute_pipeline(pipeline: &FlashPipeline, dry_run: bool) -> Result<(), String> {
-    for step in &pipeline.steps {
-        match step {
-            PipelineStep::Message { text } => {
-                println!("[PIPELINE] {}", text);
-            }
+/// Failure of a pipeline run. `run_id` is set once a journal exists, so
+/// the caller can inspect or resume the failed run.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunError {
+    pub run_id: Option<String>,
+    pub message: String,
+}
+
+impl From<String> for RunError {
+    fn from(message: String) -> Self {
+        Self { run_id: None, message }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.run_id {
+            Some(run_id) => write!(f, "run {}: {}", run_id, self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Run the steps of a reviewed plan.
+/// The caller must re-plan against a fresh snapshot and pass the hash
+/// the user approved; any drift aborts before the first command.
+pub fn execute_plan(
+    app: &AppHandle,
+    pipeline: &FlashPipeline,
+    plan: &ExecutionPlan,
+    approved_hash: &str,
+) -> Result<RunJournal, RunError> {
+    if plan.plan_hash != approved_hash {
+        return Err(RunError::from(
+            "Plan changed since it was approved; review it again".to_string(),
+        ));
+    }
 
-            PipelineStep::AdbCommand { args } => {
-                if dry_run {
-                    println!("[DRY-RUN] adb {:?}", args);
-                    continue;
-                }
+    if !plan.is_executable() {
+        return Err(RunError::from(format!("Plan is blocked: {}", plan.blockers.join("; "))));
+    }
 
-                let status = Command::new("adb")
-                    .args(args)
-                    .status()
-                    .map_err(|e| e.to_string())?;
+    let mut journal = RunJournal::from_plan(plan, pipeline);
+    emit_log(app, "info", format!("Run {} started", journal.run_id));
 
-                if !status.success() {
-                    return Err("ADB command failed".into());
-                }
-            }
+    run_journal(app, &mut journal)
+        .map_err(|message| RunError { run_id: Some(journal.run_id.clone()), message })?;
+
+    Ok(journal)
+}
 
-            PipelineStep::FastbootCommand { args } => {
-                if dry_run {
-                    println!("[DRY-RUN] fastboot {:?}", args);
-                    continue;
-                }
+/// Execute every step of `journal` that has not completed yet, persisting
+/// the journal after each one. Used for both fresh and resumed runs.
+pub fn run_journal(app: &AppHandle, journal: &mut RunJournal) -> Result<(), String> {
+    journal.status = RunStatus::Running;
+    journal.error = None;
+    journal.save()?;
 
-                let status = Command::new("fastboot")
-                    .args(args)
-                    .status()
-                    .map_err(|e| e.to_string())?;
+    while let Some(index) = journal.next_step() {
+        let record = &mut journal.steps[index];
+        record.started_at = Some(now_secs());
 
-                if !status.success() {
-                    return Err("Fastboot command failed".into());
-                }
+        let (result, attempts) = run_step(app, &record.step);
+
+        record.attempts = attempts;
+        record.finished_at = Some(now_secs());
+
+        match result {
+            Ok(output) => {
+                record.status = StepStatus::Completed;
+                record.output = output;
+                journal.save()?;
+            }
+            Err(output) => {
+                record.status = StepStatus::Failed;
+                record.output = output.clone();
+
+                journal.status = RunStatus::Failed;
+                journal.error = Some(format!("Step {} failed", index));
+                journal.save()?;
+
+                emit_log(app, "error", format!("Run {} failed at step {}", journal.run_id, index));
+                return Err(output);
             }
         }
     }
 
+    journal.status = RunStatus::Completed;
+    journal.save()?;
+
+    emit_log(app, "info", format!("Run {} completed", journal.run_id));
     Ok(())
 }
+
+/// Run one resolved step, honouring its retry policy and timeout.
+/// Returns combined stdout/stderr and the number of attempts made.
+fn run_step(app: &AppHandle, step: &PipelineStep) -> (Result<String, String>, u32) {
+    let (tool, args, retry, timeout_secs, level) = match step {
+        PipelineStep::Message { text } => {
+            emit_log(app, "info", format!("[PIPELINE] {}", text));
+            return (Ok(text.clone()), 1);
+        }
+
+        // Checked by the planner against the approved snapshot
+        PipelineStep::Require { message, .. } => {
+            return (Ok(format!("Requirement checked at planning: {}", message)), 1);
+        }
+
+        PipelineStep::AdbCommand { args, retry, timeout_secs } => {
+            ("adb", args, retry, timeout_secs, "info")
+        }
+
+        PipelineStep::FastbootCommand { args, retry, timeout_secs } => {
+            ("fastboot", args, retry, timeout_secs, "warn")
+        }
+
+        PipelineStep::When { .. } => {
+            return (Err("Unflattened branch in execution plan".into()), 0);
+        }
+    };
+
+    let attempts = retry.as_ref().map(|r| r.attempts.max(1)).unwrap_or(1);
+    let backoff_ms = retry.as_ref().map(|r| r.backoff_ms).unwrap_or(0);
+    let timeout = timeout_secs.map(Duration::from_secs);
+
+    let mut attempt = 1;
+
+    loop {
+        emit_log(app, level, format!("[PIPELINE] {} {}", tool, args.join(" ")));
+
+        match run_tool(tool, args, timeout) {
+            Ok(output) => return (Ok(output), attempt),
+
+            Err(output) if attempt < attempts => {
+                let delay = retry_delay(backoff_ms, attempt);
+
+                emit_log(
+                    app,
+                    "warn",
+                    format!(
+                        "[PIPELINE] attempt {}/{} failed, retrying in {} ms: {}",
+                        attempt,
+                        attempts,
+                        delay,
+                        output.lines().last().unwrap_or("")
+                    ),
+                );
+
+                thread::sleep(Duration::from_millis(delay));
+                attempt += 1;
+            }
+
+            Err(output) => return (Err(output), attempt),
+        }
+    }
+}
+
+/// Delay after failed `attempt` (1-based): `backoff_ms`, doubling each time.
+fn retry_delay(backoff_ms: u64, attempt: u32) -> u64 {
+    backoff_ms.saturating_mul(1 << (attempt - 1).min(16))
+}
+
+fn run_tool(tool: &str, args: &[String], timeout: Option<Duration>) -> Result<String, String> {
+    let out = run_with_timeout(tool, args, timeout)?;
+
+    let mut output = String::new();
+    output.push_str(&String::from_utf8_lossy(&out.stdout));
+    output.push_str(&String::from_utf8_lossy(&out.stderr));
+
+    if out.status.success() {
+        Ok(output)
+    } else {
+        Err(format!("{} command failed\n{}", tool, output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_and_saturates() {
+        assert_eq!(retry_delay(500, 1), 500);
+        assert_eq!(retry_delay(500, 2), 1000);
+        assert_eq!(retry_delay(500, 4), 4000);
+        assert_eq!(retry_delay(1, 40), 1 << 16);
+        assert_eq!(retry_delay(u64::MAX, 3), u64::MAX);
+        assert_eq!(retry_delay(0, 5), 0);
+    }
+
+    #[test]
+    fn run_error_carries_run_id() {
+        let error = RunError { run_id: Some("1-abcdef12-0".into()), message: "boom".into() };
+        let json = serde_json::to_value(&error).unwrap();
+
+        assert_eq!(json["run_id"], "1-abcdef12-0");
+        assert_eq!(error.to_string(), "run 1-abcdef12-0: boom");
+        assert_eq!(RunError::from("early".to_string()).to_string(), "early");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_tool_failure_with_output() {
+        let args = vec!["-c".to_string(), "echo out; echo err >&2; exit 3".to_string()];
+        let err = run_tool("sh", &args, None).unwrap_err();
+
+        assert!(err.starts_with("sh command failed"));
+        assert!(err.contains("out") && err.contains("err"));
+    }
+}
@@ -0,0 +1,284 @@
+pub mod factory;
+pub mod flashfile;
+pub mod package;
+pub mod scatter;
+pub mod vbmeta;
+
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use md5::Md5;
+use std::{
+    fs,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+use zip::ZipArchive;
+
+use crate::pipeline::{FlashPipeline, PipelineStep};
+use crate::risk::{classify_fastboot_args, RiskLevel};
+use crate::tools::tools_root_dir;
+
+// NOTE:
+// Importers turn vendor firmware packages into `FlashPipeline`s.
+// They never talk to the device; the planner and executor do that.
+
+/// Result of importing a firmware package.
+#[derive(Debug, Clone, Serialize)]
+pub struct FirmwareImport {
+    pub pipeline: FlashPipeline,
+    /// Directory the pipeline's image paths point into
+    pub source_dir: String,
+    pub files_verified: usize,
+    pub max_risk: RiskLevel,
+    pub warnings: Vec<String>,
+}
+
+impl FirmwareImport {
+    pub fn new(pipeline: FlashPipeline, source_dir: &Path, files_verified: usize) -> Self {
+        let max_risk = pipeline_max_risk(&pipeline.steps);
+
+        Self {
+            pipeline,
+            source_dir: source_dir.to_string_lossy().to_string(),
+            files_verified,
+            max_risk,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// Highest static risk of any command in `steps`, branches included.
+pub fn pipeline_max_risk(steps: &[PipelineStep]) -> RiskLevel {
+    steps
+        .iter()
+        .map(|step| match step {
+            PipelineStep::FastbootCommand { args, .. } => classify_fastboot_args(args).level,
+            PipelineStep::When { steps, otherwise, .. } => {
+                pipeline_max_risk(steps).max(pipeline_max_risk(otherwise))
+            }
+            _ => RiskLevel::None,
+        })
+        .max()
+        .unwrap_or(RiskLevel::None)
+}
+
+/* ================= SLOTS ================= */
+
+/// How explicit `_a` / `_b` suffixes in a package are treated.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum SlotMode {
+    /// Flash exactly the slots named in the package.
+    #[default]
+    AsPackaged,
+    /// Retarget suffixed partitions to the device's current slot.
+    ActiveSlot,
+}
+
+pub fn has_slot_suffix(partition: &str) -> bool {
+    partition.ends_with("_a") || partition.ends_with("_b")
+}
+
+pub fn retarget_slot(partition: &str, mode: SlotMode) -> String {
+    match mode {
+        SlotMode::ActiveSlot if has_slot_suffix(partition) => {
+            format!("{}_${{slot}}", &partition[..partition.len() - 2])
+        }
+        _ => partition.to_string(),
+    }
+}
+
+/* ================= PACKAGES ================= */
+
+pub fn firmware_dir() -> PathBuf {
+    tools_root_dir().join("firmware")
+}
+
+/// Directories are used in place; zip archives are unpacked once into
+/// the firmware cache and reused on later imports of the same file.
+pub fn unpack_package(path: &Path) -> Result<PathBuf, String> {
+    if path.is_dir() {
+        return Ok(path.to_path_buf());
+    }
+
+    let dest = firmware_dir().join(cache_name(path)?);
+
+    if !dest.exists() {
+        extract_zip(path, &dest)?;
+    }
+
+    Ok(dest)
+}
+
+/// Cache directory name for a package zip. Size and mtime are part of
+/// the name so a different zip saved under the same file name is never
+/// served from an older extraction.
+pub fn cache_name(path: &Path) -> Result<String, String> {
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or("Invalid package path")?;
+
+    let meta = fs::metadata(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+
+    Ok(format!("{}-{:x}-{:x}", stem, meta.len(), mtime))
+}
+
+/// Extract `zip` into `dest`, skipping entries that would escape it.
+pub fn extract_zip(zip: &Path, dest: &Path) -> Result<(), String> {
+    let file = fs::File::open(zip).map_err(|e| e.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
+
+    // Unpack next to the destination, then move into place, so a failed
+    // extraction is never mistaken for a cached one.
+    let partial = dest.with_extension("partial");
+    fs::remove_dir_all(&partial).ok();
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
+
+        let Some(name) = entry.enclosed_name().map(|n| n.to_path_buf()) else {
+            continue;
+        };
+        let outpath = partial.join(name);
+
+        if entry.is_dir() {
+            fs::create_dir_all(&outpath).map_err(|e| e.to_string())?;
+        } else {
+            if let Some(parent) = outpath.parent() {
+                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            let mut outfile = fs::File::create(&outpath).map_err(|e| e.to_string())?;
+            io::copy(&mut entry, &mut outfile).map_err(|e| e.to_string())?;
+        }
+    }
+
+    fs::rename(&partial, dest).map_err(|e| e.to_string())
+}
+
+/// Join a file name taken from a package manifest onto `base`, refusing
+/// names that could point outside the package.
+pub fn package_path(base: &Path, name: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(name);
+
+    let contained = relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+
+    if name.is_empty() || !contained {
+        return Err(format!("Refusing file name outside the package: {}", name));
+    }
+
+    Ok(base.join(relative))
+}
+
+/// Find `name` at the package root or one directory below it, since
+/// many vendor zips wrap everything in a top-level folder.
+pub fn find_in_package(root: &Path, name: &str) -> Option<PathBuf> {
+    let direct = root.join(name);
+    if direct.is_file() {
+        return Some(direct);
+    }
+
+    fs::read_dir(root)
+        .ok()?
+        .flatten()
+        .map(|e| e.path().join(name))
+        .find(|p| p.is_file())
+}
+
+/* ================= HASHING ================= */
+
+const HASH_BUF_SIZE: usize = 1024 * 1024;
+
+pub fn md5_file(path: &Path) -> Result<String, String> {
+    hash_file::<Md5>(path)
+}
+
+/// Streams the file so multi-GB images are never held in memory.
+fn hash_file<D: Digest>(path: &Path) -> Result<String, String> {
+    let mut file = fs::File::open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let mut hasher = D::new();
+    let mut buf = vec![0u8; HASH_BUF_SIZE];
+
+    loop {
+        let n = file.read(&mut buf).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_path_stays_inside_base() {
+        let base = Path::new("/fw");
+
+        assert_eq!(package_path(base, "boot.img").unwrap(), base.join("boot.img"));
+        assert_eq!(package_path(base, "./img/lk.bin").unwrap(), base.join("./img/lk.bin"));
+        assert!(package_path(base, "").is_err());
+        assert!(package_path(base, "../boot.img").is_err());
+        assert!(package_path(base, "img/../../boot.img").is_err());
+        assert!(package_path(base, "/boot.img").is_err());
+    }
+
+    #[test]
+    fn cache_name_follows_package_contents() {
+        let dir = std::env::temp_dir().join(format!("fw-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let zip = dir.join("stock.zip");
+
+        fs::write(&zip, b"first").unwrap();
+        let first = cache_name(&zip).unwrap();
+        fs::write(&zip, b"second build").unwrap();
+        let second = cache_name(&zip).unwrap();
+        let missing = cache_name(&dir.join("missing.zip"));
+
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(first.starts_with("stock-5-"));
+        assert!(second.starts_with("stock-c-"));
+        assert_ne!(first, second);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn retargets_only_suffixed_partitions() {
+        assert_eq!(retarget_slot("boot_a", SlotMode::ActiveSlot), "boot_${slot}");
+        assert_eq!(retarget_slot("boot_b", SlotMode::AsPackaged), "boot_b");
+        assert_eq!(retarget_slot("preloader", SlotMode::ActiveSlot), "preloader");
+    }
+
+    #[test]
+    fn max_risk_includes_branches() {
+        let steps = vec![
+            PipelineStep::fastboot(&["getvar", "all"]),
+            PipelineStep::When {
+                condition: crate::pipeline::StepCondition::SlotAb,
+                steps: vec![],
+                otherwise: vec![PipelineStep::fastboot(&["flash", "preloader", "p.bin"])],
+            },
+        ];
+
+        assert_eq!(pipeline_max_risk(&steps), RiskLevel::Critical);
+        assert_eq!(pipeline_max_risk(&[]), RiskLevel::None);
+    }
+}
@@ -0,0 +1,389 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::firmware::{find_in_package, unpack_package, FirmwareImport};
+use crate::pipeline::{pipeline_id_fragment, FlashPipeline, PipelineStep, StepCondition};
+
+// AOSP factory packages (Pixel factory zips, GSI bundles):
+//
+//   <device>-<build>/
+//     flash-all.sh
+//     bootloader-<device>-<version>.img
+//     radio-<device>-<version>.img
+//     image-<device>-<build>.zip   (android-info.txt + partition images)
+//
+// The generated pipeline follows `fastboot update`: bootloader and radio
+// first, then physical partitions, then super_empty.img's layout and the
+// logical partitions from fastbootd.
+
+/// Flashed from the bootloader, in this order.
+const PHYSICAL_IMAGES: &[&str] = &[
+    "boot",
+    "init_boot",
+    "dtbo",
+    "pvmfw",
+    "vendor_boot",
+    "vendor_kernel_boot",
+    "recovery",
+    "vbmeta",
+    "vbmeta_system",
+    "vbmeta_vendor",
+];
+
+/// Live inside `super` on dynamic-partition devices.
+const LOGICAL_IMAGES: &[&str] = &[
+    "system",
+    "system_ext",
+    "system_dlkm",
+    "product",
+    "vendor",
+    "vendor_dlkm",
+    "odm",
+    "odm_dlkm",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Requirement {
+    pub key: String,
+    pub values: Vec<String>,
+    /// Set for `require-for-product:<product>` lines
+    pub product: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FactoryOptions {
+    /// Wipe userdata/metadata like `flash-all.sh` (`fastboot -w`)
+    #[serde(default)]
+    pub wipe: bool,
+}
+
+pub fn parse_android_info(text: &str) -> Vec<Requirement> {
+    let mut reqs = Vec::new();
+
+    for line in text.lines().map(|l| l.trim()) {
+        let (product, rest) = if let Some(rest) = line.strip_prefix("require-for-product:") {
+            match rest.split_once(char::is_whitespace) {
+                Some((product, rest)) => (Some(product.to_string()), rest),
+                None => continue,
+            }
+        } else if let Some(rest) = line.strip_prefix("require ") {
+            (None, rest)
+        } else {
+            continue;
+        };
+
+        if let Some((key, values)) = rest.trim().split_once('=') {
+            reqs.push(Requirement {
+                key: key.trim().to_string(),
+                values: values.split('|').map(|v| v.trim().to_string()).collect(),
+                product,
+            });
+        }
+    }
+
+    reqs
+}
+
+/// `board` in android-info.txt is reported as `product` by fastboot.
+fn getvar_name(key: &str) -> &str {
+    match key {
+        "board" => "product",
+        other => other,
+    }
+}
+
+fn requirement_condition(req: &Requirement) -> StepCondition {
+    StepCondition::Any(
+        req.values
+            .iter()
+            .map(|v| StepCondition::Getvar {
+                name: getvar_name(&req.key).to_string(),
+                equals: v.clone(),
+            })
+            .collect(),
+    )
+}
+
+/// Import an AOSP factory package (zip or extracted directory) as a pipeline.
+pub fn import_factory_image(
+    package: &Path,
+    options: &FactoryOptions,
+) -> Result<FirmwareImport, String> {
+    let root = unpack_package(package)?;
+    let base = find_in_package(&root, "flash-all.sh")
+        .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+        .unwrap_or(root);
+
+    let bootloader = find_prefixed(&base, "bootloader-", ".img");
+    let radio = find_prefixed(&base, "radio-", ".img");
+
+    let inner_zip = find_prefixed(&base, "image-", ".zip")
+        .ok_or("No image-*.zip found in factory package")?;
+    let images = unpack_package(&inner_zip)?;
+
+    let info = fs::read_to_string(images.join("android-info.txt"))
+        .map_err(|e| format!("android-info.txt: {}", e))?;
+    let requirements = parse_android_info(&info);
+
+    let mut steps = Vec::new();
+    let mut warnings = Vec::new();
+
+    steps.push(PipelineStep::Message {
+        text: format!("Factory image {}", file_name(&inner_zip)),
+    });
+
+    /* ---- REQUIREMENTS ---- */
+
+    let board = requirements
+        .iter()
+        .find(|r| r.key == "board" && r.product.is_none())
+        .map(|r| r.values.join("|"));
+
+    for req in &requirements {
+        // Bootloader / baseband versions are satisfied by the bundled
+        // images, which are flashed before anything else.
+        let bundled = match req.key.as_str() {
+            "version-bootloader" => bootloader.as_ref(),
+            "version-baseband" => radio.as_ref(),
+            _ => None,
+        };
+
+        if let Some(image) = bundled {
+            if req.values.iter().any(|v| file_name(image).contains(v.as_str())) {
+                continue;
+            }
+            warnings.push(format!(
+                "{} does not match required {} {}",
+                file_name(image),
+                req.key,
+                req.values.join("|")
+            ));
+        }
+
+        if req.key == "partition-exists" {
+            warnings.push(format!("Not checked: partition-exists={}", req.values.join("|")));
+            continue;
+        }
+
+        let condition = requirement_condition(req);
+
+        let condition = match &req.product {
+            // Only applies when the device reports that product
+            Some(product) => StepCondition::Any(vec![
+                StepCondition::Not(Box::new(StepCondition::Getvar {
+                    name: "product".into(),
+                    equals: product.clone(),
+                })),
+                condition,
+            ]),
+            None => condition,
+        };
+
+        steps.push(PipelineStep::Require {
+            condition,
+            message: format!("android-info.txt requires {}={}", req.key, req.values.join("|")),
+        });
+    }
+
+    /* ---- BOOTLOADER / RADIO ---- */
+
+    for (partition, image) in [("bootloader", &bootloader), ("radio", &radio)] {
+        if let Some(image) = image {
+            steps.push(PipelineStep::fastboot(&["flash", partition, &path_str(image)]));
+            steps.push(PipelineStep::fastboot(&["reboot-bootloader"]));
+        }
+    }
+
+    /* ---- PARTITIONS ---- */
+
+    let mut flashed = Vec::new();
+
+    for name in PHYSICAL_IMAGES {
+        let image = images.join(format!("{}.img", name));
+        if image.is_file() {
+            steps.push(PipelineStep::fastboot(&["flash", name, &path_str(&image)]));
+            flashed.push(name.to_string());
+        }
+    }
+
+    let super_empty = images.join("super_empty.img");
+
+    if super_empty.is_file() {
+        // Logical partitions can only be written by userspace fastboot,
+        // into a super layout matching this build
+        steps.push(PipelineStep::fastboot(&["reboot", "fastboot"]));
+        steps.push(PipelineStep::fastboot(&["wipe-super", &path_str(&super_empty)]));
+    }
+
+    for name in LOGICAL_IMAGES {
+        let image = images.join(format!("{}.img", name));
+        if image.is_file() {
+            steps.push(PipelineStep::fastboot(&["flash", name, &path_str(&image)]));
+            flashed.push(name.to_string());
+        }
+    }
+
+    if flashed.is_empty() {
+        return Err("Factory image contains no flashable partitions".into());
+    }
+
+    for entry in fs::read_dir(&images).map_err(|e| e.to_string())?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some(stem) = name.strip_suffix(".img") {
+            if stem != "super_empty" && !flashed.iter().any(|f| f == stem) {
+                warnings.push(format!("{} is not flashed by this pipeline", name));
+            }
+        }
+    }
+
+    if options.wipe {
+        steps.push(PipelineStep::fastboot(&["-w"]));
+    }
+
+    steps.push(PipelineStep::fastboot(&["reboot"]));
+
+    let product = board.as_deref().unwrap_or("unknown");
+    let build = file_name(&inner_zip);
+
+    let pipeline = FlashPipeline {
+        id: format!("factory-{}", pipeline_id_fragment(build.trim_end_matches(".zip"))),
+        description: format!("Factory image restore: {} ({})", product, build),
+        requires_adb: false,
+        requires_fastboot: true,
+        destructive: true,
+        variables: BTreeMap::new(),
+        steps,
+    };
+
+    let mut import = FirmwareImport::new(pipeline, &images, 0);
+    import.warnings = warnings;
+
+    Ok(import)
+}
+
+fn find_prefixed(dir: &Path, prefix: &str, suffix: &str) -> Option<PathBuf> {
+    fs::read_dir(dir).ok()?.flatten().map(|e| e.path()).find(|p| {
+        let name = file_name(p);
+        name.starts_with(prefix) && name.ends_with(suffix)
+    })
+}
+
+fn file_name(path: &Path) -> &str {
+    path.file_name().and_then(|n| n.to_str()).unwrap_or("")
+}
+
+fn path_str(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::firmware::firmware_dir;
+    use std::io::Write;
+    use zip::{write::FileOptions, ZipWriter};
+
+    const ANDROID_INFO: &str = "require board=sunfish\n\
+        require version-bootloader=s5-0.5\n\
+        require-for-product:sunfish-pro version-baseband=g7150\n\
+        require partition-exists=vendor_dlkm\n";
+
+    /// Factory package directory whose image zip holds `images`.
+    fn package(name: &str, images: &[&str]) -> (PathBuf, PathBuf) {
+        let stem = format!("image-sunfish-{}-{}", name, std::process::id());
+        let dir = std::env::temp_dir().join(format!("factory-{}", stem));
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join("flash-all.sh"), "fastboot update image.zip\n").unwrap();
+        fs::write(dir.join("bootloader-sunfish-s5-0.5.img"), b"bl").unwrap();
+
+        let file = fs::File::create(dir.join(format!("{}.zip", stem))).unwrap();
+        let mut zip = ZipWriter::new(file);
+        zip.start_file("android-info.txt", FileOptions::default()).unwrap();
+        zip.write_all(ANDROID_INFO.as_bytes()).unwrap();
+        for image in images {
+            zip.start_file(format!("{}.img", image), FileOptions::default()).unwrap();
+            zip.write_all(b"img").unwrap();
+        }
+        zip.finish().unwrap();
+
+        (dir, firmware_dir().join(stem))
+    }
+
+    fn fastboot_args(steps: &[PipelineStep]) -> Vec<Vec<String>> {
+        steps
+            .iter()
+            .filter_map(|s| match s {
+                PipelineStep::FastbootCommand { args, .. } => {
+                    Some(args.iter().map(|a| a.rsplit('/').next().unwrap().to_string()).collect())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_android_info() {
+        let reqs = parse_android_info(ANDROID_INFO);
+
+        assert_eq!(reqs.len(), 4);
+        assert_eq!(reqs[0].key, "board");
+        assert_eq!(reqs[0].values, vec!["sunfish"]);
+        assert_eq!(reqs[2].product.as_deref(), Some("sunfish-pro"));
+        assert_eq!(reqs[2].key, "version-baseband");
+    }
+
+    #[test]
+    fn imports_dynamic_factory_image() {
+        let (dir, unpacked) = package("dynamic", &["boot", "vbmeta", "system", "super_empty"]);
+
+        let import = import_factory_image(&dir, &FactoryOptions { wipe: true });
+        fs::remove_dir_all(&dir).ok();
+        fs::remove_dir_all(&unpacked).ok();
+        let import = import.unwrap();
+
+        let args = fastboot_args(&import.pipeline.steps);
+        let expected: Vec<Vec<&str>> = vec![
+            vec!["flash", "bootloader", "bootloader-sunfish-s5-0.5.img"],
+            vec!["reboot-bootloader"],
+            vec!["flash", "boot", "boot.img"],
+            vec!["flash", "vbmeta", "vbmeta.img"],
+            vec!["reboot", "fastboot"],
+            vec!["wipe-super", "super_empty.img"],
+            vec!["flash", "system", "system.img"],
+            vec!["-w"],
+            vec!["reboot"],
+        ];
+        assert_eq!(args, expected);
+
+        // board and the product-scoped baseband become requirements; the
+        // bundled bootloader satisfies version-bootloader
+        let requires = import
+            .pipeline
+            .steps
+            .iter()
+            .filter(|s| matches!(s, PipelineStep::Require { .. }))
+            .count();
+        assert_eq!(requires, 2);
+        assert_eq!(import.max_risk, crate::risk::RiskLevel::Critical);
+        assert!(import.warnings.iter().any(|w| w.contains("partition-exists")));
+    }
+
+    #[test]
+    fn static_image_skips_fastbootd() {
+        let (dir, unpacked) = package("static", &["boot", "system", "extra"]);
+
+        let import = import_factory_image(&dir, &FactoryOptions::default());
+        fs::remove_dir_all(&dir).ok();
+        fs::remove_dir_all(&unpacked).ok();
+        let import = import.unwrap();
+
+        let args = fastboot_args(&import.pipeline.steps);
+        assert!(!args.iter().any(|a| a[0] == "wipe-super" || a[..] == ["reboot", "fastboot"]));
+        assert!(import.warnings.iter().any(|w| w == "extra.img is not flashed by this pipeline"));
+    }
+}
@@ -0,0 +1,320 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::firmware::{
+    find_in_package, has_slot_suffix, md5_file, package_path, retarget_slot, unpack_package,
+    FirmwareImport, SlotMode,
+};
+use crate::pipeline::{pipeline_id_fragment, FlashPipeline, PipelineStep};
+
+// Motorola fastboot packages describe the flash sequence in
+// `flashfile.xml` (full restore) or `servicefile.xml` (keeps userdata):
+//
+//   <step operation="flash" partition="boot_a" filename="boot.img" MD5="..."/>
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FlashfileStep {
+    pub operation: String,
+    pub partition: Option<String>,
+    pub filename: Option<String>,
+    pub md5: Option<String>,
+    pub var: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Flashfile {
+    pub model: Option<String>,
+    pub version: Option<String>,
+    pub steps: Vec<FlashfileStep>,
+}
+
+pub fn parse_flashfile(xml: &str) -> Result<Flashfile, String> {
+    let doc = roxmltree::Document::parse(xml).map_err(|e| format!("Invalid flashfile: {}", e))?;
+
+    let attr = |tag: &str, name: &str| {
+        doc.descendants()
+            .find(|n| n.has_tag_name(tag))
+            .and_then(|n| n.attribute(name))
+            .map(|v| v.to_string())
+    };
+
+    let steps: Vec<FlashfileStep> = doc
+        .descendants()
+        .filter(|n| n.has_tag_name("step"))
+        .map(|n| FlashfileStep {
+            operation: n.attribute("operation").unwrap_or("").to_string(),
+            partition: n.attribute("partition").map(|v| v.to_string()),
+            filename: n.attribute("filename").map(|v| v.to_string()),
+            md5: n
+                .attribute("MD5")
+                .or_else(|| n.attribute("md5"))
+                .map(|v| v.to_lowercase()),
+            var: n.attribute("var").map(|v| v.to_string()),
+        })
+        .collect();
+
+    if steps.is_empty() {
+        return Err("Flashfile contains no steps".into());
+    }
+
+    Ok(Flashfile {
+        model: attr("phone_model", "model"),
+        version: attr("software_version", "version"),
+        steps,
+    })
+}
+
+/// Import a Motorola package (zip or extracted directory) as a pipeline.
+/// Every referenced file must exist and match its MD5.
+pub fn import_flashfile(package: &Path, slot_mode: SlotMode) -> Result<FirmwareImport, String> {
+    let root = unpack_package(package)?;
+
+    let xml_path = find_in_package(&root, "flashfile.xml")
+        .or_else(|| find_in_package(&root, "servicefile.xml"))
+        .ok_or("No flashfile.xml or servicefile.xml in package")?;
+
+    let base = xml_path.parent().unwrap_or(&root).to_path_buf();
+    let xml = fs::read_to_string(&xml_path).map_err(|e| e.to_string())?;
+    let flashfile = parse_flashfile(&xml)?;
+
+    let mut problems = Vec::new();
+    let mut warnings = Vec::new();
+    let mut verified = 0;
+    let mut steps = Vec::new();
+    let mut destructive = false;
+    let mut explicit_slots = false;
+
+    steps.push(PipelineStep::Message {
+        text: format!(
+            "Motorola firmware {} for {}",
+            flashfile.version.as_deref().unwrap_or("(unknown version)"),
+            flashfile.model.as_deref().unwrap_or("(unknown model)")
+        ),
+    });
+
+    for (i, step) in flashfile.steps.iter().enumerate() {
+        match step.operation.as_str() {
+            "flash" => {
+                let (Some(partition), Some(filename)) = (&step.partition, &step.filename) else {
+                    problems.push(format!("Step {}: flash without partition or filename", i));
+                    continue;
+                };
+
+                let file = match package_path(&base, filename) {
+                    Ok(file) => file,
+                    Err(e) => {
+                        problems.push(format!("Step {}: {}", i, e));
+                        continue;
+                    }
+                };
+
+                match (&step.md5, md5_file(&file)) {
+                    (_, Err(e)) => problems.push(format!("Missing {}: {}", filename, e)),
+                    (Some(expected), Ok(actual)) if *expected != actual => problems.push(format!(
+                        "MD5 mismatch for {}: expected {}, got {}",
+                        filename, expected, actual
+                    )),
+                    (Some(_), Ok(_)) => verified += 1,
+                    (None, Ok(_)) => warnings.push(format!("{} has no MD5 in flashfile", filename)),
+                }
+
+                explicit_slots |= has_slot_suffix(partition);
+                destructive = true;
+
+                let partition = retarget_slot(partition, slot_mode);
+                let file = file.to_string_lossy();
+                steps.push(PipelineStep::fastboot(&["flash", &partition, &file]));
+            }
+
+            "erase" => {
+                let Some(partition) = &step.partition else {
+                    problems.push(format!("Step {}: erase without partition", i));
+                    continue;
+                };
+
+                destructive = true;
+                let partition = retarget_slot(partition, slot_mode);
+                steps.push(PipelineStep::fastboot(&["erase", &partition]));
+            }
+
+            "getvar" | "oem" => {
+                let Some(var) = &step.var else {
+                    problems.push(format!("Step {}: {} without var", i, step.operation));
+                    continue;
+                };
+
+                let mut args = vec![step.operation.as_str()];
+                args.extend(var.split_whitespace());
+                steps.push(PipelineStep::fastboot(&args));
+            }
+
+            // Without a slot the planner blocks until the user picks one
+            "set_active" => {
+                let slot = step.var.as_deref().unwrap_or("${set_active_slot}");
+                steps.push(PipelineStep::fastboot(&["set_active", slot]));
+
+                if step.var.is_none() {
+                    warnings.push(format!(
+                        "Step {}: set_active names no slot; set ${{set_active_slot}} to run it",
+                        i
+                    ));
+                }
+            }
+
+            "reboot" | "reboot-bootloader" | "continue" => {
+                steps.push(PipelineStep::fastboot(&[step.operation.as_str()]));
+            }
+
+            // Refusing beats silently skipping a step the vendor thought necessary
+            other => problems.push(format!("Step {}: unsupported operation '{}'", i, other)),
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(format!("Flashfile import failed:\n{}", problems.join("\n")));
+    }
+
+    if explicit_slots && matches!(slot_mode, SlotMode::AsPackaged) {
+        warnings.push(
+            "Package names explicit slots; they are flashed as-is regardless of the active slot"
+                .into(),
+        );
+    }
+
+    let model = flashfile.model.as_deref().unwrap_or("unknown");
+    let version = flashfile.version.as_deref().unwrap_or("unknown");
+
+    let pipeline = FlashPipeline {
+        id: format!("moto-{}-{}", pipeline_id_fragment(model), pipeline_id_fragment(version)),
+        description: format!("Motorola stock restore: {} ({})", model, version),
+        requires_adb: false,
+        requires_fastboot: true,
+        destructive,
+        variables: BTreeMap::new(),
+        steps,
+    };
+
+    let mut import = FirmwareImport::new(pipeline, &base, verified);
+    import.warnings = warnings;
+
+    Ok(import)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detection_service::DeviceState;
+    use crate::planner::plan_pipeline;
+    use crate::snapshot::DeviceSnapshot;
+    use std::path::PathBuf;
+
+    const BOOT: &[u8] = b"ANDROID!boot";
+    // md5 of BOOT
+    const BOOT_MD5: &str = "1b02f59b154e4844ea02ea2e79ffddcf";
+
+    fn package(name: &str, xml: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("flashfile-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("flashfile.xml"), xml).unwrap();
+        fs::write(dir.join("boot.img"), BOOT).unwrap();
+        dir
+    }
+
+    fn flashfile(steps: &str) -> String {
+        format!(
+            r#"<?xml version="1.0"?>
+<flashing>
+  <header>
+    <phone_model model="kansas"/>
+    <software_version version="kansas_g-user 14 U1TKS34.1"/>
+  </header>
+  <steps interface="AP">
+{}
+  </steps>
+</flashing>"#,
+            steps
+        )
+    }
+
+    #[test]
+    fn parses_steps_and_header() {
+        let xml = flashfile(&format!(
+            r#"<step operation="getvar" var="max-sparse-size"/>
+<step operation="flash" partition="boot_a" filename="boot.img" MD5="{}"/>
+<step operation="erase" partition="userdata"/>"#,
+            BOOT_MD5.to_uppercase()
+        ));
+
+        let parsed = parse_flashfile(&xml).unwrap();
+
+        assert_eq!(parsed.model.as_deref(), Some("kansas"));
+        assert_eq!(parsed.version.as_deref(), Some("kansas_g-user 14 U1TKS34.1"));
+        assert_eq!(parsed.steps.len(), 3);
+        assert_eq!(parsed.steps[1].md5.as_deref(), Some(BOOT_MD5));
+        assert_eq!(parsed.steps[2].partition.as_deref(), Some("userdata"));
+        assert!(parse_flashfile("<flashing/>").is_err());
+    }
+
+    #[test]
+    fn imports_verified_package() {
+        let dir = package(
+            "ok",
+            &flashfile(&format!(
+                r#"<step operation="flash" partition="boot_a" filename="boot.img" MD5="{}"/>
+<step operation="erase" partition="userdata"/>
+<step operation="reboot"/>"#,
+                BOOT_MD5
+            )),
+        );
+
+        let import = import_flashfile(&dir, SlotMode::ActiveSlot).unwrap();
+        fs::remove_dir_all(&dir).ok();
+
+        let pipeline = &import.pipeline;
+        assert_eq!(import.files_verified, 1);
+        assert!(pipeline.destructive);
+        assert!(pipeline.id.starts_with("moto-kansas-"));
+
+        let PipelineStep::FastbootCommand { args, .. } = &pipeline.steps[1] else {
+            panic!("expected flash step");
+        };
+        assert_eq!(args[..2], ["flash", "boot_${slot}"]);
+        assert!(args[2].ends_with("boot.img"));
+    }
+
+    #[test]
+    fn rejects_md5_mismatch_and_escaping_paths() {
+        let dir = package(
+            "bad",
+            &flashfile(
+                r#"<step operation="flash" partition="boot" filename="boot.img" MD5="00"/>
+<step operation="flash" partition="lk" filename="../lk.img"/>
+<step operation="flash" partition="tee" filename="/etc/passwd"/>"#,
+            ),
+        );
+
+        let err = import_flashfile(&dir, SlotMode::AsPackaged).unwrap_err();
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(err.contains("MD5 mismatch for boot.img"));
+        assert!(err.contains("Step 1: Refusing file name outside the package: ../lk.img"));
+        assert!(err.contains("Step 2: Refusing file name outside the package: /etc/passwd"));
+    }
+
+    #[test]
+    fn set_active_without_slot_blocks_plan() {
+        let dir = package("slot", &flashfile(r#"<step operation="set_active"/>"#));
+
+        let import = import_flashfile(&dir, SlotMode::AsPackaged).unwrap();
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(import.warnings[0].contains("set_active names no slot"));
+
+        let snapshot = DeviceSnapshot::empty(DeviceState::Fastboot);
+        let plan = plan_pipeline(&import.pipeline, &snapshot, &BTreeMap::new());
+        assert_eq!(plan.blockers, vec!["Step 1: unresolved variable ${set_active_slot}"]);
+
+        let overrides = BTreeMap::from([("set_active_slot".to_string(), "b".to_string())]);
+        assert!(plan_pipeline(&import.pipeline, &snapshot, &overrides).is_executable());
+    }
+}
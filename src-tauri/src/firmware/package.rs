@@ -0,0 +1,561 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, BufReader, Cursor, Read},
+    path::Path,
+};
+use zip::ZipArchive;
+
+use crate::android::{avb, bootimg, dtbo, fsimage, lp, payload, sparse};
+use crate::android::bootimg::BootImageKind;
+use crate::firmware::{factory::parse_android_info, flashfile::parse_flashfile};
+use crate::mtk::{partition, preloader, scatter::parse_scatter};
+
+// Identify what a dropped zip is before anything is unpacked: the
+// marker files inside decide the package type, every image is sniffed
+// and hashed while streaming out of the archive, and the report names
+// the import that handles it.
+//
+//   flashfile.xml / servicefile.xml      Motorola fastboot package
+//   *_scatter.txt                        MTK SP Flash Tool package
+//   flash-all.sh + image-*.zip           AOSP factory image
+//   android-info.txt                     factory image-*.zip on its own
+//   payload.bin                          A/B OTA
+//   META-INF/.../update-binary           recovery-flashable zip
+
+const UPDATE_BINARY: &str = "META-INF/com/google/android/update-binary";
+
+/// Package metadata rather than images; listed as markers only.
+const METADATA_EXTENSIONS: &[&str] = &["xml", "txt", "sh", "bat", "cfg", "prop", "md", "html"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageReport {
+    pub path: String,
+    /// "motorola", "mtk_scatter", "factory", "factory_images", "ota",
+    /// "recovery_zip" or "unknown"
+    pub kind: &'static str,
+    pub description: String,
+    /// Entries that identified the package type
+    pub markers: Vec<String>,
+    pub images: Vec<PackageImage>,
+    pub import: ImportProposal,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageImage {
+    /// Path inside the archive
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+    /// Detected from content, e.g. "sparse", "boot", "ext4"
+    pub format: &'static str,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportProposal {
+    /// Command that takes this package as-is, if there is one
+    pub command: Option<&'static str>,
+    pub summary: String,
+}
+
+/* ================= INSPECT ================= */
+
+/// Identify a firmware zip, list its images with their formats and
+/// SHA-256, and propose how to import it. Nothing is written to disk.
+pub fn inspect_package(path: &Path) -> Result<PackageReport, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut archive =
+        ZipArchive::new(BufReader::new(file)).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    let names: Vec<String> = archive.file_names().map(String::from).collect();
+    let mut warnings = Vec::new();
+
+    let mut report = identify(&mut archive, &names, &mut warnings);
+    report.path = path.display().to_string();
+
+    if report.kind == "ota" {
+        describe_ota(path, &mut report, &mut warnings);
+    }
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
+
+        if entry.is_dir() || is_metadata(entry.name()) || entry.name().starts_with("META-INF/") {
+            continue;
+        }
+
+        let name = entry.name().to_string();
+        let size = entry.size();
+        let compressed_size = entry.compressed_size();
+
+        let (format, sha256) =
+            sniff_and_hash(&mut entry).map_err(|e| format!("{}: {}", name, e))?;
+
+        report.images.push(PackageImage { name, size, compressed_size, format, sha256 });
+    }
+
+    if report.kind == "unknown" && !report.images.is_empty() {
+        report.import.summary = format!(
+            "No flashing instructions found; {} image(s) can be flashed individually",
+            report.images.len()
+        );
+    }
+
+    report.warnings = warnings;
+    Ok(report)
+}
+
+/// Decide the package type from marker entries, most specific first.
+fn identify<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    names: &[String],
+    warnings: &mut Vec<String>,
+) -> PackageReport {
+    let mut report = PackageReport {
+        path: String::new(),
+        kind: "unknown",
+        description: "Unrecognized zip archive".into(),
+        markers: Vec::new(),
+        images: Vec::new(),
+        import: ImportProposal { command: None, summary: "No matching import".into() },
+        warnings: Vec::new(),
+    };
+
+    let flashfile = find_marker(names, |n| n == "flashfile.xml" || n == "servicefile.xml");
+    let scatter = find_marker(names, |n| n.to_lowercase().ends_with("_scatter.txt"));
+    let flash_all = find_marker(names, |n| n == "flash-all.sh");
+    let image_zip = find_marker(names, |n| n.starts_with("image-") && n.ends_with(".zip"));
+    let android_info = find_marker(names, |n| n == "android-info.txt");
+    let payload_bin = names.iter().find(|n| *n == "payload.bin").cloned();
+    let update_binary = names.iter().find(|n| *n == UPDATE_BINARY).cloned();
+
+    if let Some(marker) = flashfile {
+        let text = read_text(archive, &marker, warnings);
+        let parsed = text.as_deref().map(parse_flashfile);
+
+        report.kind = "motorola";
+        report.description = match parsed {
+            Some(Ok(f)) => format!(
+                "Motorola firmware {} for {} ({} steps)",
+                f.version.as_deref().unwrap_or("(unknown version)"),
+                f.model.as_deref().unwrap_or("(unknown model)"),
+                f.steps.len()
+            ),
+            Some(Err(e)) => {
+                warnings.push(format!("{}: {}", marker, e));
+                "Motorola firmware".into()
+            }
+            None => "Motorola firmware".into(),
+        };
+        report.import = ImportProposal {
+            command: Some("import_flashfile"),
+            summary: format!("Import as a Motorola flashfile package ({})", file_name(&marker)),
+        };
+        report.markers.push(marker);
+    } else if let Some(marker) = scatter {
+        let text = read_text(archive, &marker, warnings);
+
+        report.kind = "mtk_scatter";
+        report.description = match text.as_deref().map(parse_scatter) {
+            Some(Ok(s)) => format!(
+                "MTK scatter package for {} ({} partitions, {} downloadable)",
+                s.platform.as_deref().unwrap_or("(unknown platform)"),
+                s.partitions.len(),
+                s.downloadable().count()
+            ),
+            Some(Err(e)) => {
+                warnings.push(format!("{}: {}", marker, e));
+                "MTK scatter package".into()
+            }
+            None => "MTK scatter package".into(),
+        };
+        report.import = ImportProposal {
+            command: Some("import_scatter"),
+            summary: "Import as an MTK scatter package (fastboot pipeline)".into(),
+        };
+        report.markers.push(marker);
+    } else if let (Some(script), Some(inner)) = (flash_all, image_zip.clone()) {
+        report.kind = "factory";
+        report.description = format!("AOSP factory image ({})", file_name(&inner));
+        report.import = ImportProposal {
+            command: Some("import_factory_image"),
+            summary: "Import as an AOSP factory image".into(),
+        };
+        report.markers.extend([script, inner]);
+    } else if let Some(marker) = android_info {
+        let text = read_text(archive, &marker, warnings).unwrap_or_default();
+        let board = parse_android_info(&text)
+            .into_iter()
+            .find(|r| r.key == "board" && r.product.is_none())
+            .map(|r| r.values.join("|"));
+
+        report.kind = "factory_images";
+        report.description = format!(
+            "Factory partition images for board {}",
+            board.as_deref().unwrap_or("(unknown)")
+        );
+        report.import = ImportProposal {
+            command: None,
+            summary: "Inner image-*.zip of a factory image; import the full factory zip (with \
+                      flash-all.sh and the bootloader/radio images) instead"
+                .into(),
+        };
+        report.markers.push(marker);
+    } else if let Some(marker) = payload_bin {
+        report.kind = "ota";
+        report.description = "A/B OTA package".into();
+        report.import = ImportProposal {
+            command: Some("extract_payload"),
+            summary: "Extract partition images from payload.bin, then flash them".into(),
+        };
+        report.markers.push(marker);
+    } else if let Some(marker) = update_binary {
+        report.kind = "recovery_zip";
+        report.description = "Recovery-flashable zip (updater script)".into();
+        report.import = ImportProposal {
+            command: None,
+            summary: "Install from a custom recovery or with `adb sideload`; it runs its own \
+                      script and cannot be turned into a fastboot pipeline"
+                .into(),
+        };
+        report.markers.push(marker);
+    }
+
+    if let (Some(marker), false) = (image_zip, report.kind == "factory") {
+        warnings.push(format!("{} looks like a factory image but flash-all.sh is missing", marker));
+    }
+
+    report
+}
+
+/// Payload summary: partitions and whether the OTA is full or delta.
+fn describe_ota(path: &Path, report: &mut PackageReport, warnings: &mut Vec<String>) {
+    match payload::read_payload(path) {
+        Ok(p) => {
+            let delta: Vec<&str> = p
+                .partitions
+                .iter()
+                .filter(|part| !part.is_full())
+                .map(|part| part.name.as_str())
+                .collect();
+
+            report.description = format!(
+                "{} A/B OTA, {} partitions{}",
+                if delta.is_empty() { "Full" } else { "Incremental" },
+                p.partitions.len(),
+                p.security_patch_level
+                    .as_deref()
+                    .map(|spl| format!(", security patch {}", spl))
+                    .unwrap_or_default()
+            );
+
+            if !delta.is_empty() {
+                report.import = ImportProposal {
+                    command: None,
+                    summary: "Incremental OTA; apply it on the device (recovery or \
+                              `adb sideload`), it cannot be extracted"
+                        .into(),
+                };
+                warnings.push(format!("Delta operations in: {}", delta.join(", ")));
+            }
+        }
+        Err(e) => warnings.push(format!("payload.bin: {}", e)),
+    }
+}
+
+/* ================= SNIFFING ================= */
+
+/// Detect the image format from its first bytes and hash the whole
+/// entry in the same pass.
+fn sniff_and_hash(entry: &mut impl Read) -> Result<(&'static str, String), String> {
+    let mut head = Vec::new();
+    entry
+        .take(preloader::GFH_SEARCH_LIMIT as u64)
+        .read_to_end(&mut head)
+        .map_err(|e| e.to_string())?;
+
+    let format = detect_format(&head);
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut head.as_slice().chain(entry), &mut hasher).map_err(|e| e.to_string())?;
+
+    let sha256 = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+    Ok((format, sha256))
+}
+
+pub fn detect_format(head: &[u8]) -> &'static str {
+    if sparse::is_sparse(head) {
+        return "sparse";
+    }
+
+    match bootimg::detect_kind(head) {
+        Some(BootImageKind::Boot) => return "boot",
+        Some(BootImageKind::VendorBoot) => return "vendor_boot",
+        None => {}
+    }
+
+    if avb::is_vbmeta(head) {
+        "vbmeta"
+    } else if dtbo::is_dtbo(head) {
+        "dtbo"
+    } else if head.starts_with(&[0xD0, 0x0D, 0xFE, 0xED]) {
+        "dtb"
+    } else if head.starts_with(b"CrAU") {
+        "payload"
+    } else if head.starts_with(b"PK\x03\x04") {
+        "zip"
+    } else if partition::has_mtk_header(head) {
+        "mtk"
+    } else if preloader::is_preloader(head) {
+        "preloader"
+    } else if lp::is_super(head) {
+        "super"
+    } else if let Some(fs) = fsimage::detect(&mut Cursor::new(head)) {
+        fs
+    } else {
+        "unknown"
+    }
+}
+
+/* ================= HELPERS ================= */
+
+/// An entry named `name` at the archive root or one folder below, as
+/// vendor zips often wrap everything in a top-level directory.
+fn find_marker(names: &[String], matches: impl Fn(&str) -> bool) -> Option<String> {
+    names
+        .iter()
+        .filter(|n| !n.ends_with('/') && n.matches('/').count() <= 1)
+        .find(|n| matches(file_name(n)))
+        .cloned()
+}
+
+fn read_text<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+    warnings: &mut Vec<String>,
+) -> Option<String> {
+    let mut text = String::new();
+
+    let result = archive
+        .by_name(name)
+        .map_err(|e| e.to_string())
+        .and_then(|mut entry| entry.read_to_string(&mut text).map_err(|e| e.to_string()));
+
+    match result {
+        Ok(_) => Some(text),
+        Err(e) => {
+            warnings.push(format!("{}: {}", name, e));
+            None
+        }
+    }
+}
+
+fn is_metadata(name: &str) -> bool {
+    name.rsplit_once('.')
+        .is_some_and(|(_, ext)| METADATA_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::android::{avb, erofs, ext4, payload};
+    use std::{fs, io::Write, path::PathBuf};
+    use zip::{write::FileOptions, ZipWriter};
+
+    const FLASHFILE: &str = r#"<flashing>
+  <header>
+    <phone_model model="kansas_g"/>
+    <software_version version="V1"/>
+  </header>
+  <steps>
+    <step operation="flash" partition="boot" filename="boot.img"/>
+  </steps>
+</flashing>"#;
+
+    const SCATTER: &str = "\
+- general: MTK_PLATFORM_CFG
+  info:
+    - config_version: V1.1.2
+      platform: MT6835
+      storage: UFS
+
+- partition_index: SYS0
+  partition_name: boot_a
+  file_name: boot.img
+  is_download: true
+  linear_start_addr: 0x100000
+  partition_size: 0x100000
+  region: UFS_LU2
+  operation_type: UPDATE
+";
+
+    fn boot() -> Vec<u8> {
+        let mut boot = bootimg::BOOT_MAGIC.to_vec();
+        boot.resize(8192, 0);
+        boot
+    }
+
+    fn sha256(data: &[u8]) -> String {
+        Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    struct Packages(PathBuf);
+
+    impl Packages {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("package-{}-{}", std::process::id(), name));
+            fs::create_dir_all(&dir).unwrap();
+            Packages(dir)
+        }
+
+        fn inspect(&self, name: &str, files: &[(&str, &[u8])]) -> PackageReport {
+            let path = self.0.join(name);
+            let mut zip = ZipWriter::new(File::create(&path).unwrap());
+            // Stored, as payload.bin must be to be read in place
+            let stored = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+            for (entry, data) in files {
+                zip.start_file(*entry, stored).unwrap();
+                zip.write_all(data).unwrap();
+            }
+            zip.finish().unwrap();
+
+            inspect_package(&path).unwrap()
+        }
+    }
+
+    impl Drop for Packages {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn identifies_motorola_package_and_hashes_images() {
+        let packages = Packages::new("moto");
+        let boot = boot();
+        let mut sparse = 0xED26_FF3Au32.to_le_bytes().to_vec();
+        sparse.resize(64, 0);
+        let mut super_image = vec![0u8; 4096];
+        super_image.extend(0x616C_4467u32.to_le_bytes());
+        super_image.resize(8192, 0);
+
+        // Wrapped in one top-level folder, as vendor zips often are
+        let report = packages.inspect(
+            "moto.zip",
+            &[
+                ("fw/flashfile.xml", FLASHFILE.as_bytes()),
+                ("fw/boot.img", &boot),
+                ("fw/system.img_sparsechunk.0", &sparse),
+                ("fw/super.img", &super_image),
+            ],
+        );
+
+        assert_eq!(report.kind, "motorola");
+        assert_eq!(report.markers, ["fw/flashfile.xml"]);
+        assert_eq!(report.import.command, Some("import_flashfile"));
+        assert!(report.description.contains("V1 for kansas_g (1 steps)"), "{}", report.description);
+
+        let formats: Vec<_> = report.images.iter().map(|i| (i.name.as_str(), i.format)).collect();
+        assert_eq!(
+            formats,
+            [
+                ("fw/boot.img", "boot"),
+                ("fw/system.img_sparsechunk.0", "sparse"),
+                ("fw/super.img", "super"),
+            ]
+        );
+        assert_eq!(report.images[0].sha256, sha256(&boot));
+        assert_eq!(report.images[0].size, boot.len() as u64);
+    }
+
+    #[test]
+    fn identifies_other_package_kinds() {
+        let packages = Packages::new("kinds");
+        let boot = boot();
+
+        let report = packages.inspect(
+            "scatter.zip",
+            &[("MT6835_Android_scatter.txt", SCATTER.as_bytes()), ("boot.img", &boot)],
+        );
+        assert_eq!(report.kind, "mtk_scatter");
+        assert_eq!(report.import.command, Some("import_scatter"));
+        assert!(report.description.contains("MT6835 (1 partitions, 1 downloadable)"));
+
+        let report = packages.inspect(
+            "factory.zip",
+            &[("oriole-tq1/flash-all.sh", b""), ("oriole-tq1/image-oriole-tq1.zip", b"PK\x03\x04")],
+        );
+        assert_eq!(report.kind, "factory");
+        assert_eq!(report.import.command, Some("import_factory_image"));
+        assert_eq!(report.images[0].format, "zip");
+
+        let report = packages.inspect(
+            "inner.zip",
+            &[("android-info.txt", b"require board=oriole\n"), ("boot.img", &boot)],
+        );
+        assert_eq!(report.kind, "factory_images");
+        assert_eq!(report.import.command, None);
+        assert!(report.description.contains("board oriole"));
+
+        let report =
+            packages.inspect("recovery.zip", &[(UPDATE_BINARY, b"#!"), ("boot.img", &boot)]);
+        assert_eq!(report.kind, "recovery_zip");
+        assert_eq!(report.import.command, None);
+        assert_eq!(report.images.len(), 1);
+
+        let report = packages.inspect("orphan.zip", &[("image-oriole-tq1.zip", b"PK\x03\x04")]);
+        assert_eq!(report.kind, "unknown");
+        assert!(report.warnings[0].contains("flash-all.sh is missing"));
+    }
+
+    #[test]
+    fn describes_ota_payload() {
+        let packages = Packages::new("ota");
+        let (bin, _) = payload::tests::full_payload();
+
+        let report = packages.inspect("ota.zip", &[("payload.bin", &bin)]);
+        assert_eq!(report.kind, "ota");
+        assert_eq!(report.images[0].format, "payload");
+        assert!(report.description.starts_with("Incremental A/B OTA, 2 partitions"));
+        assert!(report.description.ends_with("security patch 2026-09-05"));
+        assert_eq!(report.import.command, None);
+        assert_eq!(report.warnings, ["Delta operations in: system"]);
+
+        let report = packages.inspect("broken.zip", &[("payload.bin", b"CrAU")]);
+        assert_eq!(report.import.command, Some("extract_payload"));
+        assert!(report.warnings[0].starts_with("payload.bin: "));
+    }
+
+    #[test]
+    fn unknown_zip_lists_loose_images() {
+        let packages = Packages::new("loose");
+        let system = ext4::tests::image();
+        let (vendor, _) = erofs::tests::image();
+        let vbmeta = avb::tests::vbmeta(&[], 0, 0);
+
+        let report = packages.inspect(
+            "loose.zip",
+            &[("system.img", &system), ("vendor.img", &vendor), ("vbmeta.img", &vbmeta)],
+        );
+
+        assert_eq!(report.kind, "unknown");
+        let formats: Vec<_> = report.images.iter().map(|i| i.format).collect();
+        assert_eq!(formats, ["ext4", "erofs", "vbmeta"]);
+        assert!(report.import.summary.contains("3 image(s) can be flashed individually"));
+    }
+
+    #[test]
+    fn detects_raw_formats() {
+        assert_eq!(detect_format(&[0xD0, 0x0D, 0xFE, 0xED, 0, 0]), "dtb");
+        assert_eq!(detect_format(&0xD7B7_AB1Eu32.to_be_bytes()), "dtbo");
+        assert_eq!(detect_format(b"random bytes"), "unknown");
+        assert_eq!(detect_format(&[]), "unknown");
+    }
+}
@@ -0,0 +1,361 @@
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::firmware::{
+    has_slot_suffix, package_path, retarget_slot, unpack_package, FirmwareImport, SlotMode,
+};
+use crate::image_check::check_image;
+use crate::mtk::scatter::{parse_scatter_file, validate_scatter};
+use crate::pipeline::{pipeline_id_fragment, FlashPipeline, PipelineStep, StepCondition};
+
+// Turns an SP Flash Tool package into a fastboot pipeline for devices
+// whose unlocked LK accepts `fastboot flash`. Boot-region images
+// (preloader) normally need BROM + DA and are skipped unless requested.
+
+/// Calibration / identity data unique to each unit (IMEI, MAC, FRP...).
+/// Flashing another unit's copy is never what the user wants.
+const DEVICE_UNIQUE: &[&str] = &[
+    "nvram", "nvdata", "nvcfg", "protect1", "protect2", "proinfo", "persist", "otp", "frp",
+    "seccfg", "flashinfo",
+];
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ScatterOptions {
+    #[serde(default)]
+    pub slot_mode: SlotMode,
+    /// Flash preloader / boot-region images through fastboot as well,
+    /// gated on an unlocked bootloader.
+    #[serde(default)]
+    pub include_boot_regions: bool,
+}
+
+/// Import an MTK scatter package (zip or extracted directory) as a pipeline.
+pub fn import_scatter(package: &Path, options: &ScatterOptions) -> Result<FirmwareImport, String> {
+    let root = unpack_package(package)?;
+    let scatter_path = find_scatter(&root).ok_or("No *_scatter.txt in package")?;
+    let base = scatter_path.parent().unwrap_or(&root).to_path_buf();
+
+    let scatter = parse_scatter_file(&scatter_path)?;
+
+    let mut warnings = Vec::new();
+    let mut problems = Vec::new();
+
+    // Issues only block the import for partitions that are actually flashed
+    let mut issues = validate_scatter(&scatter, &base);
+
+    let platform = scatter.platform.clone().unwrap_or_else(|| "MTK".into());
+    let ab_scatter = scatter.partitions.iter().any(|p| has_slot_suffix(&p.name));
+
+    let mut steps = vec![PipelineStep::Message {
+        text: format!("MTK scatter firmware for {}", platform),
+    }];
+    let mut boot_steps = Vec::new();
+    let mut targets = Vec::new();
+    let mut flashed = 0;
+
+    for part in scatter.downloadable() {
+        let name = fastboot_name(&part.name);
+
+        if DEVICE_UNIQUE.contains(&name.as_str()) {
+            warnings.push(format!("Skipped {}: device-unique data", name));
+            continue;
+        }
+
+        if part.is_boot_region() && !options.include_boot_regions {
+            warnings.push(format!("Skipped {}: boot region (BROM / DA only)", name));
+            continue;
+        }
+
+        // Both slots of an A/B scatter collapse onto one target in ActiveSlot mode
+        let target = retarget_slot(&name, options.slot_mode);
+        if targets.contains(&target) {
+            warnings.push(format!("Skipped {}: already flashed as {}", name, target));
+            continue;
+        }
+        targets.push(target);
+
+        let (own, rest) = issues.into_iter().partition(|i| i.partition == part.name);
+        issues = rest;
+
+        for issue in own {
+            let line = format!("{}: {}", issue.partition, issue.message);
+            if issue.message.starts_with("Partition size unknown") {
+                warnings.push(line);
+            } else {
+                problems.push(line);
+            }
+        }
+
+        // Missing or escaping images are already reported by validate_scatter
+        let Ok(image) = package_path(&base, part.file_name.as_deref().unwrap_or_default())
+        else {
+            continue;
+        };
+
+        if image.is_file() {
+            // Unverified checks are repeated against the device when planning
+            let check = check_image(&image, &name, scatter.platform.as_deref());
+            problems.extend(check.blockers);
+            warnings.extend(check.warnings);
+            warnings.extend(check.unverified);
+        }
+
+        let step = flash_step(&name, &image, ab_scatter, options.slot_mode);
+
+        if part.is_boot_region() {
+            boot_steps.push(step);
+        } else {
+            steps.push(step);
+        }
+        flashed += 1;
+    }
+
+    warnings.extend(issues.iter().map(|i| format!("{}: {}", i.partition, i.message)));
+
+    if !problems.is_empty() {
+        return Err(format!("Scatter import failed:\n{}", problems.join("\n")));
+    }
+
+    if flashed == 0 {
+        return Err("Scatter file has no partitions flashable through fastboot".into());
+    }
+
+    if !boot_steps.is_empty() {
+        // Boot chain first, as SP Flash Tool does, behind an explicit gate
+        let mut gated = vec![PipelineStep::Require {
+            condition: StepCondition::Unlocked,
+            message: "Boot-region images are only flashed on an unlocked bootloader".into(),
+        }];
+        gated.extend(boot_steps);
+        steps.splice(1..1, gated);
+    }
+
+    steps.push(PipelineStep::fastboot(&["reboot"]));
+
+    let stem = scatter.project.clone().unwrap_or_else(|| file_stem(package));
+
+    let pipeline = FlashPipeline {
+        id: format!("scatter-{}", pipeline_id_fragment(&format!("{}-{}", platform, stem))),
+        description: format!("MTK scatter flash via fastboot: {} ({})", platform, stem),
+        requires_adb: false,
+        requires_fastboot: true,
+        destructive: true,
+        variables: BTreeMap::new(),
+        steps,
+    };
+
+    let mut import = FirmwareImport::new(pipeline, &base, 0);
+    import.warnings = warnings;
+
+    Ok(import)
+}
+
+/// Flash step for one partition. Names without a suffix in a non-A/B
+/// scatter are mapped to the active slot if the bootloader says the
+/// partition is slotted.
+fn flash_step(name: &str, image: &Path, ab_scatter: bool, slot_mode: SlotMode) -> PipelineStep {
+    let image = image.to_string_lossy();
+
+    if ab_scatter || has_slot_suffix(name) {
+        let target = retarget_slot(name, slot_mode);
+        return PipelineStep::fastboot(&["flash", &target, &image]);
+    }
+
+    let slotted = format!("{}_${{slot}}", name);
+
+    PipelineStep::When {
+        condition: StepCondition::HasSlot { partition: name.to_string() },
+        steps: vec![PipelineStep::fastboot(&["flash", &slotted, &image])],
+        otherwise: vec![PipelineStep::fastboot(&["flash", name, &image])],
+    }
+}
+
+/// Fastboot partition name for a scatter entry. Legacy scatter files
+/// use SP Flash Tool's upper-case names.
+pub fn fastboot_name(scatter_name: &str) -> String {
+    match scatter_name.to_uppercase().as_str() {
+        "BOOTIMG" => "boot".into(),
+        "ANDROID" => "system".into(),
+        "USRDATA" => "userdata".into(),
+        "UBOOT" => "lk".into(),
+        "SEC_RO" => "secro".into(),
+        _ => scatter_name.to_lowercase(),
+    }
+}
+
+fn find_scatter(root: &Path) -> Option<PathBuf> {
+    let is_scatter = |p: &Path| {
+        p.file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.to_lowercase().ends_with("_scatter.txt"))
+    };
+
+    let entries = |dir: &Path| -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .map(|e| e.flatten().map(|e| e.path()).collect())
+            .unwrap_or_default()
+    };
+
+    let top = entries(root);
+
+    top.iter().find(|p| is_scatter(p)).cloned().or_else(|| {
+        top.iter()
+            .filter(|p| p.is_dir())
+            .flat_map(|d| entries(d))
+            .find(|p| is_scatter(p))
+    })
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("package")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCATTER: &str = "\
+- general: MTK_PLATFORM_CFG
+  info:
+    - config_version: V1.1.2
+      platform: MT6765
+      project: k65v1_64_bsp
+- partition_index: SYS0
+  partition_name: preloader
+  file_name: preloader_k65v1_64_bsp.bin
+  is_download: true
+  linear_start_addr: 0x0
+  partition_size: 0x40000
+  region: EMMC_BOOT1_BOOT2
+  operation_type: BOOTLOADERS
+- partition_index: SYS1
+  partition_name: nvram
+  file_name: nvram.bin
+  is_download: true
+  linear_start_addr: 0x0
+  partition_size: 0x500000
+  region: EMMC_USER
+- partition_index: SYS2
+  partition_name: boot
+  file_name: boot.img
+  is_download: true
+  linear_start_addr: 0x500000
+  partition_size: 0x2000000
+  region: EMMC_USER
+- partition_index: SYS3
+  partition_name: ANDROID
+  file_name: system.img
+  is_download: true
+  linear_start_addr: 0x2500000
+  partition_size: 0x10000000
+  region: EMMC_USER
+";
+
+    fn package(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("scatter-import-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join("MT6765_Android_scatter.txt"), SCATTER).unwrap();
+        fs::write(dir.join("nvram.bin"), b"nv").unwrap();
+        fs::write(dir.join("system.img"), vec![0u8; 4096]).unwrap();
+
+        let mut boot = b"ANDROID!".to_vec();
+        boot.resize(4096, 0);
+        fs::write(dir.join("boot.img"), boot).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn maps_names_and_skips_unsafe_partitions() {
+        let dir = package("skip");
+        fs::write(dir.join("preloader_k65v1_64_bsp.bin"), b"junk").unwrap();
+
+        let import = import_scatter(&dir, &ScatterOptions::default());
+        fs::remove_dir_all(&dir).ok();
+        let import = import.unwrap();
+
+        assert_eq!(import.pipeline.id, "scatter-mt6765-k65v1-64-bsp");
+        assert!(import.warnings.contains(&"Skipped nvram: device-unique data".to_string()));
+        assert!(import.warnings.iter().any(|w| w.starts_with("Skipped preloader: boot region")));
+
+        // Non-A/B scatter: each flash branches on has-slot
+        let PipelineStep::When { condition, steps, otherwise } = &import.pipeline.steps[2] else {
+            panic!("expected a slot branch for system");
+        };
+        assert!(matches!(condition, StepCondition::HasSlot { partition } if partition == "system"));
+        let PipelineStep::FastbootCommand { args, .. } = &steps[0] else {
+            panic!("expected a flash step");
+        };
+        assert_eq!(args[1], "system_${slot}");
+        let PipelineStep::FastbootCommand { args, .. } = &otherwise[0] else {
+            panic!("expected a flash step");
+        };
+        assert_eq!(args[1], "system");
+    }
+
+    #[test]
+    fn boot_regions_are_gated_and_checked() {
+        let dir = package("boot-region");
+        fs::write(dir.join("preloader_k65v1_64_bsp.bin"), b"not a preloader").unwrap();
+
+        let options = ScatterOptions { include_boot_regions: true, ..Default::default() };
+        let err = import_scatter(&dir, &options).unwrap_err();
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(err.contains("Invalid preloader"));
+    }
+
+    #[test]
+    fn missing_images_fail_the_import() {
+        let dir = package("missing");
+        fs::remove_file(dir.join("system.img")).unwrap();
+
+        let err = import_scatter(&dir, &ScatterOptions::default()).unwrap_err();
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(err.contains("ANDROID: Image not found: system.img"));
+    }
+
+    #[test]
+    fn issues_on_skipped_partitions_only_warn() {
+        let dir = package("no-preloader");
+        fs::remove_file(dir.join("nvram.bin")).unwrap();
+
+        let import = import_scatter(&dir, &ScatterOptions::default());
+        fs::remove_dir_all(&dir).ok();
+        let import = import.unwrap();
+
+        let warned = |w: &str| import.warnings.iter().any(|x| x == w);
+        assert!(warned("preloader: Image not found: preloader_k65v1_64_bsp.bin"));
+        assert!(warned("nvram: Image not found: nvram.bin"));
+    }
+
+    #[test]
+    fn missing_preloader_blocks_when_boot_regions_are_flashed() {
+        let dir = package("no-preloader-boot");
+
+        let options = ScatterOptions { include_boot_regions: true, ..Default::default() };
+        let err = import_scatter(&dir, &options).unwrap_err();
+        fs::remove_dir_all(&dir).ok();
+
+        assert!(err.contains("preloader: Image not found: preloader_k65v1_64_bsp.bin"));
+        assert!(!err.contains("nvram"));
+    }
+
+    #[test]
+    fn legacy_names_map_to_fastboot() {
+        assert_eq!(fastboot_name("BOOTIMG"), "boot");
+        assert_eq!(fastboot_name("ANDROID"), "system");
+        assert_eq!(fastboot_name("UBOOT"), "lk");
+        assert_eq!(fastboot_name("vendor_a"), "vendor_a");
+    }
+}
@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+use crate::android::avb::{self, VbmetaPatch, FLAG_HASHTREE_DISABLED, FLAG_VERIFICATION_DISABLED};
+use crate::firmware::FirmwareImport;
+use crate::pipeline::{pipeline_id_fragment, FlashPipeline, PipelineStep, StepCondition};
+
+// A stock vbmeta with verity / verification disabled, as a one-step
+// pipeline. Patching locally instead of relying on `fastboot
+// --disable-verity` makes the change reviewable and works with any
+// fastboot build; the flash itself goes through the normal plan review.
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VbmetaPatchOptions {
+    #[serde(default = "enabled")]
+    pub disable_verity: bool,
+    #[serde(default = "enabled")]
+    pub disable_verification: bool,
+    /// Partition to flash; `vbmeta` unless given
+    #[serde(default)]
+    pub partition: Option<String>,
+}
+
+impl Default for VbmetaPatchOptions {
+    fn default() -> Self {
+        Self { disable_verity: true, disable_verification: true, partition: None }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PatchedVbmeta {
+    /// Before / after header and the fields that changed
+    pub patch: VbmetaPatch,
+    pub import: FirmwareImport,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Write `<stem>_patched.img` next to `source` and build the pipeline
+/// that flashes it.
+pub fn import_patched_vbmeta(
+    source: &Path,
+    options: &VbmetaPatchOptions,
+) -> Result<PatchedVbmeta, String> {
+    let mut flags = 0;
+    if options.disable_verity {
+        flags |= FLAG_HASHTREE_DISABLED;
+    }
+    if options.disable_verification {
+        flags |= FLAG_VERIFICATION_DISABLED;
+    }
+    if flags == 0 {
+        return Err("Nothing to patch: no flags selected".into());
+    }
+
+    let stem = source.file_stem().and_then(|s| s.to_str()).unwrap_or("vbmeta");
+    let output = source.with_file_name(format!("{}_patched.img", stem));
+
+    let patch = avb::patch_flags(source, &output, flags)?;
+    let partition = options.partition.as_deref().unwrap_or("vbmeta");
+    let flags_text = format!("vbmeta flags {:#x} → {:#x}", patch.before.flags, patch.after.flags);
+
+    let pipeline = FlashPipeline {
+        id: format!("vbmeta-patched-{}", pipeline_id_fragment(stem)),
+        description: format!("Flash {} with verity / verification disabled ({})", partition, stem),
+        requires_adb: false,
+        requires_fastboot: true,
+        destructive: true,
+        variables: BTreeMap::new(),
+        steps: vec![
+            PipelineStep::Require {
+                condition: StepCondition::Unlocked,
+                message: "A patched vbmeta only boots on an unlocked bootloader".into(),
+            },
+            PipelineStep::Message { text: flags_text },
+            PipelineStep::fastboot(&["flash", partition, &patch.output]),
+        ],
+    };
+
+    let dir = output.parent().unwrap_or(Path::new("."));
+    let mut import = FirmwareImport::new(pipeline, dir, 0);
+
+    if patch.changes.is_empty() {
+        import.warnings.push(format!("{} already has the requested flags", patch.source));
+    }
+
+    Ok(PatchedVbmeta { patch, import })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::android::avb::tests::vbmeta;
+    use crate::risk::RiskLevel;
+    use std::{fs, path::PathBuf};
+
+    fn source(name: &str, flags: u32) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("vbmeta-patch-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut data = vbmeta(&[], 0, 0);
+        data[120..124].copy_from_slice(&flags.to_be_bytes());
+        fs::write(dir.join("vbmeta_system.img"), data).unwrap();
+        dir.join("vbmeta_system.img")
+    }
+
+    #[test]
+    fn writes_patched_copy_and_flash_pipeline() {
+        let source = source("patch", 0);
+        let options =
+            VbmetaPatchOptions { partition: Some("vbmeta_system".into()), ..Default::default() };
+
+        let result = import_patched_vbmeta(&source, &options);
+        let original = fs::read(&source).unwrap();
+        let patched = fs::read(source.with_file_name("vbmeta_system_patched.img"));
+        fs::remove_dir_all(source.parent().unwrap()).ok();
+
+        let result = result.unwrap();
+        assert_eq!(result.patch.after.flags, 3);
+        assert_eq!(result.import.max_risk, RiskLevel::Critical);
+        assert!(result.import.warnings.is_empty());
+        assert!(matches!(
+            &result.import.pipeline.steps[0],
+            PipelineStep::Require { condition: StepCondition::Unlocked, .. }
+        ));
+        let PipelineStep::FastbootCommand { args, .. } = &result.import.pipeline.steps[2] else {
+            panic!("expected a flash step");
+        };
+        assert_eq!(args[..2], ["flash", "vbmeta_system"]);
+        assert!(args[2].ends_with("vbmeta_system_patched.img"));
+
+        let patched = patched.unwrap();
+        assert_eq!(patched.len(), original.len());
+        assert_eq!(patched[123], 3);
+        assert_eq!(original[123], 0);
+    }
+
+    #[test]
+    fn only_requested_flags_are_set() {
+        let source = source("verity-only", 0);
+        let options = VbmetaPatchOptions { disable_verification: false, ..Default::default() };
+
+        let result = import_patched_vbmeta(&source, &options);
+        let none = VbmetaPatchOptions {
+            disable_verity: false,
+            disable_verification: false,
+            partition: None,
+        };
+        let nothing = import_patched_vbmeta(&source, &none);
+        fs::remove_dir_all(source.parent().unwrap()).ok();
+
+        let result = result.unwrap();
+        assert_eq!(result.patch.after.flags, FLAG_HASHTREE_DISABLED);
+        assert!(!result.patch.after.verification_disabled);
+        assert!(nothing.unwrap_err().starts_with("Nothing to patch"));
+    }
+
+    #[test]
+    fn already_patched_image_warns() {
+        let source = source("already", 3);
+
+        let result = import_patched_vbmeta(&source, &VbmetaPatchOptions::default());
+        fs::remove_dir_all(source.parent().unwrap()).ok();
+
+        let result = result.unwrap();
+        assert!(result.patch.changes.is_empty());
+        assert!(result.import.warnings[0].ends_with("already has the requested flags"));
+    }
+}
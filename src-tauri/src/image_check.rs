@@ -0,0 +1,382 @@
+use std::{fs::File, io::Read, path::Path};
+
+use crate::android::avb::{self, CheckStatus};
+use crate::android::bootimg::{self, BootImageKind};
+use crate::android::dtbo;
+use crate::mtk::{partition, preloader};
+use crate::risk::base_partition;
+
+// Pre-flash content checks: does the image look like what the target
+// partition expects? Catches the classic mix-ups (lk into logo, a
+// preloader into boot, another SoC's preloader) before anything is sent.
+
+/// Partitions whose images start with an MTK partition header, and the
+/// first sub-image names accepted for each.
+const MTK_HEADER_PARTITIONS: &[(&str, &[&str])] = &[
+    ("lk", &["lk"]),
+    ("lk2", &["lk"]),
+    ("bootloader", &["lk"]),
+    ("logo", &["logo"]),
+    ("md1img", &["md1rom"]),
+    ("tee", &["tee", "atf"]),
+    ("tee1", &["tee", "atf"]),
+    ("tee2", &["tee", "atf"]),
+];
+
+#[derive(Debug, Default)]
+pub struct ImageCheck {
+    pub notes: Vec<String>,
+    pub warnings: Vec<String>,
+    pub blockers: Vec<String>,
+    /// AVB failures: harmless when unlocked, a boot failure when locked
+    pub verification: Vec<String>,
+    /// Safety checks that could not be made (device SoC unknown); the
+    /// planner blocks on these unless explicitly overridden
+    pub unverified: Vec<String>,
+}
+
+/// Check `image` against the partition it is about to be flashed to.
+/// `device_platform` is the device's SoC / `ro.board.platform`, if known.
+pub fn check_image(image: &Path, partition: &str, device_platform: Option<&str>) -> ImageCheck {
+    let mut check = ImageCheck::default();
+    let target = base_partition(partition);
+
+    let head = match read_head(image) {
+        Ok(head) => head,
+        Err(e) => {
+            check.blockers.push(e);
+            return check;
+        }
+    };
+
+    if target == "preloader" {
+        check_preloader(&mut check, image, device_platform);
+        return check;
+    }
+
+    if target.starts_with("vbmeta") {
+        if !avb::is_vbmeta(&head) {
+            check.blockers.push(format!("{} is not a vbmeta image", image.display()));
+        } else {
+            check_avb(&mut check, image);
+        }
+        return check;
+    }
+
+    // Images signed in place (boot, dtbo...) are verified through their footer
+    check_avb(&mut check, image);
+
+    // A preloader only ever belongs in the boot region. Signed MTK images
+    // carry a GFH too, so only headerless images are candidates.
+    if !partition::has_mtk_header(&head) && preloader::is_preloader(&head) {
+        check.blockers.push(format!("{} is a preloader image, not {}", image.display(), target));
+        return check;
+    }
+
+    if target == "dtbo" {
+        check_dtbo(&mut check, image, &head, device_platform);
+        return check;
+    }
+
+    let expected_boot = match target {
+        "boot" | "recovery" | "init_boot" => Some(BootImageKind::Boot),
+        "vendor_boot" => Some(BootImageKind::VendorBoot),
+        _ => None,
+    };
+
+    if let Some(kind) = expected_boot {
+        if bootimg::detect_kind(&head) != Some(kind) {
+            check.blockers.push(format!(
+                "{} is not {} image",
+                image.display(),
+                if kind == BootImageKind::Boot { "an Android boot" } else { "a vendor_boot" }
+            ));
+        }
+        return check;
+    }
+
+    let expected = MTK_HEADER_PARTITIONS
+        .iter()
+        .find(|(p, _)| *p == target)
+        .map(|(_, names)| *names);
+
+    if !partition::has_mtk_header(&head) {
+        if expected.is_some() {
+            check.blockers.push(format!(
+                "{} has no MTK partition header; {} images always do",
+                image.display(),
+                target
+            ));
+        }
+        return check;
+    }
+
+    let images = match partition::read_images(image) {
+        Ok(images) => images,
+        Err(e) => {
+            check.blockers.push(format!("{}: {}", image.display(), e));
+            return check;
+        }
+    };
+
+    let first = images[0].name.as_str();
+    let names: Vec<&str> = images.iter().map(|i| i.name.as_str()).collect();
+    check.notes.push(format!("MTK image: {}", names.join(", ")));
+
+    match expected {
+        Some(accepted) if !accepted.iter().any(|n| n.eq_ignore_ascii_case(first)) => {
+            check.blockers.push(format!(
+                "{} contains a '{}' image, not {}",
+                image.display(),
+                first,
+                target
+            ));
+        }
+        Some(_) => {}
+        None => {
+            // Unknown target: still refuse images that clearly belong elsewhere
+            let owner = MTK_HEADER_PARTITIONS
+                .iter()
+                .find(|(_, accepted)| accepted.iter().any(|n| n.eq_ignore_ascii_case(first)));
+
+            if let Some((owner, _)) = owner {
+                check.blockers.push(format!(
+                    "{} is a {} image, not {}",
+                    image.display(),
+                    owner,
+                    target
+                ));
+            }
+        }
+    }
+
+    check
+}
+
+/// A preloader built for another SoC bricks the device before fastboot
+/// or BROM recovery can help, so a mismatch blocks and an unchecked
+/// target is reported as unverified.
+fn check_preloader(check: &mut ImageCheck, image: &Path, device_platform: Option<&str>) {
+    let info = match preloader::parse_preloader_file(image) {
+        Ok(info) => info,
+        Err(e) => {
+            check.blockers.push(format!("Invalid preloader: {}", e));
+            return;
+        }
+    };
+
+    let image_soc = info
+        .chip
+        .map(|c| c.soc)
+        .or(info.platform.as_deref())
+        .unwrap_or("unknown SoC");
+
+    check.notes.push(format!(
+        "Preloader for {} ({})",
+        image_soc,
+        if info.signed { "signed" } else { "unsigned" }
+    ));
+
+    match device_platform.map(|p| (p, info.matches_platform(p))) {
+        Some((platform, Some(false))) => check.blockers.push(format!(
+            "Preloader is built for {} but device is {}",
+            image_soc, platform
+        )),
+        Some((_, Some(true))) => {}
+        Some((platform, None)) => check.unverified.push(format!(
+            "Preloader target SoC could not be read; cannot check it against {}",
+            platform
+        )),
+        None => check
+            .unverified
+            .push("Device SoC is unknown; preloader target cannot be checked".into()),
+    }
+}
+
+/// Overlays name their board in the root compatible. Platform names and
+/// compatibles do not always share a spelling (Qualcomm codenames), so
+/// a mismatch only warns.
+fn check_dtbo(check: &mut ImageCheck, image: &Path, head: &[u8], device_platform: Option<&str>) {
+    if !dtbo::is_dtbo(head) {
+        check.blockers.push(format!("{} is not a DTBO image", image.display()));
+        return;
+    }
+
+    let table = match dtbo::read_dtbo(image) {
+        Ok(table) => table,
+        Err(e) => {
+            check.blockers.push(format!("Invalid DTBO: {}", e));
+            return;
+        }
+    };
+
+    let compatibles = table.compatibles();
+
+    check.notes.push(format!(
+        "DTBO: {} overlays, compatible: {}",
+        table.entries.len(),
+        if compatibles.is_empty() { "none".to_string() } else { compatibles.join(", ") }
+    ));
+
+    for entry in &table.entries {
+        if let Some(e) = &entry.error {
+            check.warnings.push(format!("DTBO entry {} does not decode: {}", entry.index, e));
+        }
+    }
+
+    if let Some(platform) = device_platform {
+        if table.matches_platform(platform) == Some(false) {
+            check.warnings.push(format!(
+                "No DTBO overlay is compatible with {}; check it is built for this board",
+                platform
+            ));
+        }
+    }
+}
+
+/// Summarise a vbmeta (bare or from a footer) for the flash review.
+/// Images without any AVB data are left alone.
+fn check_avb(check: &mut ImageCheck, image: &Path) {
+    if !avb::has_avb(image) {
+        return;
+    }
+
+    let report = match avb::verify_vbmeta(image, None) {
+        Ok(report) => report,
+        Err(e) => {
+            check.blockers.push(format!("Invalid vbmeta: {}", e));
+            return;
+        }
+    };
+
+    let header = &report.vbmeta.header;
+
+    check.notes.push(format!(
+        "AVB: {}, rollback index {} (location {}), key sha1 {}",
+        header.algorithm,
+        header.rollback_index,
+        header.rollback_index_location,
+        report.vbmeta.public_key_sha1.as_deref().unwrap_or("none")
+    ));
+
+    if header.verification_disabled || header.hashtree_disabled {
+        check.warnings.push(format!(
+            "vbmeta flags {:#x}:{}{}; only boots on an unlocked bootloader",
+            header.flags,
+            if header.verification_disabled { " verification disabled" } else { "" },
+            if header.hashtree_disabled { " hashtree disabled" } else { "" }
+        ));
+    }
+
+    if report.signature != CheckStatus::Ok {
+        check.verification.push(report.signature_detail.clone());
+    }
+
+    for descriptor in &report.descriptors {
+        let line = format!("{} {}: {}", descriptor.kind, descriptor.partition, descriptor.detail);
+
+        match descriptor.status {
+            CheckStatus::Mismatch => check.verification.push(line),
+            CheckStatus::Ok => check.notes.push(line),
+            CheckStatus::Missing | CheckStatus::Skipped => {}
+        }
+    }
+}
+
+/// Enough of the image to find a preloader's GFH or an MTK header.
+fn read_head(image: &Path) -> Result<Vec<u8>, String> {
+    let file = File::open(image).map_err(|e| format!("{}: {}", image.display(), e))?;
+    let mut head = Vec::new();
+
+    file.take(preloader::GFH_SEARCH_LIMIT as u64)
+        .read_to_end(&mut head)
+        .map_err(|e| format!("{}: {}", image.display(), e))?;
+
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{fs, path::PathBuf};
+
+    /// EMMC boot header, FILE_INFO GFH at 0x800, MT6765 BLOADER_INFO body
+    fn preloader_image() -> Vec<u8> {
+        let mut data = vec![0u8; 0x2000];
+        data[..9].copy_from_slice(b"EMMC_BOOT");
+
+        let gfh = 0x800;
+        data[gfh..gfh + 4].copy_from_slice(b"MMM\x01");
+        data[gfh + 4..gfh + 6].copy_from_slice(&0x38u16.to_le_bytes());
+        data[gfh + 8..gfh + 17].copy_from_slice(b"FILE_INFO");
+        data[gfh + 0x1A] = 5;
+        data[gfh + 0x1B] = 2;
+        data[gfh + 0x2C..gfh + 0x30].copy_from_slice(&0x100u32.to_le_bytes());
+
+        let body = b"\0MTK_BLOADER_INFO_v13\0MT6765\0";
+        data[0x1000..0x1000 + body.len()].copy_from_slice(body);
+        data
+    }
+
+    fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("image-check-{}-{}", std::process::id(), name));
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn preloader_soc_mismatch_blocks() {
+        let path = temp_file("preloader-mismatch.bin", &preloader_image());
+        let matching = check_image(&path, "preloader", Some("mt6765"));
+        let other = check_image(&path, "preloader", Some("mt6768"));
+        fs::remove_file(&path).ok();
+
+        assert!(matching.blockers.is_empty() && matching.unverified.is_empty());
+        assert_eq!(other.blockers, vec!["Preloader is built for MT6765 but device is mt6768"]);
+    }
+
+    #[test]
+    fn unknown_device_soc_is_unverified() {
+        let path = temp_file("preloader-unknown.bin", &preloader_image());
+        let check = check_image(&path, "preloader", None);
+        fs::remove_file(&path).ok();
+
+        assert!(check.blockers.is_empty());
+        assert_eq!(
+            check.unverified,
+            vec!["Device SoC is unknown; preloader target cannot be checked"]
+        );
+    }
+
+    #[test]
+    fn signed_mtk_image_is_not_a_preloader() {
+        // 512-byte MTK partition header followed by a GFH, as signed lk has
+        let mut data = partition::build_header("lk", 0x200).to_vec();
+        data.extend(vec![0u8; 0x200]);
+        data[0x200..0x204].copy_from_slice(b"MMM\x01");
+        data[0x204..0x206].copy_from_slice(&0x38u16.to_le_bytes());
+        data[0x208..0x211].copy_from_slice(b"FILE_INFO");
+        data[0x218..0x21A].copy_from_slice(&0x0102u16.to_le_bytes());
+
+        let path = temp_file("signed-lk.img", &data);
+        let as_lk = check_image(&path, "lk_a", None);
+        let as_logo = check_image(&path, "logo", None);
+        fs::remove_file(&path).ok();
+
+        assert!(as_lk.blockers.is_empty(), "{:?}", as_lk.blockers);
+        assert_eq!(as_lk.notes, vec!["MTK image: lk"]);
+        assert_eq!(as_logo.blockers.len(), 1);
+        assert!(as_logo.blockers[0].ends_with("contains a 'lk' image, not logo"));
+    }
+
+    #[test]
+    fn preloader_into_another_partition_blocks() {
+        let path = temp_file("preloader-as-boot.bin", &preloader_image());
+        let check = check_image(&path, "boot_a", None);
+        fs::remove_file(&path).ok();
+
+        assert_eq!(check.blockers.len(), 1);
+        assert!(check.blockers[0].ends_with("is a preloader image, not boot"));
+    }
+}
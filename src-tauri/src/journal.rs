@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::detection_service::DeviceState;
+use crate::pipeline::{FlashPipeline, PipelineStep};
+use crate::planner::ExecutionPlan;
+use crate::snapshot::{now_secs, DeviceSnapshot};
+use crate::tools::tools_root_dir;
+
+/// Disambiguates runs started within the same second
+static RUN_SEQUENCE: AtomicU32 = AtomicU32::new(0);
+
+/* ================= RECORDS ================= */
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RunStatus {
+    Running,
+    Completed,
+    Failed,
+    /// Was `Running` when the app last exited.
+    Interrupted,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StepStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepRecord {
+    /// Index of the step in the originating plan
+    pub plan_index: usize,
+    /// Resolved step exactly as approved
+    pub step: PipelineStep,
+    pub status: StepStatus,
+    pub output: String,
+    #[serde(default)]
+    pub attempts: u32,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+}
+
+/// On-disk record of a single pipeline run. Written after every step so
+/// an interrupted run can be inspected and resumed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunJournal {
+    pub run_id: String,
+    pub pipeline_id: String,
+    pub pipeline_hash: String,
+    pub plan_hash: String,
+    pub device_serial: Option<String>,
+    pub variables: BTreeMap<String, String>,
+    pub status: RunStatus,
+    pub steps: Vec<StepRecord>,
+    pub error: Option<String>,
+    pub resumed: u32,
+    pub started_at: u64,
+    pub updated_at: u64,
+}
+
+impl RunJournal {
+    pub fn from_plan(plan: &ExecutionPlan, pipeline: &FlashPipeline) -> Self {
+        let now = now_secs();
+
+        Self {
+            run_id: new_run_id(now, &plan.plan_hash),
+            pipeline_id: plan.pipeline_id.clone(),
+            pipeline_hash: pipeline_hash(pipeline),
+            plan_hash: plan.plan_hash.clone(),
+            device_serial: plan.snapshot.serial.clone(),
+            variables: plan.variables.clone(),
+            status: RunStatus::Running,
+            steps: plan
+                .runnable_steps()
+                .map(|s| StepRecord {
+                    plan_index: s.index,
+                    step: s.step.clone(),
+                    status: StepStatus::Pending,
+                    output: String::new(),
+                    attempts: 0,
+                    started_at: None,
+                    finished_at: None,
+                })
+                .collect(),
+            error: None,
+            resumed: 0,
+            started_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// First step that has not completed, if any.
+    pub fn next_step(&self) -> Option<usize> {
+        self.steps.iter().position(|s| s.status != StepStatus::Completed)
+    }
+
+    pub fn path(&self) -> PathBuf {
+        runs_dir().join(format!("{}.json", self.run_id))
+    }
+
+    /// Atomically persist the journal (write temp file, then rename).
+    pub fn save(&mut self) -> Result<(), String> {
+        self.updated_at = now_secs();
+
+        fs::create_dir_all(runs_dir()).map_err(|e| e.to_string())?;
+
+        let json = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        let tmp = self.path().with_extension("json.tmp");
+
+        fs::write(&tmp, json).map_err(|e| e.to_string())?;
+        fs::rename(&tmp, self.path()).map_err(|e| e.to_string())
+    }
+
+    /// Checks that a run may continue on the device described by `snapshot`
+    /// using the current definition of its pipeline.
+    pub fn verify_resume(
+        &self,
+        pipeline: &FlashPipeline,
+        snapshot: &DeviceSnapshot,
+    ) -> Result<usize, String> {
+        if !matches!(self.status, RunStatus::Failed | RunStatus::Interrupted) {
+            return Err(format!(
+                "Run {} is {:?}; only failed or interrupted runs can resume",
+                self.run_id, self.status
+            ));
+        }
+
+        if pipeline_hash(pipeline) != self.pipeline_hash {
+            return Err("Pipeline definition changed since this run started".into());
+        }
+
+        if self.device_serial.is_some() && snapshot.serial != self.device_serial {
+            return Err(format!(
+                "Device mismatch: run used {}, attached is {}",
+                self.device_serial.as_deref().unwrap_or("?"),
+                snapshot.serial.as_deref().unwrap_or("unknown")
+            ));
+        }
+
+        let next = self.next_step().ok_or("Run has no remaining steps")?;
+
+        let required = match &self.steps[next].step {
+            PipelineStep::AdbCommand { .. } => Some(DeviceState::AdbDevice),
+            PipelineStep::FastbootCommand { .. } => Some(DeviceState::Fastboot),
+            _ => None,
+        };
+
+        if let Some(required) = required {
+            if snapshot.state != required {
+                return Err(format!(
+                    "Step {} needs {:?} but device is {:?}",
+                    next, required, snapshot.state
+                ));
+            }
+        }
+
+        Ok(next)
+    }
+}
+
+/* ================= STORAGE ================= */
+
+pub fn runs_dir() -> PathBuf {
+    tools_root_dir().join("runs")
+}
+
+pub fn load_run(run_id: &str) -> Result<RunJournal, String> {
+    // Run ids are generated by us; reject anything path-like
+    if run_id.contains(['/', '\\', '.']) {
+        return Err("Invalid run id".into());
+    }
+
+    let path = runs_dir().join(format!("{}.json", run_id));
+    let data = fs::read(&path).map_err(|e| format!("Run {} not found: {}", run_id, e))?;
+
+    serde_json::from_slice(&data).map_err(|e| e.to_string())
+}
+
+/// All journals, newest first.
+pub fn list_runs() -> Vec<RunJournal> {
+    let mut runs: Vec<RunJournal> = fs::read_dir(runs_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().extension().and_then(|e| e.to_str()) == Some("json"))
+                .filter_map(|e| fs::read(e.path()).ok())
+                .filter_map(|data| serde_json::from_slice(&data).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    runs.sort_by_key(|r| std::cmp::Reverse(r.started_at));
+    runs
+}
+
+/// Called once at startup: anything still `Running` was cut off by a crash.
+pub fn mark_interrupted_runs() {
+    for mut run in list_runs() {
+        if run.status == RunStatus::Running {
+            run.status = RunStatus::Interrupted;
+            run.save().ok();
+        }
+    }
+}
+
+pub fn pipeline_hash(pipeline: &FlashPipeline) -> String {
+    let payload = serde_json::to_vec(pipeline).unwrap_or_default();
+    format!("{:x}", Sha256::digest(&payload))
+}
+
+/// `<secs>-<plan hash prefix>-<sequence>`, skipping ids already on disk
+/// in case an earlier process started a run in the same second.
+fn new_run_id(now: u64, plan_hash: &str) -> String {
+    loop {
+        let sequence = RUN_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        let run_id = format!("{}-{}-{}", now, &plan_hash[..8], sequence);
+
+        if !runs_dir().join(format!("{}.json", run_id)).exists() {
+            return run_id;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::StepCondition;
+    use crate::planner::plan_pipeline;
+
+    fn pipeline() -> FlashPipeline {
+        FlashPipeline {
+            id: "reboot-twice".into(),
+            description: "test".into(),
+            requires_adb: false,
+            requires_fastboot: true,
+            destructive: false,
+            variables: BTreeMap::new(),
+            steps: vec![
+                PipelineStep::fastboot(&["getvar", "all"]),
+                PipelineStep::When {
+                    condition: StepCondition::Unlocked,
+                    steps: vec![PipelineStep::Message { text: "unlocked".into() }],
+                    otherwise: vec![],
+                },
+                PipelineStep::fastboot(&["reboot"]),
+            ],
+        }
+    }
+
+    fn snapshot(serial: &str) -> DeviceSnapshot {
+        let mut snapshot = DeviceSnapshot::empty(DeviceState::Fastboot);
+        snapshot.serial = Some(serial.into());
+        snapshot.getvars.insert("unlocked".into(), "no".into());
+        snapshot
+    }
+
+    fn failed_run(pipeline: &FlashPipeline, serial: &str) -> RunJournal {
+        let plan = plan_pipeline(pipeline, &snapshot(serial), &BTreeMap::new());
+        let mut run = RunJournal::from_plan(&plan, pipeline);
+
+        run.steps[0].status = StepStatus::Completed;
+        run.steps[1].status = StepStatus::Failed;
+        run.status = RunStatus::Failed;
+        run
+    }
+
+    #[test]
+    fn journals_only_runnable_steps() {
+        let pipeline = pipeline();
+        let plan = plan_pipeline(&pipeline, &snapshot("ABC"), &BTreeMap::new());
+        let run = RunJournal::from_plan(&plan, &pipeline);
+
+        let indexes: Vec<usize> = run.steps.iter().map(|s| s.plan_index).collect();
+        assert_eq!(indexes, vec![0, 2]);
+        assert_eq!(run.status, RunStatus::Running);
+        assert_eq!(run.next_step(), Some(0));
+        assert!(run.run_id.contains(&plan.plan_hash[..8]));
+    }
+
+    #[test]
+    fn run_ids_are_unique_within_a_second() {
+        let pipeline = pipeline();
+        let plan = plan_pipeline(&pipeline, &snapshot("ABC"), &BTreeMap::new());
+
+        let first = RunJournal::from_plan(&plan, &pipeline);
+        let second = RunJournal::from_plan(&plan, &pipeline);
+        assert_ne!(first.run_id, second.run_id);
+    }
+
+    #[test]
+    fn resumes_at_first_incomplete_step() {
+        let pipeline = pipeline();
+        let run = failed_run(&pipeline, "ABC");
+
+        assert_eq!(run.verify_resume(&pipeline, &snapshot("ABC")), Ok(1));
+    }
+
+    #[test]
+    fn refuses_resume_on_changes() {
+        let pipeline = pipeline();
+        let run = failed_run(&pipeline, "ABC");
+
+        let mut completed = run.clone();
+        completed.status = RunStatus::Completed;
+        assert!(completed.verify_resume(&pipeline, &snapshot("ABC")).is_err());
+
+        let mut edited = pipeline.clone();
+        edited.steps.pop();
+        let err = run.verify_resume(&edited, &snapshot("ABC")).unwrap_err();
+        assert!(err.contains("Pipeline definition changed"));
+
+        let err = run.verify_resume(&pipeline, &snapshot("XYZ")).unwrap_err();
+        assert!(err.contains("Device mismatch"));
+
+        let mut adb = snapshot("ABC");
+        adb.state = DeviceState::AdbDevice;
+        let err = run.verify_resume(&pipeline, &adb).unwrap_err();
+        assert!(err.contains("needs Fastboot"));
+    }
+
+    #[test]
+    fn rejects_path_like_run_ids() {
+        assert_eq!(load_run("../secrets").unwrap_err(), "Invalid run id");
+        assert_eq!(load_run("a/b").unwrap_err(), "Invalid run id");
+    }
+}
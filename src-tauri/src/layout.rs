@@ -0,0 +1,275 @@
+use serde::Serialize;
+
+use crate::android::gpt::{self, GptTable};
+use crate::mtk::scatter::ScatterFile;
+use crate::process::run;
+use crate::profile::DeviceProfile;
+
+// Real partition layout: the GPT from a dump or read off the device
+// through root, compared with what a scatter file or the device profile
+// expects. Only reads; nothing here writes to a block device.
+
+/// Covers the protective MBR, header and a 128-entry array for both
+/// 512- and 4096-byte sectors.
+const GPT_READ_SECTORS: u64 = 128;
+const READ_SECTOR: u64 = 512;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GptReport {
+    /// Dump path or device node the table was read from
+    pub source: String,
+    pub table: GptTable,
+    pub issues: Vec<LayoutIssue>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LayoutIssue {
+    pub partition: String,
+    pub message: String,
+}
+
+/* ================= DEVICE READ ================= */
+
+/// Read the GPT of `disk` (e.g. `sdc`, `mmcblk0`) through `su`. Without
+/// a disk, the one holding `userdata` is used.
+pub fn read_via_root(disk: Option<&str>) -> Result<(String, GptTable), String> {
+    let disk = match disk {
+        Some(disk) => disk.trim_start_matches("/dev/block/").to_string(),
+        None => userdata_disk()?,
+    };
+
+    let sectors: u64 = su_output(&format!("cat /sys/class/block/{}/size", disk))?
+        .trim()
+        .parse()
+        .map_err(|_| format!("Could not read the size of {}", disk))?;
+
+    if sectors < 2 * GPT_READ_SECTORS {
+        return Err(format!("{} is too small for a GPT", disk));
+    }
+
+    let node = format!("/dev/block/{}", disk);
+    let head = su_dd(&node, 0)?;
+    let tail_sector = sectors - GPT_READ_SECTORS;
+    let tail = su_dd(&node, tail_sector)?;
+
+    let size = sectors * READ_SECTOR;
+    let tail_offset = tail_sector * READ_SECTOR;
+
+    // Both ends of the disk are in memory; anything between is not
+    let table = gpt::parse_gpt(size, |offset, len| {
+        let end = offset + len as u64;
+        let (data, base) = if end <= head.len() as u64 {
+            (&head, 0)
+        } else if offset >= tail_offset && end <= tail_offset + tail.len() as u64 {
+            (&tail, tail_offset)
+        } else {
+            return Err(format!("Offset {:#x} is outside the sectors read", offset));
+        };
+
+        let start = (offset - base) as usize;
+        Ok(data[start..start + len].to_vec())
+    })?;
+
+    Ok((node, table))
+}
+
+/// `/dev/block/by-name/userdata` → `sdc77` / `mmcblk0p50` → its disk.
+fn userdata_disk() -> Result<String, String> {
+    let target = su_output("readlink -f /dev/block/by-name/userdata")?;
+    let name = target.trim().rsplit('/').next().unwrap_or_default();
+
+    let disk = if name.starts_with("mmcblk") {
+        name.rfind('p').map_or(name, |i| &name[..i])
+    } else {
+        name.trim_end_matches(|c: char| c.is_ascii_digit())
+    };
+
+    if disk.is_empty() || disk == name {
+        return Err(format!("Could not find the disk holding userdata ({})", target.trim()));
+    }
+
+    Ok(disk.to_string())
+}
+
+fn su_dd(node: &str, skip: u64) -> Result<Vec<u8>, String> {
+    let command = format!(
+        "su -c 'dd if={} bs={} skip={} count={} 2>/dev/null'",
+        node, READ_SECTOR, skip, GPT_READ_SECTORS
+    );
+
+    let out = run("adb", &["exec-out", &command])?;
+    let expected = (READ_SECTOR * GPT_READ_SECTORS) as usize;
+
+    if !out.status.success() || out.stdout.len() != expected {
+        return Err(format!(
+            "Could not read {} ({} of {} bytes): {}",
+            node,
+            out.stdout.len(),
+            expected,
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+
+    Ok(out.stdout)
+}
+
+fn su_output(command: &str) -> Result<String, String> {
+    let out = run("adb", &["exec-out", &format!("su -c '{}'", command)])?;
+
+    if !out.status.success() {
+        return Err(format!("{} failed: {}", command, String::from_utf8_lossy(&out.stderr).trim()));
+    }
+
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/* ================= COMPARE ================= */
+
+/// Differences between the table and a scatter file / profile. Scatter
+/// entries in the boot region and MTK's `pgpt` / `sgpt` pseudo
+/// partitions are not GPT entries and are skipped. The last partition
+/// grows to fill the disk, so its size is not compared.
+pub fn compare(
+    table: &GptTable,
+    scatter: Option<&ScatterFile>,
+    profile: Option<&DeviceProfile>,
+) -> Vec<LayoutIssue> {
+    let mut issues = Vec::new();
+    let mut issue = |partition: &str, message: String| {
+        issues.push(LayoutIssue { partition: partition.to_string(), message })
+    };
+
+    let last = table.partitions.iter().max_by_key(|p| p.last_lba).map(|p| p.index);
+
+    if let Some(scatter) = scatter {
+        let listed = scatter.partitions.iter().filter(|p| {
+            !p.is_boot_region() && !matches!(p.name.to_ascii_lowercase().as_str(), "pgpt" | "sgpt")
+        });
+
+        for part in listed.clone() {
+            let Some(actual) = table.partition(&part.name) else {
+                issue(&part.name, "In scatter but not in the partition table".into());
+                continue;
+            };
+
+            if actual.start != part.linear_start {
+                issue(
+                    &part.name,
+                    format!(
+                        "Starts at {:#x} on the device, {:#x} in scatter",
+                        actual.start, part.linear_start
+                    ),
+                );
+            }
+
+            match part.size {
+                Some(size) if size > 0 && size != actual.size && Some(actual.index) != last => {
+                    issue(
+                        &part.name,
+                        format!("Size {:#x} on the device, {:#x} in scatter", actual.size, size),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        for actual in &table.partitions {
+            if !listed.clone().any(|p| p.name.eq_ignore_ascii_case(&actual.name)) {
+                issue(&actual.name, "In the partition table but not in scatter".into());
+            }
+        }
+    }
+
+    if let Some(profile) = profile {
+        for expected in &profile.partitions {
+            match table.partition(&expected.name) {
+                None => issue(
+                    &expected.name,
+                    format!("Expected by profile {} but missing", profile.device.name),
+                ),
+                Some(actual) => match expected.size {
+                    Some(size) if size != actual.size => issue(
+                        &expected.name,
+                        format!("Size {:#x}, profile expects {:#x}", actual.size, size),
+                    ),
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::android::gpt::tests::disk;
+    use crate::mtk::scatter::parse_scatter;
+    use std::path::Path;
+
+    fn scatter_entry(index: u32, name: &str, start: u64, size: u64, region: &str) -> String {
+        format!(
+            "- partition_index: SYS{}\n  partition_name: {}\n  file_name: {}.img\n  \
+             is_download: true\n  linear_start_addr: {:#x}\n  partition_size: {:#x}\n  \
+             region: {}\n  operation_type: UPDATE\n\n",
+            index, name, name, start, size, region
+        )
+    }
+
+    #[test]
+    fn compares_with_scatter_and_profile() {
+        let image = disk(
+            512,
+            16384,
+            &[("boot_a", 2048, 4095), ("vbmeta_a", 4096, 4159), ("userdata", 8192, 16000)],
+        );
+        let table = gpt::parse_gpt(image.len() as u64, |offset, len| {
+            Ok(image[offset as usize..offset as usize + len].to_vec())
+        })
+        .unwrap();
+
+        let mut scatter = String::from(
+            "- general: MTK_PLATFORM_CFG\n  info: \n    - config_version: V1.1.2\n      \
+             platform: MT6765\n      storage: EMMC\n\n",
+        );
+        for (index, (name, start, size, region)) in [
+            ("preloader", 0, 0x40000, "EMMC_BOOT1_BOOT2"),
+            ("pgpt", 0, 0x8000, "EMMC_USER"),
+            ("boot_a", 0x100000, 0x100000, "EMMC_USER"),
+            ("vbmeta_a", 0x200000, 0x10000, "EMMC_USER"),
+            // Last partition: its size differs but is not compared
+            ("userdata", 0x400000, 0x1000, "EMMC_USER"),
+            ("nvram", 0x500000, 0x1000, "EMMC_USER"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            scatter.push_str(&scatter_entry(index as u32, name, start, size, region));
+        }
+        let scatter = parse_scatter(&scatter).unwrap();
+
+        let profile = crate::profile::parse_profile(
+            "device:\n  name: T\npartitions:\n  - name: boot_a\n    size: 0x200000\n  \
+             - name: super\n",
+            Path::new("t.yaml"),
+        )
+        .unwrap();
+
+        let issues: Vec<String> = compare(&table, Some(&scatter), Some(&profile))
+            .iter()
+            .map(|i| format!("{}: {}", i.partition, i.message))
+            .collect();
+
+        assert_eq!(
+            issues,
+            [
+                "vbmeta_a: Size 0x8000 on the device, 0x10000 in scatter",
+                "nvram: In scatter but not in the partition table",
+                "boot_a: Size 0x100000, profile expects 0x200000",
+                "super: Expected by profile T but missing",
+            ]
+        );
+        assert!(compare(&table, None, None).is_empty());
+    }
+}
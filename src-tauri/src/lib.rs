@@ -1,16 +1,28 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod adb;
+mod android;
 mod app_state;
 mod commands;
 mod detection_service;
+mod executor;
+mod firmware;
 mod fastboot;
+mod image_check;
+mod journal;
 mod kernel;
+mod layout;
 mod logger;
 mod mtk;
 mod pipeline;
+mod planner;
 mod profile;
+mod risk;
+mod rollback;
 mod root;
+mod snapshot;
+#[cfg(test)]
+mod test_util;
 mod tools;
 mod process;
 
@@ -33,6 +45,8 @@ pub fn run() {
 
     emit_log(&app_handle, "info", "MTK Atlas starting");
 
+    journal::mark_interrupted_runs();
+
     start_detection_loop(app_handle.clone(), app_state.clone());
 
     Ok(())
@@ -42,6 +56,47 @@ pub fn run() {
             commands::fastboot_run,
             commands::fastboot_flash,
             commands::export_diagnostics,
+            commands::plan_pipeline,
+            commands::execute_pipeline,
+            commands::read_rollback_indexes,
+            commands::list_runs,
+            commands::get_run,
+            commands::resume_run,
+            commands::import_flashfile,
+            commands::import_factory_image,
+            commands::import_scatter,
+            commands::inspect_package,
+            commands::patch_vbmeta,
+            commands::inspect_scatter,
+            commands::inspect_gpt,
+            commands::read_device_gpt,
+            commands::device_info,
+            commands::list_mtk_ports,
+            commands::mtk_identify,
+            commands::device_capabilities,
+            commands::inspect_preloader,
+            commands::inspect_mtk_image,
+            commands::unpack_logo,
+            commands::repack_logo,
+            commands::unpack_boot_image,
+            commands::repack_boot_image,
+            commands::list_ramdisk,
+            commands::replace_ramdisk_file,
+            commands::read_ramdisk_file,
+            commands::remove_ramdisk_file,
+            commands::inspect_vbmeta,
+            commands::inspect_sparse,
+            commands::unsparse_image,
+            commands::sparse_image,
+            commands::split_sparse_image,
+            commands::inspect_super,
+            commands::extract_logical_partition,
+            commands::inspect_payload,
+            commands::extract_payload,
+            commands::inspect_dtbo,
+            commands::extract_dtbo_entry,
+            commands::browse_image,
+            commands::extract_image_file,
         ])
         .run(tauri::generate_context!())
         .expect("error while running MTK Atlas");
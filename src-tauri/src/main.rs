@@ -2,13 +2,28 @@
 
 mod tools;
 mod adb;
+mod android;
 mod app_state;
 mod detection_service;
 mod fastboot;
 mod logger;
+mod mtk;
 mod root;
 mod commands;
 mod process;
+mod profile;
+mod executor;
+mod firmware;
+mod image_check;
+mod journal;
+mod layout;
+mod pipeline;
+mod planner;
+mod risk;
+mod rollback;
+mod snapshot;
+#[cfg(test)]
+mod test_util;
 
 use crate::{
     app_state::AppState,
@@ -28,6 +43,8 @@ fn main() {
 
             emit_log(&app_handle, "info", "MTK Atlas starting");
 
+            journal::mark_interrupted_runs();
+
             // 3️⃣ Start detection ONCE with state
             start_detection_loop(app_handle.clone(), app_state.clone());
 
@@ -40,6 +57,47 @@ fn main() {
             commands::export_diagnostics,
             commands::platform_tools_installed_cmd,
             commands::install_platform_tools_cmd,
+            commands::plan_pipeline,
+            commands::execute_pipeline,
+            commands::read_rollback_indexes,
+            commands::list_runs,
+            commands::get_run,
+            commands::resume_run,
+            commands::import_flashfile,
+            commands::import_factory_image,
+            commands::import_scatter,
+            commands::inspect_package,
+            commands::patch_vbmeta,
+            commands::inspect_scatter,
+            commands::inspect_gpt,
+            commands::read_device_gpt,
+            commands::device_info,
+            commands::list_mtk_ports,
+            commands::mtk_identify,
+            commands::device_capabilities,
+            commands::inspect_preloader,
+            commands::inspect_mtk_image,
+            commands::unpack_logo,
+            commands::repack_logo,
+            commands::unpack_boot_image,
+            commands::repack_boot_image,
+            commands::list_ramdisk,
+            commands::replace_ramdisk_file,
+            commands::read_ramdisk_file,
+            commands::remove_ramdisk_file,
+            commands::inspect_vbmeta,
+            commands::inspect_sparse,
+            commands::unsparse_image,
+            commands::sparse_image,
+            commands::split_sparse_image,
+            commands::inspect_super,
+            commands::extract_logical_partition,
+            commands::inspect_payload,
+            commands::extract_payload,
+            commands::inspect_dtbo,
+            commands::extract_dtbo_entry,
+            commands::browse_image,
+            commands::extract_image_file,
         ])
         .run(tauri::generate_context!())
         .expect("error running MTK Atlas");
@@ -1,4 +1,10 @@
+pub mod brom;
 pub mod capabilities;
+pub mod chips;
+pub mod logo;
+pub mod partition;
+pub mod preloader;
+pub mod scatter;
 
 // NOTE:
 // MTK device detection is centralized in `detection_service`.
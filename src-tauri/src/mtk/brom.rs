@@ -0,0 +1,615 @@
+use serde::Serialize;
+use std::{
+    io::{ErrorKind, Read, Write},
+    time::Duration,
+};
+
+use crate::mtk::chips::{self, ChipInfo};
+
+// BROM and the preloader expose the same byte protocol on their CDC-ACM
+// port (USB VID 0x0E8D). Every command byte is echoed back, multi-byte
+// values are big-endian and most replies end with a u16 status (0 = ok).
+//
+// Only identification commands are issued here: nothing loads a DA,
+// writes memory or touches storage.
+
+pub const MTK_USB_VID: u16 = 0x0E8D;
+pub const BROM_USB_PID: u16 = 0x0003;
+/// Preloader CDC ports; other MediaTek PIDs are gadget functions of a
+/// booted device (ADB, MTP, modem) and not this protocol.
+pub const PRELOADER_USB_PIDS: &[u16] = &[0x2000, 0x2001, 0x20FF];
+
+/// Each byte is answered with its bitwise complement.
+const START_SEQUENCE: [u8; 4] = [0xA0, 0x0A, 0x50, 0x05];
+
+/// The preloader prints "READY" before it listens; keep poking until synced.
+const HANDSHAKE_ATTEMPTS: usize = 20;
+const MAX_BANNER_BYTES: usize = 64;
+
+const CMD_GET_TARGET_CONFIG: u8 = 0xD8;
+const CMD_GET_ME_ID: u8 = 0xE1;
+const CMD_GET_SOC_ID: u8 = 0xE7;
+const CMD_GET_HW_SW_VER: u8 = 0xFC;
+const CMD_GET_HW_CODE: u8 = 0xFD;
+const CMD_GET_BL_VER: u8 = 0xFE;
+
+const TARGET_CONFIG_SBC: u32 = 0x1;
+const TARGET_CONFIG_SLA: u32 = 0x2;
+const TARGET_CONFIG_DAA: u32 = 0x4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum BootMode {
+    Brom,
+    Preloader,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetConfig {
+    pub raw: u32,
+    /// Secure boot: only signed preloader / DA images are accepted
+    pub sbc: bool,
+    /// Serial link authentication: the DA must pass a challenge
+    pub sla: bool,
+    /// Download agent authentication: the DA must be signed
+    pub daa: bool,
+}
+
+impl TargetConfig {
+    pub fn from_raw(raw: u32) -> Self {
+        Self {
+            raw,
+            sbc: raw & TARGET_CONFIG_SBC != 0,
+            sla: raw & TARGET_CONFIG_SLA != 0,
+            daa: raw & TARGET_CONFIG_DAA != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HwSwVersion {
+    pub hw_sub_code: u16,
+    pub hw_version: u16,
+    pub sw_version: u16,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BromIdentity {
+    pub mode: BootMode,
+    /// Preloader version; BROM has none
+    pub bl_version: Option<u8>,
+    pub hw_code: u16,
+    pub version: HwSwVersion,
+    pub target_config: Option<TargetConfig>,
+    pub me_id: Option<String>,
+    pub soc_id: Option<String>,
+    pub chip: Option<&'static ChipInfo>,
+    pub warnings: Vec<String>,
+}
+
+/* ================= SESSION ================= */
+
+/// Protocol session over any byte stream: a serial port in the app,
+/// a pty or in-memory fake when testing.
+pub struct BromSession<T: Read + Write> {
+    io: T,
+}
+
+impl<T: Read + Write> BromSession<T> {
+    pub fn new(io: T) -> Self {
+        Self { io }
+    }
+
+    pub fn handshake(&mut self) -> Result<(), String> {
+        let first = START_SEQUENCE[0];
+        let mut synced = false;
+
+        'attempts: for _ in 0..HANDSHAKE_ATTEMPTS {
+            self.write(&[first])?;
+
+            // Skip banner bytes; resend only after the device goes quiet
+            for _ in 0..MAX_BANNER_BYTES {
+                match self.read_byte() {
+                    Ok(b) if b == !first => {
+                        synced = true;
+                        break 'attempts;
+                    }
+                    Ok(_) => continue,
+                    Err(e) if e.contains("timed out") => continue 'attempts,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        if !synced {
+            return Err("No handshake response; is the device in BROM / preloader mode?".into());
+        }
+
+        for &b in &START_SEQUENCE[1..] {
+            self.write(&[b])?;
+            let reply = self.read_byte()?;
+            if reply != !b {
+                return Err(format!(
+                    "Handshake failed: sent {:#04x}, got {:#04x}",
+                    b, reply
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get_hw_code(&mut self) -> Result<u16, String> {
+        self.command(CMD_GET_HW_CODE)?;
+        let hw_code = self.read_u16()?;
+        self.status("GET_HW_CODE")?;
+        Ok(hw_code)
+    }
+
+    pub fn get_hw_sw_ver(&mut self) -> Result<HwSwVersion, String> {
+        self.command(CMD_GET_HW_SW_VER)?;
+        let version = HwSwVersion {
+            hw_sub_code: self.read_u16()?,
+            hw_version: self.read_u16()?,
+            sw_version: self.read_u16()?,
+        };
+        self.status("GET_HW_SW_VER")?;
+        Ok(version)
+    }
+
+    pub fn get_target_config(&mut self) -> Result<TargetConfig, String> {
+        self.command(CMD_GET_TARGET_CONFIG)?;
+        let raw = self.read_u32()?;
+        self.status("GET_TARGET_CONFIG")?;
+        Ok(TargetConfig::from_raw(raw))
+    }
+
+    /// BROM echoes the command; the preloader answers with its version.
+    pub fn get_bl_ver(&mut self) -> Result<(BootMode, Option<u8>), String> {
+        self.write(&[CMD_GET_BL_VER])?;
+
+        Ok(match self.read_byte()? {
+            CMD_GET_BL_VER => (BootMode::Brom, None),
+            version => (BootMode::Preloader, Some(version)),
+        })
+    }
+
+    pub fn get_me_id(&mut self) -> Result<Vec<u8>, String> {
+        self.command(CMD_GET_ME_ID)?;
+        self.read_blob("GET_ME_ID")
+    }
+
+    pub fn get_soc_id(&mut self) -> Result<Vec<u8>, String> {
+        self.command(CMD_GET_SOC_ID)?;
+        self.read_blob("GET_SOC_ID")
+    }
+
+    /// Full read-only identification. Commands older chips don't know
+    /// are reported as warnings; after the first one the stream can't
+    /// be trusted, so later optional commands are skipped.
+    pub fn identify(&mut self) -> Result<BromIdentity, String> {
+        self.handshake()?;
+
+        let hw_code = self.get_hw_code()?;
+        let version = self.get_hw_sw_ver()?;
+        let (mode, bl_version) = self.get_bl_ver()?;
+
+        let mut warnings = Vec::new();
+        let mut identity = BromIdentity {
+            mode,
+            bl_version,
+            hw_code,
+            version,
+            target_config: None,
+            me_id: None,
+            soc_id: None,
+            chip: chips::lookup_hw_code(hw_code),
+            warnings: Vec::new(),
+        };
+
+        if identity.chip.is_none() {
+            warnings.push(format!("Unknown hw_code {:#06x}", hw_code));
+        }
+
+        let result = (|| -> Result<(), String> {
+            identity.target_config = Some(self.get_target_config()?);
+            identity.me_id = Some(hex(&self.get_me_id()?));
+            identity.soc_id = Some(hex(&self.get_soc_id()?));
+            Ok(())
+        })();
+
+        if let Err(e) = result {
+            warnings.push(format!("Identification incomplete: {}", e));
+        }
+
+        identity.warnings = warnings;
+        Ok(identity)
+    }
+
+    /* ---------- framing ---------- */
+
+    fn command(&mut self, cmd: u8) -> Result<(), String> {
+        self.write(&[cmd])?;
+        let echo = self.read_byte()?;
+        if echo != cmd {
+            return Err(format!(
+                "Command {:#04x} not acknowledged (got {:#04x})",
+                cmd, echo
+            ));
+        }
+        Ok(())
+    }
+
+    fn status(&mut self, name: &str) -> Result<(), String> {
+        match self.read_u16()? {
+            0 => Ok(()),
+            status => Err(format!("{} failed with status {:#06x}", name, status)),
+        }
+    }
+
+    /// u32 length, payload, u16 status
+    fn read_blob(&mut self, name: &str) -> Result<Vec<u8>, String> {
+        let len = self.read_u32()? as usize;
+        if len > 256 {
+            return Err(format!("{} returned implausible length {}", name, len));
+        }
+
+        let mut data = vec![0u8; len];
+        self.read_exact(&mut data)?;
+        self.status(name)?;
+        Ok(data)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), String> {
+        self.io
+            .write_all(data)
+            .and_then(|_| self.io.flush())
+            .map_err(|e| format!("Write failed: {}", e))
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), String> {
+        self.io.read_exact(buf).map_err(|e| match e.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => "Read timed out".to_string(),
+            ErrorKind::UnexpectedEof => "Device closed the connection".to_string(),
+            _ => format!("Read failed: {}", e),
+        })
+    }
+
+    fn read_byte(&mut self) -> Result<u8, String> {
+        let mut buf = [0u8; 1];
+        self.read_exact(&mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, String> {
+        let mut buf = [0u8; 2];
+        self.read_exact(&mut buf)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, String> {
+        let mut buf = [0u8; 4];
+        self.read_exact(&mut buf)?;
+        Ok(u32::from_be_bytes(buf))
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/* ================= SERIAL PORTS ================= */
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MtkPort {
+    pub port: String,
+    pub product_id: u16,
+    /// `None` for MediaTek ports that are not BROM or preloader
+    pub mode: Option<BootMode>,
+}
+
+pub fn boot_mode_for_pid(product_id: u16) -> Option<BootMode> {
+    if product_id == BROM_USB_PID {
+        Some(BootMode::Brom)
+    } else if PRELOADER_USB_PIDS.contains(&product_id) {
+        Some(BootMode::Preloader)
+    } else {
+        None
+    }
+}
+
+/// MediaTek serial ports currently enumerated by the OS.
+/// Enumeration only; the ports are not opened.
+pub fn find_ports() -> Vec<MtkPort> {
+    let Ok(ports) = serialport::available_ports() else {
+        return Vec::new();
+    };
+
+    ports
+        .into_iter()
+        .filter_map(|p| match p.port_type {
+            serialport::SerialPortType::UsbPort(usb) if usb.vid == MTK_USB_VID => Some(MtkPort {
+                port: p.port_name,
+                product_id: usb.pid,
+                mode: boot_mode_for_pid(usb.pid),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Ports speaking the BROM / preloader protocol.
+pub fn find_brom_ports() -> Vec<MtkPort> {
+    find_ports().into_iter().filter(|p| p.mode.is_some()).collect()
+}
+
+/// Open `port` (or the first MTK port found) and identify the device.
+pub fn identify_port(port: Option<&str>) -> Result<BromIdentity, String> {
+    let port = match port {
+        Some(p) => p.to_string(),
+        None => find_brom_ports()
+            .into_iter()
+            .next()
+            .map(|p| p.port)
+            .ok_or("No MediaTek BROM / preloader port found")?,
+    };
+
+    // Baud rate is ignored by CDC-ACM but required by the API
+    let io = serialport::new(&port, 115_200)
+        .timeout(Duration::from_millis(500))
+        .open()
+        .map_err(|e| format!("Cannot open {}: {}", port, e))?;
+
+    BromSession::new(io).identify()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::VecDeque, io};
+
+    #[cfg(unix)]
+    use serialport::SerialPort;
+
+    /// Scripted device: each exchange waits for the host to write exactly
+    /// `expect`, then queues `reply`. Reading an empty queue times out.
+    struct FakeDevice {
+        script: VecDeque<(Vec<u8>, Vec<u8>)>,
+        pending: Vec<u8>,
+        rx: VecDeque<u8>,
+    }
+
+    impl FakeDevice {
+        fn new(banner: &[u8], script: &[(&[u8], &[u8])]) -> Self {
+            Self {
+                script: script.iter().map(|(e, r)| (e.to_vec(), r.to_vec())).collect(),
+                pending: Vec::new(),
+                rx: banner.iter().copied().collect(),
+            }
+        }
+
+        fn finished(&self) -> bool {
+            self.script.is_empty() && self.rx.is_empty()
+        }
+    }
+
+    impl Read for FakeDevice {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.rx.is_empty() {
+                return Err(io::Error::new(ErrorKind::TimedOut, "timed out"));
+            }
+
+            let n = buf.len().min(self.rx.len());
+            for (slot, byte) in buf.iter_mut().zip(self.rx.drain(..n)) {
+                *slot = byte;
+            }
+            Ok(n)
+        }
+    }
+
+    impl Write for FakeDevice {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.pending.extend_from_slice(buf);
+
+            if let Some((expect, reply)) = self.script.front() {
+                let pending = &self.pending;
+                assert!(expect.starts_with(pending), "unexpected write {:02x?}", pending);
+
+                if *expect == self.pending {
+                    self.rx.extend(reply);
+                    self.script.pop_front();
+                    self.pending.clear();
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const HANDSHAKE: &[(&[u8], &[u8])] =
+        &[(&[0xA0], &[0x5F]), (&[0x0A], &[0xF5]), (&[0x50], &[0xAF]), (&[0x05], &[0xFA])];
+
+    fn session(banner: &[u8], script: &[(&[u8], &[u8])]) -> BromSession<FakeDevice> {
+        let mut full = HANDSHAKE.to_vec();
+        full.extend_from_slice(script);
+        BromSession::new(FakeDevice::new(banner, &full))
+    }
+
+    #[test]
+    fn handshake_skips_banner() {
+        let mut brom = session(b"READY", &[]);
+
+        brom.handshake().unwrap();
+        assert!(brom.io.finished());
+    }
+
+    #[test]
+    fn handshake_retries_until_device_answers() {
+        let mut script = vec![(&[0xA0][..], &[][..]), (&[0xA0][..], &[][..])];
+        script.extend_from_slice(HANDSHAKE);
+        let mut brom = BromSession::new(FakeDevice::new(&[], &script));
+
+        brom.handshake().unwrap();
+        assert!(brom.io.finished());
+    }
+
+    #[test]
+    fn handshake_rejects_wrong_complement() {
+        let script: &[(&[u8], &[u8])] = &[(&[0xA0], &[0x5F]), (&[0x0A], &[0x0A])];
+        let mut brom = BromSession::new(FakeDevice::new(&[], script));
+
+        assert_eq!(brom.handshake().unwrap_err(), "Handshake failed: sent 0x0a, got 0x0a");
+    }
+
+    #[test]
+    fn silent_device_fails_handshake() {
+        let script: Vec<(&[u8], &[u8])> = vec![(&[0xA0], &[]); HANDSHAKE_ATTEMPTS];
+        let mut brom = BromSession::new(FakeDevice::new(&[], &script));
+
+        assert!(brom.handshake().unwrap_err().starts_with("No handshake response"));
+    }
+
+    /// MT6835 preloader answering every identification command.
+    const PRELOADER: &[(&[u8], &[u8])] = &[
+        (&[CMD_GET_HW_CODE], &[CMD_GET_HW_CODE, 0x08, 0x13, 0, 0]),
+        (&[CMD_GET_HW_SW_VER], &[CMD_GET_HW_SW_VER, 0x8A, 0x00, 0xCA, 0x00, 0, 0, 0, 0]),
+        (&[CMD_GET_BL_VER], &[0x03]),
+        (&[CMD_GET_TARGET_CONFIG], &[CMD_GET_TARGET_CONFIG, 0, 0, 0, 0x05, 0, 0]),
+        (&[CMD_GET_ME_ID], &[CMD_GET_ME_ID, 0, 0, 0, 2, 0xAB, 0xCD, 0, 0]),
+        (&[CMD_GET_SOC_ID], &[CMD_GET_SOC_ID, 0, 0, 0, 1, 0xEE, 0, 0]),
+    ];
+
+    #[test]
+    fn identifies_preloader() {
+        let mut brom = session(b"READY", PRELOADER);
+
+        let id = brom.identify().unwrap();
+
+        assert_eq!(id.mode, BootMode::Preloader);
+        assert_eq!(id.bl_version, Some(3));
+        assert_eq!(id.hw_code, 0x0813);
+        assert_eq!(id.version.hw_sub_code, 0x8A00);
+        assert_eq!(id.version.hw_version, 0xCA00);
+        assert_eq!(id.chip.unwrap().soc, "MT6835");
+
+        let config = id.target_config.unwrap();
+        assert_eq!(config.raw, 5);
+        assert!(config.sbc && !config.sla && config.daa);
+
+        assert_eq!(id.me_id.as_deref(), Some("abcd"));
+        assert_eq!(id.soc_id.as_deref(), Some("ee"));
+        assert!(id.warnings.is_empty());
+        assert!(brom.io.finished());
+    }
+
+    #[test]
+    fn brom_echoes_bl_ver_and_missing_commands_warn() {
+        let mut brom = session(
+            &[],
+            &[
+                (&[CMD_GET_HW_CODE], &[CMD_GET_HW_CODE, 0xAB, 0xCD, 0, 0]),
+                (&[CMD_GET_HW_SW_VER], &[CMD_GET_HW_SW_VER, 0, 0, 0, 0, 0, 0, 0, 0]),
+                (&[CMD_GET_BL_VER], &[CMD_GET_BL_VER]),
+                // Old BROM: answers an unknown command with garbage
+                (&[CMD_GET_TARGET_CONFIG], &[0x00]),
+            ],
+        );
+
+        let id = brom.identify().unwrap();
+
+        assert_eq!(id.mode, BootMode::Brom);
+        assert_eq!(id.bl_version, None);
+        assert!(id.chip.is_none());
+        assert!(id.target_config.is_none());
+        assert_eq!(
+            id.warnings,
+            vec![
+                "Unknown hw_code 0xabcd",
+                "Identification incomplete: Command 0xd8 not acknowledged (got 0x00)",
+            ]
+        );
+    }
+
+    #[test]
+    fn status_and_blob_errors() {
+        let mut brom = session(
+            &[],
+            &[
+                (&[CMD_GET_HW_CODE], &[CMD_GET_HW_CODE, 0x07, 0x66, 0x1D, 0x0C]),
+                (&[CMD_GET_ME_ID], &[CMD_GET_ME_ID, 0, 0, 0x10, 0]),
+            ],
+        );
+        brom.handshake().unwrap();
+
+        assert_eq!(brom.get_hw_code().unwrap_err(), "GET_HW_CODE failed with status 0x1d0c");
+        assert_eq!(brom.get_me_id().unwrap_err(), "GET_ME_ID returned implausible length 4096");
+    }
+
+    /// Play `script` on the master side of a pty: wait for each expected
+    /// write from the host, then answer. Fails on the first mismatch.
+    /// The master is handed back rather than closed, since a hangup
+    /// would fail the host's last flush.
+    #[cfg(unix)]
+    fn pty_responder(
+        mut master: serialport::TTYPort,
+        script: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> std::thread::JoinHandle<Result<serialport::TTYPort, String>> {
+        std::thread::spawn(move || {
+            master.set_timeout(Duration::from_secs(5)).map_err(|e| e.to_string())?;
+
+            for (expect, reply) in script {
+                let mut got = vec![0u8; expect.len()];
+                master.read_exact(&mut got).map_err(|e| e.to_string())?;
+                if got != expect {
+                    return Err(format!("expected {:02x?}, got {:02x?}", expect, got));
+                }
+                master.write_all(&reply).map_err(|e| e.to_string())?;
+            }
+            Ok(master)
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn identifies_over_a_pty() {
+        // The pair's slave stays open so the master never sees a hangup
+        // before identify_port opens the same device by name.
+        let (master, slave) = serialport::TTYPort::pair().unwrap();
+        let name = slave.name().unwrap();
+
+        // The banner only arrives once the host starts talking
+        let mut script: Vec<(Vec<u8>, Vec<u8>)> = vec![(vec![0xA0], b"READY\x5F".to_vec())];
+        let exchanges = HANDSHAKE[1..].iter().chain(PRELOADER);
+        script.extend(exchanges.map(|(e, r)| (e.to_vec(), r.to_vec())));
+        let responder = pty_responder(master, script);
+
+        let id = identify_port(Some(&name));
+        let master = responder.join().unwrap().unwrap();
+        drop((master, slave));
+        let id = id.unwrap();
+
+        assert_eq!(id.mode, BootMode::Preloader);
+        assert_eq!(id.hw_code, 0x0813);
+        assert_eq!(id.me_id.as_deref(), Some("abcd"));
+        assert_eq!(id.soc_id.as_deref(), Some("ee"));
+        assert!(id.warnings.is_empty());
+    }
+
+    #[test]
+    fn missing_port_fails_to_open() {
+        let err = identify_port(Some("/dev/does-not-exist")).unwrap_err();
+        assert!(err.starts_with("Cannot open /dev/does-not-exist"));
+    }
+
+    #[test]
+    fn classifies_usb_product_ids() {
+        assert_eq!(boot_mode_for_pid(0x0003), Some(BootMode::Brom));
+        assert_eq!(boot_mode_for_pid(0x2000), Some(BootMode::Preloader));
+        assert_eq!(boot_mode_for_pid(0x2001), Some(BootMode::Preloader));
+        assert_eq!(boot_mode_for_pid(0x20FF), Some(BootMode::Preloader));
+        assert_eq!(boot_mode_for_pid(0x201C), None);
+        assert_eq!(boot_mode_for_pid(0x2008), None);
+    }
+}
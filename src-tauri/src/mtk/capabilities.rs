@@ -1,38 +1,226 @@
 use serde::Serialize;
 
+use crate::detection_service::DeviceState;
+use crate::mtk::brom::{self, BootMode, TargetConfig};
+use crate::profile::ProfilePolicy;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Interface {
+    None,
+    AdbUnauthorized,
+    Adb,
+    Fastboot,
+    Preloader,
+    Brom,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct MtkCapabilities {
+    pub interface: Interface,
+
     pub adb: bool,
     pub fastboot: bool,
     pub preloader: bool,
     pub brom: bool,
 
+    pub can_read_partitions: bool,
+    pub can_write_partitions: bool,
+    /// `None` until the BROM target config has been read
+    pub needs_auth: Option<bool>,
+    /// Storage is only reachable through a download agent
+    pub needs_da: bool,
+    pub target_config: Option<TargetConfig>,
+
+    pub notes: Vec<String>,
     pub description: String,
 }
 
+/// What the connected device lets us do, from its current interface.
+///
+/// `boot_mode` and `target_config` come from BROM port enumeration and
+/// identification; `unlocked` and `rooted` from the fastboot / ADB side.
+/// Writes additionally require the matched profile to allow flashing.
 pub fn evaluate(
-    adb: bool,
-    fastboot: bool,
-    mtk_state: &str,
+    state: &DeviceState,
+    boot_mode: Option<BootMode>,
+    target_config: Option<&TargetConfig>,
+    unlocked: Option<bool>,
+    rooted: Option<bool>,
+    policy: &ProfilePolicy,
 ) -> MtkCapabilities {
-    let preloader = matches!(mtk_state, "MTK Preloader");
-    let brom = preloader; // for now, treat preloader as BROM access
-
-    let description = if brom {
-        "BROM / Preloader access detected (dangerous)".to_string()
-    } else if fastboot {
-        "Fastboot mode available".to_string()
-    } else if adb {
-        "ADB access available".to_string()
+    let mut notes = Vec::new();
+
+    let interface = match state {
+        DeviceState::Disconnected => Interface::None,
+        DeviceState::AdbUnauthorized => Interface::AdbUnauthorized,
+        DeviceState::AdbDevice => Interface::Adb,
+        DeviceState::Fastboot => Interface::Fastboot,
+        DeviceState::MtkPreloader => match boot_mode {
+            Some(BootMode::Brom) => Interface::Brom,
+            Some(BootMode::Preloader) => Interface::Preloader,
+            None => {
+                notes.push("BROM / preloader mode not determined; assuming preloader".into());
+                Interface::Preloader
+            }
+        },
+    };
+
+    let needs_da = matches!(interface, Interface::Preloader | Interface::Brom);
+
+    let needs_auth = if needs_da {
+        target_config.map(|c| c.sla || c.daa)
     } else {
-        "No active MediaTek interface".to_string()
+        None
+    };
+
+    let can_read_partitions = match interface {
+        Interface::Adb => {
+            if rooted != Some(true) {
+                notes.push("Partition reads over ADB need root".into());
+            }
+            rooted == Some(true)
+        }
+        Interface::Fastboot => {
+            notes.push("Fastboot cannot read partitions back".into());
+            false
+        }
+        Interface::Preloader | Interface::Brom => match needs_auth {
+            Some(false) => true,
+            Some(true) => {
+                notes.push("Device requires an authenticated DA (SLA / DAA)".into());
+                false
+            }
+            None => {
+                notes.push("Target config unknown; run identification first".into());
+                false
+            }
+        },
+        Interface::None | Interface::AdbUnauthorized => false,
     };
 
+    let device_allows_write = match interface {
+        Interface::Fastboot => {
+            if unlocked != Some(true) {
+                notes.push("Bootloader is locked".into());
+            }
+            unlocked == Some(true)
+        }
+        Interface::Preloader | Interface::Brom => can_read_partitions,
+        _ => false,
+    };
+
+    if device_allows_write && !policy.allow_flash {
+        notes.push("Device profile does not allow flashing".into());
+    }
+
+    if target_config.is_some_and(|c| c.sbc) {
+        notes.push("Secure boot enabled: only signed boot images are accepted".into());
+    }
+
+    let description = match interface {
+        Interface::Brom => "BROM access detected (dangerous)",
+        Interface::Preloader => "Preloader access detected (dangerous)",
+        Interface::Fastboot => "Fastboot mode available",
+        Interface::Adb => "ADB access available",
+        Interface::AdbUnauthorized => "ADB connected but not authorized",
+        Interface::None => "No active MediaTek interface",
+    }
+    .to_string();
+
     MtkCapabilities {
-        adb,
-        fastboot,
-        preloader,
-        brom,
+        interface,
+        adb: interface == Interface::Adb,
+        fastboot: interface == Interface::Fastboot,
+        preloader: interface == Interface::Preloader,
+        brom: interface == Interface::Brom,
+        can_read_partitions,
+        can_write_partitions: device_allows_write && policy.allow_flash,
+        needs_auth,
+        needs_da,
+        target_config: target_config.cloned(),
+        notes,
         description,
     }
 }
+
+/// BROM side inputs for `evaluate`: the boot mode from port enumeration
+/// and, when `probe` is set, the target config from a read-only handshake.
+pub fn probe_brom(probe: bool) -> Result<(Option<BootMode>, Option<TargetConfig>), String> {
+    let mode = brom::find_brom_ports().first().and_then(|p| p.mode);
+
+    if !probe {
+        return Ok((mode, None));
+    }
+
+    let identity = brom::identify_port(None)?;
+    Ok((Some(identity.mode), identity.target_config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::{parse_profile, profile_id, profile_policy};
+    use std::path::Path;
+
+    fn fastboot(unlocked: Option<bool>, profile: &str) -> MtkCapabilities {
+        evaluate(&DeviceState::Fastboot, None, None, unlocked, None, &profile_policy(profile))
+    }
+
+    #[test]
+    fn developer_profile_can_write_unlocked_device() {
+        let yaml = "id: developer-unlocked\ndevice:\n  name: Dev Board\n  model: DEV-1\n";
+        let profiles = vec![parse_profile(yaml, Path::new("devices/dev.yaml")).unwrap()];
+
+        let caps = fastboot(Some(true), &profile_id(&profiles, "Dev Board"));
+
+        assert_eq!(caps.interface, Interface::Fastboot);
+        assert!(caps.can_write_partitions);
+        assert!(!caps.can_read_partitions);
+    }
+
+    #[test]
+    fn writes_need_unlock_and_profile() {
+        let locked = fastboot(Some(false), "developer-unlocked");
+        assert!(!locked.can_write_partitions);
+        assert!(locked.notes.contains(&"Bootloader is locked".to_string()));
+
+        let generic = fastboot(Some(true), "generic-mtk");
+        assert!(!generic.can_write_partitions);
+        assert!(generic.notes.contains(&"Device profile does not allow flashing".to_string()));
+    }
+
+    #[test]
+    fn preloader_access_depends_on_auth() {
+        let policy = profile_policy("developer-unlocked");
+        let open = TargetConfig::from_raw(0x1);
+        let sla = TargetConfig::from_raw(0x3);
+
+        let caps = evaluate(
+            &DeviceState::MtkPreloader,
+            Some(BootMode::Brom),
+            Some(&open),
+            None,
+            None,
+            &policy,
+        );
+        assert_eq!(caps.interface, Interface::Brom);
+        assert_eq!(caps.needs_auth, Some(false));
+        assert!(caps.needs_da && caps.can_read_partitions && caps.can_write_partitions);
+
+        let caps = evaluate(&DeviceState::MtkPreloader, None, Some(&sla), None, None, &policy);
+        assert_eq!(caps.interface, Interface::Preloader);
+        assert_eq!(caps.needs_auth, Some(true));
+        assert!(!caps.can_read_partitions && !caps.can_write_partitions);
+    }
+
+    #[test]
+    fn adb_reads_need_root() {
+        let policy = profile_policy("generic-mtk");
+
+        let caps = evaluate(&DeviceState::AdbDevice, None, None, None, Some(true), &policy);
+        assert!(caps.adb && caps.can_read_partitions && !caps.can_write_partitions);
+
+        let caps = evaluate(&DeviceState::AdbDevice, None, None, None, None, &policy);
+        assert!(!caps.can_read_partitions);
+    }
+}
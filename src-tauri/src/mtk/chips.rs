@@ -0,0 +1,324 @@
+use serde::Serialize;
+
+use crate::mtk::scatter::StorageType;
+
+// Built-in MediaTek SoC table, keyed by the BROM `GET_HW_CODE` value and
+// by `ro.board.platform`. Only chips seen in service or documented in
+// public BROM tables are listed; unknown chips simply don't match.
+
+/// Download-agent protocol generation the chip's BROM/preloader expects.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum DaProtocol {
+    /// Legacy DA (SP Flash Tool v3 era)
+    Legacy,
+    /// XFlash / V5 DA
+    XFlash,
+    /// XML / V6 DA; authenticated DA is the norm
+    Xml,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChipInfo {
+    /// BROM hw_codes; empty when not yet confirmed for this chip
+    pub hw_codes: &'static [u16],
+    pub soc: &'static str,
+    /// Additional `ro.board.platform` values sharing this die
+    pub aliases: &'static [&'static str],
+    pub marketing_name: &'static str,
+    pub storage: &'static [StorageType],
+    pub da_protocol: DaProtocol,
+    pub security_notes: &'static [&'static str],
+}
+
+const EMMC: &[StorageType] = &[StorageType::Emmc];
+const UFS: &[StorageType] = &[StorageType::Ufs];
+const EMMC_UFS: &[StorageType] = &[StorageType::Emmc, StorageType::Ufs];
+
+const SBC_FUSED: &str = "Secure boot (SBC) is fused per device; check target config";
+const AUTH_DA: &str = "Production units usually require an authenticated DA (SLA/DAA)";
+
+pub static CHIPS: &[ChipInfo] = &[
+    ChipInfo {
+        hw_codes: &[0x0633],
+        soc: "MT6570",
+        aliases: &[],
+        marketing_name: "MT6570",
+        storage: EMMC,
+        da_protocol: DaProtocol::Legacy,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x0321],
+        soc: "MT6735",
+        aliases: &["MT6735M"],
+        marketing_name: "MT6735",
+        storage: EMMC,
+        da_protocol: DaProtocol::Legacy,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x0335],
+        soc: "MT6737",
+        aliases: &["MT6737M", "MT6737T"],
+        marketing_name: "MT6737",
+        storage: EMMC,
+        da_protocol: DaProtocol::Legacy,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x0601],
+        soc: "MT6750",
+        aliases: &["MT6750T"],
+        marketing_name: "MT6750",
+        storage: EMMC,
+        da_protocol: DaProtocol::Legacy,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x0326],
+        soc: "MT6755",
+        aliases: &[],
+        marketing_name: "Helio P10",
+        storage: EMMC,
+        da_protocol: DaProtocol::Legacy,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x0551],
+        soc: "MT6757",
+        aliases: &[],
+        marketing_name: "Helio P20 / P25",
+        storage: EMMC,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x0699],
+        soc: "MT6739",
+        aliases: &[],
+        marketing_name: "MT6739",
+        storage: EMMC,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x0690],
+        soc: "MT6763",
+        aliases: &[],
+        marketing_name: "Helio P23",
+        storage: EMMC,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x0717],
+        soc: "MT6761",
+        aliases: &[],
+        marketing_name: "Helio A22",
+        storage: EMMC,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x0766],
+        soc: "MT6765",
+        aliases: &[],
+        marketing_name: "Helio P35 / G35 / G37",
+        storage: EMMC,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x0707],
+        soc: "MT6768",
+        aliases: &["MT6769", "MT6769T", "MT6769Z"],
+        marketing_name: "Helio G85 / G88 / P65",
+        storage: EMMC,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x0788],
+        soc: "MT6771",
+        aliases: &[],
+        marketing_name: "Helio P60 / P70",
+        storage: EMMC,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x0725],
+        soc: "MT6779",
+        aliases: &[],
+        marketing_name: "Helio P90",
+        storage: EMMC_UFS,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[],
+        soc: "MT6781",
+        aliases: &[],
+        marketing_name: "Helio G96",
+        storage: EMMC_UFS,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED, AUTH_DA],
+    },
+    ChipInfo {
+        hw_codes: &[],
+        soc: "MT6785",
+        aliases: &[],
+        marketing_name: "Helio G90 / G90T / G95",
+        storage: EMMC_UFS,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED],
+    },
+    ChipInfo {
+        hw_codes: &[0x1208],
+        soc: "MT6789",
+        aliases: &[],
+        marketing_name: "Helio G99",
+        storage: EMMC_UFS,
+        da_protocol: DaProtocol::Xml,
+        security_notes: &[SBC_FUSED, AUTH_DA],
+    },
+    ChipInfo {
+        hw_codes: &[],
+        soc: "MT6833",
+        aliases: &[],
+        marketing_name: "Dimensity 700",
+        storage: EMMC_UFS,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED, AUTH_DA],
+    },
+    ChipInfo {
+        hw_codes: &[0x0813],
+        soc: "MT6835",
+        aliases: &["MT6835T"],
+        marketing_name: "Dimensity 6100+ / 6300",
+        storage: EMMC_UFS,
+        da_protocol: DaProtocol::Xml,
+        security_notes: &[SBC_FUSED, AUTH_DA],
+    },
+    ChipInfo {
+        hw_codes: &[0x0996],
+        soc: "MT6853",
+        aliases: &[],
+        marketing_name: "Dimensity 720",
+        storage: UFS,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED, AUTH_DA],
+    },
+    ChipInfo {
+        hw_codes: &[0x0886],
+        soc: "MT6873",
+        aliases: &[],
+        marketing_name: "Dimensity 800",
+        storage: UFS,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED, AUTH_DA],
+    },
+    ChipInfo {
+        hw_codes: &[0x0959],
+        soc: "MT6877",
+        aliases: &[],
+        marketing_name: "Dimensity 900 / 920 / 1080",
+        storage: UFS,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED, AUTH_DA],
+    },
+    ChipInfo {
+        hw_codes: &[0x0816],
+        soc: "MT6885",
+        aliases: &["MT6883", "MT6889"],
+        marketing_name: "Dimensity 1000 / 1000+",
+        storage: UFS,
+        da_protocol: DaProtocol::XFlash,
+        security_notes: &[SBC_FUSED, AUTH_DA],
+    },
+    ChipInfo {
+        hw_codes: &[0x1172],
+        soc: "MT6895",
+        aliases: &["MT6896"],
+        marketing_name: "Dimensity 8100 / 8200",
+        storage: UFS,
+        da_protocol: DaProtocol::Xml,
+        security_notes: &[SBC_FUSED, AUTH_DA],
+    },
+];
+
+/* ================= LOOKUP ================= */
+
+pub fn lookup_hw_code(hw_code: u16) -> Option<&'static ChipInfo> {
+    CHIPS.iter().find(|c| c.hw_codes.contains(&hw_code))
+}
+
+/// Accepts SoC names ("MT6765") and `ro.board.platform` values ("mt6765").
+pub fn lookup_platform(platform: &str) -> Option<&'static ChipInfo> {
+    let platform = platform.trim();
+
+    CHIPS.iter().find(|c| {
+        c.soc.eq_ignore_ascii_case(platform)
+            || c.aliases.iter().any(|a| a.eq_ignore_ascii_case(platform))
+    })
+}
+
+/// BROM identification wins; the Android platform property is the fallback.
+pub fn identify(hw_code: Option<u16>, platform: Option<&str>) -> Option<&'static ChipInfo> {
+    hw_code
+        .and_then(lookup_hw_code)
+        .or_else(|| platform.and_then(lookup_platform))
+}
+
+/// True if both names refer to the same die (e.g. MT6768 and MT6769).
+pub fn same_chip(a: &str, b: &str) -> bool {
+    if a.eq_ignore_ascii_case(b) {
+        return true;
+    }
+
+    match (lookup_platform(a), lookup_platform(b)) {
+        (Some(x), Some(y)) => std::ptr::eq(x, y),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hw_codes_are_unique() {
+        let mut codes: Vec<u16> = CHIPS.iter().flat_map(|c| c.hw_codes.iter().copied()).collect();
+        let total = codes.len();
+
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(codes.len(), total);
+    }
+
+    #[test]
+    fn looks_up_by_hw_code_and_platform() {
+        assert_eq!(lookup_hw_code(0x0813).unwrap().soc, "MT6835");
+        assert_eq!(lookup_hw_code(0x0766).unwrap().soc, "MT6765");
+        assert!(lookup_hw_code(0xFFFF).is_none());
+
+        assert_eq!(lookup_platform("mt6835").unwrap().soc, "MT6835");
+        assert_eq!(lookup_platform(" mt6769t ").unwrap().soc, "MT6768");
+        assert!(lookup_platform("mt6753").is_none());
+    }
+
+    #[test]
+    fn brom_identification_wins_over_platform() {
+        assert_eq!(identify(Some(0x0813), Some("mt6765")).unwrap().soc, "MT6835");
+        assert_eq!(identify(Some(0xFFFF), Some("mt6765")).unwrap().soc, "MT6765");
+        assert!(identify(None, None).is_none());
+    }
+
+    #[test]
+    fn aliases_are_the_same_chip() {
+        assert!(same_chip("MT6768", "mt6769z"));
+        assert!(same_chip("mt6000", "MT6000"));
+        assert!(!same_chip("MT6765", "MT6762"));
+        assert!(!same_chip("MT6835", "MT6833"));
+    }
+}
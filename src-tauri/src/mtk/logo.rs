@@ -0,0 +1,273 @@
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use crate::mtk::partition::{self, HEADER_SIZE};
+use crate::profile::{DisplayInfo, PixelFormat};
+
+// logo.bin layout, after the 512-byte MTK header named "logo":
+//
+//   u32 count | u32 body size | u32 offset[count] | zlib frame data...
+//
+// Offsets are relative to the start of the body. Each frame inflates to
+// a raw framebuffer; full-screen frames are width * height pixels, the
+// rest (progress digits, battery icons) have no recorded dimensions.
+
+const MANIFEST: &str = "logo.json";
+const HEADER_FILE: &str = "header.bin";
+const MAX_FRAMES: usize = 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogoFrame {
+    pub index: usize,
+    pub file: String,
+    pub size: usize,
+    /// Only known for frames matching the profile's panel size
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogoManifest {
+    pub pixel_format: PixelFormat,
+    pub frames: Vec<LogoFrame>,
+}
+
+/* ================= PARSE / BUILD ================= */
+
+/// Split a logo.bin into its MTK header and inflated frames.
+pub fn parse_logo(data: &[u8]) -> Result<(Vec<u8>, Vec<Vec<u8>>), String> {
+    if !partition::has_mtk_header(data) || data.len() < HEADER_SIZE as usize + 8 {
+        return Err("Not an MTK logo image (no partition header)".into());
+    }
+
+    let header = data[..HEADER_SIZE as usize].to_vec();
+    let body = &data[HEADER_SIZE as usize..];
+
+    let count = le32(body, 0) as usize;
+    let table_end = 8 + count * 4;
+
+    if count == 0 || count > MAX_FRAMES || table_end > body.len() {
+        return Err(format!("Implausible logo frame count {}", count));
+    }
+
+    let offsets: Vec<usize> = (0..count).map(|i| le32(body, 8 + i * 4) as usize).collect();
+    let mut frames = Vec::with_capacity(count);
+
+    for (i, &start) in offsets.iter().enumerate() {
+        let end = offsets.get(i + 1).copied().unwrap_or(body.len());
+
+        if start < table_end || end < start || end > body.len() {
+            return Err(format!("Frame {} has invalid offset {:#x}", i, start));
+        }
+
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&body[start..end])
+            .read_to_end(&mut raw)
+            .map_err(|e| format!("Frame {}: {}", i, e))?;
+
+        frames.push(raw);
+    }
+
+    Ok((header, frames))
+}
+
+/// Compress `frames` into a logo.bin. `header` is the original MTK
+/// header to keep (only its size field is updated); a fresh one is
+/// written otherwise.
+pub fn build_logo(header: Option<&[u8]>, frames: &[Vec<u8>]) -> Result<Vec<u8>, String> {
+    if frames.is_empty() {
+        return Err("Logo needs at least one frame".into());
+    }
+
+    let table_end = 8 + frames.len() * 4;
+    let mut offsets = Vec::with_capacity(frames.len());
+    let mut data = Vec::new();
+
+    for frame in frames {
+        offsets.push((table_end + data.len()) as u32);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(frame).map_err(|e| e.to_string())?;
+        data.extend(encoder.finish().map_err(|e| e.to_string())?);
+    }
+
+    let body_len = u32::try_from(table_end + data.len()).map_err(|_| "Logo too large")?;
+
+    let mut body = Vec::with_capacity(body_len as usize);
+    body.extend((frames.len() as u32).to_le_bytes());
+    body.extend(body_len.to_le_bytes());
+    for offset in offsets {
+        body.extend(offset.to_le_bytes());
+    }
+    body.extend(data);
+
+    let mut out = match header {
+        Some(h) if h.len() == HEADER_SIZE as usize && partition::has_mtk_header(h) => h.to_vec(),
+        Some(_) => return Err("Invalid MTK header".into()),
+        None => partition::build_header("logo", 0).to_vec(),
+    };
+
+    out[0x04..0x08].copy_from_slice(&body_len.to_le_bytes());
+    out.extend(body);
+
+    Ok(out)
+}
+
+/* ================= UNPACK / REPACK ================= */
+
+/// Unpack `logo` into `out_dir` as `frame_NNN.raw` files plus a manifest.
+/// Frames the size of the panel get its dimensions so they can be
+/// converted to PNG.
+pub fn unpack_logo(
+    logo: &Path,
+    out_dir: &Path,
+    display: Option<&DisplayInfo>,
+) -> Result<LogoManifest, String> {
+    let data = fs::read(logo).map_err(|e| format!("{}: {}", logo.display(), e))?;
+    let (header, raw_frames) = parse_logo(&data)?;
+
+    fs::create_dir_all(out_dir).map_err(|e| e.to_string())?;
+    fs::write(out_dir.join(HEADER_FILE), &header).map_err(|e| e.to_string())?;
+
+    let pixel_format = display.map(|d| d.pixel_format).unwrap_or_default();
+    let panel_bytes = display
+        .map(|d| d.width as usize * d.height as usize * pixel_format.bytes_per_pixel());
+
+    let mut frames = Vec::new();
+
+    for (index, raw) in raw_frames.iter().enumerate() {
+        let file = format!("frame_{:03}.raw", index);
+        fs::write(out_dir.join(&file), raw).map_err(|e| e.to_string())?;
+
+        let full_screen = panel_bytes == Some(raw.len());
+
+        frames.push(LogoFrame {
+            index,
+            file,
+            size: raw.len(),
+            width: display.filter(|_| full_screen).map(|d| d.width),
+            height: display.filter(|_| full_screen).map(|d| d.height),
+        });
+    }
+
+    let manifest = LogoManifest { pixel_format, frames };
+    let json = serde_json::to_string_pretty(&manifest).map_err(|e| e.to_string())?;
+    fs::write(out_dir.join(MANIFEST), json).map_err(|e| e.to_string())?;
+
+    Ok(manifest)
+}
+
+/// Rebuild a logo.bin from a directory written by `unpack_logo`.
+/// Frames keep their size, so edited images must match the original.
+pub fn repack_logo(dir: &Path, out: &Path) -> Result<LogoManifest, String> {
+    let json = fs::read_to_string(dir.join(MANIFEST))
+        .map_err(|e| format!("{}: {}", dir.join(MANIFEST).display(), e))?;
+    let manifest: LogoManifest = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+
+    let header = fs::read(dir.join(HEADER_FILE)).ok();
+    let mut frames = Vec::with_capacity(manifest.frames.len());
+
+    for frame in &manifest.frames {
+        let raw = fs::read(dir.join(&frame.file)).map_err(|e| format!("{}: {}", frame.file, e))?;
+
+        if raw.len() != frame.size {
+            return Err(format!(
+                "{} is {} bytes, expected {}; frame dimensions must not change",
+                frame.file,
+                raw.len(),
+                frame.size
+            ));
+        }
+
+        frames.push(raw);
+    }
+
+    let data = build_logo(header.as_deref(), &frames)?;
+    fs::write(out, data).map_err(|e| format!("{}: {}", out.display(), e))?;
+
+    Ok(manifest)
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+    use std::path::PathBuf;
+
+    const PANEL: DisplayInfo =
+        DisplayInfo { width: 4, height: 4, pixel_format: PixelFormat::Bgra8888 };
+
+    fn frames() -> Vec<Vec<u8>> {
+        vec![(0..64).map(|i| i as u8).collect(), vec![7u8; 30], vec![0u8; 64]]
+    }
+
+    #[test]
+    fn builds_and_parses_frames() {
+        let logo = build_logo(None, &frames()).unwrap();
+        let (header, parsed) = parse_logo(&logo).unwrap();
+
+        assert_eq!(parsed, frames());
+        assert_eq!(le32(&header, 4) as usize, logo.len() - HEADER_SIZE as usize);
+
+        let images = partition::parse_images(&mut std::io::Cursor::new(&logo)).unwrap();
+        assert_eq!(images[0].name, "logo");
+    }
+
+    #[test]
+    fn rejects_bad_frame_tables() {
+        let mut logo = build_logo(None, &frames()).unwrap();
+        logo[HEADER_SIZE as usize..HEADER_SIZE as usize + 4]
+            .copy_from_slice(&5000u32.to_le_bytes());
+        assert!(parse_logo(&logo).is_err());
+
+        assert!(parse_logo(&[0u8; 1024]).is_err());
+        assert!(build_logo(None, &[]).is_err());
+    }
+
+    #[test]
+    fn unpack_repack_is_byte_identical() {
+        let dir = temp_dir("logo", "roundtrip");
+        let logo = build_logo(None, &frames()).unwrap();
+        fs::write(dir.join("logo.bin"), &logo).unwrap();
+
+        let manifest = unpack_logo(&dir.join("logo.bin"), &dir.join("unpacked"), Some(&PANEL));
+        let repacked = repack_logo(&dir.join("unpacked"), &dir.join("out.bin"))
+            .and_then(|_| fs::read(dir.join("out.bin")).map_err(|e| e.to_string()));
+        fs::remove_dir_all(&dir).ok();
+
+        let manifest = manifest.unwrap();
+        assert_eq!(manifest.frames.len(), 3);
+        assert_eq!((manifest.frames[0].width, manifest.frames[0].height), (Some(4), Some(4)));
+        assert_eq!(manifest.frames[1].width, None);
+        assert_eq!(repacked.unwrap(), logo);
+    }
+
+    #[test]
+    fn repack_replaces_frames_of_the_same_size() {
+        let dir = temp_dir("logo", "replace");
+        fs::write(dir.join("logo.bin"), build_logo(None, &frames()).unwrap()).unwrap();
+        unpack_logo(&dir.join("logo.bin"), &dir.join("unpacked"), Some(&PANEL)).unwrap();
+
+        let replacement = vec![0xABu8; 64];
+        fs::write(dir.join("unpacked/frame_000.raw"), &replacement).unwrap();
+        repack_logo(&dir.join("unpacked"), &dir.join("out.bin")).unwrap();
+        let (_, parsed) = parse_logo(&fs::read(dir.join("out.bin")).unwrap()).unwrap();
+
+        fs::write(dir.join("unpacked/frame_001.raw"), [1u8; 31]).unwrap();
+        let resized = repack_logo(&dir.join("unpacked"), &dir.join("bad.bin"));
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(parsed[0], replacement);
+        assert_eq!(parsed[1..], frames()[1..]);
+        assert!(resized.unwrap_err().contains("frame dimensions must not change"));
+    }
+}
@@ -0,0 +1,217 @@
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+// Many MTK partition images (lk, logo, md1img, tee, scp...) are one or
+// more sub-images, each behind a 512-byte little-endian header:
+//
+//   0x00  magic 0x58881688      0x30  ext_magic 0x58891689
+//   0x04  data size             0x34  header size
+//   0x08  name[32]              0x3C  image type
+//   0x28  load address          0x40  list end (1 = last image)
+//   0x2C  mode                  0x44  alignment
+//
+// Data follows the header and the next header starts at the aligned end.
+
+pub const MTK_HEADER_MAGIC: u32 = 0x5888_1688;
+const MTK_EXT_MAGIC: u32 = 0x5889_1689;
+pub const HEADER_SIZE: u64 = 512;
+const DEFAULT_ALIGN: u64 = 16;
+const MAX_IMAGES: usize = 64;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MtkImage {
+    pub name: String,
+    /// Offset of the header within the file
+    pub offset: u64,
+    pub data_offset: u64,
+    pub size: u64,
+    pub load_addr: u32,
+    pub mode: u32,
+    /// Only present in headers with the extension block
+    pub image_type: Option<u32>,
+}
+
+/// True if `data` starts with an MTK partition header.
+pub fn has_mtk_header(data: &[u8]) -> bool {
+    data.len() >= 4 && le32(data, 0) == MTK_HEADER_MAGIC
+}
+
+/// Walk the header chain of an image. Fails if the first header is missing.
+pub fn parse_images<R: Read + Seek>(reader: &mut R) -> Result<Vec<MtkImage>, String> {
+    let len = reader.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+    let mut images = Vec::new();
+    let mut offset = 0u64;
+
+    while offset + HEADER_SIZE <= len && images.len() < MAX_IMAGES {
+        let Some(header) = read_header(reader, offset)? else {
+            break;
+        };
+
+        let ext = le32(&header, 0x30) == MTK_EXT_MAGIC;
+        let (header_size, align) = if ext {
+            (
+                u64::from(le32(&header, 0x34)).max(HEADER_SIZE),
+                u64::from(le32(&header, 0x44)).max(1),
+            )
+        } else {
+            (HEADER_SIZE, DEFAULT_ALIGN)
+        };
+
+        let image = MtkImage {
+            name: name_field(&header[0x08..0x28]),
+            offset,
+            data_offset: offset + header_size,
+            size: u64::from(le32(&header, 0x04)),
+            load_addr: le32(&header, 0x28),
+            mode: le32(&header, 0x2C),
+            image_type: ext.then(|| le32(&header, 0x3C)),
+        };
+
+        if image.data_offset + image.size > len {
+            return Err(format!(
+                "Sub-image {} at {:#x} runs past end of file",
+                image.name, offset
+            ));
+        }
+
+        let last = ext && le32(&header, 0x40) == 1;
+        let end = image.data_offset + image.size;
+        images.push(image);
+
+        if last {
+            break;
+        }
+
+        // Alignment isn't recorded in old headers; fall back to 512
+        let aligned = end.next_multiple_of(align);
+        offset = if read_header(reader, aligned)?.is_some() {
+            aligned
+        } else {
+            end.next_multiple_of(HEADER_SIZE)
+        };
+    }
+
+    if images.is_empty() {
+        return Err("No MTK partition header (magic 0x58881688)".into());
+    }
+
+    Ok(images)
+}
+
+/// Minimal single-image header, as mkimage writes it.
+pub fn build_header(name: &str, size: u32) -> [u8; 512] {
+    let mut header = [0xFFu8; HEADER_SIZE as usize];
+
+    header[0x00..0x04].copy_from_slice(&MTK_HEADER_MAGIC.to_le_bytes());
+    header[0x04..0x08].copy_from_slice(&size.to_le_bytes());
+
+    let name_field = &mut header[0x08..0x28];
+    name_field.fill(0);
+    let len = name.len().min(name_field.len() - 1);
+    name_field[..len].copy_from_slice(&name.as_bytes()[..len]);
+
+    header
+}
+
+pub fn read_images(path: &Path) -> Result<Vec<MtkImage>, String> {
+    let mut file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_images(&mut file)
+}
+
+/// Header at `offset`, or `None` if there is no magic there.
+fn read_header<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<Option<[u8; 512]>, String> {
+    let mut header = [0u8; HEADER_SIZE as usize];
+
+    reader.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+
+    match reader.read_exact(&mut header) {
+        Ok(()) if has_mtk_header(&header) => Ok(Some(header)),
+        Ok(()) => Ok(None),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn name_field(raw: &[u8]) -> String {
+    let end = raw.iter().position(|&b| b == 0).unwrap_or(raw.len());
+    String::from_utf8_lossy(&raw[..end]).trim().to_string()
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn ext_header(name: &str, size: u32, last: bool, align: u32) -> Vec<u8> {
+        let mut header = build_header(name, size).to_vec();
+        header[0x30..0x34].copy_from_slice(&MTK_EXT_MAGIC.to_le_bytes());
+        header[0x34..0x38].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        header[0x3C..0x40].copy_from_slice(&7u32.to_le_bytes());
+        header[0x40..0x44].copy_from_slice(&u32::from(last).to_le_bytes());
+        header[0x44..0x48].copy_from_slice(&align.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn walks_aligned_header_chain() {
+        let mut data = ext_header("lk", 100, false, 16);
+        data.extend([1u8; 100]);
+        data.extend([0u8; 12]);
+        data.extend(ext_header("lk_main_dtb", 20, true, 16));
+        data.extend([2u8; 20]);
+
+        let images = parse_images(&mut Cursor::new(&data)).unwrap();
+        let names: Vec<&str> = images.iter().map(|i| i.name.as_str()).collect();
+
+        assert_eq!(names, ["lk", "lk_main_dtb"]);
+        assert_eq!(images[0].data_offset, 512);
+        assert_eq!(images[1].offset, 624);
+        assert_eq!(images[1].size, 20);
+        assert_eq!(images[1].image_type, Some(7));
+    }
+
+    #[test]
+    fn legacy_headers_fall_back_to_sector_alignment() {
+        let mut data = build_header("logo", 600).to_vec();
+        data.extend([3u8; 600]);
+        data.resize(1536, 0);
+        data.extend(build_header("extra", 8));
+        data.extend([4u8; 8]);
+
+        let images = parse_images(&mut Cursor::new(&data)).unwrap();
+
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].image_type, None);
+        assert_eq!(images[1].offset, 1536);
+    }
+
+    #[test]
+    fn built_header_parses_back() {
+        let mut data = build_header("md1rom", 32).to_vec();
+        data.extend([5u8; 32]);
+
+        assert!(has_mtk_header(&data));
+        let images = parse_images(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].name, "md1rom");
+        assert_eq!(images[0].size, 32);
+    }
+
+    #[test]
+    fn rejects_truncated_or_missing_headers() {
+        let mut data = build_header("lk", 4096).to_vec();
+        data.extend([0u8; 16]);
+        assert!(parse_images(&mut Cursor::new(&data)).is_err());
+
+        assert!(!has_mtk_header(b"ANDROID!"));
+        assert!(parse_images(&mut Cursor::new(vec![0u8; 1024])).is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::mtk::chips::{self, ChipInfo};
+
+// Preloader images as shipped in scatter packages:
+//
+//   0x000  EMMC_BOOT / UFS_BOOT / COMBO_BOOT storage header (optional)
+//   ...    BRLYT boot-region layout (eMMC only)
+//   GFH    chain of "MMM" blocks, FILE_INFO first:
+//            magic "MMM" | version u8 | size u16 | type u16 | payload
+//   code   preloader body, signature appended when sig_len > 0
+//
+// All GFH fields are little-endian.
+
+const GFH_MAGIC: &[u8] = b"MMM";
+const FILE_INFO_ID: &[u8] = b"FILE_INFO";
+const FILE_INFO_SIZE: usize = 0x38;
+pub const GFH_SEARCH_LIMIT: usize = 0x10000;
+const MAX_PRELOADER_SIZE: u64 = 4 * 1024 * 1024;
+
+const BOOT_HEADERS: &[&str] = &["EMMC_BOOT", "UFS_BOOT", "COMBO_BOOT"];
+
+/// FILE_INFO file types of boot-ROM loaded code (ARM_BL, ARM_EXT_BL)
+const PRELOADER_FILE_TYPES: &[u16] = &[0x0000, 0x0001];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileInfo {
+    pub file_version: u32,
+    pub file_type: u16,
+    pub flash_dev: String,
+    pub sig_type: String,
+    pub load_addr: u32,
+    pub file_len: u32,
+    pub max_size: u32,
+    pub content_offset: u32,
+    pub sig_len: u32,
+    pub jump_offset: u32,
+    pub attr: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GfhBlock {
+    pub offset: usize,
+    pub gfh_type: String,
+    pub version: u8,
+    pub size: u16,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreloaderInfo {
+    /// Storage header at offset 0, if the image carries one
+    pub boot_header: Option<String>,
+    pub gfh_offset: usize,
+    pub file_info: FileInfo,
+    pub gfh_blocks: Vec<GfhBlock>,
+    /// SoC named in the image body (e.g. "MT6765")
+    pub platform: Option<String>,
+    /// `MTK_BLOADER_INFO_vNN` tag version
+    pub bloader_version: Option<String>,
+    pub signed: bool,
+    pub has_cert_chain: bool,
+    pub has_sec_key: bool,
+    pub chip: Option<&'static ChipInfo>,
+}
+
+impl PreloaderInfo {
+    /// `None` if either side's SoC is unknown.
+    pub fn matches_platform(&self, platform: &str) -> Option<bool> {
+        let image = self.chip.map(|c| c.soc).or(self.platform.as_deref())?;
+        Some(chips::same_chip(image, platform))
+    }
+}
+
+/* ================= PARSER ================= */
+
+pub fn parse_preloader(data: &[u8]) -> Result<PreloaderInfo, String> {
+    let boot_header = BOOT_HEADERS
+        .iter()
+        .find(|h| data.starts_with(h.as_bytes()))
+        .map(|h| h.to_string());
+
+    let gfh_offset = find_file_info(data).ok_or("No GFH FILE_INFO header; not an MTK preloader")?;
+    let file_info = parse_file_info(&data[gfh_offset..]);
+
+    let gfh_blocks = walk_gfh(data, gfh_offset);
+    let has_sec_key = gfh_blocks.iter().any(|b| b.gfh_type == "BL_SEC_KEY");
+
+    let signed = file_info.sig_len > 0 && file_info.sig_type != "NONE";
+    let has_cert_chain = file_info.sig_type == "CERT_CHAIN";
+
+    let platform = find_platform(data);
+    let chip = platform.as_deref().and_then(chips::lookup_platform);
+
+    Ok(PreloaderInfo {
+        boot_header,
+        gfh_offset,
+        file_info,
+        gfh_blocks,
+        platform,
+        bloader_version: find_bloader_version(data),
+        signed,
+        has_cert_chain,
+        has_sec_key,
+        chip,
+    })
+}
+
+/// Whether `data` is a preloader rather than some other GFH-signed image.
+/// Signed lk / tee images also carry a GFH, behind their MTK partition
+/// header, so a FILE_INFO block alone is not enough: it must sit at the
+/// start of the image or behind a storage header, or declare a
+/// preloader file type.
+pub fn is_preloader(data: &[u8]) -> bool {
+    let Some(gfh_offset) = find_file_info(data) else {
+        return false;
+    };
+
+    gfh_offset == 0
+        || BOOT_HEADERS.iter().any(|h| data.starts_with(h.as_bytes()))
+        || PRELOADER_FILE_TYPES.contains(&parse_file_info(&data[gfh_offset..]).file_type)
+}
+
+pub fn parse_preloader_file(path: &Path) -> Result<PreloaderInfo, String> {
+    let len = fs::metadata(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?
+        .len();
+
+    if len > MAX_PRELOADER_SIZE {
+        return Err(format!("{} is {} bytes; too large for a preloader", path.display(), len));
+    }
+
+    let data = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_preloader(&data)
+}
+
+fn find_file_info(data: &[u8]) -> Option<usize> {
+    let limit = data.len().min(GFH_SEARCH_LIMIT);
+
+    (0..limit).find(|&i| {
+        data.len() >= i + FILE_INFO_SIZE
+            && data[i..].starts_with(GFH_MAGIC)
+            && le16(data, i + 4) as usize == FILE_INFO_SIZE
+            && le16(data, i + 6) == 0
+            && data[i + 8..].starts_with(FILE_INFO_ID)
+    })
+}
+
+/// `data` starts at the FILE_INFO block; length already checked.
+fn parse_file_info(data: &[u8]) -> FileInfo {
+    FileInfo {
+        file_version: le32(data, 0x14),
+        file_type: le16(data, 0x18),
+        flash_dev: flash_dev_name(data[0x1A]),
+        sig_type: sig_type_name(data[0x1B]).to_string(),
+        load_addr: le32(data, 0x1C),
+        file_len: le32(data, 0x20),
+        max_size: le32(data, 0x24),
+        content_offset: le32(data, 0x28),
+        sig_len: le32(data, 0x2C),
+        jump_offset: le32(data, 0x30),
+        attr: le32(data, 0x34),
+    }
+}
+
+/// Follow the GFH chain from FILE_INFO until the magic stops matching.
+fn walk_gfh(data: &[u8], start: usize) -> Vec<GfhBlock> {
+    let mut blocks = Vec::new();
+    let mut offset = start;
+
+    while data.len() >= offset + 8 && data[offset..].starts_with(GFH_MAGIC) {
+        let size = le16(data, offset + 4);
+        if size < 8 {
+            break;
+        }
+
+        blocks.push(GfhBlock {
+            offset,
+            gfh_type: gfh_type_name(le16(data, offset + 6)),
+            version: data[offset + 3],
+            size,
+        });
+
+        offset += size as usize;
+    }
+
+    blocks
+}
+
+/// Most frequent `MTxxxx` string that names a known chip, else the most
+/// frequent one overall.
+fn find_platform(data: &[u8]) -> Option<String> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+
+    for i in 0..data.len().saturating_sub(6) {
+        let word = &data[i..i + 6];
+        let bounded = |j: usize| data.get(j).is_none_or(|b| !b.is_ascii_alphanumeric());
+
+        if word.starts_with(b"MT")
+            && word[2..].iter().all(|b| b.is_ascii_digit())
+            && (i == 0 || bounded(i - 1))
+            && bounded(i + 6)
+        {
+            *counts.entry(String::from_utf8_lossy(word).into_owned()).or_default() += 1;
+        }
+    }
+
+    let best = |known: bool| {
+        counts
+            .iter()
+            .filter(|(p, _)| !known || chips::lookup_platform(p).is_some())
+            .max_by_key(|(_, n)| **n)
+            .map(|(p, _)| p.clone())
+    };
+
+    best(true).or_else(|| best(false))
+}
+
+fn find_bloader_version(data: &[u8]) -> Option<String> {
+    let tag = b"MTK_BLOADER_INFO_v";
+    let start = data.windows(tag.len()).position(|w| w == tag)? + tag.len();
+
+    let version: String = data[start..]
+        .iter()
+        .take_while(|b| b.is_ascii_digit())
+        .map(|&b| b as char)
+        .collect();
+
+    (!version.is_empty()).then_some(version)
+}
+
+fn flash_dev_name(value: u8) -> String {
+    match value {
+        1 => "NOR".into(),
+        2 => "NAND_SEQUENTIAL".into(),
+        3 => "NAND_TTBL".into(),
+        4 => "NAND_FDM50".into(),
+        5 => "EMMC_BOOT".into(),
+        6 => "EMMC_DATA".into(),
+        7 => "SERIAL_FLASH".into(),
+        0x0C => "UFS_BOOT".into(),
+        other => format!("0x{:02x}", other),
+    }
+}
+
+fn sig_type_name(value: u8) -> &'static str {
+    match value {
+        0 => "NONE",
+        1 => "PHASH",
+        2 => "SINGLE",
+        3 => "SINGLE_AND_PHASH",
+        4 => "MULTI",
+        5 => "CERT_CHAIN",
+        _ => "UNKNOWN",
+    }
+}
+
+fn gfh_type_name(value: u16) -> String {
+    match value {
+        0x0000 => "FILE_INFO".into(),
+        0x0001 => "BL_INFO".into(),
+        0x0002 => "ANTI_CLONE".into(),
+        0x0003 => "BL_SEC_KEY".into(),
+        0x0007 => "BROM_CFG".into(),
+        0x0008 => "BROM_SEC_CFG".into(),
+        other => format!("0x{:04x}", other),
+    }
+}
+
+fn le16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn le32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_info(data: &mut [u8], offset: usize, file_type: u16) {
+        data[offset..offset + 4].copy_from_slice(b"MMM\x01");
+        data[offset + 4..offset + 6].copy_from_slice(&(FILE_INFO_SIZE as u16).to_le_bytes());
+        data[offset + 8..offset + 17].copy_from_slice(FILE_INFO_ID);
+        data[offset + 0x18..offset + 0x1A].copy_from_slice(&file_type.to_le_bytes());
+    }
+
+    #[test]
+    fn parses_emmc_preloader() {
+        let mut data = vec![0u8; 0x2000];
+        data[..9].copy_from_slice(b"EMMC_BOOT");
+
+        file_info(&mut data, 0x800, 0);
+        data[0x800 + 0x1A] = 5;
+        data[0x800 + 0x1B] = 2;
+        data[0x800 + 0x2C..0x800 + 0x30].copy_from_slice(&0x100u32.to_le_bytes());
+
+        let key = 0x800 + FILE_INFO_SIZE;
+        data[key..key + 4].copy_from_slice(b"MMM\x01");
+        data[key + 4..key + 6].copy_from_slice(&0x10u16.to_le_bytes());
+        data[key + 6..key + 8].copy_from_slice(&3u16.to_le_bytes());
+
+        let body = b"\0MTK_BLOADER_INFO_v13\0MT6765\0xMT6765\0 MT6765 ";
+        data[0x1000..0x1000 + body.len()].copy_from_slice(body);
+
+        let info = parse_preloader(&data).unwrap();
+        assert_eq!(info.boot_header.as_deref(), Some("EMMC_BOOT"));
+        assert_eq!(info.gfh_offset, 0x800);
+        assert_eq!(info.file_info.flash_dev, "EMMC_BOOT");
+        assert_eq!(info.file_info.sig_type, "SINGLE");
+        assert_eq!(info.gfh_blocks.len(), 2);
+        assert!(info.signed && info.has_sec_key && !info.has_cert_chain);
+        assert_eq!(info.platform.as_deref(), Some("MT6765"));
+        assert_eq!(info.bloader_version.as_deref(), Some("13"));
+        assert_eq!(info.matches_platform("mt6765"), Some(true));
+        assert_eq!(info.matches_platform("mt6768"), Some(false));
+        assert!(is_preloader(&data));
+    }
+
+    #[test]
+    fn rejects_images_without_file_info() {
+        assert!(parse_preloader(&[0u8; 0x1000]).is_err());
+        assert!(!is_preloader(b"EMMC_BOOT"));
+    }
+
+    #[test]
+    fn bare_gfh_at_start_is_a_preloader() {
+        let mut data = vec![0u8; 0x400];
+        file_info(&mut data, 0, 0x0102);
+        assert!(is_preloader(&data));
+    }
+
+    #[test]
+    fn gfh_behind_other_headers_needs_preloader_type() {
+        // Signed lk / tee: 512-byte MTK partition header, then the GFH
+        let mut data = vec![0u8; 0x800];
+        data[..4].copy_from_slice(&0x5888_1688u32.to_le_bytes());
+
+        file_info(&mut data, 0x200, 0x0102);
+        assert!(parse_preloader(&data).is_ok());
+        assert!(!is_preloader(&data));
+
+        file_info(&mut data, 0x200, 0x0001);
+        assert!(is_preloader(&data));
+    }
+}
@@ -0,0 +1,576 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::firmware::package_path;
+use crate::snapshot::parse_size;
+
+// SP Flash Tool scatter files (`MTxxxx_Android_scatter.txt`) come in two
+// flavours:
+//
+//   YAML-style (config_version V1.1.x+):
+//     - partition_index: SYS0
+//       partition_name: preloader
+//       file_name: preloader_k65v1.bin
+//       ...
+//
+//   Legacy key/value blocks:
+//     PRELOADER 0x0
+//     {
+//     partition_index: SYS0
+//     ...
+//     }
+//
+// Very old files only list `NAME 0xADDRESS` lines. All three are parsed
+// line by line; real YAML parsers choke on the loose formatting vendors use.
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum ScatterFormat {
+    Yaml,
+    Legacy,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum StorageType {
+    Emmc,
+    Ufs,
+    Nand,
+    Nor,
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum OperationType {
+    Bootloaders,
+    Update,
+    Protected,
+    Invisible,
+    Binregion,
+    Reserved,
+    Other(String),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScatterPartition {
+    pub index: Option<String>,
+    pub name: String,
+    /// `None` for partitions without an image (`file_name: NONE`)
+    pub file_name: Option<String>,
+    pub is_download: bool,
+    pub partition_type: Option<String>,
+    pub linear_start: u64,
+    pub physical_start: u64,
+    pub size: Option<u64>,
+    pub region: Option<String>,
+    pub storage: Option<StorageType>,
+    pub boundary_check: bool,
+    pub is_reserved: bool,
+    pub operation_type: OperationType,
+}
+
+impl ScatterPartition {
+    /// Partitions only writable through BROM / preloader + DA, never fastboot.
+    pub fn is_boot_region(&self) -> bool {
+        self.operation_type == OperationType::Bootloaders
+            || self.region.as_deref().is_some_and(|r| {
+                r.starts_with("EMMC_BOOT") || matches!(r, "UFS_LU0" | "UFS_LU1" | "UFS_LU0_LU1")
+            })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScatterFile {
+    pub format: ScatterFormat,
+    pub config_version: Option<String>,
+    pub platform: Option<String>,
+    pub project: Option<String>,
+    pub storage: Option<StorageType>,
+    pub block_size: Option<u64>,
+    pub partitions: Vec<ScatterPartition>,
+}
+
+impl ScatterFile {
+    #[cfg(test)]
+    pub fn partition(&self, name: &str) -> Option<&ScatterPartition> {
+        self.partitions.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn downloadable(&self) -> impl Iterator<Item = &ScatterPartition> {
+        self.partitions.iter().filter(|p| p.is_download && p.file_name.is_some())
+    }
+}
+
+/* ================= PARSER ================= */
+
+pub fn parse_scatter(text: &str) -> Result<ScatterFile, String> {
+    let mut format = ScatterFormat::Legacy;
+    let mut general: BTreeMap<String, String> = BTreeMap::new();
+    let mut blocks: Vec<BTreeMap<String, String>> = Vec::new();
+
+    let mut current: Option<BTreeMap<String, String>> = None;
+    let mut in_general = false;
+
+    for raw in text.lines() {
+        let line = raw.split('#').next().unwrap_or("").trim();
+        if line.is_empty() || line == "{" {
+            continue;
+        }
+
+        if line == "}" {
+            blocks.extend(current.take());
+            continue;
+        }
+
+        let indent = raw.len() - raw.trim_start().len();
+
+        // A top-level YAML list item starts a new block; nested ones
+        // (the `info:` list under `general:`) belong to the current one.
+        let (line, new_item) = match line.strip_prefix("- ") {
+            Some(rest) => (rest.trim(), indent == 0),
+            None => (line, false),
+        };
+
+        if new_item {
+            format = ScatterFormat::Yaml;
+            blocks.extend(current.take());
+            in_general = line.starts_with("general:");
+            if !in_general {
+                current = Some(BTreeMap::new());
+            }
+        }
+
+        match line.split_once(':') {
+            Some((key, value)) => {
+                let key = key.trim().to_string();
+                let value = value.trim().to_string();
+
+                if in_general {
+                    general.insert(key, value);
+                } else if let Some(block) = current.as_mut() {
+                    block.insert(key, value);
+                }
+            }
+
+            // Legacy `NAME 0xADDRESS` header (block may or may not follow)
+            None => {
+                let mut parts = line.split_whitespace();
+                if let (Some(name), Some(addr), None) = (parts.next(), parts.next(), parts.next()) {
+                    if parse_size(addr).is_some() {
+                        blocks.extend(current.take());
+                        let mut block = BTreeMap::new();
+                        block.insert("partition_name".into(), name.to_string());
+                        block.insert("linear_start_addr".into(), addr.to_string());
+                        current = Some(block);
+                    }
+                }
+            }
+        }
+    }
+
+    blocks.extend(current.take());
+
+    let mut partitions: Vec<ScatterPartition> = blocks
+        .iter()
+        .filter_map(|b| partition_from_block(b, format))
+        .collect();
+
+    if partitions.is_empty() {
+        return Err("No partitions found in scatter file".into());
+    }
+
+    fill_missing_sizes(&mut partitions);
+
+    Ok(ScatterFile {
+        format,
+        config_version: general.get("config_version").cloned(),
+        platform: general.get("platform").cloned(),
+        project: general.get("project").cloned(),
+        storage: general.get("storage").map(|s| storage_type(s)),
+        block_size: general.get("block_size").and_then(|s| parse_size(s)),
+        partitions,
+    })
+}
+
+pub fn parse_scatter_file(path: &Path) -> Result<ScatterFile, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut scatter = parse_scatter(&text)?;
+
+    // Legacy files carry no header; the platform is in the file name
+    if scatter.platform.is_none() {
+        scatter.platform = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.split('_').next())
+            .filter(|p| p.to_uppercase().starts_with("MT"))
+            .map(|p| p.to_uppercase());
+    }
+
+    Ok(scatter)
+}
+
+fn partition_from_block(
+    block: &BTreeMap<String, String>,
+    format: ScatterFormat,
+) -> Option<ScatterPartition> {
+    let name = block.get("partition_name")?.clone();
+    let get = |k: &str| block.get(k).map(|v| v.as_str());
+    let flag = |k: &str| get(k).is_some_and(|v| v.eq_ignore_ascii_case("true"));
+
+    let linear_start = get("linear_start_addr").and_then(parse_size).unwrap_or(0);
+
+    let file_name = get("file_name")
+        .filter(|f| !f.is_empty() && *f != "NONE")
+        .map(|f| f.to_string());
+
+    // Old key/value-only files have no is_download; anything with an
+    // image was meant to be downloaded.
+    let is_download = match get("is_download") {
+        Some(v) => v.eq_ignore_ascii_case("true"),
+        None => format == ScatterFormat::Legacy && file_name.is_some(),
+    };
+
+    Some(ScatterPartition {
+        index: get("partition_index").map(|v| v.to_string()),
+        name,
+        file_name,
+        is_download,
+        partition_type: get("type").map(|v| v.to_string()),
+        linear_start,
+        physical_start: get("physical_start_addr").and_then(parse_size).unwrap_or(linear_start),
+        size: get("partition_size").and_then(parse_size),
+        region: get("region").map(|v| v.to_string()),
+        storage: get("storage").map(storage_type),
+        boundary_check: flag("boundary_check"),
+        is_reserved: flag("is_reserved"),
+        operation_type: operation_type(get("operation_type").unwrap_or("")),
+    })
+}
+
+/// Address-only legacy entries: size runs up to the next partition.
+fn fill_missing_sizes(partitions: &mut [ScatterPartition]) {
+    for i in 0..partitions.len() {
+        if partitions[i].size.is_some() {
+            continue;
+        }
+
+        let start = partitions[i].linear_start;
+        let region = partitions[i].region.clone();
+
+        partitions[i].size = partitions[i + 1..]
+            .iter()
+            .find(|p| p.region == region && p.linear_start > start)
+            .map(|p| p.linear_start - start);
+    }
+}
+
+fn storage_type(value: &str) -> StorageType {
+    let upper = value.to_uppercase();
+
+    if upper.contains("EMMC") {
+        StorageType::Emmc
+    } else if upper.contains("UFS") {
+        StorageType::Ufs
+    } else if upper.contains("NAND") {
+        StorageType::Nand
+    } else if upper.contains("NOR") {
+        StorageType::Nor
+    } else {
+        StorageType::Other(value.to_string())
+    }
+}
+
+fn operation_type(value: &str) -> OperationType {
+    match value.to_uppercase().as_str() {
+        "BOOTLOADERS" => OperationType::Bootloaders,
+        "UPDATE" => OperationType::Update,
+        "PROTECTED" => OperationType::Protected,
+        "INVISIBLE" => OperationType::Invisible,
+        "BINREGION" => OperationType::Binregion,
+        "RESERVED" => OperationType::Reserved,
+        _ => OperationType::Other(value.to_string()),
+    }
+}
+
+/* ================= VALIDATION ================= */
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScatterReport {
+    pub scatter: ScatterFile,
+    pub issues: Vec<ScatterIssue>,
+}
+
+/// Parse a scatter file and validate it against the images next to it.
+pub fn inspect_scatter(path: &Path) -> Result<ScatterReport, String> {
+    let scatter = parse_scatter_file(path)?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let issues = validate_scatter(&scatter, dir);
+
+    Ok(ScatterReport { scatter, issues })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScatterIssue {
+    pub partition: String,
+    pub message: String,
+}
+
+/// Check that every downloadable partition's image exists in `dir`
+/// and fits the space the scatter file gives it.
+pub fn validate_scatter(scatter: &ScatterFile, dir: &Path) -> Vec<ScatterIssue> {
+    let mut issues = Vec::new();
+
+    for part in scatter.downloadable() {
+        let Some(file_name) = &part.file_name else {
+            continue;
+        };
+
+        let issue = |message: String| ScatterIssue {
+            partition: part.name.clone(),
+            message,
+        };
+
+        let path = match package_path(dir, file_name) {
+            Ok(path) => path,
+            Err(e) => {
+                issues.push(issue(e));
+                continue;
+            }
+        };
+
+        let len = match fs::metadata(&path) {
+            Ok(meta) if meta.is_file() => meta.len(),
+            _ => {
+                issues.push(issue(format!("Image not found: {}", file_name)));
+                continue;
+            }
+        };
+
+        match part.size {
+            Some(size) if size > 0 && len > size => issues.push(issue(format!(
+                "{} is {} bytes but partition is {} bytes",
+                file_name, len, size
+            ))),
+            None | Some(0) => issues.push(issue("Partition size unknown; fit not checked".into())),
+            _ => {}
+        }
+    }
+
+    for part in &scatter.partitions {
+        if let Some(size) = part.size {
+            if part.linear_start.checked_add(size).is_none() {
+                issues.push(ScatterIssue {
+                    partition: part.name.clone(),
+                    message: format!(
+                        "Range 0x{:x} + 0x{:x} overflows the address space",
+                        part.linear_start, size
+                    ),
+                });
+            }
+        }
+    }
+
+    for (i, a) in scatter.partitions.iter().enumerate() {
+        for b in &scatter.partitions[i + 1..] {
+            let end = |p: &ScatterPartition| p.size.and_then(|s| p.linear_start.checked_add(s));
+            let (Some(a_end), Some(b_end)) = (end(a), end(b)) else {
+                continue;
+            };
+
+            let overlaps = a.region == b.region
+                && a_end > a.linear_start
+                && b_end > b.linear_start
+                && a.linear_start < b_end
+                && b.linear_start < a_end;
+
+            if overlaps {
+                issues.push(ScatterIssue {
+                    partition: a.name.clone(),
+                    message: format!("Overlaps {}", b.name),
+                });
+            }
+        }
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::temp_dir;
+
+    const YAML: &str = "\
+############################################################################################################
+#
+#  General Setting
+#
+############################################################################################################
+- general: MTK_PLATFORM_CFG
+  info:
+    - config_version: V1.1.2
+      platform: MT6765
+      project: k65v1_64_bsp
+      storage: EMMC
+      boot_channel: MSDC_0
+      block_size: 0x20000
+############################################################################################################
+- partition_index: SYS0
+  partition_name: preloader
+  file_name: preloader_k65v1_64_bsp.bin
+  is_download: true
+  type: SV5_BL_BIN
+  linear_start_addr: 0x0
+  physical_start_addr: 0x0
+  partition_size: 0x40000
+  region: EMMC_BOOT1_BOOT2
+  storage: HW_STORAGE_EMMC
+  boundary_check: true
+  is_reserved: false
+  operation_type: BOOTLOADERS
+  reserve: 0x00
+
+- partition_index: SYS1
+  partition_name: pgpt
+  file_name: NONE
+  is_download: false
+  type: NORMAL_ROM
+  linear_start_addr: 0x0
+  physical_start_addr: 0x0
+  partition_size: 0x8000
+  region: EMMC_USER
+  storage: HW_STORAGE_EMMC
+  operation_type: INVISIBLE
+
+- partition_index: SYS2
+  partition_name: boot_a
+  file_name: boot.img
+  is_download: true
+  type: NORMAL_ROM
+  linear_start_addr: 0x8000
+  physical_start_addr: 0x8000
+  partition_size: 0x2000000
+  region: EMMC_USER
+  storage: HW_STORAGE_EMMC
+  operation_type: UPDATE
+";
+
+    #[test]
+    fn parses_yaml_scatter() {
+        let scatter = parse_scatter(YAML).unwrap();
+
+        assert_eq!(scatter.format, ScatterFormat::Yaml);
+        assert_eq!(scatter.config_version.as_deref(), Some("V1.1.2"));
+        assert_eq!(scatter.platform.as_deref(), Some("MT6765"));
+        assert_eq!(scatter.project.as_deref(), Some("k65v1_64_bsp"));
+        assert_eq!(scatter.storage, Some(StorageType::Emmc));
+        assert_eq!(scatter.block_size, Some(0x20000));
+        assert_eq!(scatter.partitions.len(), 3);
+
+        let preloader = scatter.partition("PRELOADER").unwrap();
+        assert!(preloader.is_boot_region());
+        assert!(preloader.boundary_check);
+        assert_eq!(preloader.partition_type.as_deref(), Some("SV5_BL_BIN"));
+
+        let pgpt = scatter.partition("pgpt").unwrap();
+        assert_eq!(pgpt.file_name, None);
+        assert_eq!(pgpt.operation_type, OperationType::Invisible);
+
+        let names: Vec<&str> = scatter.downloadable().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["preloader", "boot_a"]);
+    }
+
+    #[test]
+    fn parses_legacy_blocks() {
+        let text = "\
+PRELOADER 0x0
+{
+partition_index: SYS0
+partition_name: PRELOADER
+file_name: preloader.bin
+linear_start_addr: 0x0
+partition_size: 0x40000
+region: EMMC_BOOT_1
+}
+MBR 0x0
+{
+partition_index: SYS1
+partition_name: MBR
+file_name: MBR
+linear_start_addr: 0x0
+partition_size: 0x80000
+region: EMMC_USER
+}
+";
+        let scatter = parse_scatter(text).unwrap();
+
+        assert_eq!(scatter.format, ScatterFormat::Legacy);
+        assert_eq!(scatter.partitions.len(), 2);
+        assert_eq!(scatter.partitions[1].name, "MBR");
+        // No is_download key: anything with an image is downloaded
+        assert!(scatter.partitions.iter().all(|p| p.is_download));
+        assert!(scatter.partitions[0].is_boot_region());
+    }
+
+    #[test]
+    fn sizes_address_only_entries_from_next_start() {
+        let scatter = parse_scatter("PRELOADER 0x0\nDSP_BL 0x40000\nUBOOT 0x80000\n").unwrap();
+
+        assert_eq!(scatter.partitions[0].size, Some(0x40000));
+        assert_eq!(scatter.partitions[1].size, Some(0x40000));
+        assert_eq!(scatter.partitions[2].size, None);
+        assert!(parse_scatter("# nothing here\n").is_err());
+    }
+
+    #[test]
+    fn validates_images_and_overlaps() {
+        let dir = temp_dir("scatter", "validate");
+        fs::write(dir.join("preloader_k65v1_64_bsp.bin"), vec![0u8; 0x40001]).unwrap();
+
+        let mut scatter = parse_scatter(YAML).unwrap();
+        scatter.partitions[2].linear_start = 0x4000;
+        let issues = validate_scatter(&scatter, &dir);
+
+        scatter.partitions[2].file_name = Some("../boot.img".into());
+        let escaping = validate_scatter(&scatter, &dir);
+        fs::remove_dir_all(&dir).ok();
+
+        let messages: Vec<String> =
+            issues.iter().map(|i| format!("{}: {}", i.partition, i.message)).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "preloader: preloader_k65v1_64_bsp.bin is 262145 bytes but partition is \
+                 262144 bytes",
+                "boot_a: Image not found: boot.img",
+                "pgpt: Overlaps boot_a",
+            ]
+        );
+        assert!(escaping.iter().any(|i| i.message.contains("outside the package")));
+    }
+
+    #[test]
+    fn overflowing_range_is_reported() {
+        let mut scatter = parse_scatter(YAML).unwrap();
+        scatter.partitions[2].linear_start = u64::MAX - 1;
+        let issues = validate_scatter(&scatter, Path::new("/nonexistent"));
+
+        let overflow: Vec<&str> = issues
+            .iter()
+            .filter(|i| i.message.contains("overflows"))
+            .map(|i| i.partition.as_str())
+            .collect();
+        assert_eq!(overflow, vec!["boot_a"]);
+        assert!(!issues.iter().any(|i| i.message.starts_with("Overlaps")));
+    }
+
+    #[test]
+    fn platform_falls_back_to_file_name() {
+        let dir = temp_dir("scatter", "legacy");
+        let path = dir.join("MT6580_Android_scatter.txt");
+        fs::write(&path, "PRELOADER 0x0\nUBOOT 0x40000\n").unwrap();
+
+        let scatter = parse_scatter_file(&path);
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(scatter.unwrap().platform.as_deref(), Some("MT6580"));
+    }
+}
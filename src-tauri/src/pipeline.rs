@@ -1,22 +1,152 @@
 use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::tools::tools_root_dir;
+
+/// Arguments may reference `${name}` variables, resolved by the planner
+/// from the device snapshot (`slot`, `other_slot`, `serial`, `product`),
+/// the pipeline defaults and caller overrides.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PipelineStep {
-    AdbCommand { args: Vec<String> },
-    FastbootCommand { args: Vec<String> },
+    AdbCommand {
+        args: Vec<String>,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+    FastbootCommand {
+        args: Vec<String>,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
     Message { text: String },
+
+    /// Blocks the plan unless the condition holds on the snapshot.
+    Require {
+        condition: StepCondition,
+        message: String,
+    },
+
+    /// Runs `steps` if the condition holds, `otherwise` if not.
+    When {
+        condition: StepCondition,
+        steps: Vec<PipelineStep>,
+        #[serde(default)]
+        otherwise: Vec<PipelineStep>,
+    },
+}
+
+/// Retries are only honoured for idempotent steps; see `planner`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first one
+    pub attempts: u32,
+    /// Delay before the first retry, doubled on each further attempt
+    #[serde(default)]
+    pub backoff_ms: u64,
+    /// Re-flashing the same image is idempotent but must be opted into.
+    #[serde(default)]
+    pub allow_flash: bool,
+}
+
+impl PipelineStep {
+    pub fn adb(args: &[&str]) -> Self {
+        PipelineStep::AdbCommand {
+            args: args.iter().map(|a| a.to_string()).collect(),
+            retry: None,
+            timeout_secs: None,
+        }
+    }
+
+    pub fn fastboot(args: &[&str]) -> Self {
+        PipelineStep::FastbootCommand {
+            args: args.iter().map(|a| a.to_string()).collect(),
+            retry: None,
+            timeout_secs: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum StepCondition {
+    Getvar { name: String, equals: String },
+    Prop { name: String, equals: String },
+    Variable { name: String, equals: String },
+    SlotAb,
+    /// Bootloader reports `has-slot:<partition>: yes`
+    HasSlot { partition: String },
+    Unlocked,
+    Not(Box<StepCondition>),
+    Any(Vec<StepCondition>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FlashPipeline {
     pub id: String,
     pub description: String,
     pub requires_adb: bool,
     pub requires_fastboot: bool,
     pub destructive: bool,
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
     pub steps: Vec<PipelineStep>,
 }
 
+pub fn find_builtin_pipeline(id: &str) -> Option<FlashPipeline> {
+    list_builtin_pipelines().into_iter().find(|p| p.id == id)
+}
+
+/// Built-in pipelines take precedence over saved ones with the same id.
+pub fn find_pipeline(id: &str) -> Option<FlashPipeline> {
+    find_builtin_pipeline(id).or_else(|| load_saved_pipelines().into_iter().find(|p| p.id == id))
+}
+
+/* ================= SAVED PIPELINES ================= */
+
+pub fn pipelines_dir() -> PathBuf {
+    tools_root_dir().join("pipelines")
+}
+
+/// Persist an imported pipeline so it can be planned and resumed later.
+pub fn save_pipeline(pipeline: &FlashPipeline) -> Result<PathBuf, String> {
+    if pipeline.id.is_empty() || pipeline.id.contains(['/', '\\', '.']) {
+        return Err(format!("Invalid pipeline id: {}", pipeline.id));
+    }
+
+    fs::create_dir_all(pipelines_dir()).map_err(|e| e.to_string())?;
+
+    let path = pipelines_dir().join(format!("{}.json", pipeline.id));
+    let json = serde_json::to_vec_pretty(pipeline).map_err(|e| e.to_string())?;
+
+    fs::write(&path, json).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+pub fn load_saved_pipelines() -> Vec<FlashPipeline> {
+    fs::read_dir(pipelines_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|e| e.path().extension().and_then(|e| e.to_str()) == Some("json"))
+                .filter_map(|e| fs::read(e.path()).ok())
+                .filter_map(|data| serde_json::from_slice(&data).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Lowercase, dash-separated id fragment safe for file names.
+pub fn pipeline_id_fragment(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
 pub fn list_builtin_pipelines() -> Vec<FlashPipeline> {
     vec![
         FlashPipeline {
@@ -25,13 +155,12 @@ pub fn list_builtin_pipelines() -> Vec<FlashPipeline> {
             requires_adb: true,
             requires_fastboot: true,
             destructive: false,
+            variables: BTreeMap::new(),
             steps: vec![
                 PipelineStep::Message {
                     text: "Rebooting to bootloader".into(),
                 },
-                PipelineStep::AdbCommand {
-                    args: vec!["reboot".into(), "bootloader".into()],
-                },
+                PipelineStep::adb(&["reboot", "bootloader"]),
                 PipelineStep::Message {
                     text: "Waiting for fastboot".into(),
                 },
@@ -43,10 +172,9 @@ pub fn list_builtin_pipelines() -> Vec<FlashPipeline> {
             requires_adb: false,
             requires_fastboot: true,
             destructive: false,
+            variables: BTreeMap::new(),
             steps: vec![
-                PipelineStep::FastbootCommand {
-                    args: vec!["getvar".into(), "all".into()],
-                },
+                PipelineStep::fastboot(&["getvar", "all"]),
             ],
         },
     ]
@@ -0,0 +1,934 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::android::{avb, sparse};
+use crate::detection_service::DeviceState;
+use crate::image_check;
+use crate::pipeline::{FlashPipeline, PipelineStep, StepCondition};
+use crate::risk::{
+    base_partition, classify_adb_args, classify_fastboot_args, classify_flash_risk,
+    fastboot_positional, FlashRisk, RiskLevel,
+};
+use crate::rollback::{self, RollbackCheck};
+use crate::snapshot::DeviceSnapshot;
+
+/* ================= PLAN ================= */
+
+/// A fully resolved, reviewable pipeline. Nothing in here touches the
+/// device; the executor only runs a plan whose hash the user approved.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionPlan {
+    pub pipeline_id: String,
+    pub description: String,
+    pub destructive: bool,
+    pub snapshot: DeviceSnapshot,
+    pub variables: BTreeMap<String, String>,
+    pub steps: Vec<PlannedStep>,
+    pub max_risk: RiskLevel,
+    pub total_bytes: u64,
+    pub warnings: Vec<String>,
+    /// Problems that prevent execution (missing images, unresolved variables...)
+    pub blockers: Vec<String>,
+    pub plan_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedStep {
+    pub index: usize,
+    /// Resolved step; never a `When`
+    pub step: PipelineStep,
+    pub will_run: bool,
+    /// Human-readable branch conditions this step is nested under
+    pub conditions: Vec<String>,
+    pub risk: FlashRisk,
+    pub target_partition: Option<String>,
+    pub image: Option<String>,
+    pub bytes: Option<u64>,
+    pub notes: Vec<String>,
+}
+
+impl ExecutionPlan {
+    pub fn is_executable(&self) -> bool {
+        self.blockers.is_empty()
+    }
+
+    pub fn runnable_steps(&self) -> impl Iterator<Item = &PlannedStep> {
+        self.steps.iter().filter(|s| s.will_run)
+    }
+}
+
+/* ================= PLANNER ================= */
+
+/// Variable that turns unverifiable image checks from blockers into warnings
+const ALLOW_UNVERIFIED: &str = "allow_unverified_images";
+
+struct Planner<'a> {
+    snapshot: &'a DeviceSnapshot,
+    variables: BTreeMap<String, String>,
+    steps: Vec<PlannedStep>,
+    warnings: Vec<String>,
+    blockers: Vec<String>,
+}
+
+/// Build an execution plan for `pipeline` against a captured snapshot.
+///
+/// Variable precedence: pipeline defaults < device facts < `overrides`.
+pub fn plan_pipeline(
+    pipeline: &FlashPipeline,
+    snapshot: &DeviceSnapshot,
+    overrides: &BTreeMap<String, String>,
+) -> ExecutionPlan {
+    let mut variables = pipeline.variables.clone();
+    variables.extend(device_variables(snapshot));
+    variables.extend(overrides.clone());
+
+    let mut planner = Planner {
+        snapshot,
+        variables,
+        steps: Vec::new(),
+        warnings: Vec::new(),
+        blockers: Vec::new(),
+    };
+
+    planner.check_entry_state(pipeline);
+    planner.walk(&pipeline.steps, true, &[]);
+
+    let runnable = planner.steps.iter().filter(|s| s.will_run);
+
+    let max_risk = runnable
+        .clone()
+        .map(|s| s.risk.level)
+        .max()
+        .unwrap_or(RiskLevel::None);
+
+    let total_bytes = runnable.filter_map(|s| s.bytes).sum();
+
+    if max_risk >= RiskLevel::High && !pipeline.destructive {
+        planner.warnings.push(format!(
+            "Pipeline is not marked destructive but contains {:?} risk steps",
+            max_risk
+        ));
+    }
+
+    let plan_hash = hash_plan(&pipeline.id, &planner.variables, &planner.steps, snapshot);
+
+    ExecutionPlan {
+        pipeline_id: pipeline.id.clone(),
+        description: pipeline.description.clone(),
+        destructive: pipeline.destructive,
+        snapshot: snapshot.clone(),
+        variables: planner.variables,
+        steps: planner.steps,
+        max_risk,
+        total_bytes,
+        warnings: planner.warnings,
+        blockers: planner.blockers,
+        plan_hash,
+    }
+}
+
+fn device_variables(snapshot: &DeviceSnapshot) -> BTreeMap<String, String> {
+    let mut vars = BTreeMap::new();
+
+    if let Some(slot) = snapshot.current_slot() {
+        vars.insert("slot".into(), slot.to_string());
+    }
+    if let Some(other) = snapshot.other_slot() {
+        vars.insert("other_slot".into(), other.to_string());
+    }
+    if let Some(serial) = &snapshot.serial {
+        vars.insert("serial".into(), serial.clone());
+    }
+
+    let product = snapshot
+        .getvar("product")
+        .or_else(|| snapshot.prop("ro.product.device"));
+    if let Some(product) = product {
+        vars.insert("product".into(), product.to_string());
+    }
+
+    vars
+}
+
+impl Planner<'_> {
+    fn check_entry_state(&mut self, pipeline: &FlashPipeline) {
+        let first = pipeline.steps.iter().find(|s| {
+            matches!(s, PipelineStep::AdbCommand { .. } | PipelineStep::FastbootCommand { .. })
+        });
+
+        let expected = match first {
+            Some(PipelineStep::AdbCommand { .. }) => DeviceState::AdbDevice,
+            Some(PipelineStep::FastbootCommand { .. }) => DeviceState::Fastboot,
+            _ => return,
+        };
+
+        if self.snapshot.state != expected {
+            self.warnings.push(format!(
+                "Pipeline starts in {:?} but device was captured in {:?}",
+                expected, self.snapshot.state
+            ));
+        }
+    }
+
+    fn walk(&mut self, steps: &[PipelineStep], active: bool, conditions: &[String]) {
+        for step in steps {
+            match step {
+                PipelineStep::When { condition, steps, otherwise } => {
+                    let holds = match self.evaluate(condition) {
+                        Some(v) => v,
+                        None => {
+                            self.warnings.push(format!(
+                                "Cannot evaluate {} from snapshot; assuming false",
+                                describe_condition(condition)
+                            ));
+                            false
+                        }
+                    };
+
+                    let mut taken = conditions.to_vec();
+                    taken.push(describe_condition(condition));
+                    self.walk(steps, active && holds, &taken);
+
+                    let mut not_taken = conditions.to_vec();
+                    not_taken.push(format!("not ({})", describe_condition(condition)));
+                    self.walk(otherwise, active && !holds, &not_taken);
+                }
+
+                PipelineStep::Require { condition, message } => {
+                    if active {
+                        match self.evaluate(condition) {
+                            Some(true) => {}
+                            Some(false) => self.blockers.push(format!(
+                                "Requirement not met: {} ({})",
+                                message,
+                                describe_condition(condition)
+                            )),
+                            None => self.blockers.push(format!(
+                                "Cannot verify requirement from snapshot: {} ({})",
+                                message,
+                                describe_condition(condition)
+                            )),
+                        }
+                    }
+
+                    self.plan_step(step, active, conditions);
+                }
+
+                _ => self.plan_step(step, active, conditions),
+            }
+        }
+    }
+
+    fn evaluate(&self, condition: &StepCondition) -> Option<bool> {
+        match condition {
+            StepCondition::Getvar { name, equals } => {
+                self.snapshot.getvar(name).map(|v| v == equals)
+            }
+            StepCondition::Prop { name, equals } => {
+                self.snapshot.prop(name).map(|v| v == equals)
+            }
+            StepCondition::Variable { name, equals } => {
+                Some(self.variables.get(name) == Some(equals))
+            }
+            StepCondition::SlotAb => Some(self.snapshot.is_ab()),
+            StepCondition::HasSlot { partition } => self.snapshot.has_slot(partition),
+            StepCondition::Unlocked => self.snapshot.is_unlocked(),
+            StepCondition::Not(inner) => self.evaluate(inner).map(|v| !v),
+            StepCondition::Any(options) => {
+                let results: Vec<Option<bool>> =
+                    options.iter().map(|c| self.evaluate(c)).collect();
+
+                if results.contains(&Some(true)) {
+                    Some(true)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(false)
+                }
+            }
+        }
+    }
+
+    fn plan_step(&mut self, step: &PipelineStep, will_run: bool, conditions: &[String]) {
+        let index = self.steps.len();
+
+        let resolved = match step {
+            PipelineStep::AdbCommand { args, retry, timeout_secs } => PipelineStep::AdbCommand {
+                args: self.resolve_all(index, args, will_run),
+                retry: retry.clone(),
+                timeout_secs: *timeout_secs,
+            },
+            PipelineStep::FastbootCommand { args, retry, timeout_secs } => {
+                PipelineStep::FastbootCommand {
+                    args: self.resolve_all(index, args, will_run),
+                    retry: retry.clone(),
+                    timeout_secs: *timeout_secs,
+                }
+            }
+            PipelineStep::Message { text } => PipelineStep::Message {
+                text: self.resolve(index, text, will_run),
+            },
+            PipelineStep::Require { .. } => step.clone(),
+            PipelineStep::When { .. } => unreachable!("branches are flattened by walk()"),
+        };
+
+        let mut planned = PlannedStep {
+            index,
+            step: resolved,
+            will_run,
+            conditions: conditions.to_vec(),
+            risk: FlashRisk { level: RiskLevel::None, label: "NONE: message" },
+            target_partition: None,
+            image: None,
+            bytes: None,
+            notes: Vec::new(),
+        };
+
+        match planned.step.clone() {
+            PipelineStep::FastbootCommand { args, .. } => {
+                planned.risk = classify_fastboot_args(&args);
+                self.plan_fastboot(&mut planned, &args);
+            }
+            PipelineStep::AdbCommand { args, .. } => {
+                planned.risk = classify_adb_args(&args);
+                self.plan_adb(&mut planned, &args);
+            }
+            _ => {}
+        }
+
+        self.check_retry(&planned);
+
+        self.steps.push(planned);
+    }
+
+    fn plan_fastboot(&mut self, planned: &mut PlannedStep, args: &[String]) {
+        let positional = fastboot_positional(args);
+
+        let (partition, image) = match positional.as_slice() {
+            ["flash", partition, image, ..] => (*partition, Some(*image)),
+            ["flash", partition] => (*partition, None),
+            ["erase", partition, ..] | ["format", partition, ..] => (*partition, None),
+            _ => return,
+        };
+
+        let target = self.resolve_slot(partition, args, &mut planned.notes);
+        planned.risk = classify_flash_risk(&target);
+
+        if let Some(image) = image {
+            planned.image = Some(image.to_string());
+            planned.bytes = self.image_size(planned, image, &target);
+            self.check_image(planned, image, &target);
+        } else if positional[0] == "flash" {
+            planned.notes.push("No image given; fastboot will use $ANDROID_PRODUCT_OUT".into());
+        }
+
+        if positional[0] == "flash" {
+            self.check_logical(planned, &target, args);
+        }
+
+        planned.target_partition = Some(target);
+    }
+
+    /// Logical partitions live inside `super` and can only be written by
+    /// fastbootd; the bootloader's fastboot rejects them. fastbootd names
+    /// them with their slot suffix (`is-logical:system_a`), and the
+    /// bootloader may not report `has-slot:` for them at all, so the
+    /// suffixed name is checked even when the target has none.
+    fn check_logical(&mut self, planned: &mut PlannedStep, target: &str, args: &[String]) {
+        let slot = match explicit_slot(args).as_deref() {
+            Some("other") => self.snapshot.other_slot().map(str::to_string),
+            Some("all") | None => self.snapshot.current_slot().map(str::to_string),
+            Some(slot) => Some(slot.to_string()),
+        };
+
+        let slotted = slot
+            .filter(|_| !target.ends_with("_a") && !target.ends_with("_b"))
+            .map(|slot| format!("{}_{}", target, slot));
+
+        let logical = slotted.iter().map(String::as_str).chain([target]).any(|name| {
+            self.snapshot.getvar(&format!("is-logical:{}", name)) == Some("yes")
+        });
+
+        if !logical {
+            return;
+        }
+
+        planned.notes.push(format!("{} is a logical partition in super", target));
+
+        if !planned.will_run || self.in_fastbootd() {
+            return;
+        }
+
+        self.warnings.push(format!(
+            "Step {}: {} is logical and needs fastbootd; add `fastboot reboot fastboot` first",
+            planned.index, target
+        ));
+    }
+
+    /// Whether the device will be in userspace fastboot by the next step:
+    /// decided by the last reboot planned so far, else by the snapshot.
+    fn in_fastbootd(&self) -> bool {
+        let last_reboot = self.steps.iter().rev().filter(|s| s.will_run).find_map(|s| {
+            let PipelineStep::FastbootCommand { args, .. } = &s.step else {
+                return None;
+            };
+
+            match fastboot_positional(args).as_slice() {
+                ["reboot", "fastboot"] => Some(true),
+                ["reboot-bootloader"] | ["reboot", "bootloader"] => Some(false),
+                _ => None,
+            }
+        });
+
+        last_reboot.unwrap_or_else(|| self.snapshot.getvar("is-userspace") == Some("yes"))
+    }
+
+    fn plan_adb(&mut self, planned: &mut PlannedStep, args: &[String]) {
+        let local = match args.iter().map(|a| a.as_str()).collect::<Vec<_>>().as_slice() {
+            ["push", local, ..] | ["sideload", local] => local.to_string(),
+            _ => return,
+        };
+
+        planned.image = Some(local.clone());
+        planned.bytes = self.image_size(planned, &local, "");
+    }
+
+    /// Retrying is only safe for idempotent steps: read-only queries and
+    /// reboots always, re-flashing an image only when the step opts in.
+    fn check_retry(&mut self, planned: &PlannedStep) {
+        let (retry, is_flash) = match &planned.step {
+            PipelineStep::AdbCommand { retry, .. } => (retry, false),
+            PipelineStep::FastbootCommand { args, retry, .. } => {
+                (retry, fastboot_positional(args).first() == Some(&"flash"))
+            }
+            _ => return,
+        };
+
+        let Some(policy) = retry else {
+            return;
+        };
+
+        if !planned.will_run {
+            return;
+        }
+
+        if policy.attempts == 0 {
+            self.blockers.push(format!("Step {}: retry.attempts must be at least 1", planned.index));
+            return;
+        }
+
+        let permitted = match planned.risk.level {
+            RiskLevel::None | RiskLevel::Low => true,
+            _ => is_flash && policy.allow_flash,
+        };
+
+        if !permitted {
+            let hint = if is_flash { "; set allow_flash to opt in" } else { "" };
+
+            self.blockers.push(format!(
+                "Step {}: retry not permitted for non-idempotent step ({}){}",
+                planned.index, planned.risk.label, hint
+            ));
+        }
+    }
+
+    /// Maps a bare partition name to the slot fastboot will actually write.
+    fn resolve_slot(&self, partition: &str, args: &[String], notes: &mut Vec<String>) -> String {
+        if partition.ends_with("_a") || partition.ends_with("_b") {
+            return partition.to_string();
+        }
+
+        let explicit = explicit_slot(args);
+
+        match self.snapshot.has_slot(partition) {
+            Some(false) => partition.to_string(),
+
+            Some(true) => match explicit.as_deref() {
+                Some("all") => {
+                    notes.push("Flashes both slots (--slot=all)".into());
+                    partition.to_string()
+                }
+                Some("other") => match self.snapshot.other_slot() {
+                    Some(slot) => format!("{}_{}", partition, slot),
+                    None => partition.to_string(),
+                },
+                Some(slot) => format!("{}_{}", partition, slot),
+                None => match self.snapshot.current_slot() {
+                    Some(slot) => format!("{}_{}", partition, slot),
+                    None => partition.to_string(),
+                },
+            },
+
+            None => {
+                if self.snapshot.is_ab() {
+                    notes.push(format!(
+                        "Bootloader did not report has-slot:{}; slot suffix not assumed",
+                        partition
+                    ));
+                }
+                partition.to_string()
+            }
+        }
+    }
+
+    fn image_size(&mut self, planned: &PlannedStep, image: &str, target: &str) -> Option<u64> {
+        let meta = match fs::metadata(Path::new(image)) {
+            Ok(meta) if meta.is_file() => meta,
+            _ => {
+                if planned.will_run {
+                    self.blockers.push(format!("Step {}: image not found: {}", planned.index, image));
+                }
+                return None;
+            }
+        };
+
+        let bytes = meta.len();
+
+        // Sparse images expand on the device; what has to fit is the raw size
+        let written = if sparse::is_sparse_file(Path::new(image)) {
+            sparse::read_sparse(Path::new(image)).map(|s| s.raw_size).unwrap_or(bytes)
+        } else {
+            bytes
+        };
+
+        if let Some(size) = self.snapshot.partition_size(target) {
+            if written > size && planned.will_run {
+                self.blockers.push(format!(
+                    "Step {}: {} ({} bytes) exceeds partition {} ({} bytes)",
+                    planned.index, image, written, target, size
+                ));
+            }
+        }
+
+        if let Some(max) = self.snapshot.max_download_size() {
+            if bytes > max && planned.will_run {
+                self.warnings.push(format!(
+                    "Step {}: {} exceeds max-download-size and will be sent sparse",
+                    planned.index, image
+                ));
+            }
+        }
+
+        Some(bytes)
+    }
+
+    fn check_image(&mut self, planned: &mut PlannedStep, image: &str, target: &str) {
+        if !planned.will_run || planned.bytes.is_none() {
+            return;
+        }
+
+        let check = image_check::check_image(Path::new(image), target, self.snapshot.platform());
+        let index = planned.index;
+
+        planned.notes.extend(check.notes);
+        self.warnings.extend(check.warnings.iter().map(|w| format!("Step {}: {}", index, w)));
+        self.blockers.extend(check.blockers.iter().map(|b| format!("Step {}: {}", index, b)));
+
+        // Only the user can vouch for what the planner could not check
+        let overridden = self.variables.get(ALLOW_UNVERIFIED).is_some_and(|v| v == "yes");
+
+        for unverified in check.unverified {
+            if overridden {
+                self.warnings.push(format!(
+                    "Step {}: {} (overridden by ${{{}}})",
+                    index, unverified, ALLOW_UNVERIFIED
+                ));
+            } else {
+                self.blockers.push(format!(
+                    "Step {}: {}; set ${{{}}}=yes to flash anyway",
+                    index, unverified, ALLOW_UNVERIFIED
+                ));
+            }
+        }
+
+        // A locked bootloader refuses images that fail AVB
+        for failure in check.verification {
+            let message = format!("Step {}: AVB verification fails: {}", index, failure);
+
+            match self.snapshot.is_unlocked() {
+                Some(false) => self.blockers.push(format!("{} (bootloader is locked)", message)),
+                _ => self.warnings.push(message),
+            }
+        }
+
+        self.check_rollback(planned, image, target);
+    }
+
+    /// Downgrades block unless the bootloader is known to be unlocked,
+    /// where they only brick the device once it is relocked.
+    fn check_rollback(&mut self, planned: &mut PlannedStep, image: &str, target: &str) {
+        let path = Path::new(image);
+        if !avb::has_avb(path) {
+            return;
+        }
+
+        let Ok(vbmeta) = avb::read_vbmeta(path) else {
+            return;
+        };
+
+        let index = planned.index;
+
+        let Some(location) = rollback::rollback_location(path, base_partition(target), &vbmeta)
+        else {
+            planned.notes.push("No rollback location known for this image".into());
+            return;
+        };
+
+        let stored = &self.snapshot.rollback_indexes;
+        let installed = &self.snapshot.installed_rollback_indexes;
+
+        match rollback::check_rollback(&vbmeta, location, stored, installed) {
+            RollbackCheck::Ok(note) => planned.notes.push(note),
+            RollbackCheck::Downgrade(message) => {
+                let message = format!("Step {}: {} {}", index, target, message);
+
+                match self.snapshot.is_unlocked() {
+                    Some(true) => self
+                        .warnings
+                        .push(format!("{}; relocking afterwards would brick the device", message)),
+                    _ => self.blockers.push(message),
+                }
+            }
+            RollbackCheck::Unknown(note) if vbmeta.header.rollback_index > 0 => {
+                self.warnings.push(format!("Step {}: {}", index, note))
+            }
+            RollbackCheck::Unknown(note) => planned.notes.push(note),
+        }
+    }
+
+    fn resolve_all(&mut self, index: usize, args: &[String], will_run: bool) -> Vec<String> {
+        args.iter().map(|a| self.resolve(index, a, will_run)).collect()
+    }
+
+    /// Substitute `${name}` references. Unknown names are left in place
+    /// and block the plan if the step would run.
+    fn resolve(&mut self, index: usize, input: &str, will_run: bool) -> String {
+        let mut out = String::new();
+        let mut rest = input;
+
+        while let Some(start) = rest.find("${") {
+            // An unclosed reference is kept verbatim with the tail
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+
+            out.push_str(&rest[..start]);
+
+            let name = &rest[start + 2..start + len];
+
+            match self.variables.get(name) {
+                Some(value) => out.push_str(value),
+                None => {
+                    if will_run {
+                        self.blockers.push(format!("Step {}: unresolved variable ${{{}}}", index, name));
+                    }
+                    out.push_str(&rest[start..=start + len]);
+                }
+            }
+
+            rest = &rest[start + len + 1..];
+        }
+
+        out.push_str(rest);
+        out
+    }
+}
+
+fn explicit_slot(args: &[String]) -> Option<String> {
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        if let Some(slot) = arg.strip_prefix("--slot=") {
+            return Some(slot.to_string());
+        }
+        if arg == "--slot" {
+            return iter.next().cloned();
+        }
+    }
+
+    None
+}
+
+fn describe_condition(condition: &StepCondition) -> String {
+    match condition {
+        StepCondition::Getvar { name, equals } => format!("getvar {} == {}", name, equals),
+        StepCondition::Prop { name, equals } => format!("prop {} == {}", name, equals),
+        StepCondition::Variable { name, equals } => format!("${{{}}} == {}", name, equals),
+        StepCondition::SlotAb => "device is A/B".into(),
+        StepCondition::HasSlot { partition } => format!("{} is slotted", partition),
+        StepCondition::Unlocked => "bootloader unlocked".into(),
+        StepCondition::Not(inner) => format!("not ({})", describe_condition(inner)),
+        StepCondition::Any(options) => options
+            .iter()
+            .map(describe_condition)
+            .collect::<Vec<_>>()
+            .join(" or "),
+    }
+}
+
+/// Stable identity of what would run, used to confirm user approval.
+fn hash_plan(
+    pipeline_id: &str,
+    variables: &BTreeMap<String, String>,
+    steps: &[PlannedStep],
+    snapshot: &DeviceSnapshot,
+) -> String {
+    let payload = serde_json::to_vec(&(pipeline_id, variables, steps, &snapshot.serial))
+        .unwrap_or_default();
+
+    format!("{:x}", Sha256::digest(&payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::RetryPolicy;
+
+    fn fastboot_snapshot(getvars: &[(&str, &str)]) -> DeviceSnapshot {
+        let mut snapshot = DeviceSnapshot::empty(DeviceState::Fastboot);
+        for (name, value) in getvars {
+            snapshot.getvars.insert(name.to_string(), value.to_string());
+        }
+        snapshot
+    }
+
+    fn pipeline(steps: Vec<PipelineStep>) -> FlashPipeline {
+        FlashPipeline {
+            id: "test".into(),
+            description: "test".into(),
+            requires_adb: false,
+            requires_fastboot: true,
+            destructive: true,
+            variables: BTreeMap::new(),
+            steps,
+        }
+    }
+
+    fn planner(snapshot: &DeviceSnapshot) -> Planner<'_> {
+        Planner {
+            snapshot,
+            variables: BTreeMap::from([("slot".to_string(), "a".to_string())]),
+            steps: Vec::new(),
+            warnings: Vec::new(),
+            blockers: Vec::new(),
+        }
+    }
+
+    fn temp_image(name: &str, len: usize) -> String {
+        let path = std::env::temp_dir().join(format!("planner-{}-{}", std::process::id(), name));
+        fs::write(&path, vec![0u8; len]).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn resolves_variables() {
+        let snapshot = fastboot_snapshot(&[]);
+        let mut planner = planner(&snapshot);
+
+        assert_eq!(planner.resolve(0, "boot_${slot}", true), "boot_a");
+        assert_eq!(planner.resolve(0, "${slot}${slot}-x", true), "aa-x");
+        assert_eq!(planner.resolve(0, "no refs", true), "no refs");
+        assert!(planner.blockers.is_empty());
+    }
+
+    #[test]
+    fn keeps_unclosed_reference_once() {
+        let snapshot = fastboot_snapshot(&[]);
+        let mut planner = planner(&snapshot);
+
+        assert_eq!(planner.resolve(0, "a${b", true), "a${b");
+        assert_eq!(planner.resolve(0, "${slot}-${b", true), "a-${b");
+        assert!(planner.blockers.is_empty());
+    }
+
+    #[test]
+    fn unknown_variable_blocks_only_when_step_runs() {
+        let snapshot = fastboot_snapshot(&[]);
+        let mut planner = planner(&snapshot);
+
+        assert_eq!(planner.resolve(3, "x${missing}y", false), "x${missing}y");
+        assert!(planner.blockers.is_empty());
+
+        planner.resolve(3, "x${missing}y", true);
+        assert_eq!(planner.blockers, vec!["Step 3: unresolved variable ${missing}"]);
+    }
+
+    #[test]
+    fn overrides_take_precedence_over_device_facts() {
+        let snapshot = fastboot_snapshot(&[("current-slot", "b")]);
+        let mut p = pipeline(vec![PipelineStep::fastboot(&["getvar", "${slot}"])]);
+        p.variables.insert("slot".into(), "a".into());
+
+        let plan = plan_pipeline(&p, &snapshot, &BTreeMap::new());
+        assert_eq!(plan.variables["slot"], "b");
+
+        let overrides = BTreeMap::from([("slot".to_string(), "a".to_string())]);
+        let plan = plan_pipeline(&p, &snapshot, &overrides);
+        let PipelineStep::FastbootCommand { args, .. } = &plan.steps[0].step else {
+            panic!("expected a fastboot step");
+        };
+        assert_eq!(args, &["getvar", "a"]);
+    }
+
+    #[test]
+    fn flattens_branches_by_snapshot() {
+        let snapshot = fastboot_snapshot(&[("unlocked", "yes")]);
+        let p = pipeline(vec![PipelineStep::When {
+            condition: StepCondition::Unlocked,
+            steps: vec![PipelineStep::fastboot(&["getvar", "all"])],
+            otherwise: vec![PipelineStep::fastboot(&["flashing", "unlock"])],
+        }]);
+
+        let plan = plan_pipeline(&p, &snapshot, &BTreeMap::new());
+
+        assert_eq!(plan.steps.len(), 2);
+        assert!(plan.steps[0].will_run);
+        assert!(!plan.steps[1].will_run);
+        assert_eq!(plan.steps[1].conditions, vec!["not (bootloader unlocked)"]);
+        assert!(plan.is_executable());
+    }
+
+    #[test]
+    fn unmet_or_unknown_requirement_blocks() {
+        let require = PipelineStep::Require {
+            condition: StepCondition::Unlocked,
+            message: "unlock first".into(),
+        };
+
+        let unknown = fastboot_snapshot(&[]);
+        let plan = plan_pipeline(&pipeline(vec![require.clone()]), &unknown, &BTreeMap::new());
+        assert!(plan.blockers[0].starts_with("Cannot verify requirement"));
+
+        let locked = fastboot_snapshot(&[("unlocked", "no")]);
+        let plan = plan_pipeline(&pipeline(vec![require]), &locked, &BTreeMap::new());
+        assert!(plan.blockers[0].starts_with("Requirement not met: unlock first"));
+    }
+
+    #[test]
+    fn flash_resolves_slot_and_sizes_image() {
+        let image = temp_image("boot.img", 4096);
+        let snapshot = fastboot_snapshot(&[
+            ("current-slot", "b"),
+            ("has-slot:boot", "yes"),
+            ("partition-size:boot_b", "0x1000"),
+        ]);
+        let p = pipeline(vec![PipelineStep::fastboot(&["flash", "boot", &image])]);
+
+        let plan = plan_pipeline(&p, &snapshot, &BTreeMap::new());
+        fs::remove_file(&image).ok();
+
+        let step = &plan.steps[0];
+        assert_eq!(step.target_partition.as_deref(), Some("boot_b"));
+        assert_eq!(step.bytes, Some(4096));
+        assert_eq!(plan.total_bytes, 4096);
+        assert_eq!(plan.max_risk, RiskLevel::High);
+    }
+
+    #[test]
+    fn explicit_slot_wins_over_current_slot() {
+        let snapshot = fastboot_snapshot(&[("current-slot", "a"), ("has-slot:boot", "yes")]);
+        let p = pipeline(vec![PipelineStep::fastboot(&["--slot", "other", "flash", "boot"])]);
+
+        let plan = plan_pipeline(&p, &snapshot, &BTreeMap::new());
+        assert_eq!(plan.steps[0].target_partition.as_deref(), Some("boot_b"));
+    }
+
+    #[test]
+    fn oversized_or_missing_image_blocks() {
+        let image = temp_image("cust.img", 8192);
+        let snapshot = fastboot_snapshot(&[("partition-size:cust", "4096")]);
+        let p = pipeline(vec![
+            PipelineStep::fastboot(&["flash", "cust", &image]),
+            PipelineStep::fastboot(&["flash", "vendor", "/nonexistent/vendor.img"]),
+        ]);
+
+        let plan = plan_pipeline(&p, &snapshot, &BTreeMap::new());
+        fs::remove_file(&image).ok();
+
+        assert_eq!(plan.blockers.len(), 2);
+        assert!(plan.blockers[0].contains("exceeds partition cust"));
+        assert!(plan.blockers[1].contains("image not found"));
+    }
+
+    #[test]
+    fn retry_requires_opt_in_for_flash() {
+        let image = temp_image("vendor.img", 16);
+        let retry = |allow_flash| PipelineStep::FastbootCommand {
+            args: vec!["flash".into(), "vendor".into(), image.clone()],
+            retry: Some(RetryPolicy { attempts: 3, backoff_ms: 0, allow_flash }),
+            timeout_secs: None,
+        };
+        let snapshot = fastboot_snapshot(&[]);
+
+        let plan = plan_pipeline(&pipeline(vec![retry(false)]), &snapshot, &BTreeMap::new());
+        assert!(plan.blockers[0].contains("retry not permitted"));
+
+        let plan = plan_pipeline(&pipeline(vec![retry(true)]), &snapshot, &BTreeMap::new());
+        fs::remove_file(&image).ok();
+        assert!(plan.is_executable());
+    }
+
+    #[test]
+    fn unverified_preloader_needs_override() {
+        let mut image = vec![0u8; 0x2000];
+        image[..4].copy_from_slice(b"MMM\x01");
+        image[4..6].copy_from_slice(&0x38u16.to_le_bytes());
+        image[8..17].copy_from_slice(b"FILE_INFO");
+        let image_path = std::env::temp_dir()
+            .join(format!("planner-{}-preloader.bin", std::process::id()));
+        fs::write(&image_path, image).unwrap();
+        let image = image_path.to_string_lossy().into_owned();
+
+        let snapshot = fastboot_snapshot(&[]);
+        let p = pipeline(vec![PipelineStep::fastboot(&["flash", "preloader", &image])]);
+
+        let plan = plan_pipeline(&p, &snapshot, &BTreeMap::new());
+        assert_eq!(plan.blockers.len(), 1);
+        assert!(plan.blockers[0].ends_with("set ${allow_unverified_images}=yes to flash anyway"));
+
+        let overrides = BTreeMap::from([(ALLOW_UNVERIFIED.to_string(), "yes".to_string())]);
+        let plan = plan_pipeline(&p, &snapshot, &overrides);
+        fs::remove_file(&image_path).ok();
+        assert!(plan.is_executable());
+        assert!(plan.warnings.iter().any(|w| w.contains("overridden")));
+    }
+
+    #[test]
+    fn logical_check_uses_slot_suffixed_name() {
+        let image = temp_image("system.img", 16);
+        let snapshot = fastboot_snapshot(&[
+            ("current-slot", "a"),
+            ("is-logical:system_a", "yes"),
+            ("is-logical:system_b", "yes"),
+        ]);
+        let p = pipeline(vec![PipelineStep::fastboot(&["flash", "system", &image])]);
+
+        let plan = plan_pipeline(&p, &snapshot, &BTreeMap::new());
+        assert_eq!(plan.steps[0].target_partition.as_deref(), Some("system"));
+        assert!(plan.steps[0].notes.iter().any(|n| n == "system is a logical partition in super"));
+        assert!(plan.warnings.iter().any(|w| w.contains("needs fastbootd")));
+
+        let in_fastbootd = pipeline(vec![
+            PipelineStep::fastboot(&["reboot", "fastboot"]),
+            PipelineStep::fastboot(&["flash", "system", &image]),
+        ]);
+        let plan = plan_pipeline(&in_fastbootd, &snapshot, &BTreeMap::new());
+        fs::remove_file(&image).ok();
+        assert!(!plan.warnings.iter().any(|w| w.contains("needs fastbootd")));
+    }
+
+    #[test]
+    fn plan_hash_tracks_resolved_steps() {
+        let snapshot = fastboot_snapshot(&[("current-slot", "a")]);
+        let p = pipeline(vec![PipelineStep::fastboot(&["set_active", "${slot}"])]);
+
+        let first = plan_pipeline(&p, &snapshot, &BTreeMap::new());
+        let again = plan_pipeline(&p, &snapshot, &BTreeMap::new());
+        assert_eq!(first.plan_hash, again.plan_hash);
+
+        let overrides = BTreeMap::from([("slot".to_string(), "b".to_string())]);
+        let changed = plan_pipeline(&p, &snapshot, &overrides);
+        assert_ne!(first.plan_hash, changed.plan_hash);
+    }
+}
@@ -1,4 +1,9 @@
-use std::process::{Command, Output};
+use std::{
+    io::Read,
+    process::{Command, Output, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
 
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
@@ -6,6 +11,8 @@ use std::os::windows::process::CommandExt;
 #[cfg(target_os = "windows")]
 const CREATE_NO_WINDOW: u32 = 0x08000000;
 
+const POLL_INTERVAL_MS: u64 = 100;
+
 pub fn run(cmd: &str, args: &[&str]) -> Result<Output, String> {
     let mut c = Command::new(cmd);
     c.args(args);
@@ -15,3 +22,92 @@ pub fn run(cmd: &str, args: &[&str]) -> Result<Output, String> {
 
     c.output().map_err(|e| e.to_string())
 }
+
+/// Like `run`, but kills the child if it is still alive after `timeout`.
+pub fn run_with_timeout(
+    cmd: &str,
+    args: &[String],
+    timeout: Option<Duration>,
+) -> Result<Output, String> {
+    let mut c = Command::new(cmd);
+    c.args(args).stdout(Stdio::piped()).stderr(Stdio::piped());
+
+    #[cfg(target_os = "windows")]
+    c.creation_flags(CREATE_NO_WINDOW);
+
+    let mut child = c.spawn().map_err(|e| e.to_string())?;
+
+    // Drain pipes on their own threads so a chatty child can't block on a full pipe
+    let stdout = child.stdout.take().map(drain);
+    let stderr = child.stderr.take().map(drain);
+
+    let deadline = timeout.map(|t| Instant::now() + t);
+
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            child.kill().ok();
+            child.wait().ok();
+
+            return Err(format!(
+                "{} timed out after {}s and was killed",
+                cmd,
+                timeout.unwrap_or_default().as_secs()
+            ));
+        }
+
+        thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.and_then(|h| h.join().ok()).unwrap_or_default(),
+        stderr: stderr.and_then(|h| h.join().ok()).unwrap_or_default(),
+    })
+}
+
+fn drain<R: Read + Send + 'static>(mut pipe: R) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        pipe.read_to_end(&mut buf).ok();
+        buf
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str) -> Vec<String> {
+        vec!["-c".to_string(), script.to_string()]
+    }
+
+    #[test]
+    fn collects_output_within_timeout() {
+        let script = sh("echo hello; echo oops >&2");
+        let out = run_with_timeout("sh", &script, Some(Duration::from_secs(5))).unwrap();
+
+        assert!(out.status.success());
+        assert_eq!(out.stdout, b"hello\n");
+        assert_eq!(out.stderr, b"oops\n");
+    }
+
+    #[test]
+    fn kills_child_after_timeout() {
+        let started = Instant::now();
+        let err = run_with_timeout("sh", &sh("sleep 30"), Some(Duration::from_millis(200)))
+            .unwrap_err();
+
+        assert!(err.contains("timed out"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn large_output_does_not_block() {
+        let out = run_with_timeout("sh", &sh("head -c 1000000 /dev/zero"), None).unwrap();
+        assert_eq!(out.stdout.len(), 1_000_000);
+    }
+}
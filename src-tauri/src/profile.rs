@@ -1,9 +1,23 @@
-use serde::Deserialize;
-use std::fs;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+use crate::mtk::chips;
+
+/// Used when no profile matches the attached device.
+pub const GENERIC_PROFILE_ID: &str = "generic-mtk";
+pub const GENERIC_PROFILE_NAME: &str = "Generic MediaTek Device";
 
 #[derive(Debug, Deserialize)]
 pub struct DeviceProfile {
+    /// Stable key for `profile_policy`; defaults to the file stem
+    #[serde(default)]
+    pub id: String,
     pub device: DeviceInfo,
+    #[serde(default)]
+    pub display: Option<DisplayInfo>,
+    /// Partitions the device's GPT is expected to contain
+    #[serde(default)]
+    pub partitions: Vec<ExpectedPartition>,
 }
 #[derive(Debug, Clone)]
 pub struct ProfilePolicy {
@@ -40,6 +54,52 @@ pub struct DeviceInfo {
     pub manufacturer: Option<String>,
 }
 
+/// Panel geometry; used to interpret raw framebuffers such as logo.bin.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DisplayInfo {
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub pixel_format: PixelFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PixelFormat {
+    #[default]
+    Bgra8888,
+    Rgba8888,
+    Rgb565,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Bgra8888 | PixelFormat::Rgba8888 => 4,
+            PixelFormat::Rgb565 => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExpectedPartition {
+    pub name: String,
+    /// Bytes; omitted when it varies between storage sizes
+    #[serde(default)]
+    pub size: Option<u64>,
+}
+
+pub fn find_profile<'a>(profiles: &'a [DeviceProfile], name: &str) -> Option<&'a DeviceProfile> {
+    profiles.iter().find(|p| p.device.name == name)
+}
+
+/// Policy id of the profile named `name`, as returned by `match_profile`.
+pub fn profile_id(profiles: &[DeviceProfile], name: &str) -> String {
+    find_profile(profiles, name)
+        .map(|p| p.id.clone())
+        .unwrap_or_else(|| GENERIC_PROFILE_ID.to_string())
+}
+
 pub fn load_profiles() -> Vec<DeviceProfile> {
     let mut profiles = Vec::new();
 
@@ -47,7 +107,7 @@ pub fn load_profiles() -> Vec<DeviceProfile> {
         for entry in entries.flatten() {
             if entry.path().extension().and_then(|e| e.to_str()) == Some("yaml") {
                 if let Ok(contents) = fs::read_to_string(entry.path()) {
-                    if let Ok(profile) = serde_yaml::from_str::<DeviceProfile>(&contents) {
+                    if let Ok(profile) = parse_profile(&contents, &entry.path()) {
                         profiles.push(profile);
                     }
                 }
@@ -58,6 +118,20 @@ pub fn load_profiles() -> Vec<DeviceProfile> {
     profiles
 }
 
+pub fn parse_profile(yaml: &str, path: &Path) -> Result<DeviceProfile, String> {
+    let mut profile: DeviceProfile = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
+
+    if profile.id.is_empty() {
+        profile.id = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_string();
+    }
+
+    Ok(profile)
+}
+
 pub fn match_profile(
     profiles: &[DeviceProfile],
     model: &str,
@@ -71,13 +145,77 @@ pub fn match_profile(
         }
     }
 
+    // `soc` may be a SoC name or `ro.board.platform`; the chip database
+    // also matches sibling parts of the same die (MT6768 / MT6769).
     for p in profiles {
         if let Some(s) = &p.device.soc {
-            if s.eq_ignore_ascii_case(soc) {
+            if chips::same_chip(s, soc) {
                 return p.device.name.clone();
             }
         }
     }
 
-    "Generic MediaTek Device".to_string()
+    GENERIC_PROFILE_NAME.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KANSAS: &str = "\
+device:
+  name: Motorola Kansas
+  model: XT2513-1
+  soc: MT6835
+display:
+  width: 720
+  height: 1604
+";
+
+    #[test]
+    fn id_defaults_to_file_stem() {
+        let profile = parse_profile(KANSAS, Path::new("devices/xt2513-1.yaml")).unwrap();
+        assert_eq!(profile.id, "xt2513-1");
+
+        let explicit = format!("id: kansas\n{}", KANSAS);
+        let profile = parse_profile(&explicit, Path::new("devices/other.yaml")).unwrap();
+        assert_eq!(profile.id, "kansas");
+
+        let display = profile.display.unwrap();
+        assert_eq!((display.width, display.height), (720, 1604));
+        assert_eq!(display.pixel_format, PixelFormat::Bgra8888);
+    }
+
+    #[test]
+    fn matches_by_model_then_soc() {
+        let profiles = vec![parse_profile(KANSAS, Path::new("xt2513-1.yaml")).unwrap()];
+
+        let name = match_profile(&profiles, "XT2513-1", "");
+        assert_eq!(name, "Motorola Kansas");
+        assert_eq!(profile_id(&profiles, &name), "xt2513-1");
+
+        assert_eq!(match_profile(&profiles, "other", "mt6835"), "Motorola Kansas");
+
+        let name = match_profile(&profiles, "other", "mt6765");
+        assert_eq!(name, GENERIC_PROFILE_NAME);
+        assert_eq!(profile_id(&profiles, &name), GENERIC_PROFILE_ID);
+    }
+
+    #[test]
+    fn shipped_kansas_profile_has_display() {
+        let yaml = include_str!("../../devices/xt2513-1.yaml");
+        let profile = parse_profile(yaml, Path::new("devices/xt2513-1.yaml")).unwrap();
+
+        let display = profile.display.expect("logo unpacking needs the panel size");
+        assert_eq!((display.width, display.height), (720, 1604));
+        assert_eq!(display.pixel_format, PixelFormat::Bgra8888);
+    }
+
+    #[test]
+    fn only_known_ids_get_permissive_policies() {
+        assert!(profile_policy("developer-unlocked").allow_flash);
+        assert!(!profile_policy(GENERIC_PROFILE_ID).allow_flash);
+        assert!(profile_policy(GENERIC_PROFILE_ID).allow_fastboot);
+        assert!(!profile_policy("Motorola Kansas").allow_fastboot);
+    }
 }
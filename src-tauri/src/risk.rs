@@ -0,0 +1,191 @@
+use serde::Serialize;
+
+/* ================= RISK LEVELS ================= */
+
+/// Ordered from harmless to brick-capable so plans can report
+/// the highest risk they contain with a simple `max()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum RiskLevel {
+    None,
+    Low,
+    Medium,
+    Unknown,
+    High,
+    Critical,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FlashRisk {
+    pub level: RiskLevel,
+    pub label: &'static str,
+}
+
+/* ================= FLASH RISK ================= */
+
+pub fn classify_flash_risk(partition: &str) -> FlashRisk {
+    let (level, label) = match base_partition(&partition.to_lowercase()) {
+        "preloader" | "bootloader" | "lk" | "lk2" =>
+            (RiskLevel::Critical, "CRITICAL: boot chain"),
+
+        "partition" | "gpt" | "pgpt" | "sgpt" =>
+            (RiskLevel::Critical, "CRITICAL: partition table"),
+
+        "vbmeta" | "vbmeta_system" | "vbmeta_vendor" =>
+            (RiskLevel::Critical, "CRITICAL: verified boot"),
+
+        "boot" | "vendor_boot" | "init_boot" =>
+            (RiskLevel::High, "HIGH: kernel / ramdisk"),
+
+        "dtbo" =>
+            (RiskLevel::High, "HIGH: device tree"),
+
+        "system" | "vendor" =>
+            (RiskLevel::Medium, "MEDIUM: system image"),
+
+        _ =>
+            (RiskLevel::Unknown, "UNKNOWN / USER-SPECIFIED"),
+    };
+
+    FlashRisk { level, label }
+}
+
+/// Partition name without its slot suffix (`_a`, `_b` or a `${slot}` variable).
+pub fn base_partition(partition: &str) -> &str {
+    ["_a", "_b", "_${slot}", "_${other_slot}"]
+        .iter()
+        .find_map(|suffix| partition.strip_suffix(suffix))
+        .unwrap_or(partition)
+}
+
+/// Risk of a raw fastboot invocation, by sub-command.
+pub fn classify_fastboot_args(args: &[String]) -> FlashRisk {
+    if args.iter().any(|a| a.starts_with("--set-active")) {
+        return FlashRisk { level: RiskLevel::High, label: "HIGH: active slot change" };
+    }
+
+    let positional = fastboot_positional(args);
+
+    match positional.as_slice() {
+        ["flash", partition, ..] | ["erase", partition, ..] | ["format", partition, ..]
+        | ["format:ext4", partition, ..] | ["format:f2fs", partition, ..] =>
+            classify_flash_risk(partition),
+
+        ["flashing", "lock", ..] | ["flashing", "unlock", ..]
+        | ["oem", "lock", ..] | ["oem", "unlock", ..] =>
+            FlashRisk { level: RiskLevel::Critical, label: "CRITICAL: bootloader lock state" },
+
+        ["wipe-super", ..] | ["update-super", ..] =>
+            FlashRisk { level: RiskLevel::Critical, label: "CRITICAL: super partition layout" },
+
+        ["flashall", ..] | ["update", ..] | ["-w", ..] =>
+            FlashRisk { level: RiskLevel::Critical, label: "CRITICAL: multi-partition write" },
+
+        ["set_active", ..] =>
+            FlashRisk { level: RiskLevel::High, label: "HIGH: active slot change" },
+
+        ["getvar", ..] | ["devices", ..] =>
+            FlashRisk { level: RiskLevel::None, label: "NONE: read-only query" },
+
+        ["reboot", ..] | ["reboot-bootloader", ..] | ["continue", ..] =>
+            FlashRisk { level: RiskLevel::Low, label: "LOW: reboot" },
+
+        _ =>
+            FlashRisk { level: RiskLevel::Unknown, label: "UNKNOWN / USER-SPECIFIED" },
+    }
+}
+
+/// Fastboot arguments with global options (and their values) removed.
+pub fn fastboot_positional(args: &[String]) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // Options that take a separate value
+            "--slot" | "-s" | "-S" | "--cmdline" | "--base" | "--page-size" => {
+                iter.next();
+            }
+            a if a.starts_with("--") => {}
+            a => out.push(a),
+        }
+    }
+
+    out
+}
+
+/// Risk of a raw adb invocation. Shell commands are opaque, so
+/// anything we cannot recognise is reported as unknown.
+pub fn classify_adb_args(args: &[String]) -> FlashRisk {
+    let positional: Vec<&str> = args.iter().map(|a| a.as_str()).collect();
+
+    match positional.as_slice() {
+        ["reboot", ..] =>
+            FlashRisk { level: RiskLevel::Low, label: "LOW: reboot" },
+
+        ["devices", ..] | ["get-state", ..] | ["get-serialno", ..]
+        | ["shell", "getprop", ..] | ["pull", ..] =>
+            FlashRisk { level: RiskLevel::None, label: "NONE: read-only query" },
+
+        _ =>
+            FlashRisk { level: RiskLevel::Unknown, label: "UNKNOWN / USER-SPECIFIED" },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fastboot(args: &str) -> FlashRisk {
+        let args: Vec<String> = args.split_whitespace().map(String::from).collect();
+        classify_fastboot_args(&args)
+    }
+
+    #[test]
+    fn flash_and_erase_follow_the_partition() {
+        assert_eq!(fastboot("flash preloader preloader.bin").level, RiskLevel::Critical);
+        assert_eq!(fastboot("flash vbmeta_a vbmeta.img").level, RiskLevel::Critical);
+        assert_eq!(fastboot("flash boot_b boot.img").level, RiskLevel::High);
+        assert_eq!(fastboot("--slot all flash dtbo dtbo.img").level, RiskLevel::High);
+        assert_eq!(fastboot("flash system system.img").level, RiskLevel::Medium);
+        assert_eq!(fastboot("flash cust cust.img").level, RiskLevel::Unknown);
+        assert_eq!(fastboot("erase pgpt").level, RiskLevel::Critical);
+        assert_eq!(fastboot("-s 0123456789 erase userdata").level, RiskLevel::Unknown);
+        assert_eq!(fastboot("format:ext4 vendor").level, RiskLevel::Medium);
+    }
+
+    #[test]
+    fn super_layout_and_lock_state_are_critical() {
+        let wipe = fastboot("wipe-super super_empty.img");
+        assert_eq!(wipe.level, RiskLevel::Critical);
+        assert_eq!(wipe.label, "CRITICAL: super partition layout");
+
+        for args in ["oem unlock", "oem lock", "flashing unlock", "flashing lock"] {
+            let risk = fastboot(args);
+            assert_eq!(risk.level, RiskLevel::Critical, "{}", args);
+            assert_eq!(risk.label, "CRITICAL: bootloader lock state");
+        }
+
+        assert_eq!(fastboot("-w update image.zip").level, RiskLevel::Critical);
+    }
+
+    #[test]
+    fn other_oem_commands_are_unknown() {
+        assert_eq!(fastboot("oem device-info").level, RiskLevel::Unknown);
+        assert_eq!(fastboot("oem").level, RiskLevel::Unknown);
+    }
+
+    #[test]
+    fn queries_reboots_and_slot_changes() {
+        assert_eq!(fastboot("getvar all").level, RiskLevel::None);
+        assert_eq!(fastboot("reboot bootloader").level, RiskLevel::Low);
+        assert_eq!(fastboot("set_active b").level, RiskLevel::High);
+        assert_eq!(fastboot("--set-active=b reboot").level, RiskLevel::High);
+    }
+
+    #[test]
+    fn base_partition_strips_slot_suffixes() {
+        assert_eq!(base_partition("boot_a"), "boot");
+        assert_eq!(base_partition("vbmeta_${other_slot}"), "vbmeta");
+        assert_eq!(base_partition("md1img"), "md1img");
+    }
+}
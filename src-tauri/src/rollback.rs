@@ -0,0 +1,247 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, path::Path};
+
+use crate::android::avb::{self, AvbDescriptor, Vbmeta};
+use crate::process::run;
+use crate::snapshot::DeviceSnapshot;
+
+// Anti-rollback: a locked bootloader refuses any vbmeta whose rollback
+// index is below the value stored for its location, and bumps the stored
+// value after a successful boot. Images are compared against what the
+// device reports (`getvar rollback-index:<location>`) or, failing that,
+// against the vbmeta currently installed, read back through root.
+//
+// The stored value is only ever raised to the index of a vbmeta that
+// booted, so the installed vbmeta's index is an upper bound on it. An
+// image passing against the installed index is guaranteed to pass; one
+// failing against it may still be accepted if the stored value lags.
+
+/// Enough to cover any vbmeta partition's header and blocks.
+const VBMETA_READ_BYTES: u32 = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RollbackIndexes {
+    pub serial: Option<String>,
+    /// Where the values came from, e.g. "getvar" or "root: vbmeta_a"
+    pub source: String,
+    /// Rollback index by location
+    pub indexes: BTreeMap<u32, u64>,
+    pub notes: Vec<String>,
+}
+
+/// Values a bootloader exposes as `rollback-index:<location>`.
+pub fn from_getvars(getvars: &BTreeMap<String, String>) -> BTreeMap<u32, u64> {
+    getvars
+        .iter()
+        .filter_map(|(key, value)| {
+            let location = key.strip_prefix("rollback-index:")?.parse().ok()?;
+            let index = crate::snapshot::parse_size(value)?;
+            Some((location, index))
+        })
+        .collect()
+}
+
+/// Read the installed vbmeta (and the vbmeta partitions it chains to)
+/// through `su`. Only reads; needs an authorised, rooted ADB device.
+pub fn read_via_root(snapshot: &DeviceSnapshot) -> Result<RollbackIndexes, String> {
+    let suffix = snapshot.current_slot().map(|s| format!("_{}", s)).unwrap_or_default();
+    let partition = format!("vbmeta{}", suffix);
+
+    let vbmeta = read_partition_vbmeta(&partition)?;
+
+    let mut result = RollbackIndexes {
+        serial: snapshot.serial.clone(),
+        source: format!("root: {}", partition),
+        indexes: BTreeMap::new(),
+        notes: Vec::new(),
+    };
+
+    result
+        .indexes
+        .insert(vbmeta.header.rollback_index_location, vbmeta.header.rollback_index);
+
+    for descriptor in &vbmeta.descriptors {
+        let AvbDescriptor::ChainPartition { partition, rollback_index_location, .. } = descriptor
+        else {
+            continue;
+        };
+
+        let name = format!("{}{}", partition, suffix);
+
+        // Partitions signed in place (boot...) keep their vbmeta at the end
+        // and are not found here
+        match read_partition_vbmeta(&name) {
+            Ok(chained) => {
+                result.indexes.insert(*rollback_index_location, chained.header.rollback_index);
+            }
+            Err(e) => result.notes.push(format!("{} not read: {}", name, e)),
+        }
+    }
+
+    Ok(result)
+}
+
+fn read_partition_vbmeta(partition: &str) -> Result<Vbmeta, String> {
+    let command = format!(
+        "su -c 'dd if=/dev/block/by-name/{} bs={} count=1 2>/dev/null'",
+        partition, VBMETA_READ_BYTES
+    );
+
+    let out = run("adb", &["exec-out", &command])?;
+
+    if !out.status.success() || out.stdout.is_empty() {
+        return Err(format!(
+            "Could not read {}: {}",
+            partition,
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+
+    avb::parse_vbmeta(&out.stdout)
+}
+
+/* ================= CHECK ================= */
+
+pub enum RollbackCheck {
+    /// Image index is at least the device's
+    Ok(String),
+    Downgrade(String),
+    Unknown(String),
+}
+
+/// Rollback location enforced for `image` flashed to `partition`: the
+/// header's own for vbmeta, otherwise the chain descriptor naming the
+/// partition in the package's `vbmeta.img`. Partitions that are only
+/// hashed by vbmeta have none.
+pub fn rollback_location(image: &Path, partition: &str, vbmeta: &Vbmeta) -> Option<u32> {
+    if partition == "vbmeta" {
+        return Some(vbmeta.header.rollback_index_location);
+    }
+
+    let top = avb::read_vbmeta(&image.parent()?.join("vbmeta.img")).ok()?;
+
+    top.descriptors.iter().find_map(|d| match d {
+        AvbDescriptor::ChainPartition { partition: p, rollback_index_location, .. }
+            if p == partition =>
+        {
+            Some(*rollback_index_location)
+        }
+        _ => None,
+    })
+}
+
+/// Compare an image's vbmeta against the device's stored index for
+/// `location`, or failing that the index of the installed vbmeta.
+pub fn check_rollback(
+    vbmeta: &Vbmeta,
+    location: u32,
+    device: &BTreeMap<u32, u64>,
+    installed: &BTreeMap<u32, u64>,
+) -> RollbackCheck {
+    let image = vbmeta.header.rollback_index;
+
+    match (device.get(&location), installed.get(&location)) {
+        (Some(&stored), _) if image < stored => RollbackCheck::Downgrade(format!(
+            "rollback index {} is lower than the device's {} (location {}). This is a \
+             downgrade: a locked bootloader will refuse to boot it",
+            image, stored, location
+        )),
+        (Some(&stored), _) => RollbackCheck::Ok(format!(
+            "Rollback index {} ≥ device {} (location {})",
+            image, stored, location
+        )),
+        (None, Some(&current)) if image < current => RollbackCheck::Downgrade(format!(
+            "rollback index {} is lower than the installed vbmeta's {} (location {}). \
+             This is likely a downgrade: a locked bootloader may refuse to boot it",
+            image, current, location
+        )),
+        (None, Some(&current)) => RollbackCheck::Ok(format!(
+            "Rollback index {} ≥ installed {} (location {})",
+            image, current, location
+        )),
+        (None, None) => RollbackCheck::Unknown(format!(
+            "Device rollback index for location {} unknown; downgrade check skipped",
+            location
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::android::avb::tests::{chain_descriptor, hash_descriptor, vbmeta};
+    use std::fs;
+
+    #[test]
+    fn reads_getvar_indexes() {
+        let getvars = BTreeMap::from([
+            ("rollback-index:0".to_string(), "9".to_string()),
+            ("rollback-index:1".to_string(), "0x10".to_string()),
+            ("rollback-index:x".to_string(), "1".to_string()),
+            ("current-slot".to_string(), "a".to_string()),
+        ]);
+
+        assert_eq!(from_getvars(&getvars), BTreeMap::from([(0, 9), (1, 16)]));
+    }
+
+    #[test]
+    fn lower_index_is_a_downgrade() {
+        let image = avb::parse_vbmeta(&vbmeta(&[], 7, 0)).unwrap();
+        let device = BTreeMap::from([(0, 9), (1, 7)]);
+        let none = BTreeMap::new();
+
+        let RollbackCheck::Downgrade(message) = check_rollback(&image, 0, &device, &none) else {
+            panic!("expected a downgrade");
+        };
+        assert!(message.ends_with("will refuse to boot it"));
+        assert!(matches!(check_rollback(&image, 1, &device, &none), RollbackCheck::Ok(_)));
+        assert!(matches!(check_rollback(&image, 2, &device, &none), RollbackCheck::Unknown(_)));
+    }
+
+    #[test]
+    fn installed_vbmeta_is_an_upper_bound() {
+        let image = avb::parse_vbmeta(&vbmeta(&[], 7, 0)).unwrap();
+        let device = BTreeMap::from([(1, 9)]);
+        let installed = BTreeMap::from([(0, 9), (1, 5), (2, 7)]);
+
+        let RollbackCheck::Downgrade(message) = check_rollback(&image, 0, &device, &installed)
+        else {
+            panic!("expected a downgrade");
+        };
+        assert!(message.contains("installed vbmeta's 9"));
+        assert!(message.ends_with("may refuse to boot it"));
+
+        // The stored value wins over the installed vbmeta
+        assert!(matches!(
+            check_rollback(&image, 1, &device, &installed),
+            RollbackCheck::Downgrade(_)
+        ));
+        assert!(matches!(check_rollback(&image, 2, &device, &installed), RollbackCheck::Ok(_)));
+    }
+
+    #[test]
+    fn locations_come_from_header_or_chain() {
+        let dir = std::env::temp_dir().join(format!("rollback-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let top = vbmeta(
+            &[chain_descriptor("vbmeta_system", 1, &[0u8; 8]), hash_descriptor("boot", b"k")],
+            4,
+            0,
+        );
+        fs::write(dir.join("vbmeta.img"), &top).unwrap();
+
+        let system = avb::parse_vbmeta(&vbmeta(&[], 2, 1)).unwrap();
+        let top = avb::parse_vbmeta(&top).unwrap();
+
+        let image = dir.join("vbmeta_system.img");
+        let vbmeta_location = rollback_location(&dir.join("vbmeta.img"), "vbmeta", &top);
+        let chained = rollback_location(&image, "vbmeta_system", &system);
+        let hashed = rollback_location(&dir.join("boot.img"), "boot", &top);
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(vbmeta_location, Some(0));
+        assert_eq!(chained, Some(1));
+        assert_eq!(hashed, None);
+    }
+}
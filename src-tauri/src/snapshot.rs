@@ -0,0 +1,312 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::detection_service::DeviceState;
+use crate::mtk::chips::{self, ChipInfo};
+use crate::process::run;
+use crate::profile::{load_profiles, match_profile, profile_id};
+use crate::rollback;
+
+/// Point-in-time view of the attached device, used to plan and
+/// re-verify pipelines without touching the device again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceSnapshot {
+    pub state: DeviceState,
+    pub serial: Option<String>,
+    pub getvars: BTreeMap<String, String>,
+    pub props: BTreeMap<String, String>,
+    /// AVB rollback index by location, as stored by the bootloader (getvar)
+    #[serde(default)]
+    pub rollback_indexes: BTreeMap<u32, u64>,
+    /// Rollback index of the installed vbmeta by location, read back
+    /// through root. An upper bound on the stored value.
+    #[serde(default)]
+    pub installed_rollback_indexes: BTreeMap<u32, u64>,
+    pub captured_at: u64,
+}
+
+impl DeviceSnapshot {
+    pub fn empty(state: DeviceState) -> Self {
+        Self {
+            state,
+            serial: None,
+            getvars: BTreeMap::new(),
+            props: BTreeMap::new(),
+            rollback_indexes: BTreeMap::new(),
+            installed_rollback_indexes: BTreeMap::new(),
+            captured_at: now_secs(),
+        }
+    }
+
+    pub fn getvar(&self, name: &str) -> Option<&str> {
+        self.getvars.get(name).map(|v| v.as_str())
+    }
+
+    pub fn prop(&self, name: &str) -> Option<&str> {
+        self.props.get(name).map(|v| v.as_str())
+    }
+
+    /// Current slot letter ("a" / "b"), from fastboot or Android.
+    pub fn current_slot(&self) -> Option<&str> {
+        self.getvar("current-slot")
+            .or_else(|| self.prop("ro.boot.slot_suffix"))
+            .map(|s| s.trim_start_matches('_'))
+            .filter(|s| !s.is_empty())
+    }
+
+    pub fn other_slot(&self) -> Option<&'static str> {
+        match self.current_slot()? {
+            "a" => Some("b"),
+            "b" => Some("a"),
+            _ => None,
+        }
+    }
+
+    pub fn is_ab(&self) -> bool {
+        let slots = self
+            .getvar("slot-count")
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(0);
+
+        slots >= 2 || self.current_slot().is_some()
+    }
+
+    /// `Some(true)` if the bootloader reports the partition as slotted.
+    pub fn has_slot(&self, partition: &str) -> Option<bool> {
+        self.getvar(&format!("has-slot:{}", partition))
+            .map(|v| v == "yes")
+    }
+
+    pub fn is_unlocked(&self) -> Option<bool> {
+        if let Some(v) = self.getvar("unlocked") {
+            return Some(v == "yes");
+        }
+
+        self.prop("ro.boot.flash.locked").map(|v| v == "0")
+    }
+
+    pub fn max_download_size(&self) -> Option<u64> {
+        self.getvar("max-download-size").and_then(parse_size)
+    }
+
+    pub fn partition_size(&self, partition: &str) -> Option<u64> {
+        self.getvar(&format!("partition-size:{}", partition))
+            .and_then(parse_size)
+    }
+
+    pub fn model(&self) -> Option<&str> {
+        self.prop("ro.product.model")
+            .or_else(|| self.getvar("product"))
+    }
+
+    /// `ro.board.platform` (e.g. "mt6835"); fastboot rarely reports it.
+    pub fn platform(&self) -> Option<&str> {
+        self.prop("ro.board.platform")
+            .or_else(|| self.prop("ro.hardware"))
+            .or_else(|| self.getvar("platform"))
+    }
+}
+
+/* ================= DEVICE DETAILS ================= */
+
+#[derive(Debug, Serialize)]
+pub struct DeviceDetails {
+    pub state: DeviceState,
+    pub serial: Option<String>,
+    pub model: Option<String>,
+    pub platform: Option<String>,
+    pub chip: Option<&'static ChipInfo>,
+    /// Display name of the matched profile
+    pub profile: String,
+    /// Key for `profile::profile_policy`
+    pub profile_id: String,
+}
+
+/// Summary for the device info panel: identity, SoC and matched profile.
+pub fn describe_device(snapshot: &DeviceSnapshot) -> DeviceDetails {
+    let platform = snapshot.platform();
+    let chip = chips::identify(None, platform);
+
+    let soc = chip.map(|c| c.soc).or(platform).unwrap_or("");
+    let profiles = load_profiles();
+    let profile = match_profile(&profiles, snapshot.model().unwrap_or(""), soc);
+    let profile_id = profile_id(&profiles, &profile);
+
+    DeviceDetails {
+        state: snapshot.state.clone(),
+        serial: snapshot.serial.clone(),
+        model: snapshot.model().map(|m| m.to_string()),
+        platform: platform.map(|p| p.to_string()),
+        chip,
+        profile,
+        profile_id,
+    }
+}
+
+/* ================= CAPTURE ================= */
+
+/// Query the device once for everything the planner may need.
+/// Only read-only commands are issued.
+pub fn capture_snapshot(state: &DeviceState) -> DeviceSnapshot {
+    let mut snapshot = DeviceSnapshot::empty(state.clone());
+
+    match state {
+        DeviceState::Fastboot => {
+            if let Ok(out) = run("fastboot", &["getvar", "all"]) {
+                // fastboot prints getvar results on stderr
+                let text = String::from_utf8_lossy(&out.stderr);
+                snapshot.getvars = parse_getvar_output(&text);
+                snapshot.rollback_indexes = rollback::from_getvars(&snapshot.getvars);
+            }
+
+            if let Ok(out) = run("fastboot", &["devices"]) {
+                snapshot.serial = String::from_utf8_lossy(&out.stdout)
+                    .split_whitespace()
+                    .next()
+                    .map(|s| s.to_string());
+            }
+        }
+
+        DeviceState::AdbDevice => {
+            if let Ok(out) = run("adb", &["shell", "getprop"]) {
+                snapshot.props = parse_getprop_output(&String::from_utf8_lossy(&out.stdout));
+            }
+
+            if let Ok(out) = run("adb", &["get-serialno"]) {
+                let serial = String::from_utf8_lossy(&out.stdout).trim().to_string();
+                if !serial.is_empty() && serial != "unknown" {
+                    snapshot.serial = Some(serial);
+                }
+            }
+        }
+
+        _ => {}
+    }
+
+    snapshot
+}
+
+/// Parse `(bootloader) key: value` lines from `fastboot getvar`.
+pub fn parse_getvar_output(text: &str) -> BTreeMap<String, String> {
+    let mut vars = BTreeMap::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+        let line = line.strip_prefix("(bootloader)").unwrap_or(line).trim();
+
+        // Keys like "partition-size:boot_a" contain ':' themselves
+        let split = line.split_once(": ").or_else(|| line.rsplit_once(':'));
+
+        if let Some((key, value)) = split {
+            let key = key.trim();
+            if key.is_empty() || key.contains(' ') {
+                continue;
+            }
+            vars.insert(key.to_string(), value.trim().to_string());
+        }
+    }
+
+    vars
+}
+
+/// Parse `[key]: [value]` lines from `adb shell getprop`.
+pub fn parse_getprop_output(text: &str) -> BTreeMap<String, String> {
+    let mut props = BTreeMap::new();
+
+    for line in text.lines() {
+        if let Some((key, value)) = line.trim().split_once("]: [") {
+            let key = key.trim_start_matches('[');
+            let value = value.trim_end_matches(']');
+            props.insert(key.to_string(), value.to_string());
+        }
+    }
+
+    props
+}
+
+/// Accepts both `0x`-prefixed hex and decimal sizes.
+pub fn parse_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+
+    match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GETVAR_ALL: &str = "\
+(bootloader) max-download-size: 0x10000000
+(bootloader) variant:
+(bootloader) partition-size:boot_a: 0x4000000
+(bootloader) partition-type:boot_a: raw
+(bootloader) is-logical:system_a: yes
+(bootloader) current-slot: a
+(bootloader) slot-count:2
+(bootloader) unlocked: no
+all: 
+Finished. Total time: 0.052s
+";
+
+    #[test]
+    fn getvar_all_output() {
+        let vars = parse_getvar_output(GETVAR_ALL);
+
+        assert_eq!(vars["max-download-size"], "0x10000000");
+        assert_eq!(vars["partition-size:boot_a"], "0x4000000");
+        assert_eq!(vars["partition-type:boot_a"], "raw");
+        assert_eq!(vars["is-logical:system_a"], "yes");
+        assert_eq!(vars["current-slot"], "a");
+        assert_eq!(vars["slot-count"], "2");
+        assert_eq!(vars["unlocked"], "no");
+        assert_eq!(vars["variant"], "");
+        assert!(!vars.keys().any(|k| k.starts_with("Finished")));
+    }
+
+    #[test]
+    fn single_getvar_output() {
+        let vars = parse_getvar_output("product: lancelot\nFinished. Total time: 0.001s\n");
+        assert_eq!(vars.len(), 1);
+        assert_eq!(vars["product"], "lancelot");
+    }
+
+    #[test]
+    fn getprop_output() {
+        let props = parse_getprop_output(
+            "[ro.product.model]: [Redmi 9]\n\
+             [ro.boot.slot_suffix]: []\n\
+             [ro.build.fingerprint]: [Redmi/lancelot/lancelot:11/RP1A.200720.011/V12.5:user]\n\
+             garbage line\n",
+        );
+
+        assert_eq!(props.len(), 3);
+        assert_eq!(props["ro.product.model"], "Redmi 9");
+        assert_eq!(props["ro.boot.slot_suffix"], "");
+        assert_eq!(
+            props["ro.build.fingerprint"],
+            "Redmi/lancelot/lancelot:11/RP1A.200720.011/V12.5:user"
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("0x4000000"), Some(0x400_0000));
+        assert_eq!(parse_size(" 0X10 "), Some(16));
+        assert_eq!(parse_size("67108864"), Some(67_108_864));
+        assert_eq!(parse_size("0x"), None);
+        assert_eq!(parse_size("big"), None);
+    }
+}
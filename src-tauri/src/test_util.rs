@@ -0,0 +1,12 @@
+use std::{fs, path::PathBuf};
+
+// NOTE:
+// Helpers shared by unit tests across modules. Compiled for tests only.
+
+/// Fresh, empty scratch directory unique to this test process.
+pub fn temp_dir(prefix: &str, name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}-{}", prefix, std::process::id(), name));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
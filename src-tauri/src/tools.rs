@@ -1,6 +1,6 @@
 use std::{
     fs,
-    io::{self, Read},
+    io,
     path::PathBuf,
 };
 
@@ -132,10 +132,10 @@ pub fn install_platform_tools(app: &AppHandle) -> Result<(), String> {
 
 fn verify_checksum(path: &PathBuf) -> Result<(), String> {
     let mut file = fs::File::open(path).map_err(|e| e.to_string())?;
-    let mut buf = Vec::new();
-    file.read_to_end(&mut buf).map_err(|e| e.to_string())?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).map_err(|e| e.to_string())?;
 
-    let hash = format!("{:x}", Sha256::digest(&buf));
+    let hash = format!("{:x}", hasher.finalize());
 
     if hash != PLATFORM_TOOLS_SHA256 {
         return Err("Platform-tools checksum verification failed".into());